@@ -0,0 +1,108 @@
+//! Count the heap allocations of reading a shell channel by
+//! `read`, which returns a fresh `Vec` each time, against
+//! `read_into`, which appends to a reused buffer.
+//!
+//! Run with `cargo bench --bench read_into`
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use common::{msg, MockServer};
+
+/// counts the allocations of the threads which enable it
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if ENABLED.with(|x| x.get()) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if ENABLED.with(|x| x.get()) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ROUNDS: usize = 200;
+const PACKETS: usize = 16;
+const PACKET_SIZE: usize = 1024;
+
+/// the allocations of reading `ROUNDS` bursts of `PACKETS` packets
+fn allocations(use_read_into: bool) -> usize {
+    let server = MockServer::spawn(|conn| {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        for _ in 0..ROUNDS {
+            // one burst for each request of the client
+            conn.recv_msg(msg::CHANNEL_DATA);
+            for _ in 0..PACKETS {
+                conn.send_data(&ch, &[0x5a; PACKET_SIZE]);
+            }
+        }
+        conn.wait_disconnect();
+    });
+
+    let mut session = common::builder().connect(server.addr).unwrap().run_local();
+    let mut shell = session.open_shell().unwrap();
+    let mut buf = Vec::with_capacity(PACKETS * PACKET_SIZE);
+
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    ENABLED.with(|x| x.set(true));
+    for _ in 0..ROUNDS {
+        shell.write(b"more").unwrap();
+        buf.clear();
+        while buf.len() < PACKETS * PACKET_SIZE {
+            if use_read_into {
+                shell.read_into(&mut buf).unwrap();
+            } else {
+                buf.extend(shell.read().unwrap());
+            }
+        }
+    }
+    ENABLED.with(|x| x.set(false));
+    let count = ALLOCATIONS.load(Ordering::Relaxed);
+
+    drop(shell);
+    session.close();
+    server.join();
+    count
+}
+
+fn main() {
+    let read = allocations(false);
+    let read_into = allocations(true);
+    let per_round = |count: usize| count as f64 / ROUNDS as f64;
+    println!(
+        "allocations per {} packets of {} bytes: read {:.1}, read_into {:.1}",
+        PACKETS,
+        PACKET_SIZE,
+        per_round(read),
+        per_round(read_into)
+    );
+}
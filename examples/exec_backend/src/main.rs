@@ -34,7 +34,7 @@ fn main() {
     // get command result
     let vec: Vec<u8> = exec.get_result().unwrap();
     println!("output: {}", String::from_utf8(vec).unwrap());
-    println!("exit status: {}", exec.exit_status().unwrap());
+    println!("exit status: {:?}", exec.exit_status());
     println!("terminated msg: {}", exec.terminate_msg().unwrap());
 
     // Close session.
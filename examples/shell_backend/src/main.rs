@@ -29,7 +29,7 @@ fn main() {
     // Close channel.
     shell.close().unwrap();
     sleep(Duration::from_secs(2));
-    println!("exit status: {}", shell.exit_status().unwrap());
+    println!("exit status: {:?}", shell.exit_status());
     println!("terminated msg: {}", shell.terminate_msg().unwrap());
     // Close session.
     session.close();
@@ -86,29 +86,22 @@ impl Compression for CompressZlib {
             let consumed = (self.compressor.total_in() - in_before) as usize;
             let produced = (self.compressor.total_out() - out_before) as usize;
 
-            // tracing::info!(consumed);
-            // tracing::info!(produced);
-
-            // means an empty compress
-            // 2 bytes ZLIB header at the start of the stream
-            // 4 bytes CRC checksum at the end of the stream
-            if produced == 6 {
-                break;
-            }
+            buf_in = &buf_in[consumed..];
+            buf_out.extend(&buf_once[..produced]);
 
             match result {
-                Ok(flate2::Status::Ok) => {
-                    buf_in = &buf_in[consumed..];
-                    buf_out.extend(&buf_once[..produced]);
+                // the partial flush is done
+                // once all the input is consumed and the output buffer is not full
+                Ok(flate2::Status::Ok) | Ok(flate2::Status::BufError) => {
+                    if buf_in.is_empty() && produced < buf_once.len() {
+                        break;
+                    }
                 }
                 Ok(flate2::Status::StreamEnd) => {
                     return Err(SshError::CompressionError(
                         "Stream ends during the compress".to_owned(),
                     ));
                 }
-                Ok(flate2::Status::BufError) => {
-                    break;
-                }
                 Err(e) => return Err(SshError::CompressionError(e.to_string())),
             }
         }
@@ -46,10 +46,6 @@ macro_rules! crate_aes_cbc {
                 BLOCK_SIZE
             }
 
-            fn iv_size(&self) -> usize {
-                IV_SIZE
-            }
-
             fn new(hash: Hash, mac: Box<dyn Mac>) -> Self
             where
                 Self: Sized,
@@ -118,9 +114,7 @@ macro_rules! crate_aes_cbc {
                     .sign(&self.extend.ik_s_c, server_sequence_number, d);
                 let t = tag.as_ref();
                 if m != t {
-                    return Err(SshError::EncryptionError(
-                        "Failed to decrypt the server traffic".to_owned(),
-                    ));
+                    return Err(SshError::MacVerificationError(server_sequence_number));
                 }
                 Ok(d.to_vec())
             }
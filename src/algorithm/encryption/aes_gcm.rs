@@ -0,0 +1,105 @@
+use crate::algorithm::encryption::Encryption;
+use crate::algorithm::hash::Hash;
+use crate::algorithm::mac::Mac;
+use crate::error::SshError;
+use crate::SshResult;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+const GCM256_KEY_SIZE: usize = 32;
+const IV_SIZE: usize = 12;
+const BLOCK_SIZE: usize = 16;
+const TAG_SIZE: usize = 16;
+
+/// <https://www.rfc-editor.org/rfc/rfc5647#section-7.1>
+///
+/// The nonce is a fixed field of 4 bytes and an invocation counter of 8 bytes,
+/// the counter is increased by one for each packet
+struct Invocation {
+    fixed: [u8; 4],
+    counter: u64,
+}
+
+impl Invocation {
+    fn from(iv: &[u8]) -> Self {
+        Invocation {
+            fixed: iv[..4].try_into().unwrap(),
+            counter: u64::from_be_bytes(iv[4..IV_SIZE].try_into().unwrap()),
+        }
+    }
+
+    fn next(&mut self) -> Nonce {
+        let mut nonce = [0_u8; IV_SIZE];
+        nonce[..4].copy_from_slice(&self.fixed);
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self.counter.wrapping_add(1);
+        Nonce::assume_unique_for_key(nonce)
+    }
+}
+
+/// <https://www.rfc-editor.org/rfc/rfc5647>
+///
+/// aes256-gcm@openssh.com, the packet length is not encrypted
+/// but authenticated as the associated data
+pub(super) struct Gcm256 {
+    client_key: LessSafeKey,
+    server_key: LessSafeKey,
+    client_nonce: Invocation,
+    server_nonce: Invocation,
+}
+
+impl Encryption for Gcm256 {
+    fn bsize(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn new(hash: Hash, _mac: Box<dyn Mac>) -> Self
+    where
+        Self: Sized,
+    {
+        let (ck, sk) = hash.mix_ek(GCM256_KEY_SIZE);
+        let key = |k: &[u8]| LessSafeKey::new(UnboundKey::new(&AES_256_GCM, k).unwrap());
+
+        Gcm256 {
+            client_key: key(&ck),
+            server_key: key(&sk),
+            client_nonce: Invocation::from(&hash.iv_c_s),
+            server_nonce: Invocation::from(&hash.iv_s_c),
+        }
+    }
+
+    fn encrypt(&mut self, _client_sequence_num: u32, buf: &mut Vec<u8>) {
+        let (len, data) = buf.split_at_mut(4);
+        let tag = self
+            .client_key
+            .seal_in_place_separate_tag(self.client_nonce.next(), Aad::from(&*len), data)
+            .unwrap();
+        buf.extend(tag.as_ref())
+    }
+
+    fn decrypt(&mut self, sequence_number: u32, buf: &mut [u8]) -> SshResult<Vec<u8>> {
+        let pl = self.packet_len(sequence_number, buf);
+        let (len, data) = buf[..pl + TAG_SIZE].split_at_mut(4);
+        match self
+            .server_key
+            .open_in_place(self.server_nonce.next(), Aad::from(&*len), data)
+        {
+            Ok(result) => Ok([&*len, result].concat()),
+            Err(_) => Err(SshError::EncryptionError(
+                "Failed to decrypt the server traffic".to_owned(),
+            )),
+        }
+    }
+
+    fn packet_len(&mut self, _: u32, buf: &[u8]) -> usize {
+        u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize + 4
+    }
+
+    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize {
+        self.packet_len(sequence_number, buf) + TAG_SIZE
+    }
+
+    // the packet length is not aligned either
+    fn no_pad(&self) -> bool {
+        true
+    }
+}
@@ -5,7 +5,6 @@ use crate::{algorithm::encryption::Encryption, error::SshResult};
 use ring::aead::chacha20_poly1305_openssh::{OpeningKey, SealingKey};
 
 const KEY_SIZE: usize = 64;
-const IV_SIZE: usize = 0;
 const BLOCK_SIZE: usize = 0;
 const MAC_SIZE: usize = 16;
 
@@ -19,10 +18,6 @@ impl Encryption for ChaCha20Poly1305 {
         BLOCK_SIZE
     }
 
-    fn iv_size(&self) -> usize {
-        IV_SIZE
-    }
-
     fn new(hash: Hash, _mac: Box<dyn Mac>) -> ChaCha20Poly1305 {
         let (ck, sk) = hash.mix_ek(KEY_SIZE);
         let mut sealing_key = [0_u8; KEY_SIZE];
@@ -39,10 +39,6 @@ impl Encryption for Cbc {
         BLOCK_SIZE
     }
 
-    fn iv_size(&self) -> usize {
-        IV_SIZE
-    }
-
     fn new(hash: Hash, mac: Box<dyn Mac>) -> Self
     where
         Self: Sized,
@@ -107,9 +103,7 @@ impl Encryption for Cbc {
             .sign(&self.extend.ik_s_c, server_sequence_number, d);
         let t = tag.as_ref();
         if m != t {
-            return Err(SshError::EncryptionError(
-                "Failed to decrypt the server traffic".to_owned(),
-            ));
+            return Err(SshError::MacVerificationError(server_sequence_number));
         }
         Ok(d.to_vec())
     }
@@ -1,6 +1,7 @@
 #[cfg(feature = "deprecated-aes-cbc")]
 mod aes_cbc;
 mod aes_ctr;
+mod aes_gcm;
 mod chacha20_poly1305_openssh;
 #[cfg(feature = "deprecated-des-cbc")]
 mod des_cbc;
@@ -14,7 +15,6 @@ use super::{hash::HashCtx, mac::MacNone, Enc};
 /// <https://www.rfc-editor.org/rfc/rfc4253#section-6.3>
 pub(crate) trait Encryption: Send + Sync {
     fn bsize(&self) -> usize;
-    fn iv_size(&self) -> usize;
     fn new(hash: Hash, mac: Box<dyn Mac>) -> Self
     where
         Self: Sized;
@@ -33,6 +33,7 @@ pub(crate) fn from(s: &Enc, hash: Hash, mac: Box<dyn Mac>) -> Box<dyn Encryption
         Enc::Aes128Ctr => Box::new(aes_ctr::Ctr128::new(hash, mac)),
         Enc::Aes192Ctr => Box::new(aes_ctr::Ctr192::new(hash, mac)),
         Enc::Aes256Ctr => Box::new(aes_ctr::Ctr256::new(hash, mac)),
+        Enc::Aes256GcmOpenssh => Box::new(aes_gcm::Gcm256::new(hash, mac)),
         #[cfg(feature = "deprecated-aes-cbc")]
         Enc::Aes128Cbc => Box::new(aes_cbc::Cbc128::new(hash, mac)),
         #[cfg(feature = "deprecated-aes-cbc")]
@@ -50,9 +51,6 @@ impl Encryption for EncryptionNone {
     fn bsize(&self) -> usize {
         8
     }
-    fn iv_size(&self) -> usize {
-        8
-    }
 
     fn new(_hash: Hash, _mac: Box<dyn Mac>) -> Self
     where
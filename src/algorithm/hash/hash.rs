@@ -13,28 +13,28 @@ use crate::constant;
 /// as a part of the data that is signed as a proof of possession of a
 /// private key.  Once computed, the session identifier is not changed,
 /// even if keys are later re-exchanged.
-
+///
 /// Each key exchange method specifies a hash function that is used in
 /// the key exchange.  The same hash algorithm MUST be used in key
 /// derivation.  Here, we'll call it HASH.
-
+///
 /// Encryption keys MUST be computed as HASH, of a known value and K, as
 /// follows:
-
+///
 /// o  Initial IV client to server: HASH(K || H || "A" || session_id)
 ///    (Here K is encoded as mpint and "A" as byte and session_id as raw
 ///    data.  "A" means the single character A, ASCII 65).
-
+///
 /// o  Initial IV server to client: HASH(K || H || "B" || session_id)
-
+///
 /// o  Encryption key client to server: HASH(K || H || "C" || session_id)
-
+///
 /// o  Encryption key server to client: HASH(K || H || "D" || session_id)
-
+///
 /// o  Integrity key client to server: HASH(K || H || "E" || session_id)
-
+///
 /// o  Integrity key server to client: HASH(K || H || "F" || session_id)
-
+///
 /// Key data MUST be taken from the beginning of the hash output.  As
 /// many bytes as needed are taken from the beginning of the hash value.
 /// If the key length needed is longer than the output of the HASH, the
@@ -43,13 +43,13 @@ use crate::constant;
 /// HASH generates) to the key.  This process is repeated until enough
 /// key material is available; the key is taken from the beginning of
 /// this value.  In other words:
-
+///
 ///    K1 = HASH(K || H || X || session_id)   (X is e.g., "A")
 ///    K2 = HASH(K || H || K1)
 ///    K3 = HASH(K || H || K1 || K2)
 ///    ...
 ///    key = K1 || K2 || K3 || ...
-
+///
 /// This process will lose entropy if the amount of entropy in K is
 /// larger than the internal state size of HASH.
 pub struct Hash {
@@ -108,6 +108,9 @@ impl Hash {
             ck.extend(self.extend(ck.as_slice()));
             sk.extend(self.extend(sk.as_slice()));
         }
+        // the key is taken from the beginning
+        ck.truncate(key_size);
+        sk.truncate(key_size);
         (ck, sk)
     }
 
@@ -118,6 +121,9 @@ impl Hash {
             ck.extend(self.extend(ck.as_slice()));
             sk.extend(self.extend(sk.as_slice()));
         }
+        // the key is taken from the beginning
+        ck.truncate(key_size);
+        sk.truncate(key_size);
         (ck, sk)
     }
 
@@ -5,4 +5,16 @@ pub enum HashType {
     None,
     SHA1,
     SHA256,
+    SHA512,
+}
+
+impl HashType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashType::None => "none",
+            HashType::SHA1 => "sha1",
+            HashType::SHA256 => "sha256",
+            HashType::SHA512 => "sha512",
+        }
+    }
 }
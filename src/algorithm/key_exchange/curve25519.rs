@@ -1,6 +1,7 @@
 use super::{super::hash::HashType, KeyExchange};
 use crate::error::SshError;
 use crate::SshResult;
+use rand::RngCore;
 use ring::agreement::{EphemeralPrivateKey, PublicKey, UnparsedPublicKey, X25519};
 
 pub(super) struct CURVE25519 {
@@ -9,12 +10,8 @@ pub(super) struct CURVE25519 {
 }
 
 impl KeyExchange for CURVE25519 {
-    fn new() -> SshResult<Self> {
-        let rng = ring::rand::SystemRandom::new();
-        let private_key = match EphemeralPrivateKey::generate(&X25519, &rng) {
-            Ok(v) => v,
-            Err(e) => return Err(SshError::KexError(e.to_string())),
-        };
+    fn new(rng: Option<&mut dyn RngCore>) -> SshResult<Self> {
+        let private_key = super::generate_ephemeral(&X25519, rng)?;
         match private_key.compute_public_key() {
             Ok(public_key) => Ok(CURVE25519 {
                 private_key,
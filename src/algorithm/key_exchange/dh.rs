@@ -1,4 +1,5 @@
 use num_bigint::{BigUint, RandBigInt};
+use rand::{rngs::OsRng, RngCore};
 use std::ops::Shl;
 
 use crate::SshResult;
@@ -36,6 +37,41 @@ const GROUP14: [u8; 256] = [
     0x15, 0x72, 0x8e, 0x5a, 0x8a, 0xac, 0xaa, 0x68, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
 ];
 
+const GROUP16: [u8; 512] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc9, 0xf, 0xda, 0xa2, 0x21, 0x68, 0xc2, 0x34,
+    0xc4, 0xc6, 0x62, 0x8b, 0x80, 0xdc, 0x1c, 0xd1, 0x29, 0x2, 0x4e, 0x8, 0x8a, 0x67, 0xcc, 0x74,
+    0x2, 0xb, 0xbe, 0xa6, 0x3b, 0x13, 0x9b, 0x22, 0x51, 0x4a, 0x8, 0x79, 0x8e, 0x34, 0x4, 0xdd,
+    0xef, 0x95, 0x19, 0xb3, 0xcd, 0x3a, 0x43, 0x1b, 0x30, 0x2b, 0xa, 0x6d, 0xf2, 0x5f, 0x14, 0x37,
+    0x4f, 0xe1, 0x35, 0x6d, 0x6d, 0x51, 0xc2, 0x45, 0xe4, 0x85, 0xb5, 0x76, 0x62, 0x5e, 0x7e, 0xc6,
+    0xf4, 0x4c, 0x42, 0xe9, 0xa6, 0x37, 0xed, 0x6b, 0xb, 0xff, 0x5c, 0xb6, 0xf4, 0x6, 0xb7, 0xed,
+    0xee, 0x38, 0x6b, 0xfb, 0x5a, 0x89, 0x9f, 0xa5, 0xae, 0x9f, 0x24, 0x11, 0x7c, 0x4b, 0x1f, 0xe6,
+    0x49, 0x28, 0x66, 0x51, 0xec, 0xe4, 0x5b, 0x3d, 0xc2, 0x0, 0x7c, 0xb8, 0xa1, 0x63, 0xbf, 0x5,
+    0x98, 0xda, 0x48, 0x36, 0x1c, 0x55, 0xd3, 0x9a, 0x69, 0x16, 0x3f, 0xa8, 0xfd, 0x24, 0xcf, 0x5f,
+    0x83, 0x65, 0x5d, 0x23, 0xdc, 0xa3, 0xad, 0x96, 0x1c, 0x62, 0xf3, 0x56, 0x20, 0x85, 0x52, 0xbb,
+    0x9e, 0xd5, 0x29, 0x7, 0x70, 0x96, 0x96, 0x6d, 0x67, 0xc, 0x35, 0x4e, 0x4a, 0xbc, 0x98, 0x4,
+    0xf1, 0x74, 0x6c, 0x8, 0xca, 0x18, 0x21, 0x7c, 0x32, 0x90, 0x5e, 0x46, 0x2e, 0x36, 0xce, 0x3b,
+    0xe3, 0x9e, 0x77, 0x2c, 0x18, 0xe, 0x86, 0x3, 0x9b, 0x27, 0x83, 0xa2, 0xec, 0x7, 0xa2, 0x8f,
+    0xb5, 0xc5, 0x5d, 0xf0, 0x6f, 0x4c, 0x52, 0xc9, 0xde, 0x2b, 0xcb, 0xf6, 0x95, 0x58, 0x17, 0x18,
+    0x39, 0x95, 0x49, 0x7c, 0xea, 0x95, 0x6a, 0xe5, 0x15, 0xd2, 0x26, 0x18, 0x98, 0xfa, 0x5, 0x10,
+    0x15, 0x72, 0x8e, 0x5a, 0x8a, 0xaa, 0xc4, 0x2d, 0xad, 0x33, 0x17, 0xd, 0x4, 0x50, 0x7a, 0x33,
+    0xa8, 0x55, 0x21, 0xab, 0xdf, 0x1c, 0xba, 0x64, 0xec, 0xfb, 0x85, 0x4, 0x58, 0xdb, 0xef, 0xa,
+    0x8a, 0xea, 0x71, 0x57, 0x5d, 0x6, 0xc, 0x7d, 0xb3, 0x97, 0xf, 0x85, 0xa6, 0xe1, 0xe4, 0xc7,
+    0xab, 0xf5, 0xae, 0x8c, 0xdb, 0x9, 0x33, 0xd7, 0x1e, 0x8c, 0x94, 0xe0, 0x4a, 0x25, 0x61, 0x9d,
+    0xce, 0xe3, 0xd2, 0x26, 0x1a, 0xd2, 0xee, 0x6b, 0xf1, 0x2f, 0xfa, 0x6, 0xd9, 0x8a, 0x8, 0x64,
+    0xd8, 0x76, 0x2, 0x73, 0x3e, 0xc8, 0x6a, 0x64, 0x52, 0x1f, 0x2b, 0x18, 0x17, 0x7b, 0x20, 0xc,
+    0xbb, 0xe1, 0x17, 0x57, 0x7a, 0x61, 0x5d, 0x6c, 0x77, 0x9, 0x88, 0xc0, 0xba, 0xd9, 0x46, 0xe2,
+    0x8, 0xe2, 0x4f, 0xa0, 0x74, 0xe5, 0xab, 0x31, 0x43, 0xdb, 0x5b, 0xfc, 0xe0, 0xfd, 0x10, 0x8e,
+    0x4b, 0x82, 0xd1, 0x20, 0xa9, 0x21, 0x8, 0x1, 0x1a, 0x72, 0x3c, 0x12, 0xa7, 0x87, 0xe6, 0xd7,
+    0x88, 0x71, 0x9a, 0x10, 0xbd, 0xba, 0x5b, 0x26, 0x99, 0xc3, 0x27, 0x18, 0x6a, 0xf4, 0xe2, 0x3c,
+    0x1a, 0x94, 0x68, 0x34, 0xb6, 0x15, 0xb, 0xda, 0x25, 0x83, 0xe9, 0xca, 0x2a, 0xd4, 0x4c, 0xe8,
+    0xdb, 0xbb, 0xc2, 0xdb, 0x4, 0xde, 0x8e, 0xf9, 0x2e, 0x8e, 0xfc, 0x14, 0x1f, 0xbe, 0xca, 0xa6,
+    0x28, 0x7c, 0x59, 0x47, 0x4e, 0x6b, 0xc0, 0x5d, 0x99, 0xb2, 0x96, 0x4f, 0xa0, 0x90, 0xc3, 0xa2,
+    0x23, 0x3b, 0xa1, 0x86, 0x51, 0x5b, 0xe7, 0xed, 0x1f, 0x61, 0x29, 0x70, 0xce, 0xe2, 0xd7, 0xaf,
+    0xb8, 0x1b, 0xdd, 0x76, 0x21, 0x70, 0x48, 0x1c, 0xd0, 0x6, 0x91, 0x27, 0xd5, 0xb0, 0x5a, 0xa9,
+    0x93, 0xb4, 0xea, 0x98, 0x8d, 0x8f, 0xdd, 0xc1, 0x86, 0xff, 0xb7, 0xdc, 0x90, 0xa6, 0xc0, 0x8f,
+    0x4d, 0xf4, 0x35, 0xc9, 0x34, 0x6, 0x31, 0x99, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
 struct DhGroup {
     prime: &'static [u8],
     generator: usize,
@@ -55,6 +91,12 @@ const DH_GROUP14: DhGroup = DhGroup {
     exp_size: 256,
 };
 
+const DH_GROUP16: DhGroup = DhGroup {
+    prime: &GROUP16,
+    generator: 2,
+    exp_size: 512,
+};
+
 fn biguint_to_mpint(biguint: &BigUint) -> Vec<u8> {
     let mut mpint = Vec::new();
     let bytes = biguint.to_bytes_be();
@@ -76,8 +118,12 @@ macro_rules! create_dh_with_group {
         }
 
         impl KeyExchange for $name {
-            fn new() -> SshResult<Self> {
-                let mut rng = rand::thread_rng();
+            fn new(rng: Option<&mut dyn RngCore>) -> SshResult<Self> {
+                let mut os_rng = OsRng;
+                let rng: &mut dyn RngCore = match rng {
+                    Some(rng) => rng,
+                    None => &mut os_rng,
+                };
                 let group = &($group);
                 let prime = BigUint::from_bytes_be(&group.prime);
                 let private_key = rng.gen_biguint((group.exp_size * 8) - 2u64).shl(1);
@@ -112,3 +158,4 @@ macro_rules! create_dh_with_group {
 create_dh_with_group!(DiffieHellmanGroup1Sha1, DH_GROUP1, HashType::SHA1);
 create_dh_with_group!(DiffieHellmanGroup14Sha1, DH_GROUP14, HashType::SHA1);
 create_dh_with_group!(DiffieHellmanGroup14Sha256, DH_GROUP14, HashType::SHA256);
+create_dh_with_group!(DiffieHellmanGroup16Sha512, DH_GROUP16, HashType::SHA512);
@@ -1,4 +1,5 @@
 use super::{super::hash::HashType, KeyExchange};
+use rand::RngCore;
 use ring::agreement::{EphemeralPrivateKey, PublicKey, UnparsedPublicKey, ECDH_P256};
 
 use crate::{SshError, SshResult};
@@ -9,12 +10,8 @@ pub(super) struct EcdhP256 {
 }
 
 impl KeyExchange for EcdhP256 {
-    fn new() -> SshResult<Self> {
-        let rng = ring::rand::SystemRandom::new();
-        let private_key = match EphemeralPrivateKey::generate(&ECDH_P256, &rng) {
-            Ok(v) => v,
-            Err(e) => return Err(SshError::KexError(e.to_string())),
-        };
+    fn new(rng: Option<&mut dyn RngCore>) -> SshResult<Self> {
+        let private_key = super::generate_ephemeral(&ECDH_P256, rng)?;
         match private_key.compute_public_key() {
             Ok(public_key) => Ok(EcdhP256 {
                 private_key,
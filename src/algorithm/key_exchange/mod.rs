@@ -1,7 +1,9 @@
 use crate::algorithm::hash::HashType;
 use crate::{SshError, SshResult};
+use rand::RngCore;
 use ring::agreement;
-use ring::agreement::{EphemeralPrivateKey, UnparsedPublicKey};
+use ring::agreement::{Algorithm, EphemeralPrivateKey, UnparsedPublicKey};
+use ring::rand::SystemRandom;
 
 /// # Algorithms that used for key exchange
 ///
@@ -14,11 +16,13 @@ use super::Kex;
 use curve25519::CURVE25519;
 #[cfg(feature = "deprecated-dh-group1-sha1")]
 use dh::DiffieHellmanGroup1Sha1;
-use dh::{DiffieHellmanGroup14Sha1, DiffieHellmanGroup14Sha256};
+use dh::{DiffieHellmanGroup14Sha1, DiffieHellmanGroup14Sha256, DiffieHellmanGroup16Sha512};
 use ecdh_sha2_nistp256::EcdhP256;
 
 pub(crate) trait KeyExchange: Send + Sync {
-    fn new() -> SshResult<Self>
+    /// the key is drawn from the system random source unless `rng` is given,
+    /// see [generate_ephemeral]
+    fn new(rng: Option<&mut dyn RngCore>) -> SshResult<Self>
     where
         Self: Sized;
     fn get_public_key(&self) -> &[u8];
@@ -26,6 +30,61 @@ pub(crate) trait KeyExchange: Send + Sync {
     fn get_hash_type(&self) -> HashType;
 }
 
+/// generate an ephemeral key of ring from the system random source,
+/// or with the bytes of `rng`
+///
+/// `rng` is only given by the reproducible tests,
+/// i.e. with [crate::SessionBuilder::rng_seed] or [crate::SessionBuilder::ephemeral_key]
+pub(crate) fn generate_ephemeral(
+    alg: &'static Algorithm,
+    rng: Option<&mut dyn RngCore>,
+) -> SshResult<EphemeralPrivateKey> {
+    let Some(rng) = rng else {
+        return EphemeralPrivateKey::generate(alg, &SystemRandom::new())
+            .map_err(|e| SshError::KexError(e.to_string()));
+    };
+    let mut seed = [0; 32];
+    rng.fill_bytes(&mut seed);
+    // ring only takes its own SecureRandom, which cannot be implemented outside,
+    // so feed it with the bytes drawn from `rng`
+    #[allow(deprecated)]
+    let fixed = ring::test::rand::FixedSliceRandom { bytes: &seed };
+    EphemeralPrivateKey::generate(alg, &fixed).map_err(|e| SshError::KexError(e.to_string()))
+}
+
+/// an [RngCore] repeating the bytes of the fixed ephemeral key,
+/// of which [generate_ephemeral] draws exactly one key
+struct FixedKey<'a> {
+    key: &'a [u8],
+    pos: usize,
+}
+
+impl RngCore for FixedKey<'_> {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for b in dest {
+            *b = self.key[self.pos % self.key.len()];
+            self.pos += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
 pub(crate) fn agree_ephemeral<B: AsRef<[u8]>>(
     private_key: EphemeralPrivateKey,
     peer_public_key: &UnparsedPublicKey<B>,
@@ -38,13 +97,48 @@ pub(crate) fn agree_ephemeral<B: AsRef<[u8]>>(
     }
 }
 
-pub(crate) fn from(s: &Kex) -> SshResult<Box<dyn KeyExchange>> {
+/// `fixed` is the private key set by [crate::SessionBuilder::ephemeral_key],
+/// the key is drawn from `rng` otherwise,
+/// which is None (the system random source) unless [crate::SessionBuilder::rng_seed] is set
+pub(crate) fn from(
+    s: &Kex,
+    fixed: Option<&[u8]>,
+    rng: Option<&mut dyn RngCore>,
+) -> SshResult<Box<dyn KeyExchange>> {
+    if let Some(key) = fixed {
+        return from_fixed(s, key);
+    }
     match s {
-        Kex::Curve25519Sha256 => Ok(Box::new(CURVE25519::new()?)),
-        Kex::EcdhSha2Nistrp256 => Ok(Box::new(EcdhP256::new()?)),
+        Kex::Curve25519Sha256 => Ok(Box::new(CURVE25519::new(rng)?)),
+        Kex::EcdhSha2Nistrp256 => Ok(Box::new(EcdhP256::new(rng)?)),
         #[cfg(feature = "deprecated-dh-group1-sha1")]
-        Kex::DiffieHellmanGroup1Sha1 => Ok(Box::new(DiffieHellmanGroup1Sha1::new()?)),
-        Kex::DiffieHellmanGroup14Sha1 => Ok(Box::new(DiffieHellmanGroup14Sha1::new()?)),
-        Kex::DiffieHellmanGroup14Sha256 => Ok(Box::new(DiffieHellmanGroup14Sha256::new()?)),
+        Kex::DiffieHellmanGroup1Sha1 => {
+            tracing::warn!(
+                "Using the weak diffie-hellman-group1-sha1 key exchange, only for legacy devices"
+            );
+            Ok(Box::new(DiffieHellmanGroup1Sha1::new(rng)?))
+        }
+        Kex::DiffieHellmanGroup14Sha1 => Ok(Box::new(DiffieHellmanGroup14Sha1::new(rng)?)),
+        Kex::DiffieHellmanGroup14Sha256 => Ok(Box::new(DiffieHellmanGroup14Sha256::new(rng)?)),
+        Kex::DiffieHellmanGroup16Sha512 => Ok(Box::new(DiffieHellmanGroup16Sha512::new(rng)?)),
+    }
+}
+
+fn from_fixed(s: &Kex, key: &[u8]) -> SshResult<Box<dyn KeyExchange>> {
+    tracing::warn!("Using a fixed ephemeral key, the session is not secure");
+    if key.len() != 32 {
+        return Err(SshError::KexError(format!(
+            "the fixed ephemeral key must be 32 bytes, got {}",
+            key.len()
+        )));
+    }
+    let mut rng = FixedKey { key, pos: 0 };
+    match s {
+        Kex::Curve25519Sha256 => Ok(Box::new(CURVE25519::new(Some(&mut rng))?)),
+        Kex::EcdhSha2Nistrp256 => Ok(Box::new(EcdhP256::new(Some(&mut rng))?)),
+        s => Err(SshError::KexError(format!(
+            "a fixed ephemeral key is not supported by {}",
+            s.as_ref()
+        ))),
     }
 }
@@ -13,11 +13,13 @@ pub(crate) trait Mac: Send + Sync {
     fn bsize(&self) -> usize;
 }
 
-pub(crate) fn from(s: &super::Mac) -> Box<dyn Mac> {
+/// None if no MAC is negotiated along with an AEAD cipher
+pub(crate) fn from(s: Option<&super::Mac>) -> Box<dyn Mac> {
     match s {
-        super::Mac::HmacSha1 => Box::new(HMacSha1::new()),
-        super::Mac::HmacSha2_256 => Box::new(HmacSha2_256::new()),
-        super::Mac::HmacSha2_512 => Box::new(HmacSha2_512::new()),
+        Some(super::Mac::HmacSha1) => Box::new(HMacSha1::new()),
+        Some(super::Mac::HmacSha2_256) => Box::new(HmacSha2_256::new()),
+        Some(super::Mac::HmacSha2_512) => Box::new(HmacSha2_512::new()),
+        None => Box::new(MacNone::new()),
     }
 }
 
@@ -7,7 +7,9 @@ pub(crate) mod public_key;
 
 use strum_macros::{AsRefStr, EnumString};
 
+pub use self::public_key::{parse_public_key, sign, verify, AuthorizedKey, FingerprintHash};
 use self::{hash::HashCtx, key_exchange::KeyExchange};
+use crate::error::{SshError, SshResult};
 
 /// symmetrical encryption algorithm
 #[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
@@ -20,6 +22,8 @@ pub enum Enc {
     Aes192Ctr,
     #[strum(serialize = "aes256-ctr")]
     Aes256Ctr,
+    #[strum(serialize = "aes256-gcm@openssh.com")]
+    Aes256GcmOpenssh,
     #[cfg(feature = "deprecated-aes-cbc")]
     #[strum(serialize = "aes128-cbc")]
     Aes128Cbc,
@@ -34,6 +38,69 @@ pub enum Enc {
     TripleDesCbc,
 }
 
+impl Enc {
+    /// the effective security strength in bits
+    pub fn strength(&self) -> usize {
+        match self {
+            Enc::Chacha20Poly1305Openssh => 256,
+            Enc::Aes128Ctr => 128,
+            Enc::Aes192Ctr => 192,
+            Enc::Aes256Ctr => 256,
+            Enc::Aes256GcmOpenssh => 256,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes128Cbc => 128,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes192Cbc => 192,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes256Cbc => 256,
+            // three keys of 56 bits, but only 112 bits effective due to the meet-in-the-middle attack
+            #[cfg(feature = "deprecated-des-cbc")]
+            Enc::TripleDesCbc => 112,
+        }
+    }
+
+    /// the bytes of the key derived from the key exchange for each direction
+    pub fn key_size(&self) -> usize {
+        match self {
+            // one key for the payload and one for the packet length
+            Enc::Chacha20Poly1305Openssh => 64,
+            Enc::Aes128Ctr => 16,
+            Enc::Aes192Ctr => 24,
+            Enc::Aes256Ctr => 32,
+            Enc::Aes256GcmOpenssh => 32,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes128Cbc => 16,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes192Cbc => 24,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes256Cbc => 32,
+            #[cfg(feature = "deprecated-des-cbc")]
+            Enc::TripleDesCbc => 24,
+        }
+    }
+
+    /// the bytes of the initial IV taken from the key exchange for each direction
+    pub fn iv_size(&self) -> usize {
+        match self {
+            // the nonce is the packet sequence number
+            Enc::Chacha20Poly1305Openssh => 0,
+            Enc::Aes128Ctr | Enc::Aes192Ctr | Enc::Aes256Ctr => 16,
+            // the fixed field and the invocation counter
+            Enc::Aes256GcmOpenssh => 12,
+            #[cfg(feature = "deprecated-aes-cbc")]
+            Enc::Aes128Cbc | Enc::Aes192Cbc | Enc::Aes256Cbc => 16,
+            #[cfg(feature = "deprecated-des-cbc")]
+            Enc::TripleDesCbc => 8,
+        }
+    }
+
+    /// whether it's an authenticated encryption,
+    /// which doesn't need a separate MAC algorithm
+    pub fn is_aead(&self) -> bool {
+        matches!(self, Enc::Chacha20Poly1305Openssh | Enc::Aes256GcmOpenssh)
+    }
+}
+
 /// key exchange algorithm
 #[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
 pub enum Kex {
@@ -48,6 +115,8 @@ pub enum Kex {
     DiffieHellmanGroup14Sha1,
     #[strum(serialize = "diffie-hellman-group14-sha256")]
     DiffieHellmanGroup14Sha256,
+    #[strum(serialize = "diffie-hellman-group16-sha512")]
+    DiffieHellmanGroup16Sha512,
 }
 
 /// pubkey hash algorithm
@@ -90,6 +159,39 @@ pub enum Compress {
     ZlibOpenSsh,
 }
 
+/// <https://www.rfc-editor.org/rfc/rfc4253#section-7.1>
+///
+/// The algorithm negotiation of one category:
+/// choose the first algorithm of the `client` name-list that is also in the `server` name-list
+///
+/// ```
+/// use ssh::algorithm::negotiate;
+///
+/// let chosen = negotiate(&["aes256-ctr", "aes128-ctr"], &["aes128-ctr", "aes256-ctr"]);
+/// assert_eq!(chosen.unwrap(), "aes256-ctr");
+/// assert!(negotiate(&["aes256-ctr"], &["3des-cbc"]).is_err());
+/// ```
+pub fn negotiate<'a, S>(client: &[&'a str], server: &[S]) -> SshResult<&'a str>
+where
+    S: AsRef<str>,
+{
+    client
+        .iter()
+        .find(|c| server.iter().any(|s| s.as_ref() == **c))
+        .copied()
+        .ok_or_else(|| {
+            SshError::KexError(format!(
+                "no common algorithm, client: [{}], server: [{}]",
+                client.join(","),
+                server
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<&str>>()
+                    .join(",")
+            ))
+        })
+}
+
 #[derive(Default)]
 pub(crate) struct Digest {
     pub hash_ctx: HashCtx,
@@ -1,4 +1,5 @@
-use crate::SshError;
+use crate::config::auth::KeyPair;
+use crate::{util, SshError, SshResult};
 
 #[cfg(feature = "deprecated-dss-sha1")]
 mod dss;
@@ -17,7 +18,6 @@ use ed25519::Ed25519;
 /// # Public Key Algorithms
 ///
 /// <https://www.rfc-editor.org/rfc/rfc4253#section-6.6>
-
 pub(crate) trait PublicKey: Send + Sync {
     fn new() -> Self
     where
@@ -36,3 +36,183 @@ pub(crate) fn from(s: &PubKey) -> Box<dyn PublicKey> {
         PubKey::SshDss => Box::new(DssSha1::new()),
     }
 }
+
+/// Verify an ssh signature of `data` made by the key `key_blob` with `alg`
+///
+/// `key_blob` is the ssh encoded public key (`string "ssh-ed25519", string key`, ...),
+/// as found base64 encoded in an `authorized_keys` line,
+/// `signature` is the ssh encoded signature (`string "rsa-sha2-256", string sig`)
+///
+/// `Ok(false)` is returned when the signature does not match,
+/// or it is made with an algorithm other than `alg`
+///
+/// ```
+/// use ssh::algorithm::{verify, PubKey};
+///
+/// // a malformed key is an error rather than a mismatch
+/// assert!(verify(b"bad key", b"data", b"bad signature", PubKey::SshEd25519).is_err());
+/// ```
+pub fn verify(key_blob: &[u8], data: &[u8], signature: &[u8], alg: PubKey) -> SshResult<bool> {
+    let key = ssh_key::PublicKey::from_bytes(key_blob)
+        .map_err(|e| SshError::SshPubKeyError(e.to_string()))?;
+    let key_type = match alg {
+        PubKey::SshEd25519 => "ssh-ed25519",
+        #[cfg(feature = "deprecated-rsa-sha1")]
+        PubKey::SshRsa => "ssh-rsa",
+        PubKey::RsaSha2_256 | PubKey::RsaSha2_512 => "ssh-rsa",
+        #[cfg(feature = "deprecated-dss-sha1")]
+        PubKey::SshDss => "ssh-dss",
+    };
+    if key.algorithm().as_str() != key_type {
+        return Err(SshError::SshPubKeyError(format!(
+            "a {} key cannot be used with {}",
+            key.algorithm().as_str(),
+            alg.as_ref()
+        )));
+    }
+
+    let mut signature = signature;
+    let (Some(name), Some(sig)) = (take_string(&mut signature), take_string(&mut signature)) else {
+        return Err(SshError::SshPubKeyError("malformed signature".to_owned()));
+    };
+    if name != alg.as_ref().as_bytes() {
+        return Ok(false);
+    }
+
+    // the internal verifiers take the key blob with its length
+    let mut ks = (key_blob.len() as u32).to_be_bytes().to_vec();
+    ks.extend_from_slice(key_blob);
+    from(&alg).verify_signature(&ks, data, sig)
+}
+
+/// Sign `data` with `private_key` using `alg`, the counterpart of [verify]
+///
+/// `private_key` is the content of a PKCS#1 PEM rsa key,
+/// or an unencrypted openssh rsa or ed25519 key,
+/// the ssh encoded signature (`string "ssh-ed25519", string sig`) is returned
+///
+/// ```
+/// use ssh::algorithm::{sign, PubKey};
+///
+/// assert!(sign("not a key", b"data", PubKey::SshEd25519).is_err());
+/// ```
+pub fn sign(private_key: &str, data: &[u8], alg: PubKey) -> SshResult<Vec<u8>> {
+    KeyPair::from_str(private_key, None)?.sign_data(data, &alg)
+}
+
+/// A public key line of an OpenSSH `authorized_keys` file,
+/// see [parse_public_key]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizedKey {
+    /// the key type, e.g. `ssh-ed25519`
+    pub algorithm: String,
+    /// the ssh encoded public key, which can be passed to [verify]
+    pub key_blob: Vec<u8>,
+    /// the comment after the key, empty if there is none
+    pub comment: String,
+    /// the leading options, e.g. `no-pty` or `command="ls"`
+    pub options: Vec<String>,
+}
+
+/// Parse an `authorized_keys` style line such as `ssh-ed25519 AAAA... comment`
+///
+/// The leading options are kept as is,
+/// nothing else like known_hosts markers or host patterns is accepted
+///
+/// ```
+/// use ssh::algorithm::parse_public_key;
+///
+/// let key = parse_public_key(
+///     "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPti3hHmkEba9H5JvhiP8sATekyhxWXOneXTAE4Yl3S0 me@example",
+/// )
+/// .unwrap();
+/// assert_eq!(key.algorithm, "ssh-ed25519");
+/// assert_eq!(key.comment, "me@example");
+/// ```
+pub fn parse_public_key(line: &str) -> SshResult<AuthorizedKey> {
+    let line = line.trim();
+    // a line starts with the options only if it does not start with the key
+    let (options, key) = match ssh_key::PublicKey::from_openssh(line) {
+        Ok(key) => (vec![], key),
+        Err(e) => {
+            let Some((options, rest)) = split_options(line) else {
+                return Err(SshError::SshPubKeyError(e.to_string()));
+            };
+            let key = ssh_key::PublicKey::from_openssh(rest.trim_start())
+                .map_err(|e| SshError::SshPubKeyError(e.to_string()))?;
+            (options, key)
+        }
+    };
+    let key_blob = key
+        .to_bytes()
+        .map_err(|e| SshError::SshPubKeyError(e.to_string()))?;
+    Ok(AuthorizedKey {
+        algorithm: key.algorithm().as_str().to_owned(),
+        key_blob,
+        comment: key.comment().to_owned(),
+        options,
+    })
+}
+
+/// split the comma separated options up to the first whitespace,
+/// both are allowed in the double quotes
+fn split_options(line: &str) -> Option<(Vec<String>, &str)> {
+    let mut options = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                options.push(line[start..i].to_owned());
+                start = i + 1;
+            }
+            c if c.is_whitespace() && !quoted => {
+                options.push(line[start..i].to_owned());
+                return Some((options, &line[i..]));
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The hash of a host key fingerprint, see [crate::LocalSession::host_key_fingerprint]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintHash {
+    /// `SHA256:` followed by the unpadded base64 digest, the OpenSSH default
+    Sha256,
+    /// `MD5:` followed by the colon separated hex digest, as the old OpenSSH prints
+    Md5,
+}
+
+/// the fingerprint of an ssh encoded public key, as printed by `ssh-keygen -l`
+pub(crate) fn fingerprint(key_blob: &[u8], hash: FingerprintHash) -> String {
+    match hash {
+        FingerprintHash::Sha256 => {
+            let digest = ring::digest::digest(&ring::digest::SHA256, key_blob);
+            format!("SHA256:{}", util::base64_encode(digest.as_ref(), false))
+        }
+        FingerprintHash::Md5 => {
+            let hex = super::hash::md5(key_blob)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>();
+            format!("MD5:{}", hex.join(":"))
+        }
+    }
+}
+
+/// format an ssh encoded public key as `ssh-ed25519 AAAA...`
+pub(crate) fn to_openssh(key_blob: &[u8]) -> SshResult<String> {
+    ssh_key::PublicKey::from_bytes(key_blob)
+        .and_then(|key| key.to_openssh())
+        .map_err(|e| SshError::SshPubKeyError(e.to_string()))
+}
+
+fn take_string<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_be_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+    let (s, rest) = buf[4..].split_at_checked(len)?;
+    *buf = rest;
+    Some(s)
+}
@@ -4,6 +4,20 @@ use crate::SshError;
 //use rsa::PublicKey;
 use rsa::pkcs1v15::Pkcs1v15Sign;
 
+/// the largest rsa key accepted, the same as OpenSSH
+const RSA_MAX_SIZE: usize = 16384;
+
+fn public_key(ks: &[u8]) -> Result<rsa::RsaPublicKey, SshError> {
+    let mut data = Data::from(ks[4..].to_vec());
+    data.get_u8s();
+
+    let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
+    let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
+    // `RsaPublicKey::new` rejects the keys larger than 4096 bits
+    rsa::RsaPublicKey::new_with_max_size(n, e, RSA_MAX_SIZE)
+        .map_err(|e| SshError::SshPubKeyError(e.to_string()))
+}
+
 pub(super) struct RsaSha256;
 
 impl PubK for RsaSha256 {
@@ -15,12 +29,7 @@ impl PubK for RsaSha256 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = public_key(ks)?;
         let scheme = Pkcs1v15Sign::new::<sha2::Sha256>();
 
         let digest = ring::digest::digest(&ring::digest::SHA256, message);
@@ -41,12 +50,7 @@ impl PubK for RsaSha512 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = public_key(ks)?;
         let scheme = Pkcs1v15Sign::new::<sha2::Sha512>();
 
         let digest = ring::digest::digest(&ring::digest::SHA512, message);
@@ -68,12 +72,7 @@ impl PubK for RsaSha1 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = public_key(ks)?;
         let scheme = Pkcs1v15Sign::new::<sha1::Sha1>();
 
         let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, message);
@@ -0,0 +1,113 @@
+use tracing::*;
+
+use super::AsyncChannel;
+use crate::{
+    constant::{ssh_connection_code, ssh_str},
+    error::{SshError, SshResult},
+    model::{Data, ExecOutput},
+};
+
+/// The async counterpart of [crate::channel::ExecBroker]
+pub struct AsyncExec {
+    channel: AsyncChannel,
+    command_send: bool,
+    // the output received so far
+    output: Vec<u8>,
+}
+
+impl AsyncExec {
+    pub(crate) fn open(channel: AsyncChannel) -> Self {
+        Self {
+            channel,
+            command_send: false,
+            output: vec![],
+        }
+    }
+
+    /// same as [crate::channel::ExecBroker::set_env], before the command is sent
+    ///
+    pub fn set_env(&mut self, name: &str, value: &str) -> SshResult<&mut Self> {
+        if self.command_send {
+            return Err(SshError::GeneralError(
+                "The env must be set before the command".to_owned(),
+            ));
+        }
+        self.channel.request_env(name, value)?;
+        Ok(self)
+    }
+
+    /// Send an executable command to the server
+    ///
+    /// This method will not wait the result, but the server's reply to the request
+    ///
+    /// The command is sent even if the returned future is dropped before it's ready
+    ///
+    pub async fn exec_command(&mut self, command: &str) -> SshResult<()> {
+        if self.command_send {
+            return Err(SshError::GeneralError(
+                "An exec channle can only send one command".to_owned(),
+            ));
+        }
+
+        debug!("Send command {}", command);
+        self.command_send = true;
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.channel.server_channel_no)
+            .put_str(ssh_str::EXEC)
+            .put_u8(true as u8)
+            .put_str(command);
+        self.channel.request(data).await?;
+        Ok(())
+    }
+
+    /// same as [crate::ChannelBroker::send_signal], e.g. to stop the command sent by [AsyncExec::exec_command]
+    ///
+    pub fn send_signal(&mut self, sig: &str) -> SshResult<()> {
+        self.channel.send_signal(sig)
+    }
+
+    /// Send an executable command to the server
+    /// and get the result
+    ///
+    /// This method also implicitly consume the channel object,
+    /// since the exec channel can only execute one command
+    ///
+    pub async fn send_command(mut self, command: &str) -> SshResult<Vec<u8>> {
+        self.exec_command(command).await?;
+        self.recv_to_end().await;
+        Ok(std::mem::take(&mut self.output))
+    }
+
+    /// Wait until the server closes the channel,
+    /// then return the output together with the exit status of the command
+    ///
+    /// This method also implicitly consume the channel object
+    ///
+    pub async fn wait(mut self) -> SshResult<ExecOutput> {
+        if !self.command_send {
+            return Err(SshError::GeneralError(
+                "No command has been sent to wait for".to_owned(),
+            ));
+        }
+        self.recv_to_end().await;
+        Ok(ExecOutput {
+            output: std::mem::take(&mut self.output),
+            stderr: std::mem::take(&mut self.channel.stderr),
+            exit_status: self.channel.exit_status,
+            exit_signal: self.channel.exit_signal.clone(),
+            terminate_msg: self.channel.terminate_msg.clone(),
+        })
+    }
+
+    // cancellation safe, the output read so far is kept in self
+    async fn recv_to_end(&mut self) {
+        loop {
+            let mut data = self.channel.recv().await;
+            if data.is_empty() {
+                return;
+            }
+            self.output.append(&mut data);
+        }
+    }
+}
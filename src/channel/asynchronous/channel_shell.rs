@@ -0,0 +1,87 @@
+use super::AsyncChannel;
+use crate::{
+    constant::{ssh_connection_code, ssh_str},
+    error::SshResult,
+    model::{Data, PtyConfig},
+};
+
+/// The async counterpart of [crate::channel::ShellBrocker]
+pub struct AsyncShell(AsyncChannel);
+
+impl AsyncShell {
+    pub(crate) async fn open(mut channel: AsyncChannel, pty: PtyConfig<'_>) -> SshResult<Self> {
+        // to open a shell channel, we need to request a pesudo-terminal
+        channel.set_interactive(true)?;
+
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(channel.server_channel_no)
+            .put_str(ssh_str::PTY_REQ)
+            .put_u8(true as u8);
+        pty.put(&mut data);
+        channel.request(data).await?;
+
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(channel.server_channel_no)
+            .put_str(ssh_str::SHELL)
+            .put_u8(true as u8);
+        channel.request(data).await?;
+        Ok(Self(channel))
+    }
+
+    /// this method will try to read as much data as we can from the server,
+    /// but it will wait until at least one packet is received
+    ///
+    /// nothing is lost if the returned future is dropped before it's ready,
+    /// e.g. in a `tokio::select!`, the data is returned by the next read
+    ///
+    /// an empty read tells that the channel is closed
+    ///
+    pub async fn read(&mut self) -> SshResult<Vec<u8>> {
+        let mut out = self.0.recv().await;
+        while let Some(mut data) = self.0.try_recv() {
+            if data.is_empty() {
+                break;
+            }
+            out.append(&mut data);
+        }
+        Ok(out)
+    }
+
+    /// same as [AsyncShell::read], but never waits,
+    /// None if no data has arrived yet
+    ///
+    pub fn try_read(&mut self) -> SshResult<Option<Vec<u8>>> {
+        let Some(mut out) = self.0.try_recv() else {
+            return Ok(None);
+        };
+        while let Some(mut data) = self.0.try_recv() {
+            if data.is_empty() {
+                break;
+            }
+            out.append(&mut data);
+        }
+        Ok(Some(out))
+    }
+
+    /// this method send `buf` to the remote pty
+    ///
+    /// the data is queued for the driver task of the session, which never waits
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.0.send_data(buf.to_vec().into())
+    }
+
+    /// same as [crate::ChannelBroker::send_signal]
+    ///
+    pub fn send_signal(&mut self, sig: &str) -> SshResult<()> {
+        self.0.send_signal(sig)
+    }
+
+    /// close the channel gracefully
+    ///
+    pub async fn close(mut self) -> SshResult<()> {
+        self.0.close()
+    }
+}
@@ -0,0 +1,281 @@
+//! The channels of the [crate::AsyncSession]
+//!
+//! Their messages are passed to and from the driver task of the session by the tokio channels,
+//! the reads wait for them without holding a thread, and can be cancelled without losing anything
+mod channel_exec;
+mod channel_shell;
+
+pub use channel_exec::AsyncExec;
+pub use channel_shell::AsyncShell;
+
+use tokio::sync::mpsc::{error::TryRecvError, UnboundedReceiver, UnboundedSender};
+use tracing::*;
+
+use crate::{
+    config::env::EnvVar,
+    constant::{ssh_connection_code, ssh_extended_data_code, ssh_str},
+    error::{SshError, SshResult},
+    model::{AsyncRqst, BackendResp, Data},
+};
+
+/// The async counterpart of [crate::ChannelBroker]
+pub(crate) struct AsyncChannel {
+    client_channel_no: u32,
+    server_channel_no: u32,
+    snd: UnboundedSender<AsyncRqst>,
+    rcv: UnboundedReceiver<BackendResp>,
+    close: bool,
+    exit_status: Option<u32>,
+    exit_signal: Option<String>,
+    terminate_msg: String,
+    stderr: Vec<u8>,
+    // the data which arrives while waiting for a reply, returned by the next read
+    pending_stdout: Vec<u8>,
+    // the requests sent but not replied yet,
+    // including the ones whose futures are dropped
+    pending_replies: usize,
+    // whether the last reply is SSH_MSG_CHANNEL_SUCCESS
+    accepted: bool,
+}
+
+impl AsyncChannel {
+    /// wait for the server to confirm the channel `client_id`, which is already asked for
+    ///
+    /// the channel is closed once it's confirmed if the future is dropped before
+    pub(crate) async fn open(
+        client_id: u32,
+        snd: UnboundedSender<AsyncRqst>,
+        rcv: UnboundedReceiver<BackendResp>,
+    ) -> SshResult<Self> {
+        let mut channel = Self {
+            client_channel_no: client_id,
+            server_channel_no: 0,
+            snd,
+            rcv,
+            close: false,
+            exit_status: None,
+            exit_signal: None,
+            terminate_msg: "".to_owned(),
+            stderr: vec![],
+            pending_stdout: vec![],
+            pending_replies: 0,
+            accepted: false,
+        };
+        match channel.rcv.recv().await {
+            Some(BackendResp::Ok(server_id)) => {
+                channel.server_channel_no = server_id;
+                Ok(channel)
+            }
+            Some(BackendResp::Fail(msg)) => {
+                channel.close = true;
+                Err(SshError::GeneralError(msg))
+            }
+            _ => {
+                channel.close = true;
+                Err(SshError::IpcError("the session is closed".to_owned()))
+            }
+        }
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variables, one request for each,
+    /// and apply their policies to the replies
+    pub(crate) async fn send_env(&mut self, env: &[EnvVar]) -> SshResult<()> {
+        for var in env {
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+                .put_u32(self.server_channel_no)
+                .put_str(ssh_str::ENV)
+                .put_u8(true as u8)
+                .put_str(&var.name)
+                .put_str(&var.value);
+            let accepted = self.request(data).await?;
+            var.check(accepted)?;
+        }
+        Ok(())
+    }
+
+    /// an env request with want_reply unset
+    fn request_env(&mut self, name: &str, value: &str) -> SshResult<()> {
+        debug!("Set env {}", name);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::ENV)
+            .put_u8(false as u8)
+            .put_str(name)
+            .put_str(value);
+        self.send_noreply(data)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.9>
+    ///
+    /// the same as [crate::ChannelBroker::send_signal]
+    fn send_signal(&mut self, sig: &str) -> SshResult<()> {
+        let sig = sig.strip_prefix("SIG").unwrap_or(sig);
+        debug!("Send signal {}", sig);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SIGNAL)
+            .put_u8(false as u8)
+            .put_str(sig);
+        self.send_noreply(data)
+    }
+
+    fn set_interactive(&mut self, interactive: bool) -> SshResult<()> {
+        self.snd
+            .send(AsyncRqst::Interactive(self.client_channel_no, interactive))?;
+        Ok(())
+    }
+
+    fn send_data(&mut self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(AsyncRqst::Data(self.client_channel_no, data))?;
+        Ok(())
+    }
+
+    /// send a control message that gets no reply from the server
+    fn send_noreply(&mut self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(AsyncRqst::Command(self.client_channel_no, data))?;
+        Ok(())
+    }
+
+    /// send a request which wants a reply, then wait for it,
+    /// true if the server replies SSH_MSG_CHANNEL_SUCCESS
+    ///
+    /// the request is sent even if the future is dropped before it's ready,
+    /// then its reply is skipped by the later reads
+    async fn request(&mut self, data: Data) -> SshResult<bool> {
+        self.send_noreply(data)?;
+        self.pending_replies += 1;
+        self.wait_replies().await
+    }
+
+    /// wait until all the requests are replied, the data meanwhile is kept for the next read
+    ///
+    /// cancellation safe
+    async fn wait_replies(&mut self) -> SshResult<bool> {
+        while self.pending_replies > 0 {
+            if self.close {
+                return Err(SshError::GeneralError(
+                    "Send data on a closed channel".to_owned(),
+                ));
+            }
+            match self.rcv.recv().await {
+                Some(resp) => {
+                    if let Some(mut data) = self.handle_resp(resp) {
+                        self.pending_stdout.append(&mut data);
+                    }
+                }
+                None => self.close = true,
+            }
+        }
+        Ok(self.accepted)
+    }
+
+    /// wait until some data arrives, an empty read tells that the channel is closed
+    ///
+    /// cancellation safe
+    async fn recv(&mut self) -> Vec<u8> {
+        if !self.pending_stdout.is_empty() {
+            return std::mem::take(&mut self.pending_stdout);
+        }
+        while !self.close {
+            match self.rcv.recv().await {
+                Some(resp) => {
+                    // keep waiting for the stdout until the channel is closed
+                    if let Some(data) = self.handle_resp(resp) {
+                        return data;
+                    }
+                }
+                // the session is gone, so is the channel
+                None => self.close = true,
+            }
+        }
+        vec![]
+    }
+
+    /// same as [AsyncChannel::recv] but never waits, None if nothing has arrived yet
+    fn try_recv(&mut self) -> Option<Vec<u8>> {
+        if !self.pending_stdout.is_empty() {
+            return Some(std::mem::take(&mut self.pending_stdout));
+        }
+        // go on over the messages without data, such as the exit status,
+        // so that the data behind them is not left in the queue
+        while !self.close {
+            match self.rcv.try_recv() {
+                Ok(resp) => {
+                    if let Some(data) = self.handle_resp(resp) {
+                        return Some(data);
+                    }
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => self.close = true,
+            }
+        }
+        Some(vec![])
+    }
+
+    /// the stdout if any, the other messages only update the states
+    fn handle_resp(&mut self, resp: BackendResp) -> Option<Vec<u8>> {
+        match resp {
+            BackendResp::Close => {
+                // the driver has sent back the close
+                self.close = true;
+                None
+            }
+            BackendResp::ExitStatus(status) => {
+                self.exit_status = Some(status);
+                None
+            }
+            BackendResp::TermMsg(signal, msg) => {
+                self.exit_signal = signal;
+                self.terminate_msg = msg;
+                None
+            }
+            BackendResp::Data(data) => Some(data.into_inner()),
+            BackendResp::Ok(_) | BackendResp::Fail(_) if self.pending_replies > 0 => {
+                self.pending_replies -= 1;
+                self.accepted = matches!(resp, BackendResp::Ok(_));
+                if self.accepted {
+                    trace!("{}: control command ok", self.client_channel_no);
+                } else {
+                    error!("{}: channel error", self.client_channel_no);
+                }
+                None
+            }
+            BackendResp::ExtendedData(data_type, data) => {
+                // only stderr is defined, the other types are dropped
+                if data_type == ssh_extended_data_code::STDERR {
+                    self.stderr.extend(data.into_inner());
+                } else {
+                    debug!("Drop extended data with type {data_type}");
+                }
+                None
+            }
+            _ => {
+                debug!("{}: unexpected reply", self.client_channel_no);
+                None
+            }
+        }
+    }
+
+    /// send the close, the data after it is not waited for
+    fn close(&mut self) -> SshResult<()> {
+        if !self.close {
+            self.close = true;
+            self.snd
+                .send(AsyncRqst::CloseChannel(self.client_channel_no))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AsyncChannel {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -1,66 +1,143 @@
 use std::{
-    io::{Read, Write},
-    sync::mpsc::{Receiver, Sender},
+    io::Write,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    time::{Duration, Instant},
     vec,
 };
 
 use crate::{
     client::Client,
-    constant::ssh_connection_code,
+    config::env::EnvVar,
+    constant::{size, ssh_connection_code, ssh_extended_data_code, ssh_str},
     error::{SshError, SshResult},
-    model::{BackendResp, BackendRqst, Data, FlowControl, Packet},
+    model::{BackendResp, BackendRqst, Data, FlowControl, PtyConfig, RemoteClose, RespSender},
     TerminalSize,
 };
 use tracing::*;
 
 #[cfg(feature = "scp")]
 use super::channel_scp::ScpBroker;
-use super::{channel_exec::ExecBroker, channel_shell::ShellBrocker};
+use super::{
+    channel_exec::ExecBroker, channel_shell::ShellBrocker, channel_subsystem::SubsystemBroker,
+};
+
+/// how much of the pending bulk data is sent in one round of the backend,
+/// the interactive data goes between the rounds
+const BULK_QUANTUM: usize = 4 * size::BUF_SIZE;
 
 pub(crate) struct Channel {
-    snd: Sender<BackendResp>,
+    snd: Box<dyn RespSender>,
     server_channel_no: u32,
     client_channel_no: u32,
     remote_close: bool,
+    remote_close_notifier: Arc<RemoteClose>,
     local_close: bool,
     flow_control: FlowControl,
     pending_send: Vec<u8>,
+    // waiting for the pending data to be sent
+    flush_waiters: Vec<Sender<BackendResp>>,
+    // requested by the broker, sent once the pending data is sent
+    eof_pending: bool,
+    // the pending data is sent right away rather than in the bulk rounds
+    interactive: bool,
 }
 
 impl Channel {
     pub fn new(
         server_channel_no: u32,
         client_channel_no: u32,
+        local_window: u32,
         remote_window: u32,
-        snd: Sender<BackendResp>,
+        adjust_threshold: u32,
+        remote_close_notifier: Arc<RemoteClose>,
+        snd: impl RespSender + 'static,
     ) -> SshResult<Self> {
         snd.send(BackendResp::Ok(server_channel_no))?;
 
         Ok(Self {
-            snd,
+            snd: Box::new(snd),
             server_channel_no,
             client_channel_no,
             remote_close: false,
+            remote_close_notifier,
             local_close: false,
-            flow_control: FlowControl::new(remote_window),
+            flow_control: FlowControl::new(local_window, remote_window, adjust_threshold),
             pending_send: vec![],
+            flush_waiters: vec![],
+            eof_pending: false,
+            interactive: false,
         })
     }
 
     pub fn send_data<S>(&mut self, data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         self.pending_send.append(&mut data.into_inner());
-        self.try_send_data(client, stream)
+        self.try_send_interactive(client, stream)
+    }
+
+    /// the data of an interactive channel goes ahead of the bulk data
+    /// of the other channels which waits for [Channel::send_bulk]
+    pub fn set_interactive<S>(
+        &mut self,
+        interactive: bool,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.interactive = interactive;
+        self.try_send_interactive(client, stream)
     }
 
-    fn try_send_data<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    /// send one round of the pending bulk data
+    pub fn send_bulk<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
+        if self.interactive || self.pending_send.is_empty() {
+            return Ok(());
+        }
+        self.send_pending(BULK_QUANTUM, client, stream)
+    }
+
+    fn try_send_interactive<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        // the EOF after nothing needs no round
+        if self.interactive || self.pending_send.is_empty() {
+            self.try_send_data(client, stream)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// send as much of the pending data as the window allows
+    pub fn try_send_data<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.send_pending(usize::MAX, client, stream)
+    }
+
+    fn send_pending<S>(
+        &mut self,
+        limit: usize,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let mut sent = 0;
         // try to send as much as we can
-        while !self.pending_send.is_empty() {
+        while !self.pending_send.is_empty() && sent < limit {
             if self.flow_control.can_send() {
                 let maybe_remain = self.flow_control.tune_on_send(&mut self.pending_send);
 
@@ -71,6 +148,7 @@ impl Channel {
                     .put_u8s(&self.pending_send);
 
                 // update remain
+                sent += self.pending_send.len();
                 self.pending_send = maybe_remain;
 
                 self.send(data, client, stream)?;
@@ -78,15 +156,49 @@ impl Channel {
                 break;
             }
         }
+
+        if self.pending_send.is_empty() {
+            for waiter in self.flush_waiters.drain(..) {
+                let _ = waiter.send(BackendResp::Ok(self.client_channel_no));
+            }
+            if self.eof_pending {
+                self.eof_pending = false;
+                let mut data = Data::new();
+                data.put_u8(ssh_connection_code::CHANNEL_EOF)
+                    .put_u32(self.server_channel_no);
+                self.send(data, client, stream)?;
+            }
+        }
         Ok(())
     }
 
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-5.3>
+    ///
+    /// send SSH_MSG_CHANNEL_EOF after the data that is still waiting for the window
+    pub fn send_eof<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.eof_pending = true;
+        self.try_send_interactive(client, stream)
+    }
+
+    /// reply to `waiter` once all the pending data is sent
+    #[cfg(feature = "scp")]
+    pub fn flush(&mut self, waiter: Sender<BackendResp>) {
+        if self.pending_send.is_empty() {
+            let _ = waiter.send(BackendResp::Ok(self.client_channel_no));
+        } else {
+            self.flush_waiters.push(waiter)
+        }
+    }
+
     pub fn send<S>(&mut self, data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         if !self.closed() {
-            data.pack(client).write_stream(stream)
+            client.send_msg(data, stream)
         } else {
             Err(SshError::GeneralError(
                 "Send data on a closed channel".to_owned(),
@@ -96,16 +208,45 @@ impl Channel {
 
     pub fn recv<S>(&mut self, mut data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         let mut buf = data.get_u8s();
+        // an empty packet is not the end of the data,
+        // which is told by an empty read
+        if buf.is_empty() {
+            debug!("Recv an empty data packet");
+            return Ok(());
+        }
         // flow_control
         self.flow_control.tune_on_recv(&mut buf);
-        self.send_window_adjust(buf.len() as u32, client, stream)?;
+        if let Some(to_add) = self.flow_control.consume(buf.len() as u32) {
+            self.send_window_adjust(to_add, client, stream)?;
+        }
         self.snd.send(BackendResp::Data(buf.into()))?;
         Ok(())
     }
 
+    pub fn recv_extended<S>(
+        &mut self,
+        data_type: u32,
+        mut data: Data,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let mut buf = data.get_u8s();
+        // flow_control
+        self.flow_control.tune_on_recv(&mut buf);
+        if let Some(to_add) = self.flow_control.consume(buf.len() as u32) {
+            self.send_window_adjust(to_add, client, stream)?;
+        }
+        self.snd
+            .send(BackendResp::ExtendedData(data_type, buf.into()))?;
+        Ok(())
+    }
+
     fn send_window_adjust<S>(
         &mut self,
         to_add: u32,
@@ -113,7 +254,7 @@ impl Channel {
         stream: &mut S,
     ) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         let mut data = Data::new();
         data.put_u8(ssh_connection_code::CHANNEL_WINDOW_ADJUST)
@@ -130,31 +271,63 @@ impl Channel {
         stream: &mut S,
     ) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         self.flow_control.on_recv(to_add);
         if !self.pending_send.is_empty() {
-            self.try_send_data(client, stream)
+            self.try_send_interactive(client, stream)
         } else {
             Ok(())
         }
     }
 
+    /// send SSH_MSG_CHANNEL_CLOSE after the data that the window allows
+    #[cfg(feature = "async")]
+    pub fn send_close<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.try_send_data(client, stream)?;
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_CLOSE)
+            .put_u32(self.server_channel_no);
+        self.send(data, client, stream)?;
+        self.local_close()
+    }
+
     pub fn local_close(&mut self) -> SshResult<()> {
         trace!("Channel {} send local close", self.client_channel_no);
         self.local_close = true;
         Ok(())
     }
 
-    pub fn remote_close(&mut self) -> SshResult<()> {
+    pub fn remote_close<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
         trace!("Channel {} recv remote close", self.client_channel_no);
-        self.remote_close = true;
+        self.remote_close_notifier.notify();
         if !self.local_close {
-            self.snd.send(BackendResp::Close)?;
+            // the broker may be blocked on it, let it wake up
+            let _ = self.snd.send(BackendResp::Close);
+
+            // <https://www.rfc-editor.org/rfc/rfc4254#section-5.3>
+            // send back a close, so that the server can free the channel
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::CHANNEL_CLOSE)
+                .put_u32(self.server_channel_no);
+            self.send(data, client, stream)?;
+            self.local_close = true;
         }
+        self.remote_close = true;
         Ok(())
     }
 
+    /// the session is gone, tell the broker that it's closed
+    pub fn abort(self) {
+        let _ = self.snd.send(BackendResp::Close);
+    }
+
     pub fn success(&mut self) -> SshResult<()> {
         self.snd.send(BackendResp::Ok(self.client_channel_no))?;
         Ok(())
@@ -194,9 +367,11 @@ impl Channel {
     fn handle_exit_signal(&mut self, data: &mut Data) -> SshResult<()> {
         let maybe_false = data.get_u8();
         let mut msg = "".to_owned();
+        let mut signal = None;
         if maybe_false == 0 {
             if let Ok(sig_name) = String::from_utf8(data.get_u8s()) {
                 msg += &format!("Current request is terminated by signal: {sig_name}\n");
+                signal = Some(sig_name);
             }
             let coredumped = data.get_u8();
             msg += &format!("Coredumped: {}\n", {
@@ -210,7 +385,7 @@ impl Channel {
                 msg += &format!("Error message:\n{err_msg}\n");
             }
         }
-        self.snd.send(BackendResp::TermMsg(msg))?;
+        self.snd.send(BackendResp::TermMsg(signal, msg))?;
         Ok(())
     }
 
@@ -231,8 +406,15 @@ pub struct ChannelBroker {
     pub(crate) rcv: Receiver<BackendResp>,
     pub(crate) snd: Sender<BackendRqst>,
     pub(crate) close: bool,
-    pub(crate) exit_status: u32,
+    pub(crate) remote_close: Arc<RemoteClose>,
+    pub(crate) exit_status: Option<u32>,
+    pub(crate) exit_signal: Option<String>,
     pub(crate) terminate_msg: String,
+    pub(crate) stderr: Vec<u8>,
+    // no more data is sent after the EOF
+    eof: bool,
+    // the data read by [ChannelBroker::read_stderr], returned by the next read
+    pending_stdout: Vec<u8>,
 }
 
 impl ChannelBroker {
@@ -241,6 +423,7 @@ impl ChannelBroker {
         server_id: u32,
         rcv: Receiver<BackendResp>,
         snd: Sender<BackendRqst>,
+        remote_close: Arc<RemoteClose>,
     ) -> Self {
         Self {
             client_channel_no: client_id,
@@ -248,11 +431,29 @@ impl ChannelBroker {
             rcv,
             snd,
             close: false,
-            exit_status: 0,
+            remote_close,
+            exit_status: None,
+            exit_signal: None,
             terminate_msg: "".to_owned(),
+            stderr: vec![],
+            eof: false,
+            pending_stdout: vec![],
         }
     }
 
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variable `name=value` to the command or the shell
+    /// that the channel is converted to later
+    ///
+    /// no reply is asked for, since the servers commonly ignore
+    /// the ones not in their `AcceptEnv`
+    ///
+    pub fn set_env(mut self, name: &str, value: &str) -> SshResult<Self> {
+        self.request_env(name, value)?;
+        Ok(self)
+    }
+
     /// open a [ExecBroker] channel which can excute commands
     ///
     pub fn exec(self) -> SshResult<ExecBroker> {
@@ -269,15 +470,44 @@ impl ChannelBroker {
     /// open a [ShellBrocker] channel which  can be used as a pseudo terminal (AKA PTY)
     ///
     pub fn shell(self, tv: TerminalSize) -> SshResult<ShellBrocker> {
-        ShellBrocker::open(self, tv)
+        ShellBrocker::open(self, tv.into())
+    }
+
+    /// same as [ChannelBroker::shell], with the terminal type & dimensions in `pty`
+    ///
+    pub fn shell_with_pty(self, pty: PtyConfig) -> SshResult<ShellBrocker> {
+        ShellBrocker::open(self, pty)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.5>
+    ///
+    /// open a [SubsystemBroker] channel running `name`
+    ///
+    /// [SshError::SubsystemRefused] if the server refuses it
+    ///
+    pub fn subsystem(self, name: &str) -> SshResult<SubsystemBroker> {
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SUBSYSTEM)
+            .put_u8(true as u8)
+            .put_str(name);
+        self.snd
+            .send(BackendRqst::Command(self.client_channel_no, data))?;
+        if !self.wait_reply()? {
+            return Err(SshError::SubsystemRefused(name.to_owned()));
+        }
+        info!("subsystem {} opened.", name);
+        Ok(SubsystemBroker::open(self, name))
     }
 
     /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
     ///
-    /// Return the command execute status
+    /// Return the command execute status, None if the server has not sent one (yet),
+    /// e.g. the command is killed by a signal
     ///
-    pub fn exit_status(&self) -> SshResult<u32> {
-        Ok(self.exit_status)
+    pub fn exit_status(&self) -> Option<u32> {
+        self.exit_status
     }
 
     /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
@@ -288,6 +518,24 @@ impl ChannelBroker {
         Ok(self.terminate_msg.clone())
     }
 
+    /// whether the server has closed the channel,
+    /// which is known as soon as the backend receives SSH_MSG_CHANNEL_CLOSE,
+    /// even if the data before it is not read yet
+    ///
+    pub fn is_remote_closed(&self) -> bool {
+        self.remote_close.is_closed()
+    }
+
+    /// call `callback` in the backend thread once the server closes the channel,
+    /// or right now if it already has
+    ///
+    pub fn on_remote_close<F>(&mut self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.remote_close.on_close(Box::new(callback))
+    }
+
     /// close the backend channel but do not consume
     ///
     pub fn close(&mut self) -> SshResult<()> {
@@ -302,83 +550,303 @@ impl ChannelBroker {
         Ok(())
     }
 
+    /// same as [ChannelBroker::close], but wait at most `timeout` for the server's close,
+    /// and the exit status before it
+    ///
+    /// [SshError::TimeoutError] if the server has not closed the channel in time
+    ///
+    pub fn close_with_timeout(&mut self, timeout: Duration) -> SshResult<()> {
+        self.close()?;
+        let deadline = Instant::now() + timeout;
+        // the backend wakes us up only if the server closes first,
+        // so check the flag from time to time
+        let tick = Duration::from_millis(10);
+        while !self.remote_close.is_closed() {
+            let now = Instant::now();
+            if now >= deadline {
+                error!("time out.");
+                return Err(SshError::TimeoutError);
+            }
+            match self.rcv.recv_timeout(tick.min(deadline - now)) {
+                Ok(resp) => {
+                    self.handle_resp(resp);
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // what is sent before the close
+        while let Ok(resp) = self.rcv.try_recv() {
+            self.handle_resp(resp);
+        }
+        Ok(())
+    }
+
+    /// the same as [ChannelBroker::close], which never waits for the server's close
+    ///
+    pub fn close_nowait(&mut self) -> SshResult<()> {
+        self.close()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.9>
+    ///
+    /// deliver the signal `sig` to the remote command or shell,
+    /// named without the "SIG" prefix, e.g. "INT", "TERM" or "KILL"
+    ///
+    /// a command killed by it is told by [ExecBroker::exit_signal] later,
+    /// the servers which do not support signals ignore it silently
+    ///
+    pub fn send_signal(&mut self, sig: &str) -> SshResult<()> {
+        let sig = sig.strip_prefix("SIG").unwrap_or(sig);
+        debug!("Send signal {}", sig);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SIGNAL)
+            .put_u8(false as u8)
+            .put_str(sig);
+        self.send_noreply(data)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-5.3>
+    ///
+    /// tell the server that no more data will be sent, AKA half-close,
+    /// the data from the server can still be read until it closes the channel
+    ///
+    pub fn send_eof(&mut self) -> SshResult<()> {
+        if !self.eof && !self.close {
+            self.eof = true;
+            self.snd.send(BackendRqst::Eof(self.client_channel_no))?;
+        }
+        Ok(())
+    }
+
+    /// whether [ChannelBroker::send_eof] has been called
+    ///
+    pub fn is_eof_sent(&self) -> bool {
+        self.eof
+    }
+
+    /// mark the channel as interactive, whose data is written
+    /// ahead of the bulk data of the other channels when both are waiting,
+    /// e.g. the keystrokes to a shell during a big upload
+    ///
+    /// the shells are interactive from the start, the other channels are bulk
+    ///
+    pub fn set_interactive(&mut self, interactive: bool) -> SshResult<()> {
+        self.snd.send(BackendRqst::Interactive(
+            self.client_channel_no,
+            interactive,
+        ))?;
+        Ok(())
+    }
+
     pub(super) fn send_data(&self, data: Data) -> SshResult<()> {
+        if self.eof {
+            return Err(SshError::GeneralError("Send data after the EOF".to_owned()));
+        }
         self.snd
             .send(BackendRqst::Data(self.client_channel_no, data))?;
         Ok(())
     }
 
+    /// block until all the data sent by [ChannelBroker::send_data] is on the wire,
+    /// that is, the remote window has allowed it
+    #[cfg(feature = "scp")]
+    pub(super) fn flush(&self) -> SshResult<()> {
+        let (resp_send, resp_recv) = std::sync::mpsc::channel();
+        self.snd
+            .send(BackendRqst::Flush(self.client_channel_no, resp_send))?;
+        match resp_recv.recv() {
+            Ok(BackendResp::Ok(_)) => Ok(()),
+            _ => Err(SshError::GeneralError(
+                "Send data on a closed channel".to_owned(),
+            )),
+        }
+    }
+
     pub(super) fn send(&self, data: Data) -> SshResult<()> {
         self.snd
             .send(BackendRqst::Command(self.client_channel_no, data))?;
         if !self.close {
-            match self.rcv.recv()? {
-                BackendResp::Ok(_) => trace!("{}: control command ok", self.client_channel_no),
-                BackendResp::Fail(msg) => error!(
-                    "{}: channel error with reason {}",
-                    self.client_channel_no, msg
-                ),
-                _ => unreachable!(),
+            if self.wait_reply()? {
+                trace!("{}: control command ok", self.client_channel_no)
+            } else {
+                error!("{}: channel error", self.client_channel_no)
             }
         }
         Ok(())
     }
 
+    /// send a control message that gets no reply from the server
+    pub(super) fn send_noreply(&self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(BackendRqst::Command(self.client_channel_no, data))?;
+        Ok(())
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variables, one request for each,
+    /// and apply their policies to the replies
+    pub(crate) fn send_env(&mut self, env: &[EnvVar]) -> SshResult<()> {
+        for var in env {
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+                .put_u32(self.server_channel_no)
+                .put_str(ssh_str::ENV)
+                .put_u8(true as u8)
+                .put_str(&var.name)
+                .put_str(&var.value);
+            self.snd
+                .send(BackendRqst::Command(self.client_channel_no, data))?;
+            let accepted = self.wait_reply()?;
+            var.check(accepted)?;
+        }
+        Ok(())
+    }
+
+    /// an env request with want_reply unset
+    pub(super) fn request_env(&mut self, name: &str, value: &str) -> SshResult<()> {
+        debug!("Set env {}", name);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::ENV)
+            .put_u8(false as u8)
+            .put_str(name)
+            .put_str(value);
+        self.send_noreply(data)
+    }
+
+    // whether the server replies SSH_MSG_CHANNEL_SUCCESS to the request
+    fn wait_reply(&self) -> SshResult<bool> {
+        match self.rcv.recv()? {
+            BackendResp::Ok(_) => Ok(true),
+            BackendResp::Fail(_) => Ok(false),
+            BackendResp::Close => Err(SshError::GeneralError(
+                "Send data on a closed channel".to_owned(),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
     pub(super) fn recv(&mut self) -> SshResult<Vec<u8>> {
-        if self.close {
-            Ok(vec![])
-        } else {
-            match self.rcv.recv()? {
-                BackendResp::Close => {
-                    // the remote actively close their end
-                    // but we can send close later when the broker get dropped
-                    // just set a flag here
-                    self.close = true;
-                    Ok(vec![])
-                }
-                BackendResp::ExitStatus(status) => {
-                    self.exit_status = status;
-                    Ok(vec![])
+        if !self.pending_stdout.is_empty() {
+            return Ok(std::mem::take(&mut self.pending_stdout));
+        }
+        while !self.close {
+            let resp = self.rcv.recv()?;
+            // keep waiting for the stdout until the channel is closed
+            if let Some(data) = self.handle_resp(resp) {
+                return Ok(data);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// same as [ChannelBroker::recv], but [SshError::TimeoutError] if nothing arrives in `timeout`
+    pub(super) fn recv_timeout(&mut self, timeout: Duration) -> SshResult<Vec<u8>> {
+        if !self.pending_stdout.is_empty() {
+            return Ok(std::mem::take(&mut self.pending_stdout));
+        }
+        let deadline = Instant::now() + timeout;
+        while !self.close {
+            let now = Instant::now();
+            if now >= deadline {
+                error!("time out.");
+                return Err(SshError::TimeoutError);
+            }
+            match self.rcv.recv_timeout(deadline - now) {
+                Ok(resp) => {
+                    if let Some(data) = self.handle_resp(resp) {
+                        return Ok(data);
+                    }
                 }
-                BackendResp::TermMsg(msg) => {
-                    self.terminate_msg = msg;
-                    Ok(vec![])
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(e @ RecvTimeoutError::Disconnected) => {
+                    return Err(SshError::IpcError(e.to_string()))
                 }
-                BackendResp::Data(data) => Ok(data.into_inner()),
-                _ => unreachable!(),
             }
         }
+        Ok(vec![])
     }
 
     pub(super) fn try_recv(&mut self) -> SshResult<Option<Vec<u8>>> {
-        if !self.close {
-            if let Ok(resp) = self.rcv.try_recv() {
-                match resp {
-                    BackendResp::Close => {
-                        // the remote actively close their end
-                        // but we can send close later when the broker get dropped
-                        // just set a flag here
-                        self.close = true;
-                        Ok(None)
-                    }
-                    BackendResp::Data(data) => Ok(Some(data.into_inner())),
-                    BackendResp::ExitStatus(status) => {
-                        self.exit_status = status;
-                        Ok(None)
+        if !self.pending_stdout.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.pending_stdout)));
+        }
+        if self.close {
+            return Err(SshError::GeneralError(
+                "Read data on a closed channel".to_owned(),
+            ));
+        }
+        // go on over the messages without data, such as the exit status,
+        // so that the data behind them is not left in the queue
+        loop {
+            match self.rcv.try_recv() {
+                Ok(resp) => {
+                    if let Some(data) = self.handle_resp(resp) {
+                        return Ok(Some(data));
                     }
-                    BackendResp::TermMsg(msg) => {
-                        self.terminate_msg = msg;
-                        Ok(None)
+                    if self.close {
+                        break;
                     }
-                    _ => unreachable!(),
                 }
-            } else {
-                Ok(None)
+                Err(TryRecvError::Empty) => break,
+                // the session is gone, so is the channel
+                Err(TryRecvError::Disconnected) => {
+                    self.close = true;
+                    break;
+                }
             }
-        } else {
-            Err(SshError::GeneralError(
-                "Read data on a closed channel".to_owned(),
-            ))
+        }
+        Ok(None)
+    }
+
+    /// take the stderr received so far, after handling the messages
+    /// that have already arrived without blocking
+    ///
+    /// the stdout among them is kept for the next read
+    pub(super) fn read_stderr(&mut self) -> Vec<u8> {
+        while let Ok(resp) = self.rcv.try_recv() {
+            if let Some(mut data) = self.handle_resp(resp) {
+                self.pending_stdout.append(&mut data);
+            }
+        }
+        std::mem::take(&mut self.stderr)
+    }
+
+    /// the stdout if any, the other messages only update the states
+    fn handle_resp(&mut self, resp: BackendResp) -> Option<Vec<u8>> {
+        match resp {
+            BackendResp::Close => {
+                // the remote actively close their end
+                // but we can send close later when the broker get dropped
+                // just set a flag here
+                self.close = true;
+                None
+            }
+            BackendResp::ExitStatus(status) => {
+                self.exit_status = Some(status);
+                None
+            }
+            BackendResp::TermMsg(signal, msg) => {
+                self.exit_signal = signal;
+                self.terminate_msg = msg;
+                None
+            }
+            BackendResp::Data(data) => Some(data.into_inner()),
+            BackendResp::ExtendedData(data_type, data) => {
+                // only stderr is defined, the other types are dropped
+                if data_type == ssh_extended_data_code::STDERR {
+                    self.stderr.extend(data.into_inner());
+                } else {
+                    debug!("Drop extended data with type {data_type}");
+                }
+                None
+            }
+            _ => unreachable!(),
         }
     }
 
@@ -0,0 +1,169 @@
+use super::channel::ChannelBroker;
+use crate::error::{SshError, SshResult};
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    ops::{Deref, DerefMut},
+};
+
+/// A `direct-tcpip` channel, whose bytes are forwarded to/from the target by the server
+///
+pub struct DirectTcpipBroker(ChannelBroker);
+
+impl DirectTcpipBroker {
+    pub(crate) fn open(channel: ChannelBroker) -> Self {
+        DirectTcpipBroker(channel)
+    }
+
+    /// this method will try to read as much data as we can from the target,
+    /// but it will block until at least one packet is received
+    ///
+    /// an empty result means the channel is closed
+    ///
+    pub fn read(&mut self) -> SshResult<Vec<u8>> {
+        let mut out = self.recv()?;
+        while let Ok(Some(mut data)) = self.try_recv() {
+            out.append(&mut data)
+        }
+        Ok(out)
+    }
+
+    /// same as [DirectTcpipBroker::read], but append the data to `buf`,
+    /// so that its capacity can be reused across reads
+    ///
+    /// return the number of bytes appended
+    ///
+    pub fn read_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv()?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// this method send `buf` to the target
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.send_data(buf.to_vec().into())?;
+        Ok(())
+    }
+
+    /// convert the channel into a [DirectTcpipStream],
+    /// which can be used wherever a [std::io::Read] + [std::io::Write] is expected
+    ///
+    pub fn into_stream(self) -> DirectTcpipStream {
+        DirectTcpipStream {
+            channel: self,
+            buf: vec![],
+            pos: 0,
+            read_shutdown: false,
+            nonblocking: false,
+        }
+    }
+}
+
+/// A [DirectTcpipBroker] behaving like a [std::net::TcpStream],
+/// see [DirectTcpipBroker::into_stream]
+///
+pub struct DirectTcpipStream {
+    channel: DirectTcpipBroker,
+    // the data received but not read yet
+    buf: Vec<u8>,
+    pos: usize,
+    read_shutdown: bool,
+    nonblocking: bool,
+}
+
+impl DirectTcpipStream {
+    /// the same as [std::net::TcpStream::shutdown]
+    ///
+    /// shutting down the write half sends SSH_MSG_CHANNEL_EOF,
+    /// while the data from the target can still be read.
+    /// shutting down the read half makes the later reads return 0 at once
+    ///
+    /// the channel itself is closed when the stream is dropped
+    ///
+    pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        if matches!(how, Shutdown::Write | Shutdown::Both) {
+            self.channel.send_eof().map_err(to_io_error)?;
+        }
+        if matches!(how, Shutdown::Read | Shutdown::Both) {
+            self.read_shutdown = true;
+        }
+        Ok(())
+    }
+
+    /// the same as [std::net::TcpStream::set_nonblocking]
+    ///
+    /// the reads return [io::ErrorKind::WouldBlock] rather than waiting
+    /// if nothing has arrived from the target
+    ///
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// the underlying channel
+    ///
+    pub fn into_inner(self) -> DirectTcpipBroker {
+        self.channel
+    }
+}
+
+impl Read for DirectTcpipStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_shutdown {
+            return Ok(0);
+        }
+        if self.pos == self.buf.len() {
+            self.buf = if self.nonblocking {
+                match self.channel.try_recv() {
+                    Ok(Some(data)) => data,
+                    Ok(None) => return Err(io::ErrorKind::WouldBlock.into()),
+                    // the channel is closed
+                    Err(_) => vec![],
+                }
+            } else {
+                // an empty read means the channel is closed
+                self.channel.read().map_err(to_io_error)?
+            };
+            self.pos = 0;
+        }
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Write for DirectTcpipStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.channel.write(buf).map_err(to_io_error)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn to_io_error(e: SshError) -> io::Error {
+    match e {
+        SshError::IoError(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+impl Deref for DirectTcpipBroker {
+    type Target = ChannelBroker;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for DirectTcpipBroker {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
@@ -182,18 +182,24 @@ impl ScpBroker {
         self.send_bytes(cmd.as_bytes())?;
         self.get_end()?;
 
-        let mut count = 0;
+        // stream the file chunk by chunk,
+        // the next chunk is not read until the previous one is sent
+        let mut remain = scp_file.size;
         let mut chunk = [0u8; size::FILE_CHUNK];
-
-        loop {
-            let i = file.read(&mut chunk)?;
-            count += i;
-            self.send_bytes(&chunk[..i])?;
-            if count == scp_file.size as usize {
-                self.send_end()?;
-                break;
+        while remain > 0 {
+            let want = chunk.len().min(remain as usize);
+            let i = file.read(&mut chunk[..want])?;
+            if i == 0 {
+                return Err(SshError::ScpError(format!(
+                    "file {} is truncated while uploading",
+                    scp_file.name
+                )));
             }
+            remain -= i as u64;
+            self.send_bytes(&chunk[..i])?;
+            self.flush()?;
         }
+        self.send_end()?;
         self.get_end()?;
 
         debug!("file: [{}] upload completed.", scp_file.name);
@@ -393,7 +399,6 @@ impl ScpBroker {
             fs::remove_file(path.as_path())?;
         }
         let mut file = match OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(path.as_path())
@@ -1,41 +1,28 @@
 use super::channel::ChannelBroker;
 use crate::constant::{ssh_connection_code, ssh_str};
 use crate::error::SshResult;
-use crate::model::Data;
-use crate::TerminalSize;
+use crate::model::{Data, PtyConfig};
 use std::ops::{Deref, DerefMut};
 
 pub struct ShellBrocker(ChannelBroker);
 
 impl ShellBrocker {
-    pub(crate) fn open(channel: ChannelBroker, tv: TerminalSize) -> SshResult<Self> {
+    pub(crate) fn open(channel: ChannelBroker, pty: PtyConfig) -> SshResult<Self> {
         // to open a shell channel, we need to request a pesudo-terminal
         let mut channel_shell = ShellBrocker(channel);
-        channel_shell.request_pty(tv)?;
+        channel_shell.set_interactive(true)?;
+        channel_shell.request_pty(pty)?;
         channel_shell.get_shell()?;
         Ok(channel_shell)
     }
 
-    fn request_pty(&mut self, tv: TerminalSize) -> SshResult<()> {
-        let tvs = tv.fetch();
+    fn request_pty(&mut self, pty: PtyConfig) -> SshResult<()> {
         let mut data = Data::new();
         data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
             .put_u32(self.server_channel_no)
             .put_str(ssh_str::PTY_REQ)
-            .put_u8(true as u8)
-            .put_str(ssh_str::XTERM_VAR)
-            .put_u32(tvs.0)
-            .put_u32(tvs.1)
-            .put_u32(tvs.2)
-            .put_u32(tvs.3);
-        let model = [
-            128, // TTY_OP_ISPEED
-            0, 1, 0xc2, 0,   // 115200
-            129, // TTY_OP_OSPEED
-            0, 1, 0xc2, 0,    // 115200 again
-            0_u8, // TTY_OP_END
-        ];
-        data.put_u8s(&model);
+            .put_u8(true as u8);
+        pty.put(&mut data);
         self.send(data)
     }
 
@@ -59,12 +46,79 @@ impl ShellBrocker {
         Ok(out)
     }
 
+    /// same as [ShellBrocker::read], but append the data to `buf`,
+    /// so that its capacity can be reused across reads
+    ///
+    /// return the number of bytes appended
+    ///
+    pub fn read_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv()?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// same as [ShellBrocker::read], but never block,
+    /// None if no data has arrived yet
+    ///
+    /// an empty read tells that the channel is closed
+    ///
+    pub fn try_read(&mut self) -> SshResult<Option<Vec<u8>>> {
+        let mut out = match self.try_recv() {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(self.close.then(Vec::new)),
+            Err(_) if self.close => return Ok(Some(vec![])),
+            Err(e) => return Err(e),
+        };
+        while let Ok(Some(mut data)) = self.try_recv() {
+            out.append(&mut data)
+        }
+        Ok(Some(out))
+    }
+
+    /// take the stderr received so far without blocking,
+    /// the stdout received meanwhile is kept for the next [ShellBrocker::read]
+    ///
+    /// a shell with a pty usually gets its stderr as a part of the stdout
+    ///
+    pub fn read_stderr(&mut self) -> SshResult<Vec<u8>> {
+        Ok(self.0.read_stderr())
+    }
+
     /// this method send `buf` to the remote pty
     ///
     pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
         self.send_data(buf.to_vec().into())?;
         Ok(())
     }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.7>
+    ///
+    /// tell the server that the terminal is resized to `cols` x `rows` characters,
+    /// or `width_px` x `height_px` pixels, 0 if unknown
+    ///
+    /// the request wants no reply, and it's not about the channel window
+    ///
+    pub fn change_window_size(
+        &mut self,
+        cols: u32,
+        rows: u32,
+        width_px: u32,
+        height_px: u32,
+    ) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::WINDOW_CHANGE)
+            .put_u8(false as u8)
+            .put_u32(cols)
+            .put_u32(rows)
+            .put_u32(width_px)
+            .put_u32(height_px);
+        self.send_noreply(data)
+    }
 }
 
 impl Deref for ShellBrocker {
@@ -0,0 +1,88 @@
+use super::channel::ChannelBroker;
+use crate::error::SshResult;
+use std::{
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+/// A channel running a subsystem such as sftp,
+/// whose protocol is spoken over the raw data
+///
+pub struct SubsystemBroker {
+    channel: ChannelBroker,
+    name: String,
+}
+
+impl SubsystemBroker {
+    pub(crate) fn open(channel: ChannelBroker, name: &str) -> Self {
+        Self {
+            channel,
+            name: name.to_owned(),
+        }
+    }
+
+    /// the name of the subsystem
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// this method will try to read as much data as we can from the subsystem,
+    /// but it will block until at least one packet is received
+    ///
+    /// an empty result means the channel is closed
+    ///
+    pub fn read(&mut self) -> SshResult<Vec<u8>> {
+        let mut out = self.recv()?;
+        while let Ok(Some(mut data)) = self.try_recv() {
+            out.append(&mut data)
+        }
+        Ok(out)
+    }
+
+    /// same as [SubsystemBroker::read], but append the data to `buf`,
+    /// so that its capacity can be reused across reads
+    ///
+    /// return the number of bytes appended
+    ///
+    pub fn read_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv()?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// same as [SubsystemBroker::read_into], but [crate::SshError::TimeoutError]
+    /// if nothing arrives in `timeout`
+    ///
+    pub fn read_into_timeout(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv_timeout(timeout)?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// this method send `buf` to the subsystem
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.send_data(buf.to_vec().into())?;
+        Ok(())
+    }
+}
+
+impl Deref for SubsystemBroker {
+    type Target = ChannelBroker;
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
+
+impl DerefMut for SubsystemBroker {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.channel
+    }
+}
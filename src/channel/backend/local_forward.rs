@@ -0,0 +1,169 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use tracing::*;
+
+use super::channel::ChannelBroker;
+use crate::{constant::size, error::SshResult, model::Data, SessionBroker};
+
+/// how long the background threads sleep/block when there is nothing to do
+pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A running local port forward, created by [SessionBroker::local_forward]
+///
+/// Call [LocalForward::stop] or drop it to stop the forward
+///
+pub struct LocalForward {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LocalForward {
+    pub(crate) fn start(
+        listener: TcpListener,
+        opener: SessionBroker,
+        remote_host: String,
+        remote_port: u16,
+    ) -> SshResult<Self> {
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stop_flag = stop.clone();
+        let handle = thread::spawn(move || {
+            accept_loop(listener, opener, &remote_host, remote_port, &stop_flag)
+        });
+
+        info!("Local forward {} started", local_addr);
+        Ok(Self {
+            local_addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// the local address that the forward listens on
+    ///
+    /// useful when bound to port 0
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// close the listener and all the channels opened by this forward,
+    /// then consume the forward itself
+    ///
+    /// other forwards and the session are not affected
+    ///
+    pub fn stop(self) {
+        drop(self)
+    }
+
+    fn teardown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+            info!("Local forward {} stopped", self.local_addr);
+        }
+    }
+}
+
+impl Drop for LocalForward {
+    fn drop(&mut self) {
+        self.teardown()
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    mut opener: SessionBroker,
+    remote_host: &str,
+    remote_port: u16,
+    stop: &Arc<AtomicBool>,
+) {
+    let mut connections: Vec<JoinHandle<()>> = vec![];
+    while !stop.load(Ordering::Relaxed) {
+        // forget about the finished connections
+        connections.retain(|c| !c.is_finished());
+
+        match listener.accept() {
+            Ok((socket, peer)) => {
+                debug!("Local forward accepted {}", peer);
+                // a server that never answers must not hold up the stop
+                match opener.open_direct_tcpip_from(remote_host, remote_port, peer, Some(stop)) {
+                    Ok(channel) => {
+                        let stop = stop.clone();
+                        connections.push(thread::spawn(move || {
+                            if let Err(e) = pump(channel, socket, &stop) {
+                                debug!("Local forward connection {} ended: {:?}", peer, e);
+                            }
+                        }));
+                    }
+                    Err(e) => error!(
+                        "Fail to open direct-tcpip channel to {}:{}: {:?}",
+                        remote_host, remote_port, e
+                    ),
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                error!("Local forward stops accepting: {:?}", e);
+                break;
+            }
+        }
+    }
+
+    // stop listening before tearing down the connections
+    drop(listener);
+    stop.store(true, Ordering::Relaxed);
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+/// copy bytes between the socket and the channel until the channel closes,
+/// or the forward is stopped
+///
+/// the end of the socket is passed on as SSH_MSG_CHANNEL_EOF,
+/// the answer of the target is still copied back after it
+///
+/// the channel is closed when it's dropped
+pub(super) fn pump(
+    mut channel: ChannelBroker,
+    mut socket: TcpStream,
+    stop: &AtomicBool,
+) -> SshResult<()> {
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut buf = vec![0; size::BUF_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        if channel.is_eof_sent() {
+            thread::sleep(POLL_INTERVAL);
+        } else {
+            match socket.read(&mut buf) {
+                Ok(0) => channel.send_eof()?,
+                Ok(n) => channel.send_data(Data::from(&buf[..n]))?,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        while let Some(data) = channel.try_recv()? {
+            socket.write_all(&data)?;
+        }
+        if channel.close {
+            return Ok(());
+        }
+    }
+    let _ = socket.shutdown(Shutdown::Both);
+    Ok(())
+}
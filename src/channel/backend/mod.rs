@@ -1,11 +1,19 @@
 mod channel;
+mod channel_direct_tcpip;
 mod channel_exec;
 mod channel_shell;
+mod channel_subsystem;
+mod local_forward;
+mod remote_forward;
 
 pub(crate) use channel::Channel;
 pub use channel::ChannelBroker;
+pub use channel_direct_tcpip::{DirectTcpipBroker, DirectTcpipStream};
 pub use channel_exec::ExecBroker;
 pub use channel_shell::ShellBrocker;
+pub use channel_subsystem::SubsystemBroker;
+pub use local_forward::LocalForward;
+pub use remote_forward::RemoteForward;
 
 #[cfg(feature = "scp")]
 mod channel_scp;
@@ -0,0 +1,140 @@
+use std::{
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use tracing::*;
+
+use super::local_forward::{pump, POLL_INTERVAL};
+use crate::{model::ForwardedChannel, SessionBroker};
+
+/// A running remote port forward, created by [SessionBroker::remote_forward]
+///
+/// Call [RemoteForward::stop] or drop it to stop the forward
+///
+pub struct RemoteForward {
+    bind_addr: String,
+    remote_port: u16,
+    // sends cancel-tcpip-forward on teardown
+    canceller: SessionBroker,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RemoteForward {
+    pub(crate) fn start(
+        listener: Receiver<ForwardedChannel>,
+        opener: SessionBroker,
+        bind_addr: String,
+        remote_port: u16,
+        target: Vec<SocketAddr>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let canceller = opener.opener();
+
+        let stop_flag = stop.clone();
+        let handle = thread::spawn(move || accept_loop(listener, opener, &target, &stop_flag));
+
+        info!("Remote forward {}:{} started", bind_addr, remote_port);
+        Self {
+            bind_addr,
+            remote_port,
+            canceller,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// the port that the server listens on
+    ///
+    /// useful when bound to port 0
+    ///
+    pub fn remote_port(&self) -> u16 {
+        self.remote_port
+    }
+
+    /// ask the server to stop listening, close all the channels forwarded by it,
+    /// then consume the forward itself
+    ///
+    /// other forwards and the session are not affected
+    ///
+    pub fn stop(self) {
+        drop(self)
+    }
+
+    fn teardown(&mut self) {
+        if let Err(e) = self
+            .canceller
+            .cancel_tcpip_forward(&self.bind_addr, self.remote_port)
+        {
+            warn!(
+                "Fail to cancel the remote forward {}:{}: {:?}",
+                self.bind_addr, self.remote_port, e
+            );
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+            info!(
+                "Remote forward {}:{} stopped",
+                self.bind_addr, self.remote_port
+            );
+        }
+    }
+}
+
+impl Drop for RemoteForward {
+    fn drop(&mut self) {
+        self.teardown()
+    }
+}
+
+fn accept_loop(
+    listener: Receiver<ForwardedChannel>,
+    opener: SessionBroker,
+    target: &[SocketAddr],
+    stop: &Arc<AtomicBool>,
+) {
+    let mut connections: Vec<JoinHandle<()>> = vec![];
+    while !stop.load(Ordering::Relaxed) {
+        // forget about the finished connections
+        connections.retain(|c| !c.is_finished());
+
+        match listener.recv_timeout(POLL_INTERVAL) {
+            Ok(forwarded) => {
+                let peer = forwarded.originator.clone();
+                debug!("Remote forward accepted {:?}", peer);
+                // the channel is closed if it's dropped here
+                let channel = opener.forwarded_channel(forwarded);
+                match TcpStream::connect(target) {
+                    Ok(socket) => {
+                        let stop = stop.clone();
+                        connections.push(thread::spawn(move || {
+                            if let Err(e) = pump(channel, socket, &stop) {
+                                debug!("Remote forward connection {:?} ended: {:?}", peer, e);
+                            }
+                        }));
+                    }
+                    Err(e) => error!("Fail to connect to {:?}: {:?}", target, e),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                debug!("Remote forward stops, the session is gone");
+                break;
+            }
+        }
+    }
+
+    // no more channels before tearing down the connections
+    drop(listener);
+    stop.store(true, Ordering::Relaxed);
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
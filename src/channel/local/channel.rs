@@ -1,23 +1,33 @@
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
 
 use crate::{
     algorithm::Digest,
     client::Client,
     config::algorithm::AlgList,
-    constant::ssh_connection_code,
+    config::env::EnvVar,
+    constant::{ssh_channel_fail_code, ssh_connection_code, ssh_extended_data_code, ssh_str},
     error::{SshError, SshResult},
-    model::{Data, FlowControl, Packet, RcMut, SecPacket},
+    model::{Data, FlowControl, Packet, RcMut, SecPacket, Timeout},
+};
+use crate::{
+    constant::ssh_transport_code,
+    model::{PtyConfig, TerminalSize},
 };
-use crate::{constant::ssh_transport_code, model::TerminalSize};
 use tracing::*;
 
 #[cfg(feature = "scp")]
 use super::ChannelScp;
-use super::{ChannelExec, ChannelShell};
+use super::{ChannelExec, ChannelShell, ChannelSubsystem};
 
 pub(super) enum ChannelRead {
     Data(Vec<u8>),
+    #[allow(dead_code)]
     Code(u8),
+    // the reply to the request sent by [Channel::request]
+    Reply(bool),
 }
 
 pub struct Channel<S>
@@ -27,12 +37,20 @@ where
     pub(crate) server_channel_no: u32,
     pub(crate) client_channel_no: u32,
     pub(crate) remote_close: bool,
+    on_remote_close: Option<Box<dyn FnOnce()>>,
     pub(crate) local_close: bool,
     pub(crate) flow_control: FlowControl,
+    pub(crate) auto_window_adjust: bool,
     pub(crate) client: RcMut<Client>,
     pub(crate) stream: RcMut<S>,
-    pub(crate) exit_status: u32,
+    pub(crate) exit_status: Option<u32>,
+    pub(crate) exit_signal: Option<String>,
     pub(crate) terminate_msg: String,
+    pub(crate) stderr: Vec<u8>,
+    // the data read by [Channel::read_stderr], returned by the next read
+    pending_stdout: Vec<u8>,
+    // whether a request is waiting for its SSH_MSG_CHANNEL_SUCCESS/FAILURE
+    want_reply: bool,
 }
 
 impl<S> Channel<S>
@@ -42,23 +60,44 @@ where
     pub(crate) fn new(
         server_channel_no: u32,
         client_channel_no: u32,
+        local_window: u32,
         remote_window: u32,
         client: RcMut<Client>,
         stream: RcMut<S>,
     ) -> Self {
+        let adjust_threshold = client.borrow().get_window_adjust_threshold();
         Self {
             server_channel_no,
             client_channel_no,
             remote_close: false,
+            on_remote_close: None,
             local_close: false,
-            flow_control: FlowControl::new(remote_window),
+            flow_control: FlowControl::new(local_window, remote_window, adjust_threshold),
+            auto_window_adjust: true,
             client,
             stream,
-            exit_status: 0,
+            exit_status: None,
+            exit_signal: None,
             terminate_msg: "".to_owned(),
+            stderr: vec![],
+            pending_stdout: vec![],
+            want_reply: false,
         }
     }
 
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variable `name=value` to the command or the shell
+    /// that the channel is converted to later
+    ///
+    /// no reply is asked for, since the servers commonly ignore
+    /// the ones not in their `AcceptEnv`
+    ///
+    pub fn set_env(mut self, name: &str, value: &str) -> SshResult<Self> {
+        self.request_env(name, value)?;
+        Ok(self)
+    }
+
     /// convert the raw channel to an [self::ChannelExec]
     ///
     pub fn exec(self) -> SshResult<ChannelExec<S>> {
@@ -79,8 +118,34 @@ where
     /// with `row` lines & `column` characters per one line
     ///
     pub fn shell(self, tv: TerminalSize) -> SshResult<ChannelShell<S>> {
+        self.shell_with_pty(tv.into())
+    }
+
+    /// same as [Channel::shell], with the terminal type & dimensions in `pty`
+    ///
+    pub fn shell_with_pty(self, pty: PtyConfig) -> SshResult<ChannelShell<S>> {
         info!("shell opened.");
-        ChannelShell::open(self, tv)
+        ChannelShell::open(self, pty)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.5>
+    ///
+    /// convert the raw channel to an [self::ChannelSubsystem] running `name`
+    ///
+    /// [SshError::SubsystemRefused] if the server refuses it
+    ///
+    pub fn subsystem(mut self, name: &str) -> SshResult<ChannelSubsystem<S>> {
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SUBSYSTEM)
+            .put_u8(true as u8)
+            .put_str(name);
+        if !self.request(data)? {
+            return Err(SshError::SubsystemRefused(name.to_owned()));
+        }
+        info!("subsystem {} opened.", name);
+        Ok(ChannelSubsystem::open(self, name))
     }
 
     /// close the channel gracefully, but do not consume it
@@ -91,12 +156,47 @@ where
         self.receive_close()
     }
 
+    /// same as [Channel::close], but wait at most `timeout` for the server's close,
+    /// and the exit status before it
+    ///
+    /// [SshError::TimeoutError] if the server has not closed the channel in time
+    ///
+    pub fn close_with_timeout(&mut self, timeout: Duration) -> SshResult<()> {
+        info!("channel close within {:?}.", timeout);
+        self.send_close()?;
+        let mut timeout = Timeout::new(Some(timeout));
+        while !self.remote_close {
+            let data = match SecPacket::try_from_stream(
+                &mut *self.stream.borrow_mut(),
+                &mut self.client.borrow_mut(),
+            )? {
+                Some(pkt) => Data::unpack(pkt)?,
+                None => {
+                    timeout.till_next_tick()?;
+                    continue;
+                }
+            };
+            self.handle_msg(data)?;
+        }
+        Ok(())
+    }
+
+    /// send the close without waiting for the server's
+    ///
+    /// the messages the server sends later to this channel are dropped
+    ///
+    pub fn close_nowait(&mut self) -> SshResult<()> {
+        info!("channel close without waiting.");
+        self.send_close()
+    }
+
     /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
     ///
-    /// Return the command execute status
+    /// Return the command execute status, None if the server has not sent one (yet),
+    /// e.g. the command is killed by a signal
     ///
-    pub fn exit_status(&self) -> SshResult<u32> {
-        Ok(self.exit_status)
+    pub fn exit_status(&self) -> Option<u32> {
+        self.exit_status
     }
 
     /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
@@ -107,6 +207,77 @@ where
         Ok(self.terminate_msg.clone())
     }
 
+    /// Enable (the default) or disable adjusting the window automatically
+    /// when receiving data
+    ///
+    /// When disabled, use [Channel::adjust_window] to allow the server to send more.
+    /// The data exceeding the window are dropped
+    ///
+    pub fn set_auto_window_adjust(&mut self, enable: bool) {
+        self.auto_window_adjust = enable
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/rfc4254#section-5.2>
+    ///
+    /// Send SSH_MSG_CHANNEL_WINDOW_ADJUST to allow the server to send `bytes` more
+    ///
+    pub fn adjust_window(&mut self, bytes: u32) -> SshResult<()> {
+        self.send_window_adjust(bytes)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variables, one request for each,
+    /// and apply their policies to the replies
+    pub(crate) fn send_env(&mut self, env: &[EnvVar]) -> SshResult<()> {
+        for var in env {
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+                .put_u32(self.server_channel_no)
+                .put_str(ssh_str::ENV)
+                .put_u8(true as u8)
+                .put_str(&var.name)
+                .put_str(&var.value);
+            let accepted = self.request(data)?;
+            var.check(accepted)?;
+        }
+        Ok(())
+    }
+
+    /// an env request with want_reply unset
+    pub(super) fn request_env(&mut self, name: &str, value: &str) -> SshResult<()> {
+        debug!("Set env {}", name);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::ENV)
+            .put_u8(false as u8)
+            .put_str(name)
+            .put_str(value);
+        self.send(data)
+    }
+
+    /// send a channel request whose want_reply is set, and wait for the reply
+    fn request(&mut self, data: Data) -> SshResult<bool> {
+        self.send(data)?;
+        self.want_reply = true;
+        let result = loop {
+            if self.remote_close {
+                break Err(SshError::GeneralError(
+                    "The channel is closed before the reply".to_owned(),
+                ));
+            }
+            match self.recv_once() {
+                Ok(ChannelRead::Reply(accepted)) => break Ok(accepted),
+                Ok(ChannelRead::Data(mut data)) => self.pending_stdout.append(&mut data),
+                Ok(ChannelRead::Code(_)) => (),
+                Err(e) => break Err(e),
+            }
+        };
+        self.want_reply = false;
+        result
+    }
+
     fn send_close(&mut self) -> SshResult<()> {
         if self.local_close {
             return Ok(());
@@ -167,9 +338,19 @@ where
         Ok(maybe_response)
     }
 
+    /// same as [Channel::send_data], but keep the data received meanwhile for the next read
+    pub(super) fn write_data(&mut self, buf: Vec<u8>) -> SshResult<()> {
+        let mut received = self.send_data(buf)?;
+        self.pending_stdout.append(&mut received);
+        Ok(())
+    }
+
     /// this method will receive at least one data packet
     ///
     pub(super) fn recv(&mut self) -> SshResult<Vec<u8>> {
+        if !self.pending_stdout.is_empty() {
+            return Ok(std::mem::take(&mut self.pending_stdout));
+        }
         while !self.closed() {
             let maybe_recv = self.recv_once()?;
 
@@ -180,6 +361,30 @@ where
         Ok(vec![])
     }
 
+    /// same as [Channel::recv], but [SshError::TimeoutError] if nothing arrives in `timeout`
+    pub(super) fn recv_timeout(&mut self, timeout: Duration) -> SshResult<Vec<u8>> {
+        if !self.pending_stdout.is_empty() {
+            return Ok(std::mem::take(&mut self.pending_stdout));
+        }
+        let mut timeout = Timeout::new(Some(timeout));
+        while !self.closed() {
+            let data = match SecPacket::try_from_stream(
+                &mut *self.stream.borrow_mut(),
+                &mut self.client.borrow_mut(),
+            )? {
+                Some(pkt) => Data::unpack(pkt)?,
+                None => {
+                    timeout.till_next_tick()?;
+                    continue;
+                }
+            };
+            if let ChannelRead::Data(d) = self.handle_msg(data)? {
+                return Ok(d);
+            }
+        }
+        Ok(vec![])
+    }
+
     pub(super) fn recv_to_end(&mut self) -> SshResult<Vec<u8>> {
         let mut resp = vec![];
         while !self.closed() {
@@ -190,20 +395,44 @@ where
     }
 
     pub(super) fn try_recv(&mut self) -> SshResult<Option<Vec<u8>>> {
-        let data = {
-            match SecPacket::try_from_stream(
+        if !self.pending_stdout.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.pending_stdout)));
+        }
+        // go on over the packets without data, such as the window adjusts,
+        // so that the data behind them is not left in the socket
+        while !self.closed() {
+            let data = match SecPacket::try_from_stream(
                 &mut *self.stream.borrow_mut(),
                 &mut self.client.borrow_mut(),
             )? {
                 Some(pkt) => Data::unpack(pkt)?,
-                None => return Ok(None),
+                None => break,
+            };
+            if let ChannelRead::Data(d) = self.handle_msg(data)? {
+                return Ok(Some(d));
             }
-        };
-        if let ChannelRead::Data(d) = self.handle_msg(data)? {
-            Ok(Some(d))
-        } else {
-            Ok(None)
         }
+        Ok(None)
+    }
+
+    /// take the stderr received so far, after handling the packets
+    /// that have already arrived without blocking
+    ///
+    /// the stdout among them is kept for the next read
+    pub(super) fn read_stderr(&mut self) -> SshResult<Vec<u8>> {
+        while !self.closed() {
+            let data = match SecPacket::try_from_stream(
+                &mut *self.stream.borrow_mut(),
+                &mut self.client.borrow_mut(),
+            )? {
+                Some(pkt) => Data::unpack(pkt)?,
+                None => break,
+            };
+            if let ChannelRead::Data(mut d) = self.handle_msg(data)? {
+                self.pending_stdout.append(&mut d);
+            }
+        }
+        Ok(std::mem::take(&mut self.stderr))
     }
 
     fn recv_once(&mut self) -> SshResult<ChannelRead> {
@@ -233,10 +462,19 @@ where
                 let cc = data.get_u32();
                 if cc == self.client_channel_no {
                     let mut data = data.get_u8s();
+                    // an empty packet is not the end of the data
+                    if data.is_empty() {
+                        debug!("Recv an empty data packet");
+                        return Ok(ChannelRead::Code(x));
+                    }
 
                     // flow_control
                     self.flow_control.tune_on_recv(&mut data);
-                    self.send_window_adjust(data.len() as u32)?;
+                    if self.auto_window_adjust {
+                        if let Some(to_add) = self.flow_control.consume(data.len() as u32) {
+                            self.send_window_adjust(to_add)?;
+                        }
+                    }
 
                     return Ok(ChannelRead::Data(data));
                 }
@@ -252,23 +490,53 @@ where
 
                     // flow_contrl
                     self.flow_control.tune_on_recv(&mut data);
-                    self.send_window_adjust(data.len() as u32)?;
+                    if self.auto_window_adjust {
+                        if let Some(to_add) = self.flow_control.consume(data.len() as u32) {
+                            self.send_window_adjust(to_add)?;
+                        }
+                    }
 
-                    return Ok(ChannelRead::Data(data));
+                    // only stderr is defined, the other types are dropped
+                    if data_type_code == ssh_extended_data_code::STDERR {
+                        self.stderr.append(&mut data);
+                    }
                 }
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_connection_code::GLOBAL_REQUEST => {
+                data.get_u8s();
+                if data.get_u8() != 0 {
+                    let mut data = Data::new();
+                    data.put_u8(ssh_connection_code::REQUEST_FAILURE);
+                    self.send(data)?;
+                }
+                Ok(ChannelRead::Code(x))
+            }
+            // the remote forwards only work with the backend session
+            x @ ssh_connection_code::CHANNEL_OPEN => {
+                data.get_u8s();
+                let server_id = data.get_u32();
                 let mut data = Data::new();
-                data.put_u8(ssh_connection_code::REQUEST_FAILURE);
+                data.put_u8(ssh_connection_code::CHANNEL_OPEN_FAILURE)
+                    .put_u32(server_id)
+                    .put_u32(ssh_channel_fail_code::ADMINISTRATIVELY_PROHIBITED)
+                    .put_str("channels from the server are not accepted")
+                    .put_str("");
                 self.send(data)?;
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_connection_code::CHANNEL_WINDOW_ADJUST => {
-                data.get_u32();
+                let cc = data.get_u32();
                 // to add
                 let rws = data.get_u32();
-                self.recv_window_adjust(rws)?;
+                if cc == self.client_channel_no {
+                    self.recv_window_adjust(rws)?;
+                } else {
+                    warn!(
+                        "Drop the window adjust of {} for unknown channel {}",
+                        rws, cc
+                    );
+                }
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_connection_code::CHANNEL_EOF => {
@@ -296,16 +564,28 @@ where
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_connection_code::CHANNEL_SUCCESS => {
+                if self.want_reply && data.get_u32() == self.client_channel_no {
+                    return Ok(ChannelRead::Reply(true));
+                }
                 debug!("Currently ignore message {}", x);
                 Ok(ChannelRead::Code(x))
             }
             ssh_connection_code::CHANNEL_FAILURE => {
-                Err(SshError::GeneralError("channel failure.".to_owned()))
+                if self.want_reply && data.get_u32() == self.client_channel_no {
+                    return Ok(ChannelRead::Reply(false));
+                }
+                // keep the stderr which may explain the failure
+                Err(SshError::ChannelFailure {
+                    stderr: std::mem::take(&mut self.stderr),
+                })
             }
             x @ ssh_connection_code::CHANNEL_CLOSE => {
                 let cc = data.get_u32();
                 if cc == self.client_channel_no {
                     self.remote_close = true;
+                    if let Some(callback) = self.on_remote_close.take() {
+                        callback();
+                    }
                     self.send_close()?;
                 }
                 Ok(ChannelRead::Code(x))
@@ -320,7 +600,7 @@ where
     fn handle_exit_status(&mut self, data: &mut Data) -> SshResult<()> {
         let maybe_false = data.get_u8();
         if maybe_false == 0 {
-            self.exit_status = data.get_u32()
+            self.exit_status = Some(data.get_u32())
         }
         Ok(())
     }
@@ -330,6 +610,7 @@ where
         if maybe_false == 0 {
             let sig_name = String::from_utf8(data.get_u8s())?;
             self.terminate_msg += &format!("Current request is terminated by signal: {sig_name}\n");
+            self.exit_signal = Some(sig_name);
             let coredumped = data.get_u8();
             self.terminate_msg += &format!("Coredumped: {}\n", {
                 if coredumped == 0 {
@@ -358,6 +639,47 @@ where
         Ok(())
     }
 
+    /// whether the server has closed the channel,
+    /// which is only known when the SSH_MSG_CHANNEL_CLOSE is read by this channel
+    ///
+    pub fn is_remote_closed(&self) -> bool {
+        self.remote_close
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.9>
+    ///
+    /// deliver the signal `sig` to the remote command or shell,
+    /// named without the "SIG" prefix, e.g. "INT", "TERM" or "KILL"
+    ///
+    /// a command killed by it is told by [ChannelExec::exit_signal] later,
+    /// the servers which do not support signals ignore it silently
+    ///
+    pub fn send_signal(&mut self, sig: &str) -> SshResult<()> {
+        let sig = sig.strip_prefix("SIG").unwrap_or(sig);
+        debug!("Send signal {}", sig);
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SIGNAL)
+            .put_u8(false as u8)
+            .put_str(sig);
+        self.send(data)
+    }
+
+    /// call `callback` once the server closes the channel,
+    /// or right now if it already has
+    ///
+    pub fn on_remote_close<F>(&mut self, callback: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        if self.remote_close {
+            callback()
+        } else {
+            self.on_remote_close = Some(Box::new(callback));
+        }
+    }
+
     /// Return if the channel is closed
     ///
     pub fn closed(&self) -> bool {
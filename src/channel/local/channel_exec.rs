@@ -1,6 +1,6 @@
 use super::channel::Channel;
 use crate::error::SshResult;
-use crate::model::Data;
+use crate::model::{Data, ExecOutput};
 use crate::{
     constant::{ssh_connection_code, ssh_str},
     SshError,
@@ -26,6 +26,24 @@ where
         }
     }
 
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// pass the environment variable `name=value` to the command,
+    /// which must be done before the command is sent
+    ///
+    /// no reply is asked for, since the servers commonly ignore
+    /// the ones not in their `AcceptEnv`
+    ///
+    pub fn set_env(mut self, name: &str, value: &str) -> SshResult<Self> {
+        if self.command_send {
+            return Err(SshError::GeneralError(
+                "The env must be set before the command".to_owned(),
+            ));
+        }
+        self.channel.request_env(name, value)?;
+        Ok(self)
+    }
+
     /// Send an executable command to the server
     ///
     pub fn exec_command(&mut self, command: &str) -> SshResult<()> {
@@ -64,6 +82,52 @@ where
 
         self.get_output()
     }
+
+    /// Wait until the server closes the channel,
+    /// then return the output together with the exit status of the command
+    ///
+    /// This method also implicitly consume the channel object
+    ///
+    pub fn wait(mut self) -> SshResult<ExecOutput> {
+        if !self.command_send {
+            return Err(SshError::GeneralError(
+                "No command has been sent to wait for".to_owned(),
+            ));
+        }
+
+        let output = self.get_output()?;
+        Ok(ExecOutput {
+            output,
+            stderr: std::mem::take(&mut self.channel.stderr),
+            exit_status: self.channel.exit_status,
+            exit_signal: self.channel.exit_signal.clone(),
+            terminate_msg: self.terminate_msg.clone(),
+        })
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
+    ///
+    /// the exit status of the command, None if the server has not sent one (yet)
+    ///
+    pub fn exit_status(&self) -> Option<u32> {
+        self.channel.exit_status
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/rfc4254#section-6.10>
+    ///
+    /// the name of the signal (without the "SIG" prefix) which killed the command,
+    /// None if the server has not sent one (yet)
+    ///
+    pub fn exit_signal(&self) -> Option<String> {
+        self.channel.exit_signal.clone()
+    }
+
+    /// what the command has written to stderr so far,
+    /// which is not a part of the output
+    ///
+    pub fn stderr(&self) -> &[u8] {
+        &self.channel.stderr
+    }
 }
 
 impl<S> Deref for ChannelExec<S>
@@ -184,17 +184,23 @@ where
         self.send_bytes(cmd.as_bytes())?;
         self.get_end()?;
 
-        let mut count = 0;
-        loop {
-            let mut s = [0u8; size::BUF_SIZE];
-            let i = file.read(&mut s)?;
-            count += i;
-            self.send_bytes(&s[..i])?;
-            if count == scp_file.size as usize {
-                self.send_bytes(&[0])?;
-                break;
+        // stream the file chunk by chunk,
+        // sending a chunk blocks until the remote window allows it
+        let mut remain = scp_file.size;
+        let mut chunk = [0u8; size::BUF_SIZE];
+        while remain > 0 {
+            let want = chunk.len().min(remain as usize);
+            let i = file.read(&mut chunk[..want])?;
+            if i == 0 {
+                return Err(SshError::ScpError(format!(
+                    "file {} is truncated while uploading",
+                    scp_file.name
+                )));
             }
+            remain -= i as u64;
+            self.send_bytes(&chunk[..i])?;
         }
+        self.send_end()?;
         self.get_end()?;
 
         debug!("file: [{}] upload completed.", scp_file.name);
@@ -387,7 +393,6 @@ where
             fs::remove_file(path.as_path())?;
         }
         let mut file = match OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(path.as_path())
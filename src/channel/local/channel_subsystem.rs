@@ -0,0 +1,99 @@
+use super::channel::Channel;
+use crate::error::SshResult;
+use std::{
+    io::{Read, Write},
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+/// A channel running a subsystem such as sftp,
+/// whose protocol is spoken over the raw data
+///
+pub struct ChannelSubsystem<S: Read + Write> {
+    channel: Channel<S>,
+    name: String,
+}
+
+impl<S> ChannelSubsystem<S>
+where
+    S: Read + Write,
+{
+    pub(crate) fn open(channel: Channel<S>, name: &str) -> Self {
+        Self {
+            channel,
+            name: name.to_owned(),
+        }
+    }
+
+    /// the name of the subsystem
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// this method will try to read as much data as we can from the subsystem,
+    /// but it will block until at least one packet is received
+    ///
+    /// an empty result means the channel is closed
+    ///
+    pub fn read(&mut self) -> SshResult<Vec<u8>> {
+        let mut out = self.recv()?;
+        while let Ok(Some(mut data)) = self.try_recv() {
+            out.append(&mut data)
+        }
+        Ok(out)
+    }
+
+    /// same as [ChannelSubsystem::read], but append the data to `buf`,
+    /// so that its capacity can be reused across reads
+    ///
+    /// return the number of bytes appended
+    ///
+    pub fn read_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv()?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// same as [ChannelSubsystem::read_into], but [crate::SshError::TimeoutError]
+    /// if nothing arrives in `timeout`
+    ///
+    pub fn read_into_timeout(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> SshResult<usize> {
+        let len = buf.len();
+        buf.extend_from_slice(&self.recv_timeout(timeout)?);
+        while let Ok(Some(data)) = self.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        Ok(buf.len() - len)
+    }
+
+    /// this method send `buf` to the subsystem
+    ///
+    /// the data received while waiting for the window is kept for the next read
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.write_data(buf.to_vec())
+    }
+}
+
+impl<S> Deref for ChannelSubsystem<S>
+where
+    S: Read + Write,
+{
+    type Target = Channel<S>;
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
+
+impl<S> DerefMut for ChannelSubsystem<S>
+where
+    S: Read + Write,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.channel
+    }
+}
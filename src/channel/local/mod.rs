@@ -1,10 +1,12 @@
 mod channel;
 mod channel_exec;
 mod channel_shell;
+mod channel_subsystem;
 
 pub use channel::Channel;
 pub use channel_exec::ChannelExec;
 pub use channel_shell::ChannelShell;
+pub use channel_subsystem::ChannelSubsystem;
 
 #[cfg(feature = "scp")]
 mod channel_scp;
@@ -1,12 +1,21 @@
 mod backend;
 mod local;
 
+#[cfg(feature = "async")]
+pub(crate) mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncExec, AsyncShell};
+
 pub(crate) use backend::Channel as BackendChannel;
-pub use backend::{ChannelBroker, ExecBroker, ShellBrocker};
+pub use backend::{
+    ChannelBroker, DirectTcpipBroker, DirectTcpipStream, ExecBroker, LocalForward, RemoteForward,
+    ShellBrocker, SubsystemBroker,
+};
 
 pub use local::Channel as LocalChannel;
 pub use local::ChannelExec as LocalExec;
 pub use local::ChannelShell as LocalShell;
+pub use local::ChannelSubsystem as LocalSubsystem;
 
 #[cfg(feature = "scp")]
 pub use backend::ScpBroker;
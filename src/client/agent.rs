@@ -0,0 +1,103 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+use tracing::*;
+
+use crate::{
+    constant::ssh_agent_code,
+    error::{SshError, SshResult},
+    model::Data,
+};
+
+#[cfg(unix)]
+type AgentStream = std::os::unix::net::UnixStream;
+// the named pipe of the windows agent is opened as a file
+#[cfg(not(unix))]
+type AgentStream = std::fs::File;
+
+/// where the agent of openssh for windows listens
+#[cfg(windows)]
+const WINDOWS_AGENT_PIPE: &str = r"\\.\pipe\openssh-ssh-agent";
+
+/// the agent socket told by `$SSH_AUTH_SOCK`,
+/// or the named pipe of openssh on windows
+pub(crate) fn default_path() -> Option<PathBuf> {
+    match std::env::var_os("SSH_AUTH_SOCK") {
+        Some(path) if !path.is_empty() => Some(path.into()),
+        #[cfg(windows)]
+        _ => Some(WINDOWS_AGENT_PIPE.into()),
+        #[cfg(not(windows))]
+        _ => None,
+    }
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-miller-ssh-agent>
+///
+/// A client of ssh-agent, which keeps the private keys in its own process
+pub(crate) struct Agent {
+    stream: AgentStream,
+}
+
+impl Agent {
+    pub fn connect(path: &Path) -> SshResult<Self> {
+        #[cfg(unix)]
+        let stream = AgentStream::connect(path)?;
+        #[cfg(not(unix))]
+        let stream = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        debug!("connected to the agent at {:?}", path);
+        Ok(Self { stream })
+    }
+
+    /// the key blobs of all the identities in the agent
+    pub fn identities(&mut self) -> SshResult<Vec<Vec<u8>>> {
+        let mut data = Data::new();
+        data.put_u8(ssh_agent_code::REQUEST_IDENTITIES);
+        let mut reply = self.request(data, ssh_agent_code::IDENTITIES_ANSWER)?;
+        let identities = (0..reply.get_u32())
+            .map(|_| {
+                let blob = reply.get_u8s();
+                let comment = reply.get_u8s();
+                debug!("agent identity {}", String::from_utf8_lossy(&comment));
+                blob
+            })
+            .collect();
+        Ok(identities)
+    }
+
+    /// the ssh encoded signature of `data` by the key of `blob`
+    pub fn sign(&mut self, blob: &[u8], data: &[u8], flags: u32) -> SshResult<Vec<u8>> {
+        let mut request = Data::new();
+        request
+            .put_u8(ssh_agent_code::SIGN_REQUEST)
+            .put_u8s(blob)
+            .put_u8s(data)
+            .put_u32(flags);
+        let mut reply = self.request(request, ssh_agent_code::SIGN_RESPONSE)?;
+        Ok(reply.get_u8s())
+    }
+
+    fn request(&mut self, data: Data, expected: u8) -> SshResult<Data> {
+        let mut request = Data::new();
+        request.put_u8s(&data);
+        self.stream.write_all(&request)?;
+
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let mut reply = vec![0; u32::from_be_bytes(len) as usize];
+        self.stream.read_exact(&mut reply)?;
+        let mut reply = Data::from(reply);
+        match reply.get_u8() {
+            code if code == expected => Ok(reply),
+            ssh_agent_code::FAILURE => Err(SshError::AgentError(
+                "the agent replies SSH_AGENT_FAILURE".to_owned(),
+            )),
+            code => Err(SshError::AgentError(format!(
+                "unexpected agent message {code}"
+            ))),
+        }
+    }
+}
@@ -1,30 +1,107 @@
-use crate::{
-    algorithm::compression::{CompressNone, Compression},
-    config::algorithm::AlgList,
-};
+#[cfg(feature = "dangerous-key-export")]
+use crate::model::SessionKeys;
 use crate::{algorithm::encryption::Encryption, config::Config};
 use crate::{algorithm::encryption::EncryptionNone, model::Sequence};
-use std::time::Duration;
+use crate::{
+    algorithm::{
+        compression::{CompressNone, Compression},
+        Enc,
+    },
+    config::{algorithm::AlgList, env::EnvVar, window::WindowSizes},
+};
+use crate::{
+    constant::ssh_transport_code,
+    error::SshResult,
+    model::{
+        ArcMut, CompressionCounter, Data, DisconnectReason, KexDetails, NegotiatedAlgorithms,
+        Packet,
+    },
+};
+use rand::{rngs::OsRng, rngs::StdRng, RngCore, SeedableRng};
+use std::{
+    io::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::*;
 
 // the underlay connection
 pub(crate) struct Client {
     pub(super) sequence: Sequence,
     pub(super) config: Config,
     pub(super) negotiated: AlgList,
+    // the encryption of the client to server direction
     pub(super) encryptor: Box<dyn Encryption>,
+    // the encryption of the server to client direction
+    pub(super) decryptor: Box<dyn Encryption>,
     pub(super) compressor: Box<dyn Compression>,
+    compression_counter: Arc<CompressionCounter>,
     pub(super) session_id: Vec<u8>,
+    // the host key algorithms offered by the server in the initial key exchange
+    pub(super) offered_host_key_algs: Vec<String>,
+    // whether both sides agree on the strict key exchange in the initial key exchange
+    pub(super) strict_kex: bool,
+    // the extensions in the server's SSH_MSG_EXT_INFO
+    pub(super) server_extensions: Vec<(String, Vec<u8>)>,
+    // the messages of the server's SSH_MSG_USERAUTH_BANNER during the authentication
+    pub(super) banner: Option<String>,
+    // the server host key blob of the last key exchange, without its length
+    pub(super) server_host_key: Vec<u8>,
+    // the key derivation parameters of the last key exchange
+    pub(super) kex_details: KexDetails,
+    // the names of the algorithms negotiated in the last key exchange,
+    // shared with the session broker
+    negotiated_algorithms: ArcMut<NegotiatedAlgorithms>,
+    // the payload of our KEXINIT which is sent before the server's
+    pub(super) kexinit_sent: Option<Vec<u8>>,
+    // the messages held back meanwhile, sent once the keys are renewed
+    pub(super) deferred: Vec<Data>,
+    // when the last key exchange is finished
+    pub(super) last_kex: Instant,
+    // the bytes on the wire in both directions since the last key exchange
+    pub(super) kex_bytes: u64,
+    // the last time a packet is sent or received
+    last_activity: Instant,
+    // the source of all the randomness, seeded by [Config::rng_seed]
+    rng: Box<dyn RngCore + Send>,
+    // the keys derived in the last key exchange, shared with the session broker
+    #[cfg(feature = "dangerous-key-export")]
+    pub(super) session_keys: ArcMut<Option<SessionKeys>>,
 }
 
 impl Client {
     pub fn new(config: Config) -> Self {
+        let rng: Box<dyn RngCore + Send> = match config.rng_seed {
+            Some(seed) => {
+                warn!("the random generator is seeded, the session is not secure");
+                Box::new(StdRng::seed_from_u64(seed))
+            }
+            None => Box::new(OsRng),
+        };
         Self {
             config,
             encryptor: Box::<EncryptionNone>::default(),
+            decryptor: Box::<EncryptionNone>::default(),
             compressor: Box::<CompressNone>::default(),
+            compression_counter: Arc::default(),
             negotiated: AlgList::new(),
             session_id: vec![],
+            kex_details: KexDetails::default(),
+            negotiated_algorithms: ArcMut::default(),
+            offered_host_key_algs: vec![],
+            strict_kex: false,
+            server_extensions: vec![],
+            banner: None,
+            server_host_key: vec![],
             sequence: Sequence::new(),
+            kexinit_sent: None,
+            deferred: vec![],
+            last_kex: Instant::now(),
+            kex_bytes: 0,
+            last_activity: Instant::now(),
+            rng,
+            #[cfg(feature = "dangerous-key-export")]
+            session_keys: ArcMut::default(),
         }
     }
 
@@ -32,10 +109,91 @@ impl Client {
         self.encryptor.as_mut()
     }
 
+    pub fn get_decryptor(&mut self) -> &mut dyn Encryption {
+        self.decryptor.as_mut()
+    }
+
     pub fn get_compressor(&mut self) -> &mut dyn Compression {
         self.compressor.as_mut()
     }
 
+    pub fn get_compression_counter(&self) -> &Arc<CompressionCounter> {
+        &self.compression_counter
+    }
+
+    pub fn get_offered_host_key_algs(&self) -> &[String] {
+        &self.offered_host_key_algs
+    }
+
+    pub fn get_server_extensions(&self) -> &[(String, Vec<u8>)] {
+        &self.server_extensions
+    }
+
+    pub fn get_banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+
+    pub fn get_server_host_key(&self) -> &[u8] {
+        &self.server_host_key
+    }
+
+    pub fn get_session_id(&self) -> &[u8] {
+        &self.session_id
+    }
+
+    #[cfg(feature = "dangerous-key-export")]
+    pub fn get_session_keys(&self) -> &ArcMut<Option<SessionKeys>> {
+        &self.session_keys
+    }
+
+    /// the names of the algorithms negotiated in the last key exchange
+    pub fn get_negotiated_algorithms(&self) -> NegotiatedAlgorithms {
+        fn name<T: AsRef<str>>(algs: &[T]) -> String {
+            algs.first()
+                .map(|x| x.as_ref().to_owned())
+                .unwrap_or_default()
+        }
+        let algs = &self.negotiated;
+        NegotiatedAlgorithms {
+            key_exchange: name(&algs.key_exchange),
+            host_key: name(&algs.public_key),
+            c_encryption: name(&algs.c_encryption),
+            s_encryption: name(&algs.s_encryption),
+            c_mac: name(&algs.c_mac),
+            s_mac: name(&algs.s_mac),
+            c_compression: name(&algs.c_compress),
+            s_compression: name(&algs.s_compress),
+        }
+    }
+
+    /// the same as [Client::get_negotiated_algorithms], but kept up to date
+    /// by every key exchange
+    pub fn get_shared_negotiated_algorithms(&self) -> &ArcMut<NegotiatedAlgorithms> {
+        &self.negotiated_algorithms
+    }
+
+    /// keep `shared` up to date instead, e.g. for the client of a reconnection
+    pub fn share_negotiated_algorithms(&mut self, shared: ArcMut<NegotiatedAlgorithms>) {
+        *shared.lock().unwrap() = self.get_negotiated_algorithms();
+        self.negotiated_algorithms = shared;
+    }
+
+    /// the KEXINIT payloads, the hash and the key lengths used to derive the keys
+    /// in the last key exchange
+    pub fn get_kex_details(&self) -> &KexDetails {
+        &self.kex_details
+    }
+
+    /// whether the ciphers negotiated in the last key exchange are AEAD in both directions
+    pub fn is_aead(&self) -> bool {
+        let aead = |encs: &[Enc]| encs.first().is_some_and(Enc::is_aead);
+        aead(&self.negotiated.c_encryption) && aead(&self.negotiated.s_encryption)
+    }
+
+    pub fn get_rng(&mut self) -> &mut dyn RngCore {
+        self.rng.as_mut()
+    }
+
     pub fn get_seq(&mut self) -> &mut Sequence {
         &mut self.sequence
     }
@@ -47,4 +205,94 @@ impl Client {
     pub fn set_timeout(&mut self, tm: Option<Duration>) {
         self.config.timeout = tm
     }
+
+    pub fn get_packet_dump(&self) -> Option<usize> {
+        self.config.packet_dump
+    }
+
+    pub fn get_max_name_list_len(&self) -> usize {
+        self.config.max_name_list_len
+    }
+
+    pub fn get_keepalive_idle(&self) -> Option<Duration> {
+        self.config.keepalive_idle
+    }
+
+    pub fn get_keepalive_max_missed(&self) -> Option<u32> {
+        self.config.keepalive_max_missed
+    }
+
+    #[cfg(feature = "async")]
+    pub fn get_rekey_interval(&self) -> Option<Duration> {
+        self.config.rekey_interval
+    }
+
+    pub fn get_window_sizes(&self) -> &WindowSizes {
+        &self.config.window_sizes
+    }
+
+    pub fn get_window_adjust_threshold(&self) -> u32 {
+        self.config.window_adjust_threshold
+    }
+
+    pub fn get_env(&self) -> &[EnvVar] {
+        &self.config.env
+    }
+
+    pub fn get_allowed_subsystems(&self) -> Option<&[String]> {
+        self.config.allowed_subsystems.as_deref()
+    }
+
+    /// record the traffic of `len` bytes on the connection
+    pub fn touch(&mut self, len: usize) {
+        self.last_activity = Instant::now();
+        self.kex_bytes += len as u64;
+    }
+
+    /// when the last key exchange is finished
+    pub fn last_kex(&self) -> Instant {
+        self.last_kex
+    }
+
+    /// whether the keys should be renewed on the configured limits,
+    /// false if a key exchange is in progress
+    pub fn needs_rekey(&self) -> bool {
+        if self.is_rekeying() || self.session_id.is_empty() {
+            return false;
+        }
+        self.config
+            .rekey_limit
+            .is_some_and(|limit| self.kex_bytes >= limit)
+            || self
+                .config
+                .rekey_interval
+                .is_some_and(|interval| self.last_kex.elapsed() >= interval)
+    }
+
+    /// how long there's no traffic in either direction
+    pub fn idle_time(&self) -> Duration {
+        self.last_activity.elapsed()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-11.1>
+    ///
+    /// Tell the server that we are going to close the connection
+    ///
+    pub fn disconnect<S>(
+        &mut self,
+        stream: &mut S,
+        reason: DisconnectReason,
+        description: &str,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        info!("send disconnect, reason: {:?}, {}", reason, description);
+        let mut data = Data::new();
+        data.put_u8(ssh_transport_code::DISCONNECT)
+            .put_u32(reason.code())
+            .put_str(description)
+            .put_str("");
+        data.pack(self).write_stream(stream)
+    }
 }
@@ -2,18 +2,38 @@ use std::io::{Read, Write};
 use tracing::*;
 
 use crate::{
-    algorithm::{compression, Compress, Digest},
-    constant::{ssh_connection_code, ssh_str, ssh_transport_code, ssh_user_auth_code},
+    algorithm::{compression, Compress, Digest, PubKey},
+    config::Prompt,
+    constant::{
+        ssh_agent_code, ssh_connection_code, ssh_str, ssh_transport_code, ssh_user_auth_code,
+    },
     error::{SshError, SshResult},
     model::{Data, Packet, SecPacket},
 };
 
-use super::Client;
+use super::{agent::Agent, Client};
 
 impl Client {
     pub fn do_auth<S>(&mut self, stream: &mut S, digest: &Digest) -> SshResult<()>
     where
         S: Read + Write,
+    {
+        let mut auth = self.start_auth(stream)?;
+        loop {
+            let data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
+            if self.recv_auth(stream, &mut auth, data, digest)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// the steps of [Client::do_auth], which do no read,
+    /// so that the async session shares them
+    ///
+    /// ask for the userauth service
+    pub(crate) fn start_auth<S>(&mut self, stream: &mut S) -> SshResult<UserAuth>
+    where
+        S: Write,
     {
         info!("Auth start");
         let mut data = Data::new();
@@ -21,59 +41,112 @@ impl Client {
             .put_str(ssh_str::SSH_USERAUTH);
         data.pack(self).write_stream(stream)?;
 
-        let mut tried_public_key = false;
-        loop {
-            let mut data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
-            let message_code = data.get_u8();
-            match message_code {
-                ssh_transport_code::SERVICE_ACCEPT => {
-                    if self.config.auth.key_pair.is_none() {
-                        tried_public_key = true;
-                        // if no private key specified
-                        // just try password auth
-                        self.password_authentication(stream)?
-                    } else {
-                        // if private key was provided
-                        // use public key auth first, then fallback to password auth
-                        self.public_key_authentication(stream)?
-                    }
-                }
-                ssh_user_auth_code::FAILURE => {
-                    if !tried_public_key {
-                        error!("user auth failure. (public key)");
-                        info!("fallback to password authentication");
-                        tried_public_key = true;
-                        // keep the same with openssh
-                        // if the public key auth failed
-                        // try with password again
-                        self.password_authentication(stream)?
-                    } else {
-                        error!("user auth failure. (password)");
-                        return Err(SshError::AuthError);
-                    }
-                }
-                ssh_user_auth_code::PK_OK => {
-                    info!("user auth support this algorithm.");
-                    self.public_key_signature(stream, digest)?
-                }
-                ssh_user_auth_code::SUCCESS => {
-                    info!("user auth successful.");
-                    // <https://www.openssh.com/txt/draft-miller-secsh-compression-delayed-00.txt>
-                    // Now we need turn on the compressor if any
-                    if let Compress::ZlibOpenSsh = self.negotiated.c_compress[0] {
-                        let comp = compression::from(&Compress::ZlibOpenSsh);
-                        self.compressor = comp;
-                    }
-                    return Ok(());
+        // the same order as openssh
+        let mut methods = vec![];
+        if self.config.auth.agent.is_some() {
+            methods.push(Method::Agent);
+        }
+        if self.config.auth.key_pair.is_some() {
+            methods.push(Method::PublicKey);
+        }
+        if self.config.auth.keyboard_interactive.is_some() {
+            methods.push(Method::KeyboardInteractive);
+        }
+        // skip the password only if it's not given for keyboard-interactive
+        if self.config.auth.keyboard_interactive.is_none() || !self.config.auth.password.is_empty()
+        {
+            methods.push(Method::Password);
+        }
+        Ok(UserAuth {
+            methods: methods.into_iter(),
+            agent: None,
+            current: Method::Password,
+        })
+    }
+
+    /// answer one message from the server, true once the authentication succeeds
+    pub(crate) fn recv_auth<S>(
+        &mut self,
+        stream: &mut S,
+        auth: &mut UserAuth,
+        mut data: Data,
+        digest: &Digest,
+    ) -> SshResult<bool>
+    where
+        S: Write,
+    {
+        let UserAuth {
+            methods,
+            agent,
+            current,
+        } = auth;
+        let message_code = data.get_u8();
+        match message_code {
+            ssh_transport_code::SERVICE_ACCEPT => {
+                *current = self.next_method(stream, methods, agent)?
+            }
+            ssh_user_auth_code::FAILURE => {
+                error!("user auth failure. ({:?})", current);
+                // keep the same with openssh
+                // if one method failed
+                // try with the next one
+                *current = self.next_method(stream, methods, agent)?
+            }
+            ssh_user_auth_code::PK_OK if *current == Method::PublicKey => {
+                info!("user auth support this algorithm.");
+                self.public_key_signature(stream, digest)?
+            }
+            ssh_user_auth_code::PK_OK if *current == Method::Agent => {
+                info!("user auth support this identity.");
+                let signed = match agent.as_mut() {
+                    Some(agent) => self.agent_signature(stream, agent)?,
+                    None => false,
+                };
+                if !signed {
+                    *current = self.next_method(stream, methods, agent)?
                 }
-                ssh_connection_code::GLOBAL_REQUEST => {
-                    let mut data = Data::new();
-                    data.put_u8(ssh_connection_code::REQUEST_FAILURE);
-                    data.pack(self).write_stream(stream)?;
+            }
+            ssh_user_auth_code::INFO_REQUEST if *current == Method::KeyboardInteractive => {
+                self.info_response(stream, data)?
+            }
+            ssh_user_auth_code::SUCCESS => {
+                info!("user auth successful.");
+                // <https://www.openssh.com/txt/draft-miller-secsh-compression-delayed-00.txt>
+                // Now we need turn on the compressor if any
+                if let Compress::ZlibOpenSsh = self.negotiated.c_compress[0] {
+                    let comp = compression::from(&Compress::ZlibOpenSsh);
+                    self.compressor = comp;
                 }
-                _ => {}
+                return Ok(true);
+            }
+            ssh_user_auth_code::BANNER => self.recv_banner(data)?,
+            ssh_transport_code::EXT_INFO => self.recv_ext_info(data),
+            ssh_connection_code::GLOBAL_REQUEST => {
+                let mut data = Data::new();
+                data.put_u8(ssh_connection_code::REQUEST_FAILURE);
+                data.pack(self).write_stream(stream)?;
             }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4252#section-5.4>
+    ///
+    /// the banner can come before or between the attempts,
+    /// each one is kept after the former ones
+    fn recv_banner(&mut self, mut data: Data) -> SshResult<()> {
+        let message = String::from_utf8(data.get_u8s())?;
+        // the language tag
+        data.get_u8s();
+        info!("user auth banner received.");
+        if let Some(ref callback) = self.config.auth.banner {
+            (callback.lock().unwrap())(&message);
         }
+        self.banner
+            .get_or_insert_with(String::new)
+            .push_str(&message);
+        Ok(())
     }
 
     fn password_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
@@ -92,6 +165,124 @@ impl Client {
         data.pack(self).write_stream(stream)
     }
 
+    fn keyboard_interactive_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        info!("keyboard-interactive authentication.");
+        let mut data = Data::new();
+        data.put_u8(ssh_user_auth_code::REQUEST)
+            .put_str(self.config.auth.username.as_str())
+            .put_str(ssh_str::SSH_CONNECTION)
+            .put_str(ssh_str::KEYBOARD_INTERACTIVE)
+            // language tag
+            .put_str("")
+            // submethods
+            .put_str("");
+
+        data.pack(self).write_stream(stream)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4256#section-3.2>
+    ///
+    /// answer one round of the prompts, the server may send as many as it needs
+    fn info_response<S>(&mut self, stream: &mut S, mut data: Data) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let mut string = || String::from_utf8_lossy(&data.get_u8s()).into_owned();
+        let name = string();
+        let instruction = string();
+        let _language = string();
+        let num_prompts = data.get_u32();
+        let prompts: Vec<_> = (0..num_prompts)
+            .map(|_| Prompt {
+                prompt: String::from_utf8_lossy(&data.get_u8s()).into_owned(),
+                echo: data.get_u8() != 0,
+            })
+            .collect();
+        debug!(
+            "keyboard-interactive info request with {} prompts",
+            prompts.len()
+        );
+
+        let responses = {
+            let callback = self.config.auth.keyboard_interactive.as_ref().unwrap();
+            let mut callback = callback.lock().unwrap();
+            (*callback)(&name, &instruction, &prompts)
+        };
+        if responses.len() != prompts.len() {
+            return Err(SshError::GeneralError(format!(
+                "keyboard-interactive: {} answers for {} prompts",
+                responses.len(),
+                prompts.len()
+            )));
+        }
+
+        let mut data = Data::new();
+        data.put_u8(ssh_user_auth_code::INFO_RESPONSE)
+            .put_u32(responses.len() as u32);
+        for response in &responses {
+            data.put_str(response);
+        }
+        data.pack(self).write_stream(stream)
+    }
+
+    /// send the request of the next method that can be tried,
+    /// or the next identity in the agent
+    fn next_method<S>(
+        &mut self,
+        stream: &mut S,
+        methods: &mut impl Iterator<Item = Method>,
+        agent: &mut Option<AgentAuth>,
+    ) -> SshResult<Method>
+    where
+        S: Write,
+    {
+        if let Some(auth) = agent.as_mut() {
+            if self.agent_query(stream, auth)? {
+                return Ok(Method::Agent);
+            }
+            *agent = None;
+        }
+        loop {
+            let method = methods.next().ok_or(SshError::AuthError)?;
+            match method {
+                Method::Agent => {
+                    let mut auth = match self.agent_identities() {
+                        Ok(auth) => auth,
+                        Err(e) => {
+                            warn!("skip the agent: {}", e);
+                            continue;
+                        }
+                    };
+                    if self.agent_query(stream, &mut auth)? {
+                        *agent = Some(auth);
+                        return Ok(method);
+                    }
+                    continue;
+                }
+                Method::PublicKey => self.public_key_authentication(stream)?,
+                Method::KeyboardInteractive => self.keyboard_interactive_authentication(stream)?,
+                Method::Password => self.password_authentication(stream)?,
+            }
+            return Ok(method);
+        }
+    }
+
+    /// the request of publickey, the signature is appended if `signed`
+    fn public_key_request(&self, alg: &str, blob: &[u8], signed: bool) -> Data {
+        let mut data = Data::new();
+        data.put_u8(ssh_user_auth_code::REQUEST)
+            .put_str(self.config.auth.username.as_str())
+            .put_str(ssh_str::SSH_CONNECTION)
+            .put_str(ssh_str::PUBLIC_KEY)
+            .put_u8(signed as u8)
+            .put_str(alg)
+            .put_u8s(blob);
+        data
+    }
+
     fn public_key_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
     where
         S: Write,
@@ -102,23 +293,14 @@ impl Client {
                 "public key authentication. algorithm: {}",
                 pubkey_alg.as_ref()
             );
-            let mut data = Data::new();
-            data.put_u8(ssh_user_auth_code::REQUEST)
-                .put_str(self.config.auth.username.as_str())
-                .put_str(ssh_str::SSH_CONNECTION)
-                .put_str(ssh_str::PUBLIC_KEY)
-                .put_u8(false as u8)
-                .put_str(pubkey_alg.as_ref())
-                .put_u8s(
-                    &self
-                        .config
-                        .auth
-                        .key_pair
-                        .as_ref()
-                        .unwrap()
-                        .get_blob(pubkey_alg),
-                );
-            data
+            let blob = self
+                .config
+                .auth
+                .key_pair
+                .as_ref()
+                .unwrap()
+                .get_blob(pubkey_alg);
+            self.public_key_request(pubkey_alg.as_ref(), &blob, false)
         };
         data.pack(self).write_stream(stream)
     }
@@ -133,24 +315,10 @@ impl Client {
     {
         let data = {
             let pubkey_alg = &self.negotiated.public_key[0];
-
-            let mut data = Data::new();
-            data.put_u8(ssh_user_auth_code::REQUEST)
-                .put_str(self.config.auth.username.as_str())
-                .put_str(ssh_str::SSH_CONNECTION)
-                .put_str(ssh_str::PUBLIC_KEY)
-                .put_u8(true as u8)
-                .put_str(pubkey_alg.as_ref())
-                .put_u8s(
-                    &self
-                        .config
-                        .auth
-                        .key_pair
-                        .as_ref()
-                        .unwrap()
-                        .get_blob(pubkey_alg),
-                );
-            let signature = self.config.auth.key_pair.as_ref().unwrap().signature(
+            let key_pair = self.config.auth.key_pair.as_ref().unwrap();
+            let blob = key_pair.get_blob(pubkey_alg);
+            let mut data = self.public_key_request(pubkey_alg.as_ref(), &blob, true);
+            let signature = key_pair.signature(
                 data.as_slice(),
                 digest.hash_ctx.clone(),
                 digest.key_exchange.as_ref().unwrap().get_hash_type(),
@@ -161,4 +329,107 @@ impl Client {
         };
         data.pack(self).write_stream(stream)
     }
+
+    fn agent_identities(&self) -> SshResult<AgentAuth> {
+        let path = self.config.auth.agent.as_ref().unwrap();
+        let mut agent = Agent::connect(path)?;
+        let identities = agent.identities()?;
+        info!("agent authentication with {} identities.", identities.len());
+        Ok(AgentAuth {
+            agent,
+            identities: identities.into_iter(),
+            current: None,
+        })
+    }
+
+    /// ask whether the next identity in the agent is acceptable,
+    /// false if there is no more
+    fn agent_query<S>(&mut self, stream: &mut S, auth: &mut AgentAuth) -> SshResult<bool>
+    where
+        S: Write,
+    {
+        let Some(blob) = auth.identities.next() else {
+            return Ok(false);
+        };
+        let (alg, flags) = self.agent_algorithm(&blob);
+        debug!("try the agent identity of {}", alg);
+        self.public_key_request(&alg, &blob, false)
+            .pack(self)
+            .write_stream(stream)?;
+        auth.current = Some((alg, flags, blob));
+        Ok(true)
+    }
+
+    /// sign the request with the agent, false if the agent refuses
+    fn agent_signature<S>(&mut self, stream: &mut S, auth: &mut AgentAuth) -> SshResult<bool>
+    where
+        S: Write,
+    {
+        let Some((alg, flags, blob)) = auth.current.as_ref() else {
+            return Ok(false);
+        };
+        let mut data = self.public_key_request(alg, blob, true);
+        // <https://www.rfc-editor.org/rfc/rfc4252#section-7>
+        let mut signed = Data::new();
+        signed.put_u8s(&self.session_id);
+        signed.extend_from_slice(&data);
+        let signature = match auth.agent.sign(blob, &signed, *flags) {
+            Ok(signature) => signature,
+            Err(e) => {
+                warn!("the agent fails to sign with {}: {}", alg, e);
+                return Ok(false);
+            }
+        };
+        data.put_u8s(&signature);
+        data.pack(self).write_stream(stream)?;
+        Ok(true)
+    }
+
+    /// the public key algorithm to sign with the key of `blob`,
+    /// and the flags that ask the agent for it
+    fn agent_algorithm(&self, blob: &[u8]) -> (String, u32) {
+        let key_type = Data::from(blob).get_u8s();
+        let key_type = String::from_utf8_lossy(&key_type).into_owned();
+        if key_type != "ssh-rsa" {
+            return (key_type, 0);
+        }
+        // the same hash as the host key if it's rsa too
+        match self.negotiated.public_key.first() {
+            Some(PubKey::RsaSha2_512) => (
+                PubKey::RsaSha2_512.as_ref().to_owned(),
+                ssh_agent_code::RSA_SHA2_512,
+            ),
+            #[cfg(feature = "deprecated-rsa-sha1")]
+            Some(PubKey::SshRsa) => (key_type, 0),
+            _ => (
+                PubKey::RsaSha2_256.as_ref().to_owned(),
+                ssh_agent_code::RSA_SHA2_256,
+            ),
+        }
+    }
+}
+
+/// the authentication methods, in the order they are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    Agent,
+    PublicKey,
+    KeyboardInteractive,
+    Password,
+}
+
+/// an authentication between the messages, see [Client::do_auth]
+pub(crate) struct UserAuth {
+    // the methods not tried yet
+    methods: std::vec::IntoIter<Method>,
+    agent: Option<AgentAuth>,
+    current: Method,
+}
+
+/// the identities of the agent being tried
+struct AgentAuth {
+    agent: Agent,
+    identities: std::vec::IntoIter<Vec<u8>>,
+    // (algorithm, sign flags, key blob) of the one being asked
+    current: Option<(String, u32, Vec<u8>)>,
 }
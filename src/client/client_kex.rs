@@ -1,5 +1,7 @@
 #[cfg(feature = "deprecated-zlib")]
 use crate::algorithm::{compression, Compress};
+#[cfg(feature = "dangerous-key-export")]
+use crate::model::{DerivedKeys, SessionKeys};
 use crate::{
     algorithm::{
         encryption,
@@ -7,17 +9,39 @@ use crate::{
         key_exchange::{self, KeyExchange},
         mac,
         public_key::{self, PublicKey},
-        Digest,
+        Digest, Enc,
     },
     client::Client,
     config::algorithm::AlgList,
-    constant::ssh_transport_code,
+    constant::{ssh_str, ssh_transport_code},
     error::{SshError, SshResult},
-    model::{Data, Packet, SecPacket},
+    model::{Data, KeyLengths, Packet, SecPacket},
+};
+use std::{
+    io::{Read, Write},
+    time::Instant,
 };
-use std::io::{Read, Write};
 use tracing::*;
 
+/// a key exchange between its steps, see [Client::key_agreement]
+pub(crate) struct KeyAgreement {
+    negotiated: AlgList,
+    key_exchange: Box<dyn KeyExchange>,
+    public_key: Box<dyn PublicKey>,
+    // known once the KEXDH_REPLY is verified
+    session_id: Vec<u8>,
+}
+
+impl KeyAgreement {
+    fn new_hash(&self, digest: &Digest) -> hash::Hash {
+        hash::Hash::new(
+            digest.hash_ctx.clone(),
+            &self.session_id,
+            self.key_exchange.get_hash_type(),
+        )
+    }
+}
+
 impl Client {
     pub fn key_agreement<S>(
         &mut self,
@@ -27,59 +51,141 @@ impl Client {
     ) -> SshResult<()>
     where
         S: Read + Write,
+    {
+        let mut kex = self.start_key_agreement(stream, server_algs, digest)?;
+        let reply = Data::unpack(SecPacket::from_stream(stream, self)?)?;
+        self.recv_kex_reply(stream, &mut kex, reply, digest)?;
+        let new_keys = Data::unpack(SecPacket::from_stream(stream, self)?)?;
+        self.recv_new_keys(kex, new_keys, digest)?;
+        self.send_deferred(stream)
+    }
+
+    /// the steps of [Client::key_agreement], which do no read,
+    /// so that the async session shares them
+    ///
+    /// negotiate with the server's KEXINIT, then send our public key
+    pub(crate) fn start_key_agreement<S>(
+        &mut self,
+        stream: &mut S,
+        server_algs: AlgList,
+        digest: &mut Digest,
+    ) -> SshResult<KeyAgreement>
+    where
+        S: Write,
     {
         // initialize the hash context
         digest.hash_ctx.set_v_c(&self.config.ver.client_ver);
         digest.hash_ctx.set_v_s(&self.config.ver.server_ver);
 
         info!("start for key negotiation.");
-        info!("send client algorithm list.");
 
-        let algs = self.config.algs.clone();
-        let client_algs = algs.pack(self);
-        digest.hash_ctx.set_i_c(client_algs.get_inner());
-        client_algs.write_stream(stream)?;
+        // both sides may have sent their KEXINIT at the same time,
+        // then ours is already on the wire and must not be sent again
+        let client_algs = match self.kexinit_sent.take() {
+            Some(client_algs) => {
+                info!("client algorithm list already sent.");
+                client_algs
+            }
+            None => self.send_kexinit(stream)?,
+        };
+        digest.hash_ctx.set_i_c(&client_algs);
+        self.kex_details.client_kexinit = client_algs;
+        // I_S is recorded by the caller as a string, strip its length
+        self.kex_details.server_kexinit = digest.hash_ctx.i_s[4..].to_vec();
 
+        let initial = self.session_id.is_empty();
         let negotiated = self.config.algs.match_with(&server_algs)?;
+        if initial {
+            self.strict_kex = self.config.strict_kex
+                && server_algs
+                    .offered_key_exchange
+                    .iter()
+                    .any(|x| x == ssh_str::KEX_STRICT_S);
+            if self.strict_kex {
+                info!("strict key exchange enabled.");
+            }
+            self.offered_host_key_algs = server_algs.offered_public_key;
+        }
 
         // key exchange algorithm
-        let mut key_exchange = key_exchange::from(&negotiated.key_exchange[0])?;
+        let fixed_key = self.config.ephemeral_key.clone();
+        // only a seeded generator is passed, the system random source is used otherwise
+        let rng = self.config.rng_seed.map(|_| self.get_rng());
+        let key_exchange =
+            key_exchange::from(&negotiated.key_exchange[0], fixed_key.as_deref(), rng)?;
         self.send_qc(stream, key_exchange.get_public_key())?;
 
         // host key algorithm
-        let mut public_key = public_key::from(&negotiated.public_key[0]);
+        let public_key = public_key::from(&negotiated.public_key[0]);
+        Ok(KeyAgreement {
+            negotiated,
+            key_exchange,
+            public_key,
+            session_id: vec![],
+        })
+    }
 
+    /// verify the server's KEXDH_REPLY, then send NEWKEYS
+    pub(crate) fn recv_kex_reply<S>(
+        &mut self,
+        stream: &mut S,
+        kex: &mut KeyAgreement,
+        reply: Data,
+        digest: &mut Digest,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
         // generate session id
-        let session_id = {
-            let session_id = self.verify_signature_and_new_keys(
-                stream,
-                &mut public_key,
-                &mut key_exchange,
-                &mut digest.hash_ctx,
-            )?;
-
-            if self.session_id.is_empty() {
-                session_id
-            } else {
-                self.session_id.clone()
-            }
+        let session_id = self.verify_signature(
+            reply,
+            &mut kex.public_key,
+            &mut kex.key_exchange,
+            &mut digest.hash_ctx,
+        )?;
+        kex.session_id = if self.session_id.is_empty() {
+            session_id
+        } else {
+            self.session_id.clone()
         };
 
-        let hash = hash::Hash::new(
-            digest.hash_ctx.clone(),
-            &session_id,
-            key_exchange.get_hash_type(),
-        );
-
-        // mac algorithm
-        let mac = mac::from(&negotiated.c_mac[0]);
+        // the client to server direction uses the new keys right after we send NEWKEYS
+        self.new_keys(stream)?;
+        if self.strict_kex {
+            self.sequence.reset_client();
+        }
+        let negotiated = &kex.negotiated;
+        let mac = mac::from(negotiated.c_mac.first());
+        self.kex_details.c_keys = key_lengths(&negotiated.c_encryption[0], mac.as_ref());
+        self.encryptor = encryption::from(&negotiated.c_encryption[0], kex.new_hash(digest), mac);
+        Ok(())
+    }
 
-        // encryption algorithm
-        let encryption = encryption::from(&negotiated.c_encryption[0], hash, mac);
+    /// take the server's NEWKEYS, then finish the key exchange
+    ///
+    /// while the server to client direction uses the new keys after the server's NEWKEYS,
+    /// which may have been sent before ours
+    pub(crate) fn recv_new_keys(
+        &mut self,
+        kex: KeyAgreement,
+        new_keys: Data,
+        digest: &mut Digest,
+    ) -> SshResult<()> {
+        self.wait_new_keys(new_keys)?;
+        if self.strict_kex {
+            self.sequence.reset_server();
+        }
+        let negotiated = &kex.negotiated;
+        let mac = mac::from(negotiated.s_mac.first());
+        self.kex_details.s_keys = key_lengths(&negotiated.s_encryption[0], mac.as_ref());
+        self.decryptor = encryption::from(&negotiated.s_encryption[0], kex.new_hash(digest), mac);
+        self.kex_details.hash = kex.key_exchange.get_hash_type().name().to_owned();
+        #[cfg(feature = "dangerous-key-export")]
+        self.export_keys(&kex.new_hash(digest), &kex.session_id);
 
-        self.session_id = session_id;
-        self.negotiated = negotiated;
-        self.encryptor = encryption;
+        self.session_id = kex.session_id;
+        self.negotiated = kex.negotiated;
+        *self.get_shared_negotiated_algorithms().lock().unwrap() = self.get_negotiated_algorithms();
 
         #[cfg(feature = "deprecated-zlib")]
         {
@@ -89,17 +195,97 @@ impl Client {
             }
         }
 
-        digest.key_exchange = Some(key_exchange);
+        digest.key_exchange = Some(kex.key_exchange);
+        self.last_kex = Instant::now();
+        self.kex_bytes = 0;
 
         info!("key negotiation successful.");
 
         Ok(())
     }
 
+    /// start a key re-exchange by sending our KEXINIT,
+    /// which will be finished once the server's KEXINIT is received
+    ///
+    /// nothing is sent if ours is still waiting for the server's
+    pub fn start_rekey<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if self.kexinit_sent.is_none() {
+            info!("start key re-exchange.");
+            self.kexinit_sent = Some(self.send_kexinit(stream)?);
+        }
+        Ok(())
+    }
+
+    /// whether our KEXINIT is sent and the server's is not received yet,
+    /// during which no message other than the key exchange ones should be sent
+    pub fn is_rekeying(&self) -> bool {
+        self.kexinit_sent.is_some()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-7.1>
+    ///
+    /// send a message other than the key exchange ones,
+    /// which is held back while [Client::is_rekeying] until [Client::send_deferred]
+    pub(crate) fn send_msg<S>(&mut self, data: Data, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if self.is_rekeying() {
+            trace!("hold back message {} amid the key exchange", data[0]);
+            self.deferred.push(data);
+            Ok(())
+        } else {
+            data.pack(self).write_stream(stream)
+        }
+    }
+
+    /// send the messages held back by [Client::send_msg], in their order
+    pub(crate) fn send_deferred<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if !self.deferred.is_empty() {
+            debug!(
+                "send {} messages held back by the key exchange",
+                self.deferred.len()
+            );
+        }
+        for data in std::mem::take(&mut self.deferred) {
+            data.pack(self).write_stream(stream)?;
+        }
+        Ok(())
+    }
+
+    /// send the client algorithm list, then return its payload
+    pub(crate) fn send_kexinit<S>(&mut self, stream: &mut S) -> SshResult<Vec<u8>>
+    where
+        S: Write,
+    {
+        info!("send client algorithm list.");
+
+        // the markers are only meaningful in the initial key exchange
+        let mut algs = self.config.algs.clone();
+        if self.session_id.is_empty() {
+            if self.config.ext_info {
+                algs.kex_markers.push(ssh_str::EXT_INFO_C);
+            }
+            if self.config.strict_kex {
+                algs.kex_markers.push(ssh_str::KEX_STRICT_C);
+            }
+        }
+        let client_algs = algs.pack(self);
+        let payload = client_algs.get_inner().to_vec();
+        client_algs.write_stream(stream)?;
+        Ok(payload)
+    }
+
     /// Send the public key
     fn send_qc<S>(&mut self, stream: &mut S, public_key: &[u8]) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         let mut data = Data::new();
         data.put_u8(ssh_transport_code::KEXDH_INIT)
@@ -107,43 +293,84 @@ impl Client {
         data.pack(self).write_stream(stream)
     }
 
-    fn verify_signature_and_new_keys<S>(
+    /// verify the KEXDH_REPLY, then return the exchange hash
+    fn verify_signature(
         &mut self,
-        stream: &mut S,
+        mut data: Data,
         public_key: &mut Box<dyn PublicKey>,
         key_exchange: &mut Box<dyn KeyExchange>,
         h: &mut HashCtx,
-    ) -> SshResult<Vec<u8>>
-    where
-        S: Read + Write,
-    {
-        let mut session_id = vec![];
-        loop {
-            let mut data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
-            let message_code = data.get_u8();
-            match message_code {
-                ssh_transport_code::KEXDH_REPLY => {
-                    // Generate the session id, get the signature
-                    let sig = self.generate_signature(data, h, key_exchange)?;
-                    // verify the signature
-                    session_id = hash::digest(&h.as_bytes(), key_exchange.get_hash_type());
-                    let flag = public_key.verify_signature(&h.k_s, &session_id, &sig)?;
-                    if !flag {
-                        let err_msg = "signature verification failure.".to_owned();
-                        error!(err_msg);
-                        return Err(SshError::KexError(err_msg));
-                    }
-                    info!("signature verification success.");
-                }
-                ssh_transport_code::NEWKEYS => {
-                    self.new_keys(stream)?;
-                    return Ok(session_id);
+    ) -> SshResult<Vec<u8>> {
+        let message_code = data.get_u8();
+        match message_code {
+            ssh_transport_code::KEXDH_REPLY => {
+                // Generate the session id, get the signature
+                let sig = self.generate_signature(data, h, key_exchange)?;
+                // verify the signature
+                let session_id = hash::digest(&h.as_bytes(), key_exchange.get_hash_type());
+                let flag = public_key.verify_signature(&h.k_s, &session_id, &sig)?;
+                if !flag {
+                    let err_msg = "signature verification failure.".to_owned();
+                    error!(err_msg);
+                    return Err(SshError::KexError(err_msg));
                 }
-                _ => unreachable!(),
+                info!("signature verification success.");
+                // k_s is stored with its length prefix
+                self.config.host_key_check.verify(&h.k_s[4..])?;
+                self.server_host_key = h.k_s[4..].to_vec();
+                Ok(session_id)
+            }
+            x => {
+                let err_msg = format!("expect KEXDH_REPLY, but got message {x}");
+                error!(err_msg);
+                Err(SshError::KexError(err_msg))
             }
         }
     }
 
+    /// check the server's NEWKEYS
+    ///
+    /// our outgoing packets are already encrypted at this point,
+    /// while the incoming ones are still protected by the old keys
+    fn wait_new_keys(&mut self, mut data: Data) -> SshResult<()> {
+        let message_code = data.get_u8();
+        match message_code {
+            ssh_transport_code::NEWKEYS => {
+                info!("recv new keys");
+                Ok(())
+            }
+            x => {
+                let err_msg = format!("expect NEWKEYS, but got message {x}");
+                error!(err_msg);
+                Err(SshError::KexError(err_msg))
+            }
+        }
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc8308#section-2.3>
+    ///
+    /// record the extensions of the server, the message code is already taken
+    pub(super) fn recv_ext_info(&mut self, mut data: Data) {
+        if !self.config.ext_info {
+            debug!("ignore the unsolicited ext-info");
+            return;
+        }
+
+        let count = data.get_u32();
+        let mut extensions = vec![];
+        for _ in 0..count {
+            let name = String::from_utf8_lossy(&data.get_u8s()).into_owned();
+            let value = data.get_u8s();
+            debug!(
+                "server extension {}: {:?}",
+                name,
+                String::from_utf8_lossy(&value)
+            );
+            extensions.push((name, value));
+        }
+        self.server_extensions = extensions;
+    }
+
     /// get the signature
     fn generate_signature(
         &mut self,
@@ -178,3 +405,41 @@ impl Client {
         data.pack(self).write_stream(stream)
     }
 }
+
+#[cfg(feature = "dangerous-key-export")]
+impl Client {
+    /// keep a copy of the keys derived as `encryption::from` does
+    fn export_keys(&self, hash: &hash::Hash, session_id: &[u8]) {
+        warn!("DANGER: the session keys are exported, anyone holding them can decrypt the session");
+        let (c, s) = (self.kex_details.c_keys, self.kex_details.s_keys);
+        let (c_ek, _) = hash.mix_ek(c.key);
+        let (_, s_ek) = hash.mix_ek(s.key);
+        let (c_ik, _) = hash.mix_ik(c.mac_key);
+        let (_, s_ik) = hash.mix_ik(s.mac_key);
+        // the IVs are never longer than the hash output
+        let iv = |iv: &[u8], len: usize| iv[..len.min(iv.len())].to_vec();
+        let keys = SessionKeys {
+            session_id: session_id.to_vec(),
+            c_keys: DerivedKeys {
+                iv: iv(&hash.iv_c_s, c.iv),
+                key: c_ek,
+                mac_key: c_ik,
+            },
+            s_keys: DerivedKeys {
+                iv: iv(&hash.iv_s_c, s.iv),
+                key: s_ek,
+                mac_key: s_ik,
+            },
+        };
+        *self.session_keys.lock().unwrap() = Some(keys);
+    }
+}
+
+/// the lengths of the keys that `encryption::from` derives for one direction
+fn key_lengths(enc: &Enc, mac: &dyn mac::Mac) -> KeyLengths {
+    KeyLengths {
+        iv: enc.iv_size(),
+        key: enc.key_size(),
+        mac_key: if enc.is_aead() { 0 } else { mac.bsize() },
+    }
+}
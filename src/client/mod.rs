@@ -1,3 +1,4 @@
+pub(crate) mod agent;
 #[allow(clippy::module_inception)]
 pub(crate) mod client;
 mod client_auth;
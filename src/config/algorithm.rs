@@ -6,7 +6,7 @@ use std::{
 use tracing::*;
 
 use crate::{
-    algorithm::{Compress, Enc, Kex, Mac, PubKey},
+    algorithm::{negotiate, Compress, Enc, Kex, Mac, PubKey},
     client::Client,
     constant::ssh_transport_code,
     error::{SshError, SshResult},
@@ -79,6 +79,12 @@ pub(crate) struct AlgList {
     pub s_mac: Macs,
     pub c_compress: Compresses,
     pub s_compress: Compresses,
+    // all the host key algorithms in a server list, including the unsupported ones
+    pub offered_public_key: Vec<String>,
+    // all the kex names in a server list, including the markers
+    pub offered_key_exchange: Vec<String>,
+    // the markers appended to the kex list of a client list
+    pub kex_markers: Vec<&'static str>,
 }
 
 impl Debug for AlgList {
@@ -106,6 +112,7 @@ impl AlgList {
             key_exchange: vec![
                 Kex::Curve25519Sha256,
                 Kex::EcdhSha2Nistrp256,
+                Kex::DiffieHellmanGroup16Sha512,
                 Kex::DiffieHellmanGroup14Sha256,
                 Kex::DiffieHellmanGroup14Sha1,
             ]
@@ -116,6 +123,7 @@ impl AlgList {
                 Enc::Aes128Ctr,
                 Enc::Aes192Ctr,
                 Enc::Aes256Ctr,
+                Enc::Aes256GcmOpenssh,
             ]
             .into(),
             s_encryption: vec![
@@ -123,62 +131,133 @@ impl AlgList {
                 Enc::Aes128Ctr,
                 Enc::Aes192Ctr,
                 Enc::Aes256Ctr,
+                Enc::Aes256GcmOpenssh,
             ]
             .into(),
             c_mac: vec![Mac::HmacSha2_256, Mac::HmacSha2_512, Mac::HmacSha1].into(),
             s_mac: vec![Mac::HmacSha2_256, Mac::HmacSha2_512, Mac::HmacSha1].into(),
             c_compress: vec![Compress::None, Compress::ZlibOpenSsh].into(),
             s_compress: vec![Compress::None, Compress::ZlibOpenSsh].into(),
+            offered_public_key: vec![],
+            offered_key_exchange: vec![],
+            kex_markers: vec![],
         }
     }
 
-    fn from(mut data: Data) -> SshResult<Self> {
+    fn from(mut data: Data, max_name_list_len: usize) -> SshResult<Self> {
+        // every malformed KEXINIT aborts the key exchange alike
+        let malformed = |err_msg: String| {
+            error!(err_msg);
+            SshError::KexError(err_msg)
+        };
+        let truncated =
+            || malformed("Key_agreement: the server KEXINIT message is truncated".to_owned());
+
+        // the message code + the 16-byte cookie
+        if data.len() < 17 {
+            return Err(truncated());
+        }
         data.get_u8();
-        // skip the 16-bit cookie
         data.skip(16);
         let mut server_algorithm = Self::new();
 
+        // refuse the oversized or truncated ones before copying them
+        let name_list = |data: &mut Data| -> SshResult<Vec<String>> {
+            let Some(len) = data.get(..4) else {
+                return Err(truncated());
+            };
+            let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+            if len > max_name_list_len {
+                return Err(malformed(format!(
+                    "Key_agreement: a server name-list of {len} bytes exceeds the limit {max_name_list_len}"
+                )));
+            }
+            if data.len() < 4 + len {
+                return Err(truncated());
+            }
+            util::vec_u8_to_string(data.get_u8s(), ",").map_err(|_| {
+                malformed("Key_agreement: a server name-list is not valid UTF-8".to_owned())
+            })
+        };
+
         macro_rules! try_convert {
             ($hint: literal, $field: ident) => {
-                let alg_string = util::vec_u8_to_string(data.get_u8s(), ",")?;
+                let alg_string = name_list(&mut data)?;
                 info!("server {}: {:?}", $hint, alg_string);
                 server_algorithm.$field = alg_string.try_into()?;
             };
         }
-        try_convert!("key exchange", key_exchange);
-        try_convert!("public key", public_key);
+        let key_exchange = name_list(&mut data)?;
+        info!("server key exchange: {:?}", key_exchange);
+        server_algorithm.offered_key_exchange = key_exchange.clone();
+        server_algorithm.key_exchange = key_exchange.try_into()?;
+        let public_key = name_list(&mut data)?;
+        info!("server public key: {:?}", public_key);
+        server_algorithm.offered_public_key = public_key.clone();
+        server_algorithm.public_key = public_key.try_into()?;
         try_convert!("c2s encryption", c_encryption);
         try_convert!("s2c encryption", s_encryption);
         try_convert!("c2s mac", c_mac);
         try_convert!("s2c mac", s_mac);
         try_convert!("c2s compression", c_compress);
         try_convert!("s2c compression", s_compress);
+
+        // languages are not supported, just skip them
+        name_list(&mut data)?;
+        name_list(&mut data)?;
+
+        // boolean first_kex_packet_follows + uint32 reserved
+        if data.len() < 5 {
+            return Err(truncated());
+        }
+        let first_kex_packet_follows = data.get_u8() != 0;
+        debug!(
+            "server first_kex_packet_follows: {}",
+            first_kex_packet_follows
+        );
+        let reserved = data.get_u32();
+        if reserved != 0 {
+            return Err(malformed(format!(
+                "Key_agreement: the reserved field of the server KEXINIT message is not zero: {}",
+                reserved
+            )));
+        }
         debug!("converted server algorithms: [{:?}]", server_algorithm);
         Ok(server_algorithm)
     }
 
     pub fn match_with(&self, other: &Self) -> SshResult<Self> {
+        macro_rules! find_field {
+            ($our: expr,  $their:expr, $field: ident) => {
+                negotiate(
+                    &$our
+                        .$field
+                        .iter()
+                        .map(|x| x.as_ref())
+                        .collect::<Vec<&str>>(),
+                    &$their
+                        .$field
+                        .iter()
+                        .map(|x| x.as_ref())
+                        .collect::<Vec<&str>>(),
+                )
+                .ok()
+                .and_then(|name| $our.$field.iter().find(|x| x.as_ref() == name))
+            };
+        }
+
         macro_rules! match_field {
             ($our: expr,  $their:expr, $field: ident, $err_hint: literal) => {
-                $our.$field
-                    .iter()
-                    .find_map(|k| {
-                        if $their.$field.contains(k) {
-                            Some(k)
-                        } else {
-                            None
-                        }
-                    })
-                    .ok_or_else(|| {
-                        let err_msg = format!(
-                            "Key_agreement: the {} fails to match, \
+                find_field!($our, $their, $field).ok_or_else(|| {
+                    let err_msg = format!(
+                        "Key_agreement: the {} fails to match, \
                         algorithms supported by the server: {},\
                         algorithms supported by the client: {}",
-                            $err_hint, $their.$field, $our.$field
-                        );
-                        error!(err_msg);
-                        SshError::KexError(err_msg)
-                    })
+                        $err_hint, $their.$field, $our.$field
+                    );
+                    error!(err_msg);
+                    SshError::KexError(err_msg)
+                })
             };
         }
 
@@ -190,9 +269,15 @@ impl AlgList {
         let c_enc = match_field!(self, other, c_encryption, "client encryption algorithm")?;
         let s_enc = match_field!(self, other, s_encryption, "server encryption algorithm")?;
 
-        // mac
-        let c_mac = match_field!(self, other, c_mac, "client mac algorithm")?;
-        let s_mac = match_field!(self, other, s_mac, "server mac algorithm")?;
+        // mac, which is not needed by an AEAD cipher and may be left unmatched
+        let c_mac = match c_enc.is_aead() {
+            true => find_field!(self, other, c_mac),
+            false => Some(match_field!(self, other, c_mac, "client mac algorithm")?),
+        };
+        let s_mac = match s_enc.is_aead() {
+            true => find_field!(self, other, s_mac),
+            false => Some(match_field!(self, other, s_mac, "server mac algorithm")?),
+        };
 
         // compress
         let c_compress = match_field!(self, other, c_compress, "client compression algorithm")?;
@@ -203,10 +288,13 @@ impl AlgList {
             public_key: vec![*pubkey].into(),
             c_encryption: vec![*c_enc].into(),
             s_encryption: vec![*s_enc].into(),
-            c_mac: vec![*c_mac].into(),
-            s_mac: vec![*s_mac].into(),
+            c_mac: c_mac.into_iter().copied().collect::<Vec<_>>().into(),
+            s_mac: s_mac.into_iter().copied().collect::<Vec<_>>().into(),
             c_compress: vec![*c_compress].into(),
             s_compress: vec![*s_compress].into(),
+            offered_public_key: vec![],
+            offered_key_exchange: vec![],
+            kex_markers: vec![],
         };
 
         info!("matched algorithms [{:?}]", negotiated);
@@ -216,7 +304,13 @@ impl AlgList {
 
     fn as_i(&self) -> Vec<u8> {
         let mut data = Data::new();
-        data.put_str(&self.key_exchange.to_string());
+        let key_exchange = self
+            .key_exchange
+            .iter()
+            .map(|x| x.as_ref())
+            .chain(self.kex_markers.iter().copied())
+            .collect::<Vec<&str>>();
+        data.put_str(&key_exchange.join(","));
         data.put_str(&self.public_key.to_string());
         data.put_str(&self.c_encryption.to_string());
         data.put_str(&self.s_encryption.to_string());
@@ -233,7 +327,7 @@ impl<'a> Packet<'a> for AlgList {
         info!("client algorithms: [{:?}]", self);
         let mut data = Data::new();
         data.put_u8(ssh_transport_code::KEXINIT);
-        data.extend(util::cookie());
+        data.extend(util::cookie(client.get_rng()));
         data.extend(self.as_i());
         data.put_str("")
             .put_str("")
@@ -247,8 +341,20 @@ impl<'a> Packet<'a> for AlgList {
     where
         Self: Sized,
     {
+        let max_name_list_len = pkt.get_client().get_max_name_list_len();
         let data = pkt.into_inner();
-        assert_eq!(data[0], ssh_transport_code::KEXINIT);
-        AlgList::from(data)
+        // the server KEXINIT must be the first packet of a key exchange,
+        // anything injected before it is rejected
+        match data.first() {
+            Some(&ssh_transport_code::KEXINIT) => AlgList::from(data, max_name_list_len),
+            x => {
+                let err_msg = match x {
+                    Some(x) => format!("expect KEXINIT, but got message {x}"),
+                    None => "expect KEXINIT, but got an empty packet".to_owned(),
+                };
+                error!(err_msg);
+                Err(SshError::KexError(err_msg))
+            }
+        }
     }
 }
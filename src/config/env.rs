@@ -0,0 +1,28 @@
+use crate::{SshError, SshResult};
+use tracing::*;
+
+/// An environment variable passed to the exec and shell channels,
+/// see [crate::SessionBuilder::env]
+#[derive(Debug, Clone)]
+pub(crate) struct EnvVar {
+    pub name: String,
+    pub value: String,
+    // fail the channel if the server refuses it, rather than only warn
+    pub required: bool,
+}
+
+impl EnvVar {
+    /// apply the policy to the server's reply
+    pub fn check(&self, accepted: bool) -> SshResult<()> {
+        if accepted {
+            debug!("env {} is accepted", self.name);
+            Ok(())
+        } else if self.required {
+            error!("required env {} is refused by the server", self.name);
+            Err(SshError::EnvRefused(self.name.to_owned()))
+        } else {
+            warn!("env {} is refused by the server, continue", self.name);
+            Ok(())
+        }
+    }
+}
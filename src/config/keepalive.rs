@@ -0,0 +1,124 @@
+use crate::SshResult;
+use socket2::SockRef;
+use std::net::TcpStream;
+use std::time::Duration;
+use tracing::*;
+
+/// The OS level TCP keepalive (`SO_KEEPALIVE`) parameters,
+/// see [crate::SessionBuilder::tcp_keepalive]
+///
+/// The parameters left unset keep the system defaults
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    idle: Option<Duration>,
+    interval: Option<Duration>,
+    retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// how long the connection stays idle before the first probe (`TCP_KEEPIDLE`)
+    pub fn with_idle(mut self, idle: Duration) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    /// the time between two probes (`TCP_KEEPINTVL`)
+    ///
+    /// Only supported on Linux, Android, macOS, iOS, FreeBSD, NetBSD and Windows
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// how many unanswered probes until the connection is dropped (`TCP_KEEPCNT`)
+    ///
+    /// Only supported on Linux, Android, macOS, iOS, FreeBSD and NetBSD
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    pub fn retries(&self) -> Option<u32> {
+        self.retries
+    }
+
+    /// enable the keepalive on `tcp` with these parameters
+    ///
+    /// [crate::SessionBuilder::connect] calls it for the sockets it creates,
+    /// call it by yourself when using [crate::SessionBuilder::connect_bio]
+    pub fn apply(&self, tcp: &TcpStream) -> SshResult<()> {
+        let mut keepalive = socket2::TcpKeepalive::new();
+        if let Some(idle) = self.idle {
+            keepalive = keepalive.with_time(idle);
+        }
+        if let Some(interval) = self.interval {
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "windows",
+            ))]
+            {
+                keepalive = keepalive.with_interval(interval);
+            }
+            #[cfg(not(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "windows",
+            )))]
+            warn!(
+                "The tcp keepalive interval {:?} is not supported on this platform",
+                interval
+            );
+        }
+        if let Some(retries) = self.retries {
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+            ))]
+            {
+                keepalive = keepalive.with_retries(retries);
+            }
+            #[cfg(not(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+            )))]
+            warn!(
+                "The tcp keepalive retries {} is not supported on this platform",
+                retries
+            );
+        }
+
+        SockRef::from(tcp).set_tcp_keepalive(&keepalive)?;
+        debug!("tcp keepalive enabled: {:?}", self);
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{SshError, SshResult};
+use ring::hmac;
+use ssh_key::known_hosts::{Entry, HostPatterns, KnownHosts, Marker};
+use ssh_key::{HashAlg, PublicKey};
+use std::io::{ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::*;
+
+/// called with (host, fingerprint) when the host is not in known_hosts
+pub(crate) type UnknownHostKeyCallback = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+/// called with (host, old fingerprint, new fingerprint) when the host key is changed
+pub(crate) type HostKeyChangedCallback = Arc<dyn Fn(&str, &str, &str) -> bool + Send + Sync>;
+
+/// How the server host key is checked against known_hosts,
+/// see [crate::SessionBuilder::host_key_verification]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyVerification {
+    /// reject the hosts that are not in known_hosts and the changed host keys
+    Strict,
+    /// trust the hosts that are not in known_hosts and append their keys to it,
+    /// but reject the changed host keys
+    AcceptNew,
+    /// trust any host key without reading known_hosts
+    AcceptAny,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct HostKeyCheck {
+    pub known_hosts: Option<PathBuf>,
+    // None to trust the unknown hosts without recording them
+    pub mode: Option<HostKeyVerification>,
+    // the name used to look up known_hosts, "host" or "[host]:port"
+    pub name: Option<String>,
+    pub on_unknown: Option<UnknownHostKeyCallback>,
+    pub on_changed: Option<HostKeyChangedCallback>,
+}
+
+enum Lookup {
+    Known,
+    Changed(String),
+    Unknown,
+}
+
+impl HostKeyCheck {
+    /// fill the host name with the peer address if not specified
+    pub fn set_peer(&mut self, peer: SocketAddr) {
+        self.set_target(&peer.ip().to_string(), peer.port())
+    }
+
+    /// same as [HostKeyCheck::set_peer], with the host as given,
+    /// e.g. the target behind a jump host or the name passed to connect
+    pub fn set_target(&mut self, host: &str, port: u16) {
+        if self.name.is_none() {
+            self.name = Some(match port {
+                22 => host.to_owned(),
+                port => format!("[{}]:{}", host, port),
+            });
+        }
+    }
+
+    /// verify the server host key `k_s` against known_hosts
+    ///
+    /// nothing is verified if no known_hosts file is configured
+    pub fn verify(&self, k_s: &[u8]) -> SshResult<()> {
+        let Some(ref path) = self.known_hosts else {
+            return Ok(());
+        };
+        if self.mode == Some(HostKeyVerification::AcceptAny) {
+            return Ok(());
+        }
+        let Some(ref host) = self.name else {
+            return Err(SshError::KexError(
+                "the host name to look up in known_hosts is unknown".to_owned(),
+            ));
+        };
+
+        let key =
+            PublicKey::from_bytes(k_s).map_err(|e| SshError::SshPubKeyError(e.to_string()))?;
+        let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+
+        match lookup(&read_entries(path)?, host, &key)? {
+            Lookup::Known => {
+                info!("host key of {} matches known_hosts", host);
+                Ok(())
+            }
+            Lookup::Changed(old) => {
+                warn!(
+                    "host key of {} has changed from {} to {}",
+                    host, old, fingerprint
+                );
+                match self.on_changed {
+                    Some(ref callback) if callback(host, &old, &fingerprint) => Ok(()),
+                    _ => Err(SshError::HostKeyMismatch {
+                        host: host.to_owned(),
+                        expected: old,
+                        actual: fingerprint,
+                    }),
+                }
+            }
+            Lookup::Unknown => {
+                info!("host {} is not known, fingerprint {}", host, fingerprint);
+                let accepted = match self.on_unknown {
+                    Some(ref callback) => callback(host, &fingerprint),
+                    None => self.mode != Some(HostKeyVerification::Strict),
+                };
+                if !accepted {
+                    return Err(SshError::UnknownHostKey {
+                        host: host.to_owned(),
+                        fingerprint,
+                    });
+                }
+                if self.mode == Some(HostKeyVerification::AcceptNew) {
+                    append_entry(path, host, &key)?;
+                    info!("host key of {} is added to {:?}", host, path);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `~/.ssh/known_hosts`, if the home directory is known
+pub(crate) fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".ssh").join("known_hosts"))
+}
+
+fn append_entry(path: &Path, host: &str, key: &PublicKey) -> SshResult<()> {
+    let line = key
+        .to_openssh()
+        .map_err(|e| SshError::SshPubKeyError(e.to_string()))?;
+    // keep the last line of the file intact
+    let needs_newline = match std::fs::read(path) {
+        Ok(content) => content.last().is_some_and(|c| *c != b'\n'),
+        Err(_) => false,
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if needs_newline {
+        file.write_all(b"\n")?;
+    }
+    writeln!(file, "{} {}", host, line)?;
+    Ok(())
+}
+
+/// the entries of a known_hosts file, a missing file has no entries
+fn read_entries(path: &Path) -> SshResult<Vec<Entry>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            debug!("known_hosts {:?} does not exist", path);
+            return Ok(vec![]);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = vec![];
+    for entry in KnownHosts::new(&content) {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => debug!("skip a bad known_hosts line: {}", e),
+        }
+    }
+    Ok(entries)
+}
+
+fn lookup(entries: &[Entry], host: &str, key: &PublicKey) -> SshResult<Lookup> {
+    let mut result = Lookup::Unknown;
+    for entry in entries
+        .iter()
+        .filter(|entry| host_matches(entry.host_patterns(), host))
+    {
+        let same_key = entry.public_key().key_data() == key.key_data();
+        match entry.marker() {
+            Some(Marker::Revoked) if same_key => {
+                return Err(SshError::KexError(format!(
+                    "the host key of {} is revoked",
+                    host
+                )))
+            }
+            // certificates are not supported
+            Some(_) => continue,
+            None => (),
+        }
+
+        if same_key {
+            result = Lookup::Known;
+        } else if entry.public_key().algorithm() == key.algorithm()
+            && matches!(result, Lookup::Unknown)
+        {
+            let old = entry.public_key().fingerprint(HashAlg::Sha256);
+            result = Lookup::Changed(old.to_string());
+        }
+    }
+    Ok(result)
+}
+
+fn host_matches(patterns: &HostPatterns, host: &str) -> bool {
+    match patterns {
+        HostPatterns::Patterns(patterns) => {
+            let mut matched = false;
+            for pattern in patterns {
+                if let Some(pattern) = pattern.strip_prefix('!') {
+                    if glob_match(pattern.as_bytes(), host.as_bytes()) {
+                        return false;
+                    }
+                } else if glob_match(pattern.as_bytes(), host.as_bytes()) {
+                    matched = true;
+                }
+            }
+            matched
+        }
+        HostPatterns::HashedName { salt, hash } => {
+            let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, salt);
+            hmac::verify(&key, host.as_bytes(), hash).is_ok()
+        }
+    }
+}
+
+/// `*` matches any sequence and `?` matches a single character
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p.eq_ignore_ascii_case(n) => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
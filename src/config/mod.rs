@@ -1,8 +1,19 @@
 pub(crate) mod algorithm;
 pub(crate) mod auth;
+
+pub use auth::{AuthMethod, Prompt};
+pub(crate) mod env;
+pub(crate) mod keepalive;
+pub(crate) mod known_hosts;
+pub(crate) mod subsystem;
+pub use keepalive::TcpKeepalive;
+pub use known_hosts::HostKeyVerification;
 pub(crate) mod version;
-use crate::algorithm::PubKey as PubKeyAlgs;
+pub(crate) mod window;
+use crate::algorithm::{Enc as EncAlgs, PubKey as PubKeyAlgs};
+use crate::constant::size;
 use std::time::Duration;
+pub use window::ChannelKind;
 
 fn insert_or_move_first(v: &mut Vec<PubKeyAlgs>, alg: PubKeyAlgs) {
     if let Some(i) = v.iter().position(|each| *each == alg) {
@@ -18,6 +29,27 @@ pub(crate) struct Config {
     pub auth: auth::AuthInfo,
     pub algs: algorithm::AlgList,
     pub timeout: Option<Duration>,
+    pub packet_dump: Option<usize>,
+    pub keepalive_idle: Option<Duration>,
+    pub keepalive_max_missed: Option<u32>,
+    pub reconnect_interval: Option<Duration>,
+    pub rekey_limit: Option<u64>,
+    pub rekey_interval: Option<Duration>,
+    pub tcp_keepalive: Option<TcpKeepalive>,
+    pub host_key_check: known_hosts::HostKeyCheck,
+    pub strong_ciphers_only: bool,
+    pub prefer_aead: bool,
+    pub host_key_algorithm: Option<PubKeyAlgs>,
+    pub window_sizes: window::WindowSizes,
+    pub window_adjust_threshold: u32,
+    pub env: Vec<env::EnvVar>,
+    // None to allow any subsystem
+    pub allowed_subsystems: Option<Vec<String>>,
+    pub ext_info: bool,
+    pub strict_kex: bool,
+    pub rng_seed: Option<u64>,
+    pub ephemeral_key: Option<Vec<u8>>,
+    pub max_name_list_len: usize,
     auto_tune: bool,
 }
 
@@ -28,6 +60,26 @@ impl Default for Config {
             auth: auth::AuthInfo::default(),
             ver: version::SshVersion::default(),
             timeout: Some(Duration::from_secs(30)),
+            packet_dump: None,
+            keepalive_idle: None,
+            keepalive_max_missed: None,
+            reconnect_interval: None,
+            rekey_limit: Some(size::REKEY_LIMIT),
+            rekey_interval: Some(Duration::from_secs(3600)),
+            tcp_keepalive: None,
+            host_key_check: known_hosts::HostKeyCheck::default(),
+            strong_ciphers_only: false,
+            prefer_aead: false,
+            host_key_algorithm: None,
+            window_sizes: window::WindowSizes::default(),
+            window_adjust_threshold: 0,
+            env: vec![],
+            allowed_subsystems: None,
+            ext_info: true,
+            strict_kex: true,
+            rng_seed: None,
+            ephemeral_key: None,
+            max_name_list_len: size::MAX_NAME_LIST_LEN,
             auto_tune: true,
         }
     }
@@ -41,10 +93,67 @@ impl Config {
             auth: auth::AuthInfo::default(),
             ver: version::SshVersion::default(),
             timeout: Some(Duration::from_secs(30)),
+            packet_dump: None,
+            keepalive_idle: None,
+            keepalive_max_missed: None,
+            reconnect_interval: None,
+            rekey_limit: Some(size::REKEY_LIMIT),
+            rekey_interval: Some(Duration::from_secs(3600)),
+            tcp_keepalive: None,
+            host_key_check: known_hosts::HostKeyCheck::default(),
+            strong_ciphers_only: false,
+            prefer_aead: false,
+            host_key_algorithm: None,
+            window_sizes: window::WindowSizes::default(),
+            window_adjust_threshold: 0,
+            env: vec![],
+            allowed_subsystems: None,
+            ext_info: true,
+            strict_kex: true,
+            rng_seed: None,
+            ephemeral_key: None,
+            max_name_list_len: size::MAX_NAME_LIST_LEN,
             auto_tune: false,
         }
     }
 
+    // only keep the AEAD ciphers with at least 128 bits strength
+    pub(crate) fn tune_alglist_on_cipher_strength(&mut self) {
+        if !self.strong_ciphers_only {
+            return;
+        }
+
+        let strong = |enc: &EncAlgs| enc.is_aead() && enc.strength() >= 128;
+        self.algs.c_encryption.retain(strong);
+        self.algs.s_encryption.retain(strong);
+    }
+
+    // move the AEAD ciphers first, and drop the MACs of a direction
+    // which offers nothing but the AEAD ciphers
+    pub(crate) fn tune_alglist_on_aead(&mut self) {
+        if !self.prefer_aead {
+            return;
+        }
+
+        let algs = &mut self.algs;
+        for (encs, macs) in [
+            (&mut algs.c_encryption, &mut algs.c_mac),
+            (&mut algs.s_encryption, &mut algs.s_mac),
+        ] {
+            encs.sort_by_key(|enc| !enc.is_aead());
+            if !encs.is_empty() && encs.iter().all(EncAlgs::is_aead) {
+                macs.clear();
+            }
+        }
+    }
+
+    // offer the forced host key algorithm only
+    pub(crate) fn tune_alglist_on_host_key_algorithm(&mut self) {
+        if let Some(alg) = self.host_key_algorithm {
+            self.algs.public_key = vec![alg].into();
+        }
+    }
+
     pub(crate) fn tune_alglist_on_private_key(&mut self) {
         if !self.auto_tune {
             return;
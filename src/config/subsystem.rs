@@ -0,0 +1,15 @@
+use crate::{SshError, SshResult};
+use tracing::*;
+
+/// check `name` against the allowed subsystems, see [crate::SessionBuilder::allowed_subsystems]
+///
+/// None allows any subsystem
+pub(crate) fn check(allowed: Option<&[String]>, name: &str) -> SshResult<()> {
+    match allowed {
+        Some(allowed) if !allowed.iter().any(|allowed| allowed == name) => {
+            error!("subsystem {} is not allowed", name);
+            Err(SshError::SubsystemNotAllowed(name.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -23,8 +23,7 @@ impl Default for SshVersion {
     }
 }
 
-/// <https://www.rfc-editor.org/rfc/rfc4253#section-4.2>
-
+// <https://www.rfc-editor.org/rfc/rfc4253#section-4.2>
 // When the connection has been established, both sides MUST send an
 // identification string.  This identification string MUST be
 
@@ -95,6 +94,12 @@ impl SshVersion {
         S: Read,
     {
         let buf = read_version(stream, timeout)?;
+        self.set_server_version(buf)
+    }
+
+    /// take the line read by [SshVersion::read_server_version],
+    /// or by the async session
+    pub(crate) fn set_server_version(&mut self, buf: Vec<u8>) -> SshResult<()> {
         if buf.len() < 4 || &buf[0..4] != SSH_MAGIC {
             error!("SSH version magic doesn't match");
             error!("Probably not an ssh server");
@@ -113,7 +118,7 @@ impl SshVersion {
     {
         info!("client version: [{}]", self.client_ver);
         let ver_string = format!("{}\r\n", self.client_ver);
-        let _ = stream.write(ver_string.as_bytes())?;
+        stream.write_all(ver_string.as_bytes())?;
         Ok(())
     }
 
@@ -0,0 +1,38 @@
+use crate::constant::size::LOCAL_WINDOW_SIZE;
+use std::collections::HashMap;
+
+/// The kind of a channel, see [crate::SessionBuilder::window_size]
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ChannelKind {
+    /// a raw channel opened by `open_channel`
+    Session,
+    Exec,
+    Shell,
+    Scp,
+    /// `direct-tcpip`, including the channels of the local forwarding,
+    /// and the `forwarded-tcpip` ones of the remote forwarding
+    DirectTcpip,
+    /// a subsystem such as sftp
+    Subsystem,
+}
+
+/// the initial local window of each kind of channels
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WindowSizes(HashMap<ChannelKind, u32>);
+
+impl WindowSizes {
+    /// 0 resets `kind` to the default
+    pub fn set(&mut self, kind: ChannelKind, size: u32) {
+        if size == 0 {
+            self.0.remove(&kind);
+        } else {
+            self.0.insert(kind, size);
+        }
+    }
+
+    pub fn get(&self, kind: ChannelKind) -> u32 {
+        self.0.get(&kind).copied().unwrap_or(LOCAL_WINDOW_SIZE)
+    }
+}
@@ -13,18 +13,46 @@ pub(crate) mod ssh_str {
     pub const PUBLIC_KEY: &str = "publickey";
     /// Authenticate with password
     pub const PASSWORD: &str = "password";
+    /// Authenticate by answering the prompts of the server
+    pub const KEYBOARD_INTERACTIVE: &str = "keyboard-interactive";
     /// Session level msg
     pub const SESSION: &str = "session";
+    /// Local port forwarding
+    pub const DIRECT_TCPIP: &str = "direct-tcpip";
+    /// Request the server to listen for remote port forwarding
+    pub const TCPIP_FORWARD: &str = "tcpip-forward";
+    /// Stop listening for remote port forwarding
+    pub const CANCEL_TCPIP_FORWARD: &str = "cancel-tcpip-forward";
+    /// A connection to the remote forwarding port, opened by the server
+    pub const FORWARDED_TCPIP: &str = "forwarded-tcpip";
+    /// Keepalive probe, the server replies whether it knows it or not
+    pub const KEEPALIVE_OPENSSH: &str = "keepalive@openssh.com";
     /// Open a Shell
     pub const SHELL: &str = "shell";
     /// Execute a command
     pub const EXEC: &str = "exec";
     /// SCP
     pub const SCP: &str = "scp";
+    /// Pass an environment variable
+    pub const ENV: &str = "env";
     /// Request a pesudo-terminal
     pub const PTY_REQ: &str = "pty-req";
+    /// Tell the new dimensions of the terminal
+    pub const WINDOW_CHANGE: &str = "window-change";
+    /// Deliver a signal to the remote process
+    pub const SIGNAL: &str = "signal";
+    /// Start a subsystem such as sftp
+    pub const SUBSYSTEM: &str = "subsystem";
+    /// The file transfer subsystem
+    pub const SFTP: &str = "sftp";
+    pub const STATVFS: &str = "statvfs@openssh.com";
     /// The xterm style that used for the pty
     pub const XTERM_VAR: &str = "xterm-256color";
+    /// The kex marker that we accept SSH_MSG_EXT_INFO, RFC 8308
+    pub const EXT_INFO_C: &str = "ext-info-c";
+    /// The kex markers of the strict key exchange, the OpenSSH extension against the Terrapin attack
+    pub const KEX_STRICT_C: &str = "kex-strict-c-v00@openssh.com";
+    pub const KEX_STRICT_S: &str = "kex-strict-s-v00@openssh.com";
 }
 
 #[allow(dead_code)]
@@ -83,6 +111,12 @@ pub(crate) mod size {
     pub const BUF_SIZE: usize = 32768;
     /// The default window size of the flow-control
     pub const LOCAL_WINDOW_SIZE: u32 = 2097152;
+    /// The max size of a packet on the wire that we accept, the same as OpenSSH
+    pub const MAX_PACKET_SIZE: usize = 256 * 1024;
+    /// The default max length of one name-list in the server KEXINIT
+    pub const MAX_NAME_LIST_LEN: usize = 16 * 1024;
+    /// The default bytes on the wire after which the keys are renewed
+    pub const REKEY_LIMIT: u64 = 1 << 30;
 }
 
 /// <https://www.rfc-editor.org/rfc/rfc4254#section-9>
@@ -104,6 +138,11 @@ pub(crate) mod ssh_connection_code {
     pub const CHANNEL_FAILURE: u8 = 100;
 }
 
+/// <https://www.rfc-editor.org/rfc/rfc4254#section-5.2>
+pub(crate) mod ssh_extended_data_code {
+    pub const STDERR: u32 = 1;
+}
+
 /// <https://www.rfc-editor.org/rfc/rfc4254#section-5.1>
 #[allow(dead_code)]
 pub(crate) mod ssh_channel_fail_code {
@@ -122,32 +161,14 @@ pub(crate) mod ssh_transport_code {
     pub const DEBUG: u8 = 4;
     pub const SERVICE_REQUEST: u8 = 5;
     pub const SERVICE_ACCEPT: u8 = 6;
+    /// <https://www.rfc-editor.org/rfc/rfc8308#section-2.3>
+    pub const EXT_INFO: u8 = 7;
     pub const KEXINIT: u8 = 20;
     pub const NEWKEYS: u8 = 21;
     pub const KEXDH_INIT: u8 = 30;
     pub const KEXDH_REPLY: u8 = 31;
 }
 
-/// <https://www.rfc-editor.org/rfc/rfc4253#section-11.1>
-#[allow(dead_code)]
-pub(crate) mod ssh_disconnection_code {
-    pub const HOST_NOT_ALLOWED_TO_CONNECT: u8 = 1;
-    pub const PROTOCOL_ERROR: u8 = 2;
-    pub const KEY_EXCHANGE_FAILED: u8 = 3;
-    pub const RESERVED: u8 = 4;
-    pub const MAC_ERROR: u8 = 5;
-    pub const COMPRESSION_ERROR: u8 = 6;
-    pub const SERVICE_NOT_AVAILABLE: u8 = 7;
-    pub const PROTOCOL_VERSION_NOT_SUPPORTED: u8 = 8;
-    pub const HOST_KEY_NOT_VERIFIABLE: u8 = 9;
-    pub const CONNECTION_LOST: u8 = 10;
-    pub const BY_APPLICATION: u8 = 11;
-    pub const TOO_MANY_CONNECTIONS: u8 = 12;
-    pub const AUTH_CANCELLED_BY_USER: u8 = 13;
-    pub const NO_MORE_AUTH_METHODS_AVAILABLE: u8 = 14;
-    pub const ILLEGAL_USER_NAME: u8 = 15;
-}
-
 /// <https://www.rfc-editor.org/rfc/rfc4252#section-6>
 #[allow(dead_code)]
 pub(crate) mod ssh_user_auth_code {
@@ -156,6 +177,61 @@ pub(crate) mod ssh_user_auth_code {
     pub const SUCCESS: u8 = 52;
     pub const BANNER: u8 = 53;
     pub const PK_OK: u8 = 60;
+    // <https://www.rfc-editor.org/rfc/rfc4256#section-5>
+    // the same number as PK_OK, told apart by the method in progress
+    pub const INFO_REQUEST: u8 = 60;
+    pub const INFO_RESPONSE: u8 = 61;
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-3>
+#[allow(dead_code)]
+pub(crate) mod ssh_fxp_code {
+    pub const INIT: u8 = 1;
+    pub const VERSION: u8 = 2;
+    pub const OPEN: u8 = 3;
+    pub const CLOSE: u8 = 4;
+    pub const READ: u8 = 5;
+    pub const WRITE: u8 = 6;
+    pub const LSTAT: u8 = 7;
+    pub const FSTAT: u8 = 8;
+    pub const SETSTAT: u8 = 9;
+    pub const FSETSTAT: u8 = 10;
+    pub const OPENDIR: u8 = 11;
+    pub const READDIR: u8 = 12;
+    pub const REMOVE: u8 = 13;
+    pub const MKDIR: u8 = 14;
+    pub const RMDIR: u8 = 15;
+    pub const REALPATH: u8 = 16;
+    pub const STAT: u8 = 17;
+    pub const RENAME: u8 = 18;
+    pub const STATUS: u8 = 101;
+    pub const HANDLE: u8 = 102;
+    pub const DATA: u8 = 103;
+    pub const NAME: u8 = 104;
+    pub const ATTRS: u8 = 105;
+    pub const EXTENDED: u8 = 200;
+    pub const EXTENDED_REPLY: u8 = 201;
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-5>
+pub(crate) mod ssh_fxp_attr {
+    pub const SIZE: u32 = 0x00000001;
+    pub const UIDGID: u32 = 0x00000002;
+    pub const PERMISSIONS: u32 = 0x00000004;
+    pub const ACMODTIME: u32 = 0x00000008;
+    pub const EXTENDED: u32 = 0x80000000;
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-miller-ssh-agent#section-6.1>
+pub(crate) mod ssh_agent_code {
+    pub const FAILURE: u8 = 5;
+    pub const REQUEST_IDENTITIES: u8 = 11;
+    pub const IDENTITIES_ANSWER: u8 = 12;
+    pub const SIGN_REQUEST: u8 = 13;
+    pub const SIGN_RESPONSE: u8 = 14;
+    // the flags of SIGN_REQUEST to sign ssh-rsa keys with sha2
+    pub const RSA_SHA2_256: u32 = 2;
+    pub const RSA_SHA2_512: u32 = 4;
 }
 
 /// The magic that used when doing hash after kex
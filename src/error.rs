@@ -9,20 +9,44 @@ pub type SshResult<I> = Result<I, SshError>;
 pub enum SshError {
     #[error("Version dismatch: {our} vs {their}")]
     VersionDismatchError { our: String, their: String },
+    #[error("Disconnected by the server with reason {code}: {description}")]
+    Disconnected { code: u32, description: String },
     #[error("Key exchange error: {0}")]
     KexError(String),
+    #[error("Host key of {host} has changed: {expected} vs {actual}")]
+    HostKeyMismatch {
+        host: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Host key of {host} is not known: {fingerprint}")]
+    UnknownHostKey { host: String, fingerprint: String },
     #[error("Parse ssh key error: {0}")]
     SshPubKeyError(String),
     #[error("Auth error")]
     AuthError,
+    #[error("Agent error: {0}")]
+    AgentError(String),
     #[error("Timeout")]
     TimeoutError,
     #[error(transparent)]
     DataFormatError(#[from] std::string::FromUtf8Error),
     #[error("Encryption error: {0}")]
     EncryptionError(String),
+    #[error("The MAC of the server packet {0} does not match")]
+    MacVerificationError(u32),
     #[error("Compression error: {0}")]
     CompressionError(String),
+    #[error("Protocol error: {0}")]
+    ProtocolError(String),
+    #[error("The required env {0} is refused by the server")]
+    EnvRefused(String),
+    #[error("The subsystem {0} is not allowed")]
+    SubsystemNotAllowed(String),
+    #[error("The subsystem {0} is refused by the server")]
+    SubsystemRefused(String),
+    #[error("Channel failure: {}", String::from_utf8_lossy(.stderr).trim_end())]
+    ChannelFailure { stderr: Vec<u8> },
     #[cfg(feature = "scp")]
     #[error(transparent)]
     SystemTimeError(#[from] std::time::SystemTimeError),
@@ -35,6 +59,10 @@ pub enum SshError {
     #[cfg(feature = "scp")]
     #[error("Scp error: {0}")]
     ScpError(String),
+    #[error("Sftp error {code}: {message}")]
+    SftpError { code: u32, message: String },
+    #[error("Sftp request is not answered in time")]
+    SftpTimeout,
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("IPC error: {0}")]
@@ -54,3 +82,10 @@ impl<T> From<SendError<T>> for SshError {
         Self::IpcError(value.to_string())
     }
 }
+
+#[cfg(feature = "async")]
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for SshError {
+    fn from(value: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        Self::IpcError(value.to_string())
+    }
+}
@@ -38,19 +38,64 @@ mod session;
 mod util;
 
 pub mod error;
+pub mod sftp;
+
+use std::net::{TcpStream, ToSocketAddrs};
 
 pub use channel::*;
+pub use config::{AuthMethod, ChannelKind, HostKeyVerification, Prompt, TcpKeepalive};
 pub use error::SshError;
 pub use error::SshResult;
-pub use model::{TerminalSize, TerminalSizeType};
+pub use model::{
+    CompressionStat, CompressionStats, DisconnectReason, ExecOutput, KexDetails, KeyLengths,
+    NegotiatedAlgorithms, PtyConfig, TerminalSize, TerminalSizeType,
+};
+#[cfg(feature = "dangerous-key-export")]
+pub use model::{DerivedKeys, SessionKeys};
 pub use session::{LocalSession, SessionBroker, SessionBuilder, SessionConnector};
 
+#[cfg(feature = "async")]
+pub use session::AsyncSession;
+
 /// create a session via session builder w/ default configuration
 ///
 pub fn create_session() -> SessionBuilder {
     SessionBuilder::new()
 }
 
+/// connect to `addr` and authenticate as `username` with the single `method`
+///
+/// a shortcut of [SessionBuilder] with the default configuration,
+/// return a [LocalSession] which is ready to open channels
+///
+/// ```no_run
+/// use ssh::AuthMethod;
+///
+/// let mut session = ssh::connect_and_auth(
+///     "127.0.0.1:22",
+///     "ubuntu",
+///     AuthMethod::Password("password".to_owned()),
+/// )
+/// .unwrap();
+/// let exec = session.open_exec().unwrap();
+/// let vec: Vec<u8> = exec.send_command("ls -all").unwrap();
+/// session.close();
+/// ```
+pub fn connect_and_auth<A>(
+    addr: A,
+    username: &str,
+    method: AuthMethod,
+) -> SshResult<LocalSession<TcpStream>>
+where
+    A: ToSocketAddrs,
+{
+    Ok(create_session()
+        .username(username)
+        .auth_method(method)?
+        .connect(addr)?
+        .run_local())
+}
+
 /// create a session via session builder w/o default configuration
 ///
 pub fn create_session_without_default() -> SessionBuilder {
@@ -1,19 +1,98 @@
-use std::sync::mpsc::Sender;
+use std::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+    time::Instant,
+};
 
-use super::Data;
+use super::{Data, DisconnectReason, RemoteClose};
+use crate::error::SshResult;
 
 pub(crate) enum BackendRqst {
-    OpenChannel(u32, Data, Sender<BackendResp>),
+    // client channel id, local window, the open message, notified of the remote close
+    OpenChannel(u32, u32, Data, Arc<RemoteClose>, Sender<BackendResp>),
     Data(u32, Data),
+    // client channel id, replied once all the pending data is sent
+    #[cfg(feature = "scp")]
+    Flush(u32, Sender<BackendResp>),
     Command(u32, Data),
+    // client channel id, the EOF is sent after all the pending data
+    Eof(u32),
+    // client channel id, whether its data goes ahead of the bulk data
+    Interactive(u32, bool),
     CloseChannel(u32, Data),
+    GlobalRequest(Data, Sender<BackendResp>),
+    // the bind address, the requested port, the port listened on,
+    // where the forwarded-tcpip channels to the port go
+    Listen(String, u32, u32, Sender<ForwardedChannel>),
+    // when the rekey is requested, replied once the key exchange is finished
+    Rekey(Instant, Sender<BackendResp>),
+    Shutdown(DisconnectReason, String, Sender<BackendResp>),
+}
+
+/// the requests to the driver task of an [crate::AsyncSession],
+/// whose replies are the same as the backend thread
+#[cfg(feature = "async")]
+pub(crate) enum AsyncRqst {
+    // client channel id, local window, the open message, where the channel messages go
+    OpenChannel(
+        u32,
+        u32,
+        Data,
+        tokio::sync::mpsc::UnboundedSender<BackendResp>,
+    ),
+    Data(u32, Data),
+    Command(u32, Data),
+    // client channel id, whether its data goes ahead of the bulk data
+    Interactive(u32, bool),
+    // client channel id, the close is sent by the driver,
+    // which knows the server's id even if the opener does not yet
+    CloseChannel(u32),
+    // replied once the disconnect is written
+    Shutdown(tokio::sync::oneshot::Sender<()>),
 }
 
 pub(crate) enum BackendResp {
     Ok(u32),
     Fail(String),
     Data(Data),
+    // the data type code, the data
+    ExtendedData(u32, Data),
     ExitStatus(u32),
-    TermMsg(String),
+    // the signal name, the terminate message
+    TermMsg(Option<String>, String),
     Close,
 }
+
+/// where the backend delivers the messages of a channel,
+/// the std channels of the backend thread, or the tokio ones of the async session
+pub(crate) trait RespSender: Send {
+    fn send(&self, resp: BackendResp) -> SshResult<()>;
+}
+
+impl RespSender for Sender<BackendResp> {
+    fn send(&self, resp: BackendResp) -> SshResult<()> {
+        Ok(Sender::send(self, resp)?)
+    }
+}
+
+#[cfg(feature = "async")]
+impl RespSender for tokio::sync::mpsc::UnboundedSender<BackendResp> {
+    // an async channel may be dropped at any time, e.g. along with a cancelled future,
+    // then what is still sent to it before the close is done is dropped
+    fn send(&self, resp: BackendResp) -> SshResult<()> {
+        let _ = tokio::sync::mpsc::UnboundedSender::send(self, resp);
+        Ok(())
+    }
+}
+
+/// a `forwarded-tcpip` channel opened by the server and confirmed by the backend
+pub(crate) struct ForwardedChannel {
+    pub client_id: u32,
+    pub server_id: u32,
+    pub rcv: Receiver<BackendResp>,
+    pub remote_close: Arc<RemoteClose>,
+    // the address & port of the peer connected to the server
+    pub originator: (String, u32),
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The payload bytes before and after the compression in one direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStat {
+    /// bytes of the plain payloads
+    pub uncompressed: u64,
+    /// bytes of the compressed payloads, as they are on the wire
+    pub compressed: u64,
+}
+
+impl CompressionStat {
+    /// `uncompressed / compressed`, 1.0 if nothing has been transferred
+    pub fn ratio(&self) -> f64 {
+        if self.compressed == 0 {
+            1.0
+        } else {
+            self.uncompressed as f64 / self.compressed as f64
+        }
+    }
+}
+
+/// The compression statistics of all the packets since the session starts,
+/// including the ones sent before the compression is enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// client to server
+    pub send: CompressionStat,
+    /// server to client
+    pub recv: CompressionStat,
+}
+
+/// the counters shared by the client and the session
+#[derive(Debug, Default)]
+pub(crate) struct CompressionCounter {
+    send_uncompressed: AtomicU64,
+    send_compressed: AtomicU64,
+    recv_uncompressed: AtomicU64,
+    recv_compressed: AtomicU64,
+}
+
+impl CompressionCounter {
+    pub fn on_send(&self, uncompressed: usize, compressed: usize) {
+        self.send_uncompressed
+            .fetch_add(uncompressed as u64, Ordering::Relaxed);
+        self.send_compressed
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    pub fn on_recv(&self, uncompressed: usize, compressed: usize) {
+        self.recv_uncompressed
+            .fetch_add(uncompressed as u64, Ordering::Relaxed);
+        self.recv_compressed
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            send: CompressionStat {
+                uncompressed: self.send_uncompressed.load(Ordering::Relaxed),
+                compressed: self.send_compressed.load(Ordering::Relaxed),
+            },
+            recv: CompressionStat {
+                uncompressed: self.recv_uncompressed.load(Ordering::Relaxed),
+                compressed: self.recv_compressed.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
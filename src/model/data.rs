@@ -6,7 +6,7 @@ use super::Packet;
 
 /// Data Type Representations Used in the SSH Protocols
 /// <https://www.rfc-editor.org/rfc/rfc4251#section-5>
-
+///
 /// byte
 ///
 /// A byte represents an arbitrary 8-bit value (octet).  Fixed length
@@ -130,6 +130,12 @@ impl Data {
         self
     }
 
+    // write uint64
+    pub fn put_u64(&mut self, v: u64) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
     // write string
     pub fn put_str(&mut self, str: &str) -> &mut Self {
         let v = str.as_bytes();
@@ -148,8 +154,13 @@ impl Data {
     // write mpint
     pub fn put_mpint(&mut self, v: &[u8]) -> Vec<u8> {
         let mut result: Vec<u8> = Vec::new();
+        // unnecessary leading zeros MUST NOT be included
+        let v = match v.iter().position(|x| *x != 0) {
+            Some(i) => &v[i..],
+            None => &[],
+        };
         // 0x80 = 128
-        if v[0] & 0x80 != 0 {
+        if !v.is_empty() && v[0] & 0x80 != 0 {
             result.push(0);
         }
         result.extend(v);
@@ -172,6 +183,12 @@ impl Data {
         u32::from_be_bytes(u32_buf.try_into().unwrap())
     }
 
+    // get uint64
+    pub fn get_u64(&mut self) -> u64 {
+        let u64_buf = self.0.drain(..8).collect::<Vec<u8>>();
+        u64::from_be_bytes(u64_buf.try_into().unwrap())
+    }
+
     // get [bytes]
     pub fn get_u8s(&mut self) -> Vec<u8> {
         let len = self.get_u32() as usize;
@@ -0,0 +1,55 @@
+/// <https://www.rfc-editor.org/rfc/rfc4253#section-11.1>
+///
+/// The reason code that is sent along with SSH_MSG_DISCONNECT
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectReason {
+    HostNotAllowedToConnect = 1,
+    ProtocolError = 2,
+    KeyExchangeFailed = 3,
+    Reserved = 4,
+    MacError = 5,
+    CompressionError = 6,
+    ServiceNotAvailable = 7,
+    ProtocolVersionNotSupported = 8,
+    HostKeyNotVerifiable = 9,
+    ConnectionLost = 10,
+    #[default]
+    ByApplication = 11,
+    TooManyConnections = 12,
+    AuthCancelledByUser = 13,
+    NoMoreAuthMethodsAvailable = 14,
+    IllegalUserName = 15,
+}
+
+impl DisconnectReason {
+    /// the reason code on the wire
+    pub fn code(&self) -> u32 {
+        *self as u32
+    }
+
+    /// the reason of a code on the wire, e.g. in [crate::SshError::Disconnected],
+    /// None for the codes not defined by the RFC
+    pub fn from_code(code: u32) -> Option<Self> {
+        use DisconnectReason::*;
+        let reason = match code {
+            1 => HostNotAllowedToConnect,
+            2 => ProtocolError,
+            3 => KeyExchangeFailed,
+            4 => Reserved,
+            5 => MacError,
+            6 => CompressionError,
+            7 => ServiceNotAvailable,
+            8 => ProtocolVersionNotSupported,
+            9 => HostKeyNotVerifiable,
+            10 => ConnectionLost,
+            11 => ByApplication,
+            12 => TooManyConnections,
+            13 => AuthCancelledByUser,
+            14 => NoMoreAuthMethodsAvailable,
+            15 => IllegalUserName,
+            _ => return None,
+        };
+        Some(reason)
+    }
+}
@@ -0,0 +1,16 @@
+/// The result of a finished command,
+/// see [crate::LocalExec::wait] and [crate::ExecBroker::wait]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOutput {
+    /// everything the command writes to stdout
+    pub output: Vec<u8>,
+    /// everything the command writes to stderr
+    pub stderr: Vec<u8>,
+    /// the exit status sent by the server,
+    /// None if there is none, e.g. the command is killed by a signal or the connection is lost
+    pub exit_status: Option<u32>,
+    /// the name of the signal (without the "SIG" prefix) which killed the command, if any
+    pub exit_signal: Option<String>,
+    /// the message if the command is killed by a signal, empty otherwise
+    pub terminate_msg: String,
+}
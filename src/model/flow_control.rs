@@ -1,17 +1,22 @@
-use crate::constant::size::LOCAL_WINDOW_SIZE;
-
 use crate::constant::size;
 
 pub(crate) struct FlowControl {
     local_window: u32,
     remote_window: u32,
+    // the local window consumed but not given back yet
+    consumed: u32,
+    // give back the consumed window once it reaches this
+    adjust_threshold: u32,
 }
 
 impl FlowControl {
-    pub fn new(remote: u32) -> Self {
+    /// the threshold is capped at the local window, which would never be given back otherwise
+    pub fn new(local: u32, remote: u32, adjust_threshold: u32) -> Self {
         FlowControl {
-            local_window: LOCAL_WINDOW_SIZE,
+            local_window: local,
             remote_window: remote,
+            consumed: 0,
+            adjust_threshold: adjust_threshold.min(local),
         }
     }
 
@@ -49,6 +54,17 @@ impl FlowControl {
         buf.split_off(can_send)
     }
 
+    /// record `len` bytes consumed,
+    /// return the window to give back once the threshold is reached
+    pub fn consume(&mut self, len: u32) -> Option<u32> {
+        self.consumed += len;
+        if self.consumed > 0 && self.consumed >= self.adjust_threshold {
+            Some(std::mem::take(&mut self.consumed))
+        } else {
+            None
+        }
+    }
+
     pub fn on_recv(&mut self, size: u32) {
         self.remote_window += size
     }
@@ -0,0 +1,26 @@
+/// The lengths of the keys derived for one direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyLengths {
+    /// the initial IV, 0 if the cipher takes none
+    pub iv: usize,
+    /// the encryption key
+    pub key: usize,
+    /// the integrity key, 0 for the AEAD ciphers which need no separate MAC
+    pub mac_key: usize,
+}
+
+/// The inputs and the parameters of the key derivation in the last key exchange,
+/// for the interop diagnostics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KexDetails {
+    /// the hash of the key exchange which is also used to derive the keys, e.g. `sha256`
+    pub hash: String,
+    /// client to server
+    pub c_keys: KeyLengths,
+    /// server to client
+    pub s_keys: KeyLengths,
+    /// the payload of the client's SSH_MSG_KEXINIT, `I_C` in the exchange hash
+    pub client_kexinit: Vec<u8>,
+    /// the payload of the server's SSH_MSG_KEXINIT, `I_S` in the exchange hash
+    pub server_kexinit: Vec<u8>,
+}
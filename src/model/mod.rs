@@ -1,31 +1,56 @@
 mod backend_msg;
+mod compression_stats;
 mod data;
+mod disconnect;
+mod exec_output;
 mod flow_control;
+mod kex_details;
+mod negotiated;
 mod packet;
+mod remote_close;
 mod sequence;
 mod terminal;
 mod timeout;
 mod u32iter;
 
+#[cfg(feature = "async")]
+mod packet_async;
+
 #[cfg(feature = "scp")]
 mod scp_file;
 
+#[cfg(feature = "dangerous-key-export")]
+mod session_keys;
+
 use std::{
     cell::RefCell,
     rc::Rc,
     sync::{Arc, Mutex},
 };
 
+pub use compression_stats::{CompressionStat, CompressionStats};
+pub use disconnect::DisconnectReason;
+pub use exec_output::ExecOutput;
+pub use kex_details::{KexDetails, KeyLengths};
+pub use negotiated::NegotiatedAlgorithms;
 pub use terminal::*;
 
+#[cfg(feature = "dangerous-key-export")]
+pub use session_keys::{DerivedKeys, SessionKeys};
+
 pub(crate) use backend_msg::*;
+pub(crate) use compression_stats::CompressionCounter;
 pub(crate) use data::Data;
 pub(crate) use flow_control::FlowControl;
 pub(crate) use packet::{Packet, SecPacket};
+pub(crate) use remote_close::RemoteClose;
 pub(crate) use sequence::Sequence;
 pub(crate) use timeout::Timeout;
 pub(crate) use u32iter::U32Iter;
 
+#[cfg(feature = "async")]
+pub(crate) use packet_async::{PacketReader, PacketWriter};
+
 #[cfg(feature = "scp")]
 pub(crate) use scp_file::ScpFile;
 
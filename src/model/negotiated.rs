@@ -0,0 +1,15 @@
+/// The algorithms agreed on in a key exchange, by their names,
+/// see [crate::SessionBuilder::post_auth]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NegotiatedAlgorithms {
+    pub key_exchange: String,
+    pub host_key: String,
+    /// client to server
+    pub c_encryption: String,
+    /// server to client
+    pub s_encryption: String,
+    pub c_mac: String,
+    pub s_mac: String,
+    pub c_compression: String,
+    pub s_compression: String,
+}
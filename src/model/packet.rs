@@ -1,8 +1,14 @@
 use std::io::{Read, Write};
 use std::time::Duration;
 
-use crate::error::SshResult;
-use crate::{client::Client, model::Data};
+use crate::error::{SshError, SshResult};
+use crate::{
+    client::Client,
+    constant::{size, ssh_transport_code},
+    model::Data,
+    util,
+};
+use tracing::*;
 
 use super::timeout::Timeout;
 
@@ -42,13 +48,11 @@ use super::timeout::Timeout;
 /// larger.  There MUST be at least four bytes of padding.  The
 /// padding SHOULD consist of random bytes.  The maximum amount of
 /// padding is 255 bytes.
-
 ///
 /// **mac**
 /// Message Authentication Code.  If message authentication has
 /// been negotiated, this field contains the MAC bytes.  Initially,
-/// the MAC algorithm MUST be "none".。
-
+/// the MAC algorithm MUST be "none".
 fn read_with_timeout<S>(stream: &mut S, tm: Option<Duration>, buf: &mut [u8]) -> SshResult<()>
 where
     S: Read,
@@ -59,6 +63,7 @@ where
 
     loop {
         match stream.read(&mut buf[offset..]) {
+            Ok(0) if offset < want_len => return Err(eof().into()),
             Ok(i) => {
                 offset += i;
                 if offset == want_len {
@@ -80,11 +85,20 @@ where
     }
 }
 
+pub(super) fn eof() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "the connection is closed by the server",
+    )
+}
+
+// return 0 if nothing can be read for now
 fn try_read<S>(stream: &mut S, _tm: Option<Duration>, buf: &mut [u8]) -> SshResult<usize>
 where
     S: Read,
 {
     match stream.read(buf) {
+        Ok(0) => Err(eof().into()),
         Ok(i) => Ok(i),
         Err(e) => {
             if let std::io::ErrorKind::WouldBlock = e.kind() {
@@ -154,7 +168,18 @@ impl<'a> SecPacket<'a> {
         S: Write,
     {
         let tm = self.client.get_timeout();
+        let seq = self.client.get_seq().get_client();
+        if let Some(limit) = self.client.get_packet_dump() {
+            trace!(
+                ">>> send packet, seq {}: {}",
+                seq,
+                util::hex_dump(&self.payload, limit)
+            );
+        }
         let payload = self.client.get_compressor().compress(&self.payload)?;
+        self.client
+            .get_compression_counter()
+            .on_send(self.payload.len(), payload.len());
         let payload_len = payload.len() as u32;
         let pad_len = {
             let mut pad = payload_len as i32 + 1;
@@ -169,86 +194,256 @@ impl<'a> SecPacket<'a> {
         buf.extend(packet_len.to_be_bytes());
         buf.extend([pad_len]);
         buf.extend(payload);
-        buf.extend(vec![0; pad_len as usize]);
-        let seq = self.client.get_seq().get_client();
+        // the padding must be random, like the session's other randomness
+        let mut padding = vec![0; pad_len as usize];
+        self.client.get_rng().fill_bytes(&mut padding);
+        buf.extend(padding);
         self.client.get_encryptor().encrypt(seq, &mut buf);
-        write_with_timeout(stream, tm, &buf)
+        write_with_timeout(stream, tm, &buf)?;
+        self.client.touch(buf.len());
+        Ok(())
     }
 
     pub fn from_stream<S>(stream: &mut S, client: &'a mut Client) -> SshResult<Self>
     where
         S: Read,
     {
-        let tm = client.get_timeout();
-        let bsize = Self::get_align(client.get_encryptor().bsize()) as usize;
-
-        // read the first block
-        let mut first_block = vec![0; bsize];
-        read_with_timeout(stream, tm, &mut first_block)?;
-
-        // detect the total len
-        let seq = client.get_seq().get_server();
-        let data_len = client.get_encryptor().data_len(seq, &first_block);
-
-        // read remain
-        let mut data = Data::uninit_new(data_len);
-        data[0..bsize].clone_from_slice(&first_block);
-        read_with_timeout(stream, tm, &mut data[bsize..])?;
-
-        // decrypt all
-        let data = client.get_encryptor().decrypt(seq, &mut data)?;
+        loop {
+            let tm = client.get_timeout();
+            let bsize = Self::get_align(client.get_decryptor().bsize()) as usize;
 
-        // unpacking
-        let pkt_len = u32::from_be_bytes(data[0..4].try_into().unwrap());
-        let pad_len = data[4];
-        let payload_len = pkt_len - pad_len as u32 - 1;
+            // read the first block
+            let mut first_block = vec![0; bsize];
+            read_with_timeout(stream, tm, &mut first_block)?;
 
-        let payload = data[5..payload_len as usize + 5].into();
-        let payload = client.get_compressor().decompress(payload)?.into();
-
-        Ok(Self { payload, client })
+            let payload = Self::read_remain(stream, client, first_block)?;
+            if !Self::consume(&payload, client)? {
+                return Ok(Self { payload, client });
+            }
+        }
     }
 
     pub fn try_from_stream<S>(stream: &mut S, client: &'a mut Client) -> SshResult<Option<Self>>
     where
         S: Read,
     {
-        let tm = client.get_timeout();
-        let bsize = Self::get_align(client.get_encryptor().bsize()) as usize;
+        loop {
+            let tm = client.get_timeout();
+            let bsize = Self::get_align(client.get_decryptor().bsize()) as usize;
+
+            // read the first block
+            let mut first_block = vec![0; bsize];
+            let read = try_read(stream, tm, &mut first_block)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            // the socket may give us only a part of the block
+            read_with_timeout(stream, tm, &mut first_block[read..])?;
 
-        // read the first block
-        let mut first_block = vec![0; bsize];
-        let read = try_read(stream, tm, &mut first_block)?;
-        if read == 0 {
-            return Ok(None);
+            let payload = Self::read_remain(stream, client, first_block)?;
+            if !Self::consume(&payload, client)? {
+                return Ok(Some(Self { payload, client }));
+            }
         }
+    }
 
-        // detect the total len
+    /// take the first packet off `buf` if it's all there,
+    /// for the streams which are read into a buffer rather than by the packet
+    ///
+    /// the packets consumed by [SecPacket::consume] are taken too,
+    /// None if no other whole packet is in the buffer yet
+    #[cfg(feature = "async")]
+    pub fn try_from_buf(buf: &mut Vec<u8>, client: &'a mut Client) -> SshResult<Option<Self>> {
+        loop {
+            let bsize = Self::get_align(client.get_decryptor().bsize()) as usize;
+            if buf.len() < bsize {
+                return Ok(None);
+            }
+            // the sequence number is taken only once the whole packet is there
+            let seq = client.get_seq().peek_server();
+            let data_len = Self::data_len(client, seq, &buf[..bsize])?;
+            if buf.len() < data_len {
+                return Ok(None);
+            }
+            let data: Vec<u8> = buf.drain(..data_len).collect();
+            let seq = client.get_seq().get_server();
+            let payload = Self::open(client, seq, data.into())?;
+            if !Self::consume(&payload, client)? {
+                return Ok(Some(Self { payload, client }));
+            }
+        }
+    }
+
+    // read the rest of the packet whose first block is already read
+    // then return the payload
+    fn read_remain<S>(stream: &mut S, client: &mut Client, first_block: Vec<u8>) -> SshResult<Data>
+    where
+        S: Read,
+    {
+        let tm = client.get_timeout();
+        let bsize = first_block.len();
         let seq = client.get_seq().get_server();
-        let data_len = client.get_encryptor().data_len(seq, &first_block);
+        let data_len = Self::data_len(client, seq, &first_block)?;
 
         // read remain
         let mut data = Data::uninit_new(data_len);
         data[0..bsize].clone_from_slice(&first_block);
         read_with_timeout(stream, tm, &mut data[bsize..])?;
 
+        Self::open(client, seq, data)
+    }
+
+    // detect the total len of packet `seq` by its first block, the MAC included
+    //
+    // the sequence number is never sent, but implicitly increases for every packet,
+    // a replayed or injected packet which is sealed with another sequence number
+    // cannot pass the length and the integrity checks
+    fn data_len(client: &mut Client, seq: u32, first_block: &[u8]) -> SshResult<usize> {
+        let data_len = client.get_decryptor().data_len(seq, first_block);
+        if data_len < first_block.len() || data_len > size::MAX_PACKET_SIZE {
+            let err_msg = format!("Packet {seq} from the server has a bad length {data_len}");
+            error!(err_msg);
+            return Err(SshError::ProtocolError(err_msg));
+        }
+        Ok(data_len)
+    }
+
+    // decrypt the whole packet `seq`, then return the payload
+    fn open(client: &mut Client, seq: u32, mut data: Data) -> SshResult<Data> {
+        let data_len = data.len();
+
         // decrypt all
-        let data = client.get_encryptor().decrypt(seq, &mut data)?;
+        let data = match client.get_decryptor().decrypt(seq, &mut data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Packet {seq} from the server fails the integrity check");
+                return Err(e);
+            }
+        };
 
         // unpacking
         let pkt_len = u32::from_be_bytes(data[0..4].try_into().unwrap());
         let pad_len = data[4];
+        if pad_len as u32 + 1 > pkt_len || pkt_len as usize + 4 > data.len() {
+            let err_msg = format!("Packet {seq} from the server has a bad padding {pad_len}");
+            error!(err_msg);
+            return Err(SshError::ProtocolError(err_msg));
+        }
         let payload_len = pkt_len - pad_len as u32 - 1;
 
-        let payload = data[5..payload_len as usize + 5].into();
+        let payload = &data[5..payload_len as usize + 5];
+        let decompressed: Data = client.get_compressor().decompress(payload)?.into();
+        client
+            .get_compression_counter()
+            .on_recv(decompressed.len(), payload.len());
+        let payload = decompressed;
+        Self::on_recv(seq, data_len, &payload, client);
+        if payload.first() == Some(&ssh_transport_code::DISCONNECT) {
+            return Err(Self::disconnected(payload));
+        }
+        Ok(payload)
+    }
 
-        Ok(Some(Self { payload, client }))
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-11.1>
+    ///
+    /// every read loop ends with the server's SSH_MSG_DISCONNECT
+    ///
+    /// [SshError::ProtocolError] if the reason code or the description is truncated
+    fn disconnected(mut payload: Data) -> SshError {
+        payload.get_u8();
+        let description_len = payload
+            .get(4..8)
+            .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize);
+        if !matches!(description_len, Some(len) if payload.len() >= 8 + len) {
+            let err_msg = "The server SSH_MSG_DISCONNECT is truncated".to_owned();
+            error!(err_msg);
+            return SshError::ProtocolError(err_msg);
+        }
+        let code = payload.get_u32();
+        let description = String::from_utf8_lossy(&payload.get_u8s()).into_owned();
+        error!(
+            "Disconnected by the server with reason {}: {}",
+            code, description
+        );
+        SshError::Disconnected { code, description }
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-11.2>
+    ///
+    /// the messages which may come at any time are handled here for every read loop,
+    /// return true if the packet is consumed
+    ///
+    /// a malformed IGNORE or DEBUG is consumed all the same, but a malformed UNIMPLEMENTED is an error
+    ///
+    /// nothing is consumed before the first NEWKEYS, as it is not protected by any key yet
+    fn consume(payload: &Data, client: &Client) -> SshResult<bool> {
+        if client.get_session_id().is_empty() {
+            return Ok(false);
+        }
+        // the message code is skipped
+        let fields = &payload[1.min(payload.len())..];
+        // the length of a string at `at`, if it's all there
+        let string_len = |at: usize| {
+            let len = fields.get(at..at + 4)?;
+            let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+            (fields.len() >= at + 4 + len).then_some(len)
+        };
+        match payload.first() {
+            Some(&ssh_transport_code::IGNORE) => {
+                if string_len(0).is_some() {
+                    trace!("ignore the padding message");
+                } else {
+                    debug!("ignore the malformed padding message");
+                }
+            }
+            Some(&ssh_transport_code::DEBUG) => match string_len(1) {
+                Some(len) => {
+                    let always_display = fields[0] != 0;
+                    let message = String::from_utf8_lossy(&fields[5..5 + len]);
+                    if always_display {
+                        info!("debug message from the server: {}", message);
+                    } else {
+                        debug!("debug message from the server: {}", message);
+                    }
+                }
+                None => warn!("ignore the malformed debug message from the server"),
+            },
+            Some(&ssh_transport_code::UNIMPLEMENTED) => {
+                let Some(seq) = fields.get(..4) else {
+                    let err_msg = "The server SSH_MSG_UNIMPLEMENTED is truncated".to_owned();
+                    error!(err_msg);
+                    return Err(SshError::ProtocolError(err_msg));
+                };
+                warn!(
+                    "packet {} is not implemented by the server, ignore it",
+                    u32::from_be_bytes(seq.try_into().unwrap())
+                );
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // book-keeping for every received packet
+    fn on_recv(seq: u32, len: usize, payload: &Data, client: &mut Client) {
+        client.touch(len);
+        if let Some(limit) = client.get_packet_dump() {
+            trace!(
+                "<<< recv packet, seq {}: {}",
+                seq,
+                util::hex_dump(payload, limit)
+            );
+        }
     }
 
     pub fn get_inner(&self) -> &[u8] {
         &self.payload
     }
 
+    pub fn get_client(&self) -> &Client {
+        self.client
+    }
+
     pub fn into_inner(self) -> Data {
         self.payload
     }
@@ -0,0 +1,144 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+use tracing::*;
+
+use crate::{
+    client::Client,
+    constant::{size, SSH_MAGIC},
+    error::{SshError, SshResult},
+    model::{Data, Packet, SecPacket},
+};
+
+use super::packet::eof;
+
+/// The read half of an async connection
+///
+/// What is read stays in the buffer until a whole packet is there,
+/// so that a read can be cancelled, e.g. in a `tokio::select!`, without losing anything
+pub(crate) struct PacketReader {
+    stream: OwnedReadHalf,
+    buf: Vec<u8>,
+    // for each read, the same as the sync sessions
+    timeout: Option<Duration>,
+}
+
+impl PacketReader {
+    pub fn new(stream: OwnedReadHalf, timeout: Option<Duration>) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+            timeout,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-4.2>
+    ///
+    /// the server's identification string,
+    /// the other lines that the server may send before it are skipped
+    pub async fn read_version(&mut self) -> SshResult<Vec<u8>> {
+        loop {
+            while let Some(end) = self.buf.windows(2).position(|crlf| crlf == b"\r\n") {
+                let line: Vec<u8> = self.buf.drain(..end + 2).collect();
+                if line.starts_with(SSH_MAGIC) {
+                    return Ok(line);
+                }
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// the payload of the next packet,
+    /// the packets handled by the packet layer itself are skipped
+    ///
+    /// cancellation safe
+    pub async fn recv(&mut self, client: &mut Client) -> SshResult<Data> {
+        loop {
+            if let Some(pkt) = SecPacket::try_from_buf(&mut self.buf, client)? {
+                return Data::unpack(pkt);
+            }
+            self.fill().await?;
+        }
+    }
+
+    // read what has arrived into the buffer
+    async fn fill(&mut self) -> SshResult<()> {
+        self.buf.reserve(size::BUF_SIZE);
+        let read = self.stream.read_buf(&mut self.buf);
+        let len = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read).await.map_err(|_| {
+                error!("time out.");
+                SshError::TimeoutError
+            })??,
+            None => read.await?,
+        };
+        if len == 0 {
+            return Err(eof().into());
+        }
+        Ok(())
+    }
+}
+
+/// The write half of an async connection
+///
+/// The packets are sealed into the buffer as they are sent,
+/// then written out by [PacketWriter::write_some] or [PacketWriter::write_out]
+pub(crate) struct PacketWriter {
+    stream: OwnedWriteHalf,
+    buf: Vec<u8>,
+}
+
+impl PacketWriter {
+    pub fn new(stream: OwnedWriteHalf) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+        }
+    }
+
+    /// whether everything sent is written out
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// write as much as the socket takes for now
+    ///
+    /// cancellation safe
+    pub async fn write_some(&mut self) -> SshResult<()> {
+        let len = self.stream.write(&self.buf).await?;
+        if len == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+        }
+        self.buf.drain(..len);
+        Ok(())
+    }
+
+    /// write out everything sent so far
+    pub async fn write_out(&mut self) -> SshResult<()> {
+        while !self.buf.is_empty() {
+            self.write_some().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for PacketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
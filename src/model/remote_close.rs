@@ -0,0 +1,42 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// whether the server has closed a backend channel,
+/// shared by the backend and the broker of the channel
+#[derive(Default)]
+pub(crate) struct RemoteClose {
+    closed: AtomicBool,
+    callback: Mutex<Option<Callback>>,
+}
+
+impl RemoteClose {
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// call `callback` once the channel is closed, or right now if it already is
+    pub fn on_close(&self, callback: Callback) {
+        let mut slot = self.callback.lock().unwrap();
+        if self.is_closed() {
+            drop(slot);
+            callback()
+        } else {
+            *slot = Some(callback)
+        }
+    }
+
+    pub fn notify(&self) {
+        let callback = {
+            let mut slot = self.callback.lock().unwrap();
+            self.closed.store(true, Ordering::Release);
+            slot.take()
+        };
+        if let Some(callback) = callback {
+            callback()
+        }
+    }
+}
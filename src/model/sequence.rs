@@ -15,6 +15,22 @@ impl Sequence {
         self.server_sequence_num.next().unwrap()
     }
 
+    /// the number of the next server packet, which is not taken
+    #[cfg(feature = "async")]
+    pub fn peek_server(&self) -> u32 {
+        self.server_sequence_num.peek()
+    }
+
+    /// the next client packet is numbered 0
+    pub fn reset_client(&mut self) {
+        self.client_sequence_num = U32Iter::default();
+    }
+
+    /// the next server packet is numbered 0
+    pub fn reset_server(&mut self) {
+        self.server_sequence_num = U32Iter::default();
+    }
+
     pub fn new() -> Self {
         Self {
             ..Default::default()
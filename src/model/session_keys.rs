@@ -0,0 +1,27 @@
+/// The keys derived for one direction
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct DerivedKeys {
+    /// the initial IV, empty if the cipher takes none
+    pub iv: Vec<u8>,
+    /// the encryption key
+    pub key: Vec<u8>,
+    /// the integrity key, empty for the AEAD ciphers which need no separate MAC
+    pub mac_key: Vec<u8>,
+}
+
+/// **DANGER: anyone holding these can decrypt the whole session.**
+///
+/// The keys derived in the last key exchange, exported for decrypting
+/// a packet capture of the session with an external tool.
+/// Only for debugging, never enable the feature `dangerous-key-export` in production.
+///
+/// It implements no `Debug` so that the keys do not end up in the logs by accident
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SessionKeys {
+    /// the exchange hash H of the initial key exchange
+    pub session_id: Vec<u8>,
+    /// client to server
+    pub c_keys: DerivedKeys,
+    /// server to client
+    pub s_keys: DerivedKeys,
+}
@@ -1,3 +1,7 @@
+use crate::constant::ssh_str;
+
+use super::Data;
+
 pub enum TerminalSizeType {
     Character,
     Pixel,
@@ -33,3 +37,78 @@ impl TerminalSize {
         }
     }
 }
+
+/// <https://www.rfc-editor.org/rfc/rfc4254#section-6.2>
+///
+/// The pseudo terminal requested for a shell,
+/// 80x24 characters of `xterm-256color` by default
+///
+/// ```no_run
+/// # fn main() -> ssh::SshResult<()> {
+/// # let mut session = ssh::create_session().connect("127.0.0.1:22")?.run_local();
+/// let shell = session.open_shell_with_pty(ssh::PtyConfig {
+///     cols: 200,
+///     rows: 50,
+///     term: "xterm-256color",
+///     ..Default::default()
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtyConfig<'a> {
+    /// the value of `TERM`
+    pub term: &'a str,
+    /// characters per line
+    pub cols: u32,
+    /// lines
+    pub rows: u32,
+    /// width in pixels, 0 if unknown
+    pub pixel_width: u32,
+    /// height in pixels, 0 if unknown
+    pub pixel_height: u32,
+}
+
+impl Default for PtyConfig<'_> {
+    fn default() -> Self {
+        PtyConfig {
+            term: ssh_str::XTERM_VAR,
+            cols: 80,
+            rows: 24,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+}
+
+impl From<TerminalSize> for PtyConfig<'_> {
+    fn from(tv: TerminalSize) -> Self {
+        let (cols, rows, pixel_width, pixel_height) = tv.fetch();
+        PtyConfig {
+            cols,
+            rows,
+            pixel_width,
+            pixel_height,
+            ..Default::default()
+        }
+    }
+}
+
+impl PtyConfig<'_> {
+    /// the type specific data of the `pty-req` request
+    pub(crate) fn put(&self, data: &mut Data) {
+        data.put_str(self.term)
+            .put_u32(self.cols)
+            .put_u32(self.rows)
+            .put_u32(self.pixel_width)
+            .put_u32(self.pixel_height);
+        let model = [
+            128, // TTY_OP_ISPEED
+            0, 1, 0xc2, 0,   // 115200
+            129, // TTY_OP_OSPEED
+            0, 1, 0xc2, 0,    // 115200 again
+            0_u8, // TTY_OP_END
+        ];
+        data.put_u8s(&model);
+    }
+}
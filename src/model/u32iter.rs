@@ -15,6 +15,14 @@ impl Iterator for U32Iter {
     }
 }
 
+#[cfg(feature = "async")]
+impl U32Iter {
+    /// what the next call of `next` returns
+    pub fn peek(&self) -> u32 {
+        self.num.wrapping_add(1)
+    }
+}
+
 impl Default for U32Iter {
     fn default() -> Self {
         Self { num: u32::MAX }
@@ -2,23 +2,35 @@
 mod session_broker;
 mod session_local;
 
+#[cfg(feature = "async")]
+mod session_async;
+
 pub use session_broker::SessionBroker;
 pub use session_local::LocalSession;
+
+#[cfg(feature = "async")]
+pub use session_async::AsyncSession;
 use tracing::*;
 
 use std::{
-    io::{Read, Write},
-    net::{TcpStream, ToSocketAddrs},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
     path::Path,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
 use crate::{
-    algorithm::{Compress, Digest, Enc, Kex, Mac, PubKey},
-    client::Client,
-    config::{algorithm::AlgList, Config},
+    algorithm::{public_key, Compress, Digest, Enc, FingerprintHash, Kex, Mac, PubKey},
+    channel::DirectTcpipStream,
+    client::{agent, Client},
+    config::{
+        algorithm::AlgList, env::EnvVar, known_hosts, AuthMethod, ChannelKind, Config,
+        HostKeyVerification, Prompt, TcpKeepalive,
+    },
     error::SshResult,
-    model::{Packet, SecPacket},
+    model::{KexDetails, NegotiatedAlgorithms, Packet, SecPacket},
+    util,
 };
 
 enum SessionState<S>
@@ -31,11 +43,18 @@ where
     Connected(Client, S),
 }
 
+/// how the backend gets a new authenticated connection once the current one is lost
+pub(crate) struct Reconnect<S> {
+    pub interval: Duration,
+    pub connect: Box<dyn FnMut() -> SshResult<(Client, S)> + Send>,
+}
+
 pub struct SessionConnector<S>
 where
     S: Read + Write,
 {
     inner: SessionState<S>,
+    reconnect: Option<Reconnect<S>>,
 }
 
 impl<S> SessionConnector<S>
@@ -46,6 +65,7 @@ where
         match self.inner {
             SessionState::Init(config, stream) => Self {
                 inner: SessionState::Version(config, stream),
+                reconnect: self.reconnect,
             }
             .connect(),
             SessionState::Version(mut config, mut stream) => {
@@ -67,6 +87,7 @@ where
 
                 Self {
                     inner: SessionState::Auth(client, stream),
+                    reconnect: self.reconnect,
                 }
                 .connect()
             }
@@ -81,6 +102,7 @@ where
                 client.do_auth(&mut stream, &digest)?;
                 Ok(Self {
                     inner: SessionState::Connected(client, stream),
+                    reconnect: self.reconnect,
                 })
             }
             _ => unreachable!(),
@@ -99,6 +121,81 @@ where
         }
     }
 
+    /// whether the negotiated ciphers are AEAD (e.g. chacha20-poly1305@openssh.com),
+    /// which need no separate MAC, rather than a cipher plus a MAC
+    ///
+    pub fn is_aead(&self) -> bool {
+        match self.inner {
+            SessionState::Connected(ref client, _) => client.is_aead(),
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// the host key algorithms offered by the server in the initial key exchange,
+    /// in the server's order, including the ones not supported by us
+    ///
+    pub fn offered_host_key_algorithms(&self) -> Vec<String> {
+        match self.inner {
+            SessionState::Connected(ref client, _) => client.get_offered_host_key_algs().to_vec(),
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc8308#section-2.3>
+    ///
+    /// the (name, value) extensions sent by the server in SSH_MSG_EXT_INFO,
+    /// empty if the server sends none or [crate::SessionBuilder::ext_info] is disabled
+    ///
+    pub fn server_extensions(&self) -> Vec<(String, Vec<u8>)> {
+        match self.inner {
+            SessionState::Connected(ref client, _) => client.get_server_extensions().to_vec(),
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// the server host key in the `authorized_keys` format, e.g. `ssh-ed25519 AAAA...`
+    ///
+    pub fn server_public_key_openssh(&self) -> SshResult<String> {
+        match self.inner {
+            SessionState::Connected(ref client, _) => {
+                public_key::to_openssh(client.get_server_host_key())
+            }
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// the server host key (`K_S`) as ssh encoded in the key exchange,
+    /// e.g. for a trust-on-first-use check by yourself
+    ///
+    pub fn server_host_key(&self) -> Vec<u8> {
+        match self.inner {
+            SessionState::Connected(ref client, _) => client.get_server_host_key().to_vec(),
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// the fingerprint of the server host key, e.g. `SHA256:...`,
+    /// the same as `ssh-keygen -l` prints
+    ///
+    pub fn host_key_fingerprint(&self, hash: FingerprintHash) -> String {
+        match self.inner {
+            SessionState::Connected(ref client, _) => {
+                public_key::fingerprint(client.get_server_host_key(), hash)
+            }
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
+    /// the KEXINIT payloads, the hash and the lengths of the keys derived in the key exchange,
+    /// for the interop diagnostics
+    ///
+    pub fn kex_details(&self) -> KexDetails {
+        match self.inner {
+            SessionState::Connected(ref client, _) => client.get_kex_details().clone(),
+            _ => unreachable!("Why you here?"),
+        }
+    }
+
     /// close the session and consume it
     ///
     pub fn close(self) {
@@ -116,16 +213,20 @@ where
     ///
     pub fn run_backend(self) -> SessionBroker {
         if let SessionState::Connected(client, stream) = self.inner {
-            SessionBroker::new(client, stream)
+            SessionBroker::new(client, stream, self.reconnect)
         } else {
             unreachable!("Why you here?")
         }
     }
 }
 
+/// called with the session id & the negotiated algorithms once authenticated
+type PostAuthHook = Box<dyn FnOnce(&[u8], &NegotiatedAlgorithms) + Send>;
+
 #[derive(Default)]
 pub struct SessionBuilder {
     config: Config,
+    post_auth: Option<PostAuthHook>,
 }
 
 impl SessionBuilder {
@@ -138,6 +239,7 @@ impl SessionBuilder {
     pub fn disable_default() -> Self {
         Self {
             config: Config::disable_default(),
+            post_auth: None,
         }
     }
 
@@ -151,6 +253,285 @@ impl SessionBuilder {
         self
     }
 
+    /// Only offer the AEAD ciphers with at least 128 bits security strength,
+    /// all the other ciphers in the list are removed when connecting.
+    /// Disabled by default
+    ///
+    /// Currently `chacha20-poly1305@openssh.com` and `aes256-gcm@openssh.com` meet the requirement
+    pub fn strong_ciphers_only(mut self, enable: bool) -> Self {
+        self.config.strong_ciphers_only = enable;
+        self
+    }
+
+    /// Offer the AEAD ciphers ahead of the others, and send empty MAC lists
+    /// for a direction that offers the AEAD ciphers only.
+    /// Disabled by default
+    ///
+    /// The AEAD ciphers authenticate the packets themselves, so no MAC is negotiated with them
+    pub fn prefer_aead(mut self, enable: bool) -> Self {
+        self.config.prefer_aead = enable;
+        self
+    }
+
+    /// Offer exactly this host key algorithm in the key exchange,
+    /// regardless of the configured and auto tuned ones.
+    /// Use None (the default) to disable it
+    ///
+    /// Mainly for testing a specific host key path end-to-end
+    pub fn host_key_algorithm(mut self, alg: Option<PubKey>) -> Self {
+        self.config.host_key_algorithm = alg;
+        self
+    }
+
+    /// Send a keepalive probe after `idle` of no traffic in either direction.
+    /// Use None (the default) to disable it
+    ///
+    /// This only takes effect in backend mode, see [SessionConnector::run_backend]
+    pub fn keepalive_idle(mut self, idle: Option<Duration>) -> Self {
+        self.config.keepalive_idle = idle;
+        self
+    }
+
+    /// Renew the session keys once `limit` bytes are sent and received since the last
+    /// key exchange. 1 GiB by default, use None to disable it
+    ///
+    /// This only takes effect in backend mode, see [SessionConnector::run_backend]
+    pub fn rekey_limit(mut self, limit: Option<u64>) -> Self {
+        self.config.rekey_limit = limit;
+        self
+    }
+
+    /// Renew the session keys once `interval` has passed since the last key exchange.
+    /// One hour by default, use None to disable it
+    ///
+    /// This only takes effect in backend mode, see [SessionConnector::run_backend]
+    pub fn rekey_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.rekey_interval = interval;
+        self
+    }
+
+    /// Drop the connection once `count` keepalive probes in a row are not answered,
+    /// see [SessionBuilder::keepalive_idle]. Use None (the default) to keep probing
+    ///
+    /// Any reply of a global request counts as an answer. The loss is handled
+    /// like a dropped TCP connection, which is reconnected with [SessionBuilder::auto_reconnect]
+    pub fn keepalive_max_missed(mut self, count: Option<u32>) -> Self {
+        self.config.keepalive_max_missed = count;
+        self
+    }
+
+    /// Reconnect every `interval` until it succeeds once the TCP connection drops.
+    /// Use None (the default) to disable it
+    ///
+    /// This only takes effect in backend mode with [SessionBuilder::connect],
+    /// see [SessionConnector::run_backend]. The channels on the lost connection are closed,
+    /// while the local forwards keep listening and open their channels on the new one,
+    /// and the remote forwards requested by [SessionBroker::tcpip_forward]
+    /// or [SessionBroker::remote_forward] are requested again
+    pub fn auto_reconnect(mut self, interval: Option<Duration>) -> Self {
+        self.config.reconnect_interval = interval;
+        self
+    }
+
+    /// Enable the OS level TCP keepalive with the given parameters
+    /// on the socket created by [SessionBuilder::connect].
+    /// Use None (the default) to leave the socket as is
+    ///
+    /// Unlike [SessionBuilder::keepalive_idle], it works in both modes
+    /// and does not send anything at the SSH level
+    pub fn tcp_keepalive(mut self, keepalive: Option<TcpKeepalive>) -> Self {
+        self.config.tcp_keepalive = keepalive;
+        self
+    }
+
+    /// The initial window (the bytes the server can send before we adjust it)
+    /// of the channels of `kind`, defaults to 2 MiB.
+    /// 0 resets it to the default
+    ///
+    /// A large window speeds up the bulk transfers such as scp,
+    /// whereas a small one is enough for interactive shells
+    pub fn window_size(mut self, kind: ChannelKind, size: u32) -> Self {
+        self.config.window_sizes.set(kind, size);
+        self
+    }
+
+    /// Give the consumed window back to the server once `bytes` of it are consumed,
+    /// instead of after each data packet (the default, 0).
+    /// It's capped at the initial window of each channel
+    ///
+    /// A larger threshold sends fewer SSH_MSG_CHANNEL_WINDOW_ADJUST on the bulk transfers
+    pub fn window_adjust_threshold(mut self, bytes: u32) -> Self {
+        self.config.window_adjust_threshold = bytes;
+        self
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-6.4>
+    ///
+    /// Pass the environment variable `name=value` to each exec and shell channel.
+    /// Servers commonly refuse the ones not in their `AcceptEnv`,
+    /// which is only warned about, see [SessionBuilder::required_env] otherwise
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.config.env.push(EnvVar {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            required: false,
+        });
+        self
+    }
+
+    /// Same as [SessionBuilder::env], but fail opening the channel
+    /// with [crate::SshError::EnvRefused] if the server refuses it
+    pub fn required_env(mut self, name: &str, value: &str) -> Self {
+        self.config.env.push(EnvVar {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            required: true,
+        });
+        self
+    }
+
+    /// Only allow opening the subsystems in `names`, see [LocalSession::open_subsystem].
+    /// The others fail with [crate::SshError::SubsystemNotAllowed] before anything is sent.
+    /// Use None (the default) to allow any subsystem
+    pub fn allowed_subsystems(mut self, names: Option<&[&str]>) -> Self {
+        self.config.allowed_subsystems =
+            names.map(|names| names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Advertise `ext-info-c` in the initial key exchange,
+    /// so the server can send its extensions, see [LocalSession::server_extensions].
+    /// Enabled by default
+    pub fn ext_info(mut self, enable: bool) -> Self {
+        self.config.ext_info = enable;
+        self
+    }
+
+    /// Advertise `kex-strict-c-v00@openssh.com` in the initial key exchange.
+    /// Enabled by default
+    ///
+    /// If the server advertises `kex-strict-s-v00@openssh.com` as well,
+    /// any message other than the key exchange ones aborts the initial key exchange,
+    /// and the sequence numbers restart from 0 after every NEWKEYS,
+    /// which defeats the prefix truncation (Terrapin) attack
+    pub fn strict_kex(mut self, enable: bool) -> Self {
+        self.config.strict_kex = enable;
+        self
+    }
+
+    /// Draw all the randomness of the session (the KEXINIT cookies and the ephemeral keys)
+    /// from a generator seeded with `seed`, so that the handshakes are byte-identical.
+    /// Use None (the default) to use the OS random source
+    ///
+    /// Only for reproducible tests, a seeded session is not secure
+    pub fn rng_seed(mut self, seed: Option<u64>) -> Self {
+        self.config.rng_seed = seed;
+        self
+    }
+
+    /// Use the 32 bytes of `key` as the client's ephemeral private key
+    /// of curve25519-sha256 and ecdh-sha2-nistp256 in every key exchange,
+    /// to reproduce the published test vectors. The other key exchanges fail.
+    /// Use None (the default) to generate a new key each time
+    ///
+    /// Only for reproducible tests, a session with a fixed key is not secure
+    pub fn ephemeral_key(mut self, key: Option<&[u8]>) -> Self {
+        self.config.ephemeral_key = key.map(<[u8]>::to_vec);
+        self
+    }
+
+    /// The max length in bytes of each algorithm name-list in the server KEXINIT,
+    /// a longer one aborts the key exchange with [crate::SshError::KexError].
+    /// Defaults to 16 KiB
+    pub fn max_name_list_len(mut self, len: usize) -> Self {
+        self.config.max_name_list_len = len;
+        self
+    }
+
+    /// Verify the server host key against the known_hosts file at `path`.
+    /// Without it (the default), any host key is trusted
+    ///
+    /// The file is only read unless in [HostKeyVerification::AcceptNew] mode,
+    /// a missing file is treated as empty.
+    /// A host that is not in the file is accepted unless rejected by
+    /// [SessionBuilder::on_unknown_host_key], while a changed host key is rejected
+    /// with [crate::SshError::HostKeyMismatch] unless accepted by [SessionBuilder::on_host_key_changed]
+    pub fn known_hosts<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.config.host_key_check.known_hosts = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// How the host key is checked against known_hosts,
+    /// which is `~/.ssh/known_hosts` unless set by [SessionBuilder::known_hosts]
+    ///
+    /// Without it (the default), nothing is checked if no known_hosts file is set,
+    /// otherwise the unknown hosts are trusted without being added to the file.
+    /// The callbacks of [SessionBuilder::on_unknown_host_key] and
+    /// [SessionBuilder::on_host_key_changed] take precedence over the mode
+    pub fn host_key_verification(mut self, mode: HostKeyVerification) -> Self {
+        let check = &mut self.config.host_key_check;
+        check.mode = Some(mode);
+        if check.known_hosts.is_none() && mode != HostKeyVerification::AcceptAny {
+            check.known_hosts = known_hosts::default_path();
+        }
+        self
+    }
+
+    /// The name to look up in known_hosts, as it appears in the file,
+    /// e.g. `example.com` or `[example.com]:2222`
+    ///
+    /// Defaults to the host given to [SessionBuilder::connect_host],
+    /// or the peer address when connected by [SessionBuilder::connect],
+    /// required when using [SessionBuilder::connect_bio] with [SessionBuilder::known_hosts]
+    pub fn known_host_name(mut self, name: &str) -> Self {
+        self.config.host_key_check.name = Some(name.to_owned());
+        self
+    }
+
+    /// Called with the host name and the SHA256 fingerprint of its key
+    /// when the host is not in known_hosts.
+    /// Return false to abort the connection with [crate::SshError::UnknownHostKey]
+    pub fn on_unknown_host_key<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + 'static,
+    {
+        self.config.host_key_check.on_unknown = Some(Arc::new(callback));
+        self
+    }
+
+    /// Called with the host name, the SHA256 fingerprints of the stored key and of
+    /// the key presented by the server, when they differ.
+    /// Return true to continue anyway
+    pub fn on_host_key_changed<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, &str, &str) -> bool + Send + Sync + 'static,
+    {
+        self.config.host_key_check.on_changed = Some(Arc::new(callback));
+        self
+    }
+
+    /// Called once right after the authentication succeeds, before any channel is opened,
+    /// with the session id and the algorithms negotiated in the initial key exchange,
+    /// e.g. for channel binding or audit logging
+    pub fn post_auth<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(&[u8], &NegotiatedAlgorithms) + Send + 'static,
+    {
+        self.post_auth = Some(Box::new(hook));
+        self
+    }
+
+    /// Debug only. Log the payload of every sent/received packet as a hex dump
+    /// at the trace level, at most `limit` bytes for each packet.
+    /// Use None (the default) to disable it
+    ///
+    /// The dumps contain sensitive data such as the password,
+    /// never enable it in production
+    pub fn packet_dump(mut self, limit: Option<usize>) -> Self {
+        self.config.packet_dump = limit;
+        self
+    }
+
     pub fn username(mut self, username: &str) -> Self {
         self.config.auth.username(username).unwrap();
         self
@@ -161,11 +542,69 @@ impl SessionBuilder {
         self
     }
 
+    /// <https://www.rfc-editor.org/rfc/rfc4256>
+    ///
+    /// Authenticate with keyboard-interactive, e.g. for the one-time passwords.
+    /// `callback` is called with the name, the instruction and the prompts
+    /// for every round the server asks, and returns one answer for each prompt.
+    /// The prompts can be empty, when the server only has something to tell
+    ///
+    /// It is tried after the public key, and before the password if any
+    pub fn keyboard_interactive<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&str, &str, &[Prompt]) -> Vec<String> + Send + 'static,
+    {
+        self.config.auth.keyboard_interactive = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4252#section-5.4>
+    ///
+    /// Call `callback` with the message of every banner sent by the server
+    /// during the authentication, such as the legal notice to display.
+    /// The message is as sent, the control characters are not filtered
+    ///
+    /// The banners are also kept by the session, see [LocalSession::banner]
+    pub fn banner_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        self.config.auth.banner = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// Authenticate with the identities in ssh-agent at `$SSH_AUTH_SOCK`,
+    /// or the named pipe of openssh on windows, without reading any private key here
+    ///
+    /// They are tried before the private key
+    pub fn ssh_agent(mut self, enable: bool) -> Self {
+        self.config.auth.agent = if enable {
+            let path = agent::default_path();
+            if path.is_none() {
+                error!("SSH_AUTH_SOCK is not set, will skip the agent authentication");
+            }
+            path
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Authenticate with the identities in the ssh-agent listening at `path`,
+    /// see [SessionBuilder::ssh_agent]
+    pub fn ssh_agent_path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.config.auth.agent = Some(path.as_ref().to_path_buf());
+        self
+    }
+
     pub fn private_key<K>(mut self, private_key: K) -> Self
     where
         K: ToString,
     {
-        match self.config.auth.private_key(private_key) {
+        match self.config.auth.private_key(private_key, None) {
             Ok(_) => (),
             Err(e) => error!(
                 "Parse private key from string: {}, will fallback to password authentication",
@@ -179,7 +618,7 @@ impl SessionBuilder {
     where
         P: AsRef<Path>,
     {
-        match self.config.auth.private_key_path(key_path) {
+        match self.config.auth.private_key_path(key_path, None) {
             Ok(_) => (),
             Err(e) => error!(
                 "Parse private key from file: {}, will fallback to password authentication",
@@ -189,6 +628,76 @@ impl SessionBuilder {
         self
     }
 
+    /// Same as [SessionBuilder::private_key], for an openssh key file
+    /// encrypted with `passphrase`
+    pub fn private_key_with_passphrase<K>(mut self, private_key: K, passphrase: &str) -> Self
+    where
+        K: ToString,
+    {
+        match self.config.auth.private_key(private_key, Some(passphrase)) {
+            Ok(_) => (),
+            Err(e) => error!(
+                "Parse private key from string: {}, will fallback to password authentication",
+                e
+            ),
+        }
+        self
+    }
+
+    /// Same as [SessionBuilder::private_key_path], for an openssh key file
+    /// encrypted with `passphrase`
+    pub fn private_key_path_with_passphrase<P>(mut self, key_path: P, passphrase: &str) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match self
+            .config
+            .auth
+            .private_key_path(key_path, Some(passphrase))
+        {
+            Ok(_) => (),
+            Err(e) => error!(
+                "Parse private key from file: {}, will fallback to password authentication",
+                e
+            ),
+        }
+        self
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4252#section-7>
+    ///
+    /// Authenticate as `user` with the private key file at `key_path`, in PEM or openssh format.
+    /// The key is offered to the server first and only signed once it's accepted,
+    /// the connection fails with [crate::SshError::AuthError] if it's rejected
+    /// and no other method is left
+    ///
+    /// Unlike [SessionBuilder::private_key_path], a key that can't be read or parsed is an error here
+    pub fn userauth_publickey<P>(self, user: &str, key_path: P) -> SshResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        self.username(user)
+            .auth_method(AuthMethod::PrivateKeyPath(key_path.as_ref().to_path_buf()))
+    }
+
+    /// Authenticate as `user` with the identities in ssh-agent,
+    /// the same as [SessionBuilder::username] plus [SessionBuilder::ssh_agent]
+    pub fn userauth_agent(self, user: &str) -> Self {
+        self.username(user).ssh_agent(true)
+    }
+
+    /// Use `method` as the only authentication method
+    ///
+    /// Unlike [SessionBuilder::private_key], a bad private key is an error here
+    pub(crate) fn auth_method(mut self, method: AuthMethod) -> SshResult<Self> {
+        match method {
+            AuthMethod::Password(password) => self.config.auth.password(password)?,
+            AuthMethod::PrivateKey(key) => self.config.auth.private_key(key, None)?,
+            AuthMethod::PrivateKeyPath(path) => self.config.auth.private_key_path(path, None)?,
+        }
+        Ok(self)
+    }
+
     pub fn add_kex_algorithms(mut self, alg: Kex) -> Self {
         self.config.algs.key_exchange.push(alg);
         self
@@ -247,42 +756,160 @@ impl SessionBuilder {
 
     /// Create a TCP connection to the target server
     ///
+    /// With [SessionBuilder::known_hosts], the host key is looked up by the peer IP,
+    /// use [SessionBuilder::connect_host] to look it up by the host name as OpenSSH records it
+    ///
     pub fn connect<A>(self, addr: A) -> SshResult<SessionConnector<TcpStream>>
     where
         A: ToSocketAddrs,
     {
-        // connect tcp by default
-        let tcp = if let Some(ref to) = self.config.timeout {
-            TcpStream::connect_timeout(&addr.to_socket_addrs()?.next().unwrap(), *to)?
-        } else {
-            TcpStream::connect(addr)?
-        };
+        let timeout = self.config.timeout;
+        self.connect_with_timeout(addr, timeout)
+    }
 
-        // default nonblocking
-        tcp.set_nonblocking(true).unwrap();
-        self.connect_bio(tcp)
+    /// Create a TCP connection to `host`:`port`
+    ///
+    /// Same as [SessionBuilder::connect], but the host key is looked up in known_hosts
+    /// by `host` as given, e.g. `example.com` or `[example.com]:2222`,
+    /// unless [SessionBuilder::known_host_name] is set
+    ///
+    pub fn connect_host(mut self, host: &str, port: u16) -> SshResult<SessionConnector<TcpStream>> {
+        if self.config.host_key_check.known_hosts.is_some() {
+            self.config.host_key_check.set_target(host, port);
+        }
+        self.connect((host, port))
     }
 
     /// Create a TCP connection to the target server, with timeout provided
     ///
     pub fn connect_with_timeout<A>(
-        self,
+        mut self,
         addr: A,
         timeout: Option<Duration>,
     ) -> SshResult<SessionConnector<TcpStream>>
     where
         A: ToSocketAddrs,
     {
-        // connect tcp with custom connection timeout
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+        let tcp = self.connect_tcp(&addrs, timeout)?;
+
+        let reconnect = self.config.reconnect_interval.map(|interval| {
+            let config = self.config.clone();
+            Reconnect {
+                interval,
+                connect: Box::new(move || {
+                    let mut builder = SessionBuilder {
+                        config: config.clone(),
+                        post_auth: None,
+                    };
+                    let tcp = builder.connect_tcp(&addrs, timeout)?;
+                    match builder.connect_bio(tcp)?.inner {
+                        SessionState::Connected(client, stream) => Ok((client, stream)),
+                        _ => unreachable!(),
+                    }
+                })
+                    as Box<dyn FnMut() -> SshResult<(Client, TcpStream)> + Send>,
+            }
+        });
+        let mut connector = self.connect_bio(tcp)?;
+        connector.reconnect = reconnect;
+        Ok(connector)
+    }
+
+    fn connect_tcp(
+        &mut self,
+        addrs: &[SocketAddr],
+        timeout: Option<Duration>,
+    ) -> SshResult<TcpStream> {
+        // connect tcp with custom connection timeout,
+        // each address in turn as TcpStream::connect does
         let tcp = if let Some(ref to) = timeout {
-            TcpStream::connect_timeout(&addr.to_socket_addrs()?.next().unwrap(), *to)?
+            let mut last_err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the address resolves to nothing",
+            );
+            let mut connected = None;
+            for addr in addrs {
+                match TcpStream::connect_timeout(addr, *to) {
+                    Ok(tcp) => {
+                        connected = Some(tcp);
+                        break;
+                    }
+                    Err(e) => last_err = e,
+                }
+            }
+            connected.ok_or(last_err)?
         } else {
-            TcpStream::connect(addr)?
+            TcpStream::connect(addrs)?
         };
 
+        if let Some(ref keepalive) = self.config.tcp_keepalive {
+            keepalive.apply(&tcp)?;
+        }
+        if self.config.host_key_check.known_hosts.is_some() {
+            self.config.host_key_check.set_peer(tcp.peer_addr()?);
+        }
+
         // default nonblocking
         tcp.set_nonblocking(true).unwrap();
-        self.connect_bio(tcp)
+        Ok(tcp)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.2>
+    ///
+    /// Connect to `addr` (`host:port`) through a `direct-tcpip` channel of `jump`,
+    /// the same as `ssh -J`
+    ///
+    /// The key exchange and the authentication run over the channel,
+    /// `host` is resolved and connected by the jump server
+    ///
+    pub fn connect_via(
+        mut self,
+        jump: &SessionBroker,
+        addr: &str,
+    ) -> SshResult<SessionConnector<DirectTcpipStream>> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid address {addr}"),
+            )
+        };
+        let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+
+        let mut stream = jump.opener().open_direct_tcpip(host, port)?.into_stream();
+        // the backend polls the stream as a nonblocking socket
+        stream.set_nonblocking(true)?;
+        if self.config.host_key_check.known_hosts.is_some() {
+            self.config.host_key_check.set_target(host, port);
+        }
+        self.connect_bio(stream)
+    }
+
+    /// Log the client version and the KEXINIT that a connect would send first,
+    /// without opening any connection, and return the KEXINIT payload
+    ///
+    /// For checking the algorithm lists after all the tuning.
+    /// The cookie is random unless [SessionBuilder::rng_seed] is set
+    pub fn dry_run(mut self) -> SshResult<Vec<u8>> {
+        self.config.tune_alglist_on_private_key();
+        self.config.tune_alglist_on_cipher_strength();
+        self.config.tune_alglist_on_aead();
+        self.config.tune_alglist_on_host_key_algorithm();
+        // nowhere to send but a buffer
+        let mut sink = vec![];
+        self.config.ver.send_our_version(&mut sink)?;
+        let mut client = Client::new(self.config);
+        let kexinit = client.send_kexinit(&mut sink)?;
+        info!(
+            "dry run, the client KEXINIT: {}",
+            util::hex_dump(&kexinit, kexinit.len())
+        );
+        Ok(kexinit)
     }
 
     /// connect to target server w/ a bio object
@@ -294,9 +921,19 @@ impl SessionBuilder {
         S: Read + Write,
     {
         self.config.tune_alglist_on_private_key();
-        SessionConnector {
+        self.config.tune_alglist_on_cipher_strength();
+        self.config.tune_alglist_on_aead();
+        self.config.tune_alglist_on_host_key_algorithm();
+        let connector = SessionConnector {
             inner: SessionState::Init(self.config, stream),
+            reconnect: None,
+        }
+        .connect()?;
+        if let (Some(hook), SessionState::Connected(ref client, _)) =
+            (self.post_auth, &connector.inner)
+        {
+            hook(client.get_session_id(), &client.get_negotiated_algorithms());
         }
-        .connect()
+        Ok(connector)
     }
 }
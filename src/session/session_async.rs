@@ -0,0 +1,580 @@
+use std::{collections::HashMap, io, sync::Arc, sync::Mutex};
+
+use tokio::{
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    time::Instant,
+};
+use tracing::*;
+
+use crate::{
+    algorithm::Digest,
+    channel::{asynchronous::AsyncChannel, AsyncExec, AsyncShell, BackendChannel},
+    client::Client,
+    config::{algorithm::AlgList, env::EnvVar, window::WindowSizes, ChannelKind},
+    constant::{size, ssh_channel_fail_code, ssh_connection_code, ssh_str, ssh_transport_code},
+    error::{SshError, SshResult},
+    model::{
+        AsyncRqst, BackendResp, Data, DisconnectReason, Packet, PacketReader, PacketWriter,
+        PtyConfig, RemoteClose, U32Iter,
+    },
+    SessionBuilder,
+};
+
+use super::session_broker::open_failure;
+
+/// The async counterpart of [crate::SessionBroker], created by [SessionBuilder::connect_async]
+///
+/// The connection is driven by a tokio task instead of a backend thread,
+/// which stops once the session and all its channels are dropped, or by [AsyncSession::close]
+///
+pub struct AsyncSession {
+    channel_num: Mutex<U32Iter>,
+    snd: UnboundedSender<AsyncRqst>,
+    window_sizes: WindowSizes,
+    env: Vec<EnvVar>,
+}
+
+impl AsyncSession {
+    fn new(client: Client, reader: PacketReader, writer: PacketWriter) -> Self {
+        let (rqst_snd, rqst_rcv) = mpsc::unbounded_channel();
+        let window_sizes = client.get_window_sizes().clone();
+        let env = client.get_env().to_vec();
+        tokio::spawn(async move {
+            if let Err(e) = drive(client, reader, writer, rqst_rcv).await {
+                error!("Error {:?} occurred when driving the async session", e)
+            }
+        });
+        Self {
+            channel_num: Mutex::new(U32Iter::default()),
+            snd: rqst_snd,
+            window_sizes,
+            env,
+        }
+    }
+
+    /// open an [AsyncExec] channel which can excute commands
+    ///
+    pub async fn open_exec(&self) -> SshResult<AsyncExec> {
+        let mut channel = self.open_channel(ChannelKind::Exec).await?;
+        channel.send_env(&self.env).await?;
+        Ok(AsyncExec::open(channel))
+    }
+
+    /// open an [AsyncShell] channel which can be used as a pseudo terminal (AKA PTY)
+    ///
+    pub async fn open_shell(&self) -> SshResult<AsyncShell> {
+        self.open_shell_with_pty(PtyConfig::default()).await
+    }
+
+    /// open an [AsyncShell] channel
+    ///
+    /// custom terminal type & dimensions, see [PtyConfig]
+    ///
+    pub async fn open_shell_with_pty(&self, pty: PtyConfig<'_>) -> SshResult<AsyncShell> {
+        let mut channel = self.open_channel(ChannelKind::Shell).await?;
+        channel.send_env(&self.env).await?;
+        AsyncShell::open(channel, pty).await
+    }
+
+    /// send SSH_MSG_DISCONNECT to the server and stop the driver task,
+    /// then consume the session itself
+    ///
+    /// Any channel which is still open will be woken up as closed.
+    ///
+    pub async fn close(self) {
+        info!("Client close");
+        let (resp_send, resp_recv) = oneshot::channel();
+        if self.snd.send(AsyncRqst::Shutdown(resp_send)).is_ok() {
+            let _ = resp_recv.await;
+        }
+    }
+
+    /// the channel is closed if the returned future is dropped before it's ready
+    async fn open_channel(&self, kind: ChannelKind) -> SshResult<AsyncChannel> {
+        let client_id = self.channel_num.lock().unwrap().next().unwrap();
+        let window_size = self.window_sizes.get(kind);
+
+        // open channel request
+        let mut data = Data::new();
+        data.put_u8(ssh_connection_code::CHANNEL_OPEN)
+            .put_str(ssh_str::SESSION)
+            .put_u32(client_id)
+            .put_u32(window_size)
+            .put_u32(size::BUF_SIZE as u32);
+
+        let (resp_send, resp_recv) = mpsc::unbounded_channel();
+        self.snd.send(AsyncRqst::OpenChannel(
+            client_id,
+            window_size,
+            data,
+            resp_send,
+        ))?;
+        AsyncChannel::open(client_id, self.snd.clone(), resp_recv).await
+    }
+}
+
+impl SessionBuilder {
+    /// same as [SessionBuilder::connect], but on a [tokio::net::TcpStream],
+    /// the key exchange and the authentication are awaited,
+    /// then the session is driven by a tokio task
+    ///
+    pub async fn connect_async<A>(mut self, addr: A) -> SshResult<AsyncSession>
+    where
+        A: ToSocketAddrs,
+    {
+        let timeout = self.config.timeout;
+        let connect = TcpStream::connect(addr);
+        let mut tcp = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| SshError::TimeoutError)??,
+            None => connect.await?,
+        };
+        if let Some(ref keepalive) = self.config.tcp_keepalive {
+            let std_tcp = tcp.into_std()?;
+            keepalive.apply(&std_tcp)?;
+            tcp = TcpStream::from_std(std_tcp)?;
+        }
+        if self.config.host_key_check.known_hosts.is_some() {
+            self.config.host_key_check.set_peer(tcp.peer_addr()?);
+        }
+
+        self.config.tune_alglist_on_private_key();
+        self.config.tune_alglist_on_cipher_strength();
+        self.config.tune_alglist_on_aead();
+        self.config.tune_alglist_on_host_key_algorithm();
+
+        let (reader, writer) = tcp.into_split();
+        let mut reader = PacketReader::new(reader, timeout);
+        let mut writer = PacketWriter::new(writer);
+
+        info!("start for version negotiation.");
+        let mut config = self.config;
+        config.ver.send_our_version(&mut writer)?;
+        writer.write_out().await?;
+        let server_ver = reader.read_version().await?;
+        config.ver.set_server_version(server_ver)?;
+        config.ver.validate()?;
+
+        let mut client = Client::new(config);
+        let mut digest = Digest::new();
+        let server_algs = reader.recv(&mut client).await?;
+        digest.hash_ctx.set_i_s(&server_algs);
+        let server_algs = AlgList::unpack((server_algs, &mut client).into())?;
+        key_agreement(
+            &mut client,
+            &mut reader,
+            &mut writer,
+            server_algs,
+            &mut digest,
+        )
+        .await?;
+
+        let mut auth = client.start_auth(&mut writer)?;
+        loop {
+            writer.write_out().await?;
+            let data = reader.recv(&mut client).await?;
+            if client.recv_auth(&mut writer, &mut auth, data, &digest)? {
+                break;
+            }
+        }
+        if let Some(hook) = self.post_auth {
+            hook(client.get_session_id(), &client.get_negotiated_algorithms());
+        }
+
+        client.set_timeout(None);
+        reader.set_timeout(None);
+        Ok(AsyncSession::new(client, reader, writer))
+    }
+}
+
+/// the async counterpart of [Client::key_agreement]
+async fn key_agreement(
+    client: &mut Client,
+    reader: &mut PacketReader,
+    writer: &mut PacketWriter,
+    server_algs: AlgList,
+    digest: &mut Digest,
+) -> SshResult<()> {
+    let mut kex = client.start_key_agreement(writer, server_algs, digest)?;
+    writer.write_out().await?;
+    let reply = reader.recv(client).await?;
+    client.recv_kex_reply(writer, &mut kex, reply, digest)?;
+    writer.write_out().await?;
+    let new_keys = reader.recv(client).await?;
+    client.recv_new_keys(kex, new_keys, digest)?;
+    client.send_deferred(writer)
+}
+
+/// the channels and the requests waiting on the connection
+#[derive(Default)]
+struct DriverState {
+    channels: HashMap<u32, BackendChannel>,
+    // the senders and the local windows of the channels being opened
+    pendings: HashMap<u32, (UnboundedSender<BackendResp>, u32)>,
+    // the keepalive probes sent since the last reply of a global request
+    missed_keepalives: u32,
+}
+
+impl DriverState {
+    /// wake up anyone who is still waiting on the connection
+    fn abort(&mut self, reason: &str) {
+        for (_, channel) in self.channels.drain() {
+            channel.abort();
+        }
+        for (_, (pending, _)) in self.pendings.drain() {
+            let _ = pending.send(BackendResp::Fail(reason.to_owned()));
+        }
+    }
+}
+
+async fn drive(
+    mut client: Client,
+    mut reader: PacketReader,
+    mut writer: PacketWriter,
+    mut rcv: UnboundedReceiver<AsyncRqst>,
+) -> SshResult<()> {
+    let mut state = DriverState::default();
+    let result = serve(&mut client, &mut reader, &mut writer, &mut rcv, &mut state).await;
+    state.abort("session closed");
+    result
+}
+
+/// the async counterpart of the backend loop,
+/// which waits for the requests, the server and the writes at the same time
+async fn serve(
+    client: &mut Client,
+    reader: &mut PacketReader,
+    writer: &mut PacketWriter,
+    rcv: &mut UnboundedReceiver<AsyncRqst>,
+    state: &mut DriverState,
+) -> SshResult<()> {
+    loop {
+        // a round of the bulk data, then the interactive data queued behind it is sent first
+        if !client.is_rekeying() {
+            for channel in state.channels.values_mut() {
+                channel.send_bulk(client, writer)?;
+            }
+        }
+
+        if client.needs_rekey() {
+            info!("rekey limit reached.");
+            client.start_rekey(writer)?;
+        }
+
+        // no more requests until the server answers our KEXINIT,
+        // as they must not be sent amid the key exchange
+        let rekeying = client.is_rekeying();
+        let check_at = next_check(client);
+        tokio::select! {
+            written = writer.write_some(), if !writer.is_empty() => written?,
+            rqst = rcv.recv(), if !rekeying => match rqst {
+                Some(AsyncRqst::Shutdown(sender)) => {
+                    info!("Session driver shutdown");
+                    let result = disconnect(client, writer).await;
+                    let _ = sender.send(());
+                    return result;
+                }
+                Some(rqst) => handle_rqst(rqst, client, writer, state)?,
+                None => {
+                    info!("Session driver closed");
+                    return disconnect(client, writer).await;
+                }
+            },
+            data = reader.recv(client) => handle_packet(data?, client, reader, writer, state).await?,
+            _ = sleep_until(check_at) => keepalive(client, writer, state)?,
+        }
+    }
+}
+
+async fn disconnect(client: &mut Client, writer: &mut PacketWriter) -> SshResult<()> {
+    client.disconnect(writer, DisconnectReason::ByApplication, "")?;
+    writer.write_out().await
+}
+
+/// when the keepalive or the rekey interval is due, None if neither is set
+///
+/// neither is checked amid a key exchange, which is finished by the server anyway
+fn next_check(client: &Client) -> Option<Instant> {
+    if client.is_rekeying() {
+        return None;
+    }
+    let now = Instant::now();
+    let keepalive = client
+        .get_keepalive_idle()
+        .map(|idle| now + idle.saturating_sub(client.idle_time()));
+    let rekey = client
+        .get_rekey_interval()
+        .map(|interval| now + interval.saturating_sub(client.last_kex().elapsed()));
+    keepalive.into_iter().chain(rekey).min()
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn keepalive(
+    client: &mut Client,
+    writer: &mut PacketWriter,
+    state: &mut DriverState,
+) -> SshResult<()> {
+    let Some(idle) = client.get_keepalive_idle() else {
+        return Ok(());
+    };
+    if client.idle_time() < idle || client.is_rekeying() {
+        return Ok(());
+    }
+    if client
+        .get_keepalive_max_missed()
+        .is_some_and(|max| state.missed_keepalives >= max)
+    {
+        warn!(
+            "{} keepalive probes are not answered",
+            state.missed_keepalives
+        );
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the keepalive probes are not answered",
+        )
+        .into());
+    }
+    debug!("No traffic for {:?}, send keepalive", idle);
+    let mut data = Data::new();
+    data.put_u8(ssh_connection_code::GLOBAL_REQUEST)
+        .put_str(ssh_str::KEEPALIVE_OPENSSH)
+        .put_u8(true as u8);
+    client.send_msg(data, writer)?;
+    state.missed_keepalives += 1;
+    Ok(())
+}
+
+fn handle_rqst(
+    rqst: AsyncRqst,
+    client: &mut Client,
+    writer: &mut PacketWriter,
+    state: &mut DriverState,
+) -> SshResult<()> {
+    match rqst {
+        AsyncRqst::OpenChannel(id, window_size, data, sender) => {
+            info!("try open channel {}.", id);
+            client.send_msg(data, writer)?;
+            state.pendings.insert(id, (sender, window_size));
+        }
+        AsyncRqst::Data(id, data) => {
+            // the channel may have been closed by the remote meanwhile
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Channel {} is closed, drop {} data", id, data.len());
+                return Ok(());
+            };
+            trace!("Channel {} send {} data", id, data.len());
+            channel.send_data(data, client, writer)?;
+        }
+        AsyncRqst::Command(id, data) => {
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Channel {} is closed, drop control data", id);
+                return Ok(());
+            };
+            trace!("Channel {} send control data", id);
+            // after the data before it
+            channel.try_send_data(client, writer)?;
+            channel.send(data, client, writer)?;
+        }
+        AsyncRqst::Interactive(id, interactive) => {
+            if let Some(channel) = state.channels.get_mut(&id) {
+                channel.set_interactive(interactive, client, writer)?;
+            }
+        }
+        AsyncRqst::CloseChannel(id) => {
+            // the one still being opened is closed on its confirmation,
+            // as no one takes it
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Channel {} is not opened or already closed", id);
+                return Ok(());
+            };
+            info!("try close channel {}.", id);
+            channel.send_close(client, writer)?;
+            if channel.closed() {
+                state.channels.remove(&id);
+            }
+        }
+        AsyncRqst::Shutdown(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+async fn handle_packet(
+    mut data: Data,
+    client: &mut Client,
+    reader: &mut PacketReader,
+    writer: &mut PacketWriter,
+    state: &mut DriverState,
+) -> SshResult<()> {
+    let message_code = data.get_u8();
+    match message_code {
+        ssh_connection_code::CHANNEL_OPEN_CONFIRMATION => {
+            let client_id = data.get_u32();
+            let server_id = data.get_u32();
+            let remote_window_size = data.get_u32();
+            // remote packet size, currently don't need it
+            data.get_u32();
+
+            let Some((sender, local_window_size)) = state.pendings.remove(&client_id) else {
+                warn!(
+                    "Drop the open confirmation of {} for unknown channel {}",
+                    server_id, client_id
+                );
+                return Ok(());
+            };
+            if sender.is_closed() {
+                // no one is going to close it
+                info!("Channel {} is dropped before opened, close it", client_id);
+                let mut data = Data::new();
+                data.put_u8(ssh_connection_code::CHANNEL_CLOSE)
+                    .put_u32(server_id);
+                return client.send_msg(data, writer);
+            }
+            let channel = BackendChannel::new(
+                server_id,
+                client_id,
+                local_window_size,
+                remote_window_size,
+                client.get_window_adjust_threshold(),
+                Arc::new(RemoteClose::default()),
+                sender,
+            )?;
+            state.channels.insert(client_id, channel);
+        }
+        ssh_connection_code::CHANNEL_OPEN_FAILURE => {
+            let id = data.get_u32();
+            let Some((sender, _)) = state.pendings.remove(&id) else {
+                warn!("Drop the open failure for unknown channel {}", id);
+                return Ok(());
+            };
+            let _ = sender.send(BackendResp::Fail(open_failure(data)));
+        }
+        ssh_transport_code::KEXINIT => {
+            data.insert(0, message_code);
+            let mut digest = Digest::new();
+            digest.hash_ctx.set_i_s(&data);
+            let server_algs = AlgList::unpack((data, &mut *client).into())?;
+            key_agreement(client, reader, writer, server_algs, &mut digest).await?;
+        }
+        ssh_connection_code::CHANNEL_DATA => {
+            let id = data.get_u32();
+            trace!("Channel {id} get {} data", data.len());
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Drop {} data for unknown channel {}", data.len(), id);
+                return Ok(());
+            };
+            channel.recv(data, client, writer)?;
+        }
+        ssh_connection_code::CHANNEL_EXTENDED_DATA => {
+            let id = data.get_u32();
+            let data_type = data.get_u32();
+            trace!(
+                "Channel {id} get {} extended data, type {data_type}",
+                data.len(),
+            );
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!(
+                    "Drop {} extended data for unknown channel {}",
+                    data.len(),
+                    id
+                );
+                return Ok(());
+            };
+            channel.recv_extended(data_type, data, client, writer)?;
+        }
+        ssh_connection_code::CHANNEL_WINDOW_ADJUST => {
+            let id = data.get_u32();
+            let rws = data.get_u32();
+            match state.channels.get_mut(&id) {
+                Some(channel) => channel.recv_window_adjust(rws, client, writer)?,
+                None => warn!(
+                    "Drop the window adjust of {} for unknown channel {}",
+                    rws, id
+                ),
+            }
+        }
+        ssh_connection_code::CHANNEL_CLOSE => {
+            let id = data.get_u32();
+            info!("Channel {} recv close", id);
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Drop the close for unknown channel {}", id);
+                return Ok(());
+            };
+            channel.remote_close(client, writer)?;
+            if channel.closed() {
+                state.channels.remove(&id);
+            }
+        }
+        // the async session takes no channel from the server
+        ssh_connection_code::CHANNEL_OPEN => {
+            let channel_type = String::from_utf8(data.get_u8s()).unwrap_or_default();
+            let server_id = data.get_u32();
+            warn!(
+                "Reject channel {} of type {} from the server",
+                server_id, channel_type
+            );
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::CHANNEL_OPEN_FAILURE)
+                .put_u32(server_id)
+                .put_u32(ssh_channel_fail_code::ADMINISTRATIVELY_PROHIBITED)
+                .put_str(&format!("unsupported channel type {channel_type}"))
+                .put_str("");
+            client.send_msg(data, writer)?;
+        }
+        ssh_connection_code::GLOBAL_REQUEST => {
+            let name = String::from_utf8(data.get_u8s()).unwrap_or_default();
+            let want_reply = data.get_u8() != 0;
+            debug!("Currently reject global request {}", name);
+            if want_reply {
+                let mut data = Data::new();
+                data.put_u8(ssh_connection_code::REQUEST_FAILURE);
+                client.send_msg(data, writer)?;
+            }
+        }
+        // the only global requests sent are the keepalive probes
+        ssh_connection_code::REQUEST_SUCCESS | ssh_connection_code::REQUEST_FAILURE => {
+            trace!("Keepalive replied");
+            state.missed_keepalives = 0;
+        }
+        ssh_connection_code::CHANNEL_REQUEST => {
+            let id = data.get_u32();
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Drop the request for unknown channel {}", id);
+                return Ok(());
+            };
+            let _ = channel.recv_rqst(data);
+        }
+        ssh_connection_code::CHANNEL_SUCCESS => {
+            let id = data.get_u32();
+            trace!("Channel {} control success", id);
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Drop the success for unknown channel {}", id);
+                return Ok(());
+            };
+            channel.success()?
+        }
+        ssh_connection_code::CHANNEL_FAILURE => {
+            let id = data.get_u32();
+            trace!("Channel {} control failed", id);
+            let Some(channel) = state.channels.get_mut(&id) else {
+                debug!("Drop the failure for unknown channel {}", id);
+                return Ok(());
+            };
+            channel.failed()?
+        }
+        x => {
+            debug!("Currently ignore message {}", x);
+        }
+    }
+    Ok(())
+}
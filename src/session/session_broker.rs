@@ -1,48 +1,117 @@
 use std::{
-    collections::HashMap,
-    io::{Read, Write},
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
     sync::{
-        mpsc::{self, Receiver, Sender, TryRecvError},
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
         Arc, Mutex,
     },
-    thread::spawn,
+    thread::{self, spawn},
+    time::{Duration, Instant},
 };
 
 use tracing::*;
 
 use crate::{
-    algorithm::Digest,
-    channel::{BackendChannel, ExecBroker},
+    algorithm::{public_key, Digest, FingerprintHash},
+    channel::{BackendChannel, DirectTcpipBroker, ExecBroker, LocalForward, RemoteForward},
     client::Client,
-    config::algorithm::AlgList,
+    config::{algorithm::AlgList, env::EnvVar, subsystem, window::WindowSizes, ChannelKind},
     constant::{size, ssh_channel_fail_code, ssh_connection_code, ssh_str, ssh_transport_code},
     error::{SshError, SshResult},
-    model::{ArcMut, BackendResp, BackendRqst, Data, Packet, SecPacket, U32Iter},
-    ChannelBroker, ShellBrocker, TerminalSize,
+    model::{
+        ArcMut, BackendResp, BackendRqst, CompressionCounter, CompressionStats, Data,
+        DisconnectReason, ForwardedChannel, KexDetails, NegotiatedAlgorithms, Packet, RemoteClose,
+        SecPacket, U32Iter,
+    },
+    sftp::{Sftp, SftpBroker},
+    ChannelBroker, PtyConfig, ShellBrocker, SubsystemBroker, TerminalSize,
 };
 
+#[cfg(feature = "dangerous-key-export")]
+use crate::model::SessionKeys;
+
+use super::Reconnect;
+
 #[cfg(feature = "scp")]
 use crate::ScpBroker;
 
+/// how often an open that can be given up checks whether to go on waiting
+const OPEN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 pub struct SessionBroker {
     channel_num: ArcMut<U32Iter>,
     snd: Sender<BackendRqst>,
+    compression_counter: Arc<CompressionCounter>,
+    window_sizes: Arc<WindowSizes>,
+    env: Arc<Vec<EnvVar>>,
+    allowed_subsystems: Arc<Option<Vec<String>>>,
+    offered_host_key_algs: Arc<Vec<String>>,
+    server_extensions: Arc<Vec<(String, Vec<u8>)>>,
+    banner: Arc<Option<String>>,
+    server_host_key: Arc<Vec<u8>>,
+    kex_details: Arc<KexDetails>,
+    // updated by the backend on every key exchange
+    negotiated_algorithms: ArcMut<NegotiatedAlgorithms>,
+    // updated by the backend on every key exchange
+    #[cfg(feature = "dangerous-key-export")]
+    session_keys: ArcMut<Option<SessionKeys>>,
+    // updated by the backend on each key exchange
+    aead: Arc<AtomicBool>,
 }
 
 impl SessionBroker {
-    pub(crate) fn new<S>(client: Client, stream: S) -> Self
+    pub(crate) fn new<S>(client: Client, stream: S, reconnect: Option<Reconnect<S>>) -> Self
     where
         S: Read + Write + Send + 'static,
     {
         let (rqst_snd, rqst_rcv) = mpsc::channel();
+        let compression_counter = client.get_compression_counter().clone();
+        let window_sizes = Arc::new(client.get_window_sizes().clone());
+        let env = Arc::new(client.get_env().to_vec());
+        let allowed_subsystems = Arc::new(client.get_allowed_subsystems().map(<[String]>::to_vec));
+        let offered_host_key_algs = Arc::new(client.get_offered_host_key_algs().to_vec());
+        let server_extensions = Arc::new(client.get_server_extensions().to_vec());
+        let banner = Arc::new(client.get_banner().map(str::to_owned));
+        let server_host_key = Arc::new(client.get_server_host_key().to_vec());
+        let kex_details = Arc::new(client.get_kex_details().clone());
+        let negotiated_algorithms = client.get_shared_negotiated_algorithms().clone();
+        #[cfg(feature = "dangerous-key-export")]
+        let session_keys = client.get_session_keys().clone();
+        let aead = Arc::new(AtomicBool::new(client.is_aead()));
+        let backend_aead = aead.clone();
+        // the backend numbers the channels opened by the server
+        let channel_num = Arc::new(Mutex::new(U32Iter::default()));
+        let backend_channel_num = channel_num.clone();
         spawn(move || {
-            if let Err(e) = client_loop(client, stream, rqst_rcv) {
+            if let Err(e) = client_loop(
+                client,
+                stream,
+                rqst_rcv,
+                backend_aead,
+                backend_channel_num,
+                reconnect,
+            ) {
                 error!("Error {:?} occurred when running backend task", e)
             }
         });
         Self {
-            channel_num: Arc::new(Mutex::new(U32Iter::default())),
+            channel_num,
             snd: rqst_snd,
+            compression_counter,
+            window_sizes,
+            env,
+            allowed_subsystems,
+            offered_host_key_algs,
+            server_extensions,
+            banner,
+            server_host_key,
+            kex_details,
+            negotiated_algorithms,
+            #[cfg(feature = "dangerous-key-export")]
+            session_keys,
+            aead,
         }
     }
 
@@ -53,10 +122,139 @@ impl SessionBroker {
         drop(self)
     }
 
+    /// send SSH_MSG_DISCONNECT to the server and stop the backend thread,
+    /// then consume the session broker itself
+    ///
+    /// Unlike [SessionBroker::close], this does not wait for the opened channels to be dropped.
+    /// Any channel which is blocked on a read in other threads will be woken up as closed.
+    ///
+    pub fn shutdown(self) -> SshResult<()> {
+        self.disconnect(DisconnectReason::ByApplication, "")
+    }
+
+    /// same as [SessionBroker::shutdown], but with the reason code & description of the disconnection
+    ///
+    pub fn disconnect(self, reason: DisconnectReason, description: &str) -> SshResult<()> {
+        info!("Client shutdown");
+        let (resp_send, resp_recv) = mpsc::channel();
+        self.snd.send(BackendRqst::Shutdown(
+            reason,
+            description.to_owned(),
+            resp_send,
+        ))?;
+        match resp_recv.recv()? {
+            BackendResp::Fail(msg) => Err(SshError::GeneralError(msg)),
+            _ => Ok(()),
+        }
+    }
+
+    /// the bytes before and after the compression in each direction so far
+    ///
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_counter.stats()
+    }
+
+    /// whether the negotiated ciphers are AEAD (e.g. chacha20-poly1305@openssh.com),
+    /// which need no separate MAC, rather than a cipher plus a MAC
+    ///
+    pub fn is_aead(&self) -> bool {
+        self.aead.load(Ordering::Relaxed)
+    }
+
+    /// the host key algorithms offered by the server in the initial key exchange,
+    /// in the server's order, including the ones not supported by us
+    ///
+    pub fn offered_host_key_algorithms(&self) -> Vec<String> {
+        self.offered_host_key_algs.to_vec()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc8308#section-2.3>
+    ///
+    /// the (name, value) extensions sent by the server in SSH_MSG_EXT_INFO,
+    /// empty if the server sends none or [crate::SessionBuilder::ext_info] is disabled
+    ///
+    pub fn server_extensions(&self) -> Vec<(String, Vec<u8>)> {
+        self.server_extensions.to_vec()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4252#section-5.4>
+    ///
+    /// the banners sent by the server during the authentication, one after another,
+    /// None if the server sends none
+    ///
+    pub fn banner(&self) -> Option<String> {
+        (*self.banner).clone()
+    }
+
+    /// the server host key in the `authorized_keys` format, e.g. `ssh-ed25519 AAAA...`
+    ///
+    pub fn server_public_key_openssh(&self) -> SshResult<String> {
+        public_key::to_openssh(&self.server_host_key)
+    }
+
+    /// the server host key (`K_S`) as ssh encoded in the key exchange,
+    /// e.g. for a trust-on-first-use check by yourself
+    ///
+    pub fn server_host_key(&self) -> Vec<u8> {
+        self.server_host_key.to_vec()
+    }
+
+    /// the fingerprint of the server host key, e.g. `SHA256:...`,
+    /// the same as `ssh-keygen -l` prints
+    ///
+    pub fn host_key_fingerprint(&self, hash: FingerprintHash) -> String {
+        public_key::fingerprint(&self.server_host_key, hash)
+    }
+
+    /// the KEXINIT payloads, the hash and the lengths of the keys derived
+    /// in the initial key exchange,
+    /// for the interop diagnostics
+    ///
+    pub fn kex_details(&self) -> KexDetails {
+        self.kex_details.as_ref().clone()
+    }
+
+    /// the names of the key exchange, the host key, the ciphers, the MACs
+    /// and the compressions agreed on in the last key exchange,
+    /// as `ssh -v` prints
+    ///
+    pub fn negotiated_algorithms(&self) -> NegotiatedAlgorithms {
+        self.negotiated_algorithms.lock().unwrap().clone()
+    }
+
+    /// **DANGER: anyone holding the keys can decrypt the whole session.**
+    ///
+    /// the keys derived in the last key exchange, including the re-exchanges,
+    /// for decrypting a packet capture while debugging
+    ///
+    #[cfg(feature = "dangerous-key-export")]
+    pub fn dangerous_session_keys(&self) -> Option<SessionKeys> {
+        self.session_keys.lock().unwrap().clone()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-9>
+    ///
+    /// renew the session keys, and block until the key exchange is finished
+    ///
+    /// It's fine that the server starts a key exchange at the same time,
+    /// only one will be performed then
+    ///
+    pub fn rekey(&self) -> SshResult<()> {
+        let (resp_send, resp_recv) = mpsc::channel();
+        self.snd
+            .send(BackendRqst::Rekey(Instant::now(), resp_send))?;
+        match resp_recv.recv()? {
+            BackendResp::Fail(msg) => Err(SshError::GeneralError(msg)),
+            _ => Ok(()),
+        }
+    }
+
     /// open a [ExecBroker] channel which can excute commands
     ///
     pub fn open_exec(&mut self) -> SshResult<ExecBroker> {
-        let channel = self.open_channel()?;
+        let mut channel =
+            self.open_channel_with(ChannelKind::Exec, ssh_str::SESSION, Data::new())?;
+        channel.send_env(&self.env)?;
         channel.exec()
     }
 
@@ -64,14 +262,14 @@ impl SessionBroker {
     ///
     #[cfg(feature = "scp")]
     pub fn open_scp(&mut self) -> SshResult<ScpBroker> {
-        let channel = self.open_channel()?;
+        let channel = self.open_channel_with(ChannelKind::Scp, ssh_str::SESSION, Data::new())?;
         channel.scp()
     }
 
     /// open a [ShellBrocker] channel which  can be used as a pseudo terminal (AKA PTY)
     ///
     pub fn open_shell(&mut self) -> SshResult<ShellBrocker> {
-        self.open_shell_terminal(TerminalSize::from(80, 24))
+        self.open_shell_with_pty(PtyConfig::default())
     }
 
     /// open a [ShellBrocker] channel
@@ -79,8 +277,39 @@ impl SessionBroker {
     /// custom terminal dimensions
     ///
     pub fn open_shell_terminal(&mut self, tv: TerminalSize) -> SshResult<ShellBrocker> {
-        let channel = self.open_channel()?;
-        channel.shell(tv)
+        self.open_shell_with_pty(tv.into())
+    }
+
+    /// open a [ShellBrocker] channel
+    ///
+    /// custom terminal type & dimensions, see [PtyConfig]
+    ///
+    pub fn open_shell_with_pty(&mut self, pty: PtyConfig) -> SshResult<ShellBrocker> {
+        let mut channel =
+            self.open_channel_with(ChannelKind::Shell, ssh_str::SESSION, Data::new())?;
+        channel.send_env(&self.env)?;
+        channel.shell_with_pty(pty)
+    }
+
+    /// open a [SubsystemBroker] channel running `name`, such as `sftp`
+    ///
+    /// [SshError::SubsystemNotAllowed] without opening the channel
+    /// if it's not allowed by [crate::SessionBuilder::allowed_subsystems]
+    ///
+    pub fn open_subsystem(&mut self, name: &str) -> SshResult<SubsystemBroker> {
+        subsystem::check(self.allowed_subsystems.as_deref(), name)?;
+        let channel =
+            self.open_channel_with(ChannelKind::Subsystem, ssh_str::SESSION, Data::new())?;
+        channel.subsystem(name)
+    }
+
+    /// open an [SftpBroker] session on the `sftp` subsystem
+    ///
+    /// [SshError::SubsystemNotAllowed] if it's not allowed by [crate::SessionBuilder::allowed_subsystems]
+    ///
+    pub fn open_sftp(&mut self) -> SshResult<SftpBroker> {
+        let subsystem = self.open_subsystem(ssh_str::SFTP)?;
+        Sftp::start(subsystem)
     }
 
     /// open a raw channel
@@ -88,87 +317,653 @@ impl SessionBroker {
     /// need call `.exec()`, `.shell()`, `.scp()` and so on to convert it to a specific channel
     ///
     pub fn open_channel(&mut self) -> SshResult<ChannelBroker> {
+        self.open_channel_with(ChannelKind::Session, ssh_str::SESSION, Data::new())
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.2>
+    ///
+    /// open a [DirectTcpipBroker] channel to `host:port`, which is connected by the server
+    ///
+    /// the bytes sent/received by the channel are forwarded to/from the target
+    ///
+    /// `host` can be a domain name, an IPv4 or an IPv6 address,
+    /// the brackets around an IPv6 address (e.g. `[::1]`) are optional
+    ///
+    pub fn open_direct_tcpip(&mut self, host: &str, port: u16) -> SshResult<DirectTcpipBroker> {
+        let originator = SocketAddr::from(([127, 0, 0, 1], 0));
+        let channel = self.open_direct_tcpip_from(host, port, originator, None)?;
+        Ok(DirectTcpipBroker::open(channel))
+    }
+
+    /// the open is given up once `stop` is set, see [SessionBroker::open_channel_until]
+    pub(crate) fn open_direct_tcpip_from(
+        &mut self,
+        host: &str,
+        port: u16,
+        originator: SocketAddr,
+        stop: Option<&AtomicBool>,
+    ) -> SshResult<ChannelBroker> {
+        // the protocol expects a bare IPv6 address
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+
+        let mut extra = Data::new();
+        extra
+            .put_str(host)
+            .put_u32(port as u32)
+            .put_str(&originator.ip().to_string())
+            .put_u32(originator.port() as u32);
+        self.open_channel_until(ChannelKind::DirectTcpip, ssh_str::DIRECT_TCPIP, extra, stop)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.1>
+    ///
+    /// listen on `bind_addr` locally, every accepted connection is forwarded
+    /// to `remote_host:remote_port` through a new `direct-tcpip` channel
+    ///
+    /// the forward works in background until the returned [LocalForward] is dropped,
+    /// which stops listening
+    ///
+    /// it can be called several times to run multiple forwards on the same session
+    ///
+    pub fn local_forward<A>(
+        &mut self,
+        bind_addr: A,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> SshResult<LocalForward>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(bind_addr)?;
+        LocalForward::start(listener, self.opener(), remote_host.to_owned(), remote_port)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.1>
+    ///
+    /// ask the server to listen on `bind_addr:bind_port`, every connection to it
+    /// comes as a `forwarded-tcpip` channel, which is forwarded to `local_target`
+    ///
+    /// the server allocates a port if `bind_port` is 0, see [RemoteForward::remote_port]
+    ///
+    /// the forward works in background until the returned [RemoteForward] is dropped,
+    /// which sends `cancel-tcpip-forward` to the server
+    ///
+    pub fn remote_forward<A>(
+        &mut self,
+        bind_addr: &str,
+        bind_port: u16,
+        local_target: A,
+    ) -> SshResult<RemoteForward>
+    where
+        A: ToSocketAddrs,
+    {
+        let target: Vec<SocketAddr> = local_target.to_socket_addrs()?.collect();
+        if target.is_empty() {
+            return Err(SshError::GeneralError(
+                "remote forward: the local target has no address".to_owned(),
+            ));
+        }
+
+        let port = self.tcpip_forward(bind_addr, bind_port)?;
+        let (listener_send, listener_recv) = mpsc::channel();
+        self.snd.send(BackendRqst::Listen(
+            bind_addr.to_owned(),
+            bind_port as u32,
+            port as u32,
+            listener_send,
+        ))?;
+        Ok(RemoteForward::start(
+            listener_recv,
+            self.opener(),
+            bind_addr.to_owned(),
+            port,
+            target,
+        ))
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.1>
+    ///
+    /// ask the server to listen on `bind_addr:bind_port` for remote port forwarding
+    ///
+    /// return the port that the server listens on,
+    /// which is allocated by the server if `bind_port` is 0
+    ///
+    pub fn tcpip_forward(&mut self, bind_addr: &str, bind_port: u16) -> SshResult<u16> {
+        let mut data = Data::new();
+        data.put_str(bind_addr).put_u32(bind_port as u32);
+        let mut reply = self.global_request(ssh_str::TCPIP_FORWARD, data)?;
+
+        if bind_port != 0 {
+            return Ok(bind_port);
+        }
+        if reply.len() < 4 {
+            return Err(SshError::GeneralError(
+                "tcpip-forward: the server does not reply the allocated port".to_owned(),
+            ));
+        }
+        Ok(reply.get_u32() as u16)
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-7.1>
+    ///
+    /// stop the server listening on `bind_addr:bind_port`,
+    /// which is no longer requested again on reconnection
+    pub(crate) fn cancel_tcpip_forward(
+        &mut self,
+        bind_addr: &str,
+        bind_port: u16,
+    ) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(bind_addr).put_u32(bind_port as u32);
+        self.global_request(ssh_str::CANCEL_TCPIP_FORWARD, data)?;
+        Ok(())
+    }
+
+    /// the broker of a `forwarded-tcpip` channel confirmed by the backend
+    pub(crate) fn forwarded_channel(&self, forwarded: ForwardedChannel) -> ChannelBroker {
+        // the confirmation, which is sent before the channel is passed on
+        let _ = forwarded.rcv.recv();
+        ChannelBroker::new(
+            forwarded.client_id,
+            forwarded.server_id,
+            forwarded.rcv,
+            self.snd.clone(),
+            forwarded.remote_close,
+        )
+    }
+
+    /// another broker of the same session for the background forwards
+    pub(crate) fn opener(&self) -> Self {
+        Self {
+            channel_num: self.channel_num.clone(),
+            snd: self.snd.clone(),
+            compression_counter: self.compression_counter.clone(),
+            window_sizes: self.window_sizes.clone(),
+            env: self.env.clone(),
+            allowed_subsystems: self.allowed_subsystems.clone(),
+            offered_host_key_algs: self.offered_host_key_algs.clone(),
+            server_extensions: self.server_extensions.clone(),
+            banner: self.banner.clone(),
+            server_host_key: self.server_host_key.clone(),
+            kex_details: self.kex_details.clone(),
+            negotiated_algorithms: self.negotiated_algorithms.clone(),
+            #[cfg(feature = "dangerous-key-export")]
+            session_keys: self.session_keys.clone(),
+            aead: self.aead.clone(),
+        }
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4254#section-4>
+    ///
+    /// send a global request which wants a reply,
+    /// and return the request-specific data of SSH_MSG_REQUEST_SUCCESS
+    fn global_request(&mut self, name: &str, data: Data) -> SshResult<Data> {
+        let (resp_send, resp_recv) = mpsc::channel();
+
+        let mut rqst = Data::new();
+        rqst.put_u8(ssh_connection_code::GLOBAL_REQUEST)
+            .put_str(name)
+            .put_u8(true as u8);
+        rqst.extend(data.into_inner());
+
+        self.snd.send(BackendRqst::GlobalRequest(rqst, resp_send))?;
+        match resp_recv.recv()? {
+            BackendResp::Data(reply) => Ok(reply),
+            BackendResp::Fail(msg) => Err(SshError::GeneralError(format!(
+                "Global request {name} failed: {msg}"
+            ))),
+            _ => unreachable!(),
+        }
+    }
+
+    fn open_channel_with(
+        &mut self,
+        kind: ChannelKind,
+        channel_type: &str,
+        extra: Data,
+    ) -> SshResult<ChannelBroker> {
+        self.open_channel_until(kind, channel_type, extra, None)
+    }
+
+    /// open a channel, waiting for the server until `stop` is set if any
+    ///
+    /// a channel confirmed after that is closed by the backend
+    fn open_channel_until(
+        &mut self,
+        kind: ChannelKind,
+        channel_type: &str,
+        extra: Data,
+        stop: Option<&AtomicBool>,
+    ) -> SshResult<ChannelBroker> {
         let (resp_send, resp_recv) = mpsc::channel();
         let client_id = self.channel_num.lock().unwrap().next().unwrap();
+        let window_size = self.window_sizes.get(kind);
 
         // open channel request
         let mut data = Data::new();
         data.put_u8(ssh_connection_code::CHANNEL_OPEN)
-            .put_str(ssh_str::SESSION)
+            .put_str(channel_type)
             .put_u32(client_id)
-            .put_u32(size::LOCAL_WINDOW_SIZE)
+            .put_u32(window_size)
             .put_u32(size::BUF_SIZE as u32);
+        data.extend(extra.into_inner());
 
-        self.snd
-            .send(BackendRqst::OpenChannel(client_id, data, resp_send))?;
+        let remote_close = Arc::new(RemoteClose::default());
+        self.snd.send(BackendRqst::OpenChannel(
+            client_id,
+            window_size,
+            data,
+            remote_close.clone(),
+            resp_send,
+        ))?;
 
         // get the response
-        match resp_recv.recv() {
-            Ok(resp) => match resp {
-                BackendResp::Ok(server_id) => Ok(ChannelBroker::new(
-                    client_id,
-                    server_id,
-                    resp_recv,
-                    self.snd.clone(),
-                )),
-                BackendResp::Fail(msg) => Err(SshError::GeneralError(msg)),
-                _ => unreachable!(),
-            },
-            Err(e) => Err(e.into()),
+        let resp = loop {
+            let Some(stop) = stop else {
+                break resp_recv.recv()?;
+            };
+            match resp_recv.recv_timeout(OPEN_POLL_INTERVAL) {
+                Ok(resp) => break resp,
+                Err(RecvTimeoutError::Timeout) if stop.load(Ordering::Relaxed) => {
+                    return Err(SshError::GeneralError(format!(
+                        "Open of channel {client_id} is given up"
+                    )));
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(e @ RecvTimeoutError::Disconnected) => {
+                    return Err(SshError::IpcError(e.to_string()))
+                }
+            }
+        };
+        match resp {
+            BackendResp::Ok(server_id) => Ok(ChannelBroker::new(
+                client_id,
+                server_id,
+                resp_recv,
+                self.snd.clone(),
+                remote_close,
+            )),
+            BackendResp::Fail(msg) => Err(SshError::GeneralError(msg)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// the channels and the requests waiting on the current connection
+#[derive(Default)]
+struct BackendState {
+    channels: HashMap<u32, BackendChannel>,
+    // the senders and the local windows of the channels being opened
+    pendings: HashMap<u32, (Sender<BackendResp>, u32, Arc<RemoteClose>)>,
+    // the replies of global requests are in the order of the requests
+    // None for the ones whose replies are not cared, e.g. the keepalive probes
+    global_pendings: VecDeque<Option<Sender<BackendResp>>>,
+    // the ones waiting for the key re-exchange to finish
+    rekey_pendings: Vec<Sender<BackendResp>>,
+    // the keepalive probes sent since the last reply of a global request
+    missed_keepalives: u32,
+}
+
+impl BackendState {
+    /// wake up anyone who is still waiting on the connection
+    fn abort(&mut self, reason: &str) {
+        for (_, channel) in self.channels.drain() {
+            channel.abort();
+        }
+        for (_, (pending, ..)) in self.pendings.drain() {
+            let _ = pending.send(BackendResp::Fail(reason.to_owned()));
+        }
+        for pending in self.global_pendings.drain(..).flatten() {
+            let _ = pending.send(BackendResp::Fail(reason.to_owned()));
+        }
+        for pending in self.rekey_pendings.drain(..) {
+            let _ = pending.send(BackendResp::Fail(reason.to_owned()));
+        }
+    }
+}
+
+/// the remote forwards, which outlive the connection
+#[derive(Default)]
+struct Forwards {
+    // the tcpip-forward requests to send again on a new connection
+    requests: Vec<Data>,
+    // where the forwarded-tcpip channels go, by the port listened on
+    listeners: HashMap<u32, Sender<ForwardedChannel>>,
+}
+
+impl Forwards {
+    fn listen(
+        &mut self,
+        bind_addr: String,
+        requested: u32,
+        port: u32,
+        listener: Sender<ForwardedChannel>,
+    ) {
+        // ask for the allocated port again on a new connection,
+        // then the channels to it still find the listener
+        let target = (bind_addr, requested);
+        if let Some(request) = self
+            .requests
+            .iter_mut()
+            .find(|r| forward_target(r) == target)
+        {
+            let mut data = Data::new();
+            data.put_u8(ssh_connection_code::GLOBAL_REQUEST)
+                .put_str(ssh_str::TCPIP_FORWARD)
+                .put_u8(true as u8)
+                .put_str(&target.0)
+                .put_u32(port);
+            *request = data;
+        }
+        self.listeners.insert(port, listener);
+    }
+
+    fn cancel(&mut self, target: (String, u32)) {
+        self.requests.retain(|r| forward_target(r) != target);
+        self.listeners.remove(&target.1);
+    }
+}
+
+/// the reason of SSH_MSG_CHANNEL_OPEN_FAILURE, whose recipient channel is already taken
+pub(super) fn open_failure(mut data: Data) -> String {
+    // error code
+    let code = data.get_u32();
+    // error detail: By default is utf-8
+    let description = String::from_utf8(data.get_u8s()).unwrap_or_else(|_| String::from("error"));
+    // language tag, assume to be en-US
+    data.get_u8s();
+
+    match code {
+        ssh_channel_fail_code::ADMINISTRATIVELY_PROHIBITED => {
+            format!("ADMINISTRATIVELY_PROHIBITED: {description}")
+        }
+        ssh_channel_fail_code::CONNECT_FAILED => format!("CONNECT_FAILED: {description}"),
+        ssh_channel_fail_code::UNKNOWN_CHANNEL_TYPE => {
+            format!("UNKNOWN_CHANNEL_TYPE: {description}")
+        }
+        ssh_channel_fail_code::RESOURCE_SHORTAGE => format!("RESOURCE_SHORTAGE: {description}"),
+        _ => description,
+    }
+}
+
+/// the (address, port) of a tcpip-forward or cancel-tcpip-forward request
+fn forward_target(request: &Data) -> (String, u32) {
+    let mut data = request.clone();
+    data.get_u8();
+    data.get_u8s();
+    data.get_u8();
+    let addr = String::from_utf8(data.get_u8s()).unwrap_or_default();
+    (addr, data.get_u32())
+}
+
+fn client_loop<S>(
+    mut client: Client,
+    mut stream: S,
+    rcv: Receiver<BackendRqst>,
+    aead: Arc<AtomicBool>,
+    channel_num: ArcMut<U32Iter>,
+    mut reconnect: Option<Reconnect<S>>,
+) -> SshResult<()>
+where
+    S: Read + Write,
+{
+    let mut forwards = Forwards::default();
+    loop {
+        let mut state = BackendState::default();
+        let result = serve(
+            &mut client,
+            &mut stream,
+            &rcv,
+            &aead,
+            &channel_num,
+            &mut state,
+            &mut forwards,
+        );
+        let Some(ref mut reconnect) = reconnect else {
+            return result;
+        };
+        match result {
+            Err(SshError::IoError(e)) => {
+                warn!("Connection lost: {:?}, try to reconnect", e);
+                state.abort("connection lost");
+            }
+            result => return result,
+        }
+
+        loop {
+            thread::sleep(reconnect.interval);
+            match (reconnect.connect)() {
+                Ok((mut new_client, new_stream)) => {
+                    info!("Reconnected");
+                    new_client.share_negotiated_algorithms(
+                        client.get_shared_negotiated_algorithms().clone(),
+                    );
+                    (client, stream) = (new_client, new_stream);
+                    aead.store(client.is_aead(), Ordering::Relaxed);
+                    break;
+                }
+                Err(e) => warn!("Fail to reconnect: {:?}", e),
+            }
+            // fail the requests made meanwhile rather than blocking them
+            loop {
+                match rcv.try_recv() {
+                    Ok(BackendRqst::Shutdown(.., sender)) => {
+                        info!("Session backend shutdown while reconnecting");
+                        let _ = sender.send(BackendResp::Ok(0));
+                        return Ok(());
+                    }
+                    Ok(BackendRqst::OpenChannel(.., sender))
+                    | Ok(BackendRqst::GlobalRequest(_, sender))
+                    | Ok(BackendRqst::Rekey(_, sender)) => {
+                        let _ = sender.send(BackendResp::Fail("reconnecting".to_owned()));
+                    }
+                    Ok(BackendRqst::Listen(bind_addr, requested, port, listener)) => {
+                        forwards.listen(bind_addr, requested, port, listener)
+                    }
+                    // the channels are gone with the lost connection
+                    Ok(_) => (),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        info!("Session backend Closed");
+                        return Ok(());
+                    }
+                }
+            }
         }
     }
 }
 
-fn client_loop<S>(mut client: Client, mut stream: S, rcv: Receiver<BackendRqst>) -> SshResult<()>
+/// run the session on one connection
+fn serve<S>(
+    client: &mut Client,
+    stream: &mut S,
+    rcv: &Receiver<BackendRqst>,
+    aead: &AtomicBool,
+    channel_num: &ArcMut<U32Iter>,
+    state: &mut BackendState,
+    forwards: &mut Forwards,
+) -> SshResult<()>
 where
     S: Read + Write,
 {
-    let mut channels = HashMap::<u32, BackendChannel>::new();
-    let mut pendings = HashMap::<u32, Sender<BackendResp>>::new();
     client.set_timeout(None);
+    for forward in forwards.requests.iter() {
+        debug!("Request the remote forward again");
+        client.send_msg(forward.clone(), stream)?;
+        state.global_pendings.push_back(None);
+    }
     loop {
-        let try_recv = rcv.try_recv();
-        if try_recv.is_err() {
-            if let Err(TryRecvError::Disconnected) = try_recv {
-                info!("Session backend Closed");
-                return Ok(());
-            }
-        } else if let Ok(rqst) = try_recv {
+        // take all the requests before a round of the bulk data,
+        // then the interactive data queued behind it is sent first
+        loop {
+            // no more requests until the server answers our KEXINIT,
+            // as they must not be sent amid the key exchange
+            let try_recv = if client.is_rekeying() {
+                Err(TryRecvError::Empty)
+            } else {
+                rcv.try_recv()
+            };
+            let rqst = match try_recv {
+                Ok(rqst) => rqst,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    info!("Session backend Closed");
+                    return Ok(());
+                }
+            };
             match rqst {
-                BackendRqst::OpenChannel(id, data, sender) => {
+                BackendRqst::OpenChannel(id, window_size, data, remote_close, sender) => {
                     info!("try open channel {}.", id);
 
-                    data.pack(&mut client).write_stream(&mut stream)?;
+                    client.send_msg(data, stream)?;
 
                     // add to pending open list
-                    assert!(pendings.insert(id, sender).is_none());
+                    assert!(state
+                        .pendings
+                        .insert(id, (sender, window_size, remote_close))
+                        .is_none());
                 }
                 BackendRqst::Data(id, data) => {
-                    let channel = channels.get_mut(&id).unwrap();
+                    // the channel may have been closed by the remote meanwhile
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Channel {} is closed, drop {} data", id, data.len());
+                        continue;
+                    };
 
                     trace!("Channel {} send {} data", id, data.len());
-                    channel.send_data(data, &mut client, &mut stream)?;
+                    channel.send_data(data, client, stream)?;
+                }
+                #[cfg(feature = "scp")]
+                BackendRqst::Flush(id, sender) => {
+                    // a closed channel drops the sender, which fails the flush
+                    if let Some(channel) = state.channels.get_mut(&id) {
+                        channel.flush(sender);
+                    }
                 }
                 BackendRqst::Command(id, data) => {
-                    let channel = channels.get_mut(&id).unwrap();
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Channel {} is closed, drop control data", id);
+                        continue;
+                    };
 
                     trace!("Channel {} send control data", id);
-                    channel.send(data, &mut client, &mut stream)?;
+                    // after the data before it
+                    channel.try_send_data(client, stream)?;
+                    channel.send(data, client, stream)?;
+                }
+                BackendRqst::Interactive(id, interactive) => {
+                    if let Some(channel) = state.channels.get_mut(&id) {
+                        channel.set_interactive(interactive, client, stream)?;
+                    }
+                }
+                BackendRqst::Eof(id) => {
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Channel {} is closed, drop the eof", id);
+                        continue;
+                    };
+
+                    trace!("Channel {} send eof", id);
+                    channel.send_eof(client, stream)?;
                 }
                 BackendRqst::CloseChannel(id, data) => {
                     info!("try close channel {}.", id);
 
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.send(data, &mut client, &mut stream)?;
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Channel {} is already closed", id);
+                        continue;
+                    };
+                    channel.try_send_data(client, stream)?;
+                    channel.send(data, client, stream)?;
                     channel.local_close()?;
                     if channel.closed() {
-                        channels.remove(&id);
+                        state.channels.remove(&id);
+                    }
+                }
+                BackendRqst::GlobalRequest(data, sender) => {
+                    info!("send global request.");
+                    let mut name = data.clone();
+                    name.get_u8();
+                    let name = name.get_u8s();
+                    if name == ssh_str::TCPIP_FORWARD.as_bytes() {
+                        forwards.requests.push(data.clone());
+                    } else if name == ssh_str::CANCEL_TCPIP_FORWARD.as_bytes() {
+                        forwards.cancel(forward_target(&data));
+                    }
+
+                    client.send_msg(data, stream)?;
+                    state.global_pendings.push_back(Some(sender));
+                }
+                BackendRqst::Listen(bind_addr, requested, port, listener) => {
+                    info!("listen for the forwarded-tcpip channels to port {}", port);
+                    forwards.listen(bind_addr, requested, port, listener)
+                }
+                BackendRqst::Rekey(requested, sender) => {
+                    if client.last_kex() > requested {
+                        // e.g. the server starts one at the same time
+                        info!("keys already renewed since the rekey request.");
+                        sender.send(BackendResp::Ok(0))?;
+                    } else {
+                        client.start_rekey(stream)?;
+                        state.rekey_pendings.push(sender);
                     }
                 }
+                BackendRqst::Shutdown(reason, description, sender) => {
+                    info!("Session backend shutdown");
+                    let result = client.disconnect(stream, reason, &description);
+
+                    // wake up anyone who is still waiting on this session
+                    state.abort("session shutdown");
+
+                    let _ = sender.send(match result {
+                        Ok(_) => BackendResp::Ok(0),
+                        Err(ref e) => BackendResp::Fail(e.to_string()),
+                    });
+                    return result;
+                }
+            }
+        }
+        if !client.is_rekeying() {
+            for channel in state.channels.values_mut() {
+                channel.send_bulk(client, stream)?;
             }
         }
 
-        if let Some(pkt) = SecPacket::try_from_stream(&mut stream, &mut client)? {
+        if client.needs_rekey() {
+            info!("rekey limit reached.");
+            client.start_rekey(stream)?;
+        }
+
+        if let Some(idle) = client.get_keepalive_idle() {
+            if client.idle_time() >= idle && !client.is_rekeying() {
+                if client
+                    .get_keepalive_max_missed()
+                    .is_some_and(|max| state.missed_keepalives >= max)
+                {
+                    warn!(
+                        "{} keepalive probes are not answered",
+                        state.missed_keepalives
+                    );
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "the keepalive probes are not answered",
+                    )
+                    .into());
+                }
+                debug!("No traffic for {:?}, send keepalive", idle);
+                let mut data = Data::new();
+                data.put_u8(ssh_connection_code::GLOBAL_REQUEST)
+                    .put_str(ssh_str::KEEPALIVE_OPENSSH)
+                    .put_u8(true as u8);
+                client.send_msg(data, stream)?;
+                state.global_pendings.push_back(None);
+                state.missed_keepalives += 1;
+            }
+        }
+
+        if let Some(pkt) = SecPacket::try_from_stream(stream, client)? {
             let mut data = Data::unpack(pkt)?;
             let message_code = data.get_u8();
 
@@ -182,21 +977,39 @@ where
                     data.get_u32();
 
                     // remove from pending open list
-                    let sender = pendings.remove(&client_channel_no);
-                    assert!(sender.is_some());
+                    let Some((sender, local_window_size, remote_close)) =
+                        state.pendings.remove(&client_channel_no)
+                    else {
+                        warn!(
+                            "Drop the open confirmation of {} for unknown channel {}",
+                            server_channel_no, client_channel_no
+                        );
+                        continue;
+                    };
+
+                    let Ok(channel) = BackendChannel::new(
+                        server_channel_no,
+                        client_channel_no,
+                        local_window_size,
+                        remote_window_size,
+                        client.get_window_adjust_threshold(),
+                        remote_close,
+                        sender,
+                    ) else {
+                        // no one is going to close it
+                        info!(
+                            "Channel {} is given up before opened, close it",
+                            client_channel_no
+                        );
+                        let mut data = Data::new();
+                        data.put_u8(ssh_connection_code::CHANNEL_CLOSE)
+                            .put_u32(server_channel_no);
+                        client.send_msg(data, stream)?;
+                        continue;
+                    };
 
                     // add to opened list
-                    assert!(channels
-                        .insert(
-                            client_channel_no,
-                            BackendChannel::new(
-                                server_channel_no,
-                                client_channel_no,
-                                remote_window_size,
-                                sender.unwrap()
-                            )?
-                        )
-                        .is_none())
+                    assert!(state.channels.insert(client_channel_no, channel).is_none())
                 }
                 /*
                     byte CHANNEL_OPEN_FAILURE
@@ -210,45 +1023,32 @@ where
                     //  client channel number
                     let id = data.get_u32();
 
-                    let sender = pendings.remove(&id);
-                    assert!(sender.is_some());
-                    // error code
-                    let code = data.get_u32();
-                    // error detail: By default is utf-8
-                    let description =
-                        String::from_utf8(data.get_u8s()).unwrap_or_else(|_| String::from("error"));
-                    // language tag, assume to be en-US
-                    data.get_u8s();
-
-                    let err_msg = match code {
-                        ssh_channel_fail_code::ADMINISTRATIVELY_PROHIBITED => {
-                            format!("ADMINISTRATIVELY_PROHIBITED: {description}")
-                        }
-                        ssh_channel_fail_code::CONNECT_FAILED => {
-                            format!("CONNECT_FAILED: {description}")
-                        }
-                        ssh_channel_fail_code::UNKNOWN_CHANNEL_TYPE => {
-                            format!("UNKNOWN_CHANNEL_TYPE: {description}")
-                        }
-                        ssh_channel_fail_code::RESOURCE_SHORTAGE => {
-                            format!("RESOURCE_SHORTAGE: {description}")
-                        }
-                        _ => description,
+                    let Some((sender, ..)) = state.pendings.remove(&id) else {
+                        warn!("Drop the open failure for unknown channel {}", id);
+                        continue;
                     };
-                    sender.unwrap().send(BackendResp::Fail(err_msg))?;
+                    // the open may have been given up
+                    let _ = sender.send(BackendResp::Fail(open_failure(data)));
                 }
                 ssh_transport_code::KEXINIT => {
                     data.insert(0, message_code);
                     let mut digest = Digest::new();
                     digest.hash_ctx.set_i_s(&data);
-                    let server_algs = AlgList::unpack((data, &mut client).into())?;
-                    client.key_agreement(&mut stream, server_algs, &mut digest)?;
+                    let server_algs = AlgList::unpack((data, &mut *client).into())?;
+                    client.key_agreement(stream, server_algs, &mut digest)?;
+                    aead.store(client.is_aead(), Ordering::Relaxed);
+                    for pending in state.rekey_pendings.drain(..) {
+                        let _ = pending.send(BackendResp::Ok(0));
+                    }
                 }
                 ssh_connection_code::CHANNEL_DATA => {
                     let id = data.get_u32();
                     trace!("Channel {id} get {} data", data.len());
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.recv(data, &mut client, &mut stream)?;
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Drop {} data for unknown channel {}", data.len(), id);
+                        continue;
+                    };
+                    channel.recv(data, client, stream)?;
                 }
                 ssh_connection_code::CHANNEL_EXTENDED_DATA => {
                     let id = data.get_u32();
@@ -257,8 +1057,15 @@ where
                         "Channel {id} get {} extended data, type {data_type}",
                         data.len(),
                     );
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.recv(data, &mut client, &mut stream)?;
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!(
+                            "Drop {} extended data for unknown channel {}",
+                            data.len(),
+                            id
+                        );
+                        continue;
+                    };
+                    channel.recv_extended(data_type, data, client, stream)?;
                 }
                 // flow_control msg
                 ssh_connection_code::CHANNEL_WINDOW_ADJUST => {
@@ -266,43 +1073,184 @@ where
                     let id = data.get_u32();
                     // to_add
                     let rws = data.get_u32();
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.recv_window_adjust(rws, &mut client, &mut stream)?;
+                    match state.channels.get_mut(&id) {
+                        Some(channel) => channel.recv_window_adjust(rws, client, stream)?,
+                        None => {
+                            warn!(
+                                "Drop the window adjust of {} for unknown channel {}",
+                                rws, id
+                            )
+                        }
+                    }
                 }
                 ssh_connection_code::CHANNEL_CLOSE => {
                     let id = data.get_u32();
                     info!("Channel {} recv close", id);
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.remote_close()?;
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Drop the close for unknown channel {}", id);
+                        continue;
+                    };
+                    channel.remote_close(client, stream)?;
                     if channel.closed() {
-                        channels.remove(&id);
+                        state.channels.remove(&id);
                     }
                 }
-                ssh_connection_code::GLOBAL_REQUEST => {
+                /*
+                    byte CHANNEL_OPEN
+                    string channel type
+                    uint32 sender channel
+                    uint32 initial window size
+                    uint32 maximum packet size
+                    ... channel type specific data follows
+                */
+                // the server opens a channel, only for the remote forwards
+                ssh_connection_code::CHANNEL_OPEN => {
+                    let channel_type = String::from_utf8(data.get_u8s()).unwrap_or_default();
+                    let server_id = data.get_u32();
+                    let remote_window_size = data.get_u32();
+                    // remote packet size, currently don't need it
+                    data.get_u32();
+
+                    let listener = if channel_type == ssh_str::FORWARDED_TCPIP {
+                        // the address that was connected
+                        data.get_u8s();
+                        let port = data.get_u32();
+                        let originator = String::from_utf8(data.get_u8s()).unwrap_or_default();
+                        let originator_port = data.get_u32();
+                        forwards
+                            .listeners
+                            .get(&port)
+                            .map(|listener| (listener.clone(), (originator, originator_port)))
+                            .ok_or((
+                                ssh_channel_fail_code::ADMINISTRATIVELY_PROHIBITED,
+                                format!("no remote forward listens on port {port}"),
+                            ))
+                    } else {
+                        Err((
+                            ssh_channel_fail_code::UNKNOWN_CHANNEL_TYPE,
+                            format!("unsupported channel type {channel_type}"),
+                        ))
+                    };
+
+                    let (listener, originator) = match listener {
+                        Ok(found) => found,
+                        Err((code, description)) => {
+                            warn!(
+                                "Reject channel {} from the server: {}",
+                                server_id, description
+                            );
+                            let mut data = Data::new();
+                            data.put_u8(ssh_connection_code::CHANNEL_OPEN_FAILURE)
+                                .put_u32(server_id)
+                                .put_u32(code)
+                                .put_str(&description)
+                                .put_str("");
+                            client.send_msg(data, stream)?;
+                            continue;
+                        }
+                    };
+
+                    let client_id = channel_num.lock().unwrap().next().unwrap();
+                    let window_size = client.get_window_sizes().get(ChannelKind::DirectTcpip);
+                    info!("Channel {} forwarded from {:?}", client_id, originator);
                     let mut data = Data::new();
-                    data.put_u8(ssh_connection_code::REQUEST_FAILURE);
-                    data.pack(&mut client).write_stream(&mut stream)?;
+                    data.put_u8(ssh_connection_code::CHANNEL_OPEN_CONFIRMATION)
+                        .put_u32(server_id)
+                        .put_u32(client_id)
+                        .put_u32(window_size)
+                        .put_u32(size::BUF_SIZE as u32);
+                    client.send_msg(data, stream)?;
+
+                    let (resp_send, resp_recv) = mpsc::channel();
+                    let remote_close = Arc::new(RemoteClose::default());
+                    let mut channel = BackendChannel::new(
+                        server_id,
+                        client_id,
+                        window_size,
+                        remote_window_size,
+                        client.get_window_adjust_threshold(),
+                        remote_close.clone(),
+                        resp_send,
+                    )?;
+                    let forwarded = ForwardedChannel {
+                        client_id,
+                        server_id,
+                        rcv: resp_recv,
+                        remote_close,
+                        originator,
+                    };
+                    if listener.send(forwarded).is_err() {
+                        // the forward is being stopped
+                        let mut data = Data::new();
+                        data.put_u8(ssh_connection_code::CHANNEL_CLOSE)
+                            .put_u32(server_id);
+                        channel.send(data, client, stream)?;
+                        channel.local_close()?;
+                    }
+                    state.channels.insert(client_id, channel);
+                }
+                ssh_connection_code::GLOBAL_REQUEST => {
+                    let name = String::from_utf8(data.get_u8s()).unwrap_or_default();
+                    let want_reply = data.get_u8() != 0;
+                    debug!("Currently reject global request {}", name);
+                    if want_reply {
+                        let mut data = Data::new();
+                        data.put_u8(ssh_connection_code::REQUEST_FAILURE);
+                        client.send_msg(data, stream)?;
+                    }
                     continue;
                 }
+                ssh_connection_code::REQUEST_SUCCESS => {
+                    // the server is alive anyway
+                    state.missed_keepalives = 0;
+                    match state.global_pendings.pop_front() {
+                        // the remaining is request-specific data
+                        Some(Some(sender)) => {
+                            let _ = sender.send(BackendResp::Data(data));
+                        }
+                        Some(None) => trace!("Keepalive or re-sent forward replied"),
+                        None => debug!("Unexpected global request success"),
+                    }
+                }
+                ssh_connection_code::REQUEST_FAILURE => {
+                    state.missed_keepalives = 0;
+                    match state.global_pendings.pop_front() {
+                        Some(Some(sender)) => {
+                            let _ =
+                                sender.send(BackendResp::Fail("rejected by the server".to_owned()));
+                        }
+                        Some(None) => trace!("Keepalive or re-sent forward replied"),
+                        None => debug!("Unexpected global request failure"),
+                    }
+                }
 
                 x @ ssh_connection_code::CHANNEL_EOF => {
                     debug!("Currently ignore message {}", x);
                 }
                 ssh_connection_code::CHANNEL_REQUEST => {
                     let id = data.get_u32();
-                    let channel = channels.get_mut(&id).unwrap();
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Drop the request for unknown channel {}", id);
+                        continue;
+                    };
                     let _ = channel.recv_rqst(data);
                 }
                 _x @ ssh_connection_code::CHANNEL_SUCCESS => {
                     let id = data.get_u32();
                     trace!("Channel {} control success", id);
-                    let channel = channels.get_mut(&id).unwrap();
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Drop the success for unknown channel {}", id);
+                        continue;
+                    };
                     channel.success()?
                 }
                 ssh_connection_code::CHANNEL_FAILURE => {
                     let id = data.get_u32();
                     trace!("Channel {} control failed", id);
-                    let channel = channels.get_mut(&id).unwrap();
+                    let Some(channel) = state.channels.get_mut(&id) else {
+                        debug!("Drop the failure for unknown channel {}", id);
+                        continue;
+                    };
                     channel.failed()?
                 }
 
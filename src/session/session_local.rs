@@ -9,14 +9,22 @@ use tracing::*;
 #[cfg(feature = "scp")]
 use crate::channel::LocalScp;
 use crate::{
-    channel::{LocalChannel, LocalExec, LocalShell},
+    algorithm::{public_key, FingerprintHash},
+    channel::{LocalChannel, LocalExec, LocalShell, LocalSubsystem},
     client::Client,
+    config::{subsystem, ChannelKind},
     constant::{size, ssh_channel_fail_code, ssh_connection_code, ssh_str},
     error::{SshError, SshResult},
-    model::TerminalSize,
-    model::{Data, Packet, RcMut, SecPacket, U32Iter},
+    model::{
+        CompressionStats, Data, DisconnectReason, KexDetails, Packet, RcMut, SecPacket, U32Iter,
+    },
+    model::{NegotiatedAlgorithms, PtyConfig, TerminalSize},
+    sftp::{LocalSftp, Sftp},
 };
 
+#[cfg(feature = "dangerous-key-export")]
+use crate::model::SessionKeys;
+
 pub struct LocalSession<S>
 where
     S: Read + Write,
@@ -45,6 +53,22 @@ where
         drop(self)
     }
 
+    /// send SSH_MSG_DISCONNECT to the server,
+    /// then close the local session and consume it
+    ///
+    pub fn shutdown(self) -> SshResult<()> {
+        self.disconnect(DisconnectReason::ByApplication, "")
+    }
+
+    /// same as [LocalSession::shutdown], but with the reason code & description of the disconnection
+    ///
+    pub fn disconnect(self, reason: DisconnectReason, description: &str) -> SshResult<()> {
+        info!("Client shutdown");
+        self.client
+            .borrow_mut()
+            .disconnect(&mut *self.stream.borrow_mut(), reason, description)
+    }
+
     /// Modify the timeout setting
     /// in case the user wants to change the timeout during ssh operations.
     ///
@@ -52,10 +76,99 @@ where
         self.client.borrow_mut().set_timeout(timeout)
     }
 
+    /// the bytes before and after the compression in each direction so far
+    ///
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.client.borrow().get_compression_counter().stats()
+    }
+
+    /// whether the negotiated ciphers are AEAD (e.g. chacha20-poly1305@openssh.com),
+    /// which need no separate MAC, rather than a cipher plus a MAC
+    ///
+    pub fn is_aead(&self) -> bool {
+        self.client.borrow().is_aead()
+    }
+
+    /// the host key algorithms offered by the server in the initial key exchange,
+    /// in the server's order, including the ones not supported by us
+    ///
+    pub fn offered_host_key_algorithms(&self) -> Vec<String> {
+        self.client.borrow().get_offered_host_key_algs().to_vec()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc8308#section-2.3>
+    ///
+    /// the (name, value) extensions sent by the server in SSH_MSG_EXT_INFO,
+    /// empty if the server sends none or [crate::SessionBuilder::ext_info] is disabled
+    ///
+    pub fn server_extensions(&self) -> Vec<(String, Vec<u8>)> {
+        self.client.borrow().get_server_extensions().to_vec()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4252#section-5.4>
+    ///
+    /// the banners sent by the server during the authentication, one after another,
+    /// None if the server sends none
+    ///
+    pub fn banner(&self) -> Option<String> {
+        self.client.borrow().get_banner().map(str::to_owned)
+    }
+
+    /// the server host key in the `authorized_keys` format, e.g. `ssh-ed25519 AAAA...`
+    ///
+    pub fn server_public_key_openssh(&self) -> SshResult<String> {
+        public_key::to_openssh(self.client.borrow().get_server_host_key())
+    }
+
+    /// the server host key (`K_S`) as ssh encoded in the key exchange,
+    /// e.g. for a trust-on-first-use check by yourself
+    ///
+    pub fn server_host_key(&self) -> Vec<u8> {
+        self.client.borrow().get_server_host_key().to_vec()
+    }
+
+    /// the fingerprint of the server host key, e.g. `SHA256:...`,
+    /// the same as `ssh-keygen -l` prints
+    ///
+    pub fn host_key_fingerprint(&self, hash: FingerprintHash) -> String {
+        public_key::fingerprint(self.client.borrow().get_server_host_key(), hash)
+    }
+
+    /// the KEXINIT payloads, the hash and the lengths of the keys derived in the last key exchange,
+    /// for the interop diagnostics
+    ///
+    pub fn kex_details(&self) -> KexDetails {
+        self.client.borrow().get_kex_details().clone()
+    }
+
+    /// the names of the key exchange, the host key, the ciphers, the MACs
+    /// and the compressions agreed on in the last key exchange,
+    /// as `ssh -v` prints
+    ///
+    pub fn negotiated_algorithms(&self) -> NegotiatedAlgorithms {
+        self.client.borrow().get_negotiated_algorithms()
+    }
+
+    /// **DANGER: anyone holding the keys can decrypt the whole session.**
+    ///
+    /// the keys derived in the last key exchange, for decrypting a packet capture while debugging
+    ///
+    #[cfg(feature = "dangerous-key-export")]
+    pub fn dangerous_session_keys(&self) -> Option<SessionKeys> {
+        self.client
+            .borrow()
+            .get_session_keys()
+            .lock()
+            .unwrap()
+            .clone()
+    }
+
     /// open a [LocalExec] channel which can excute commands
     ///
     pub fn open_exec(&mut self) -> SshResult<LocalExec<S>> {
-        let channel = self.open_channel()?;
+        let mut channel = self.open_channel_of(ChannelKind::Exec)?;
+        let env = self.client.borrow().get_env().to_vec();
+        channel.send_env(&env)?;
         channel.exec()
     }
 
@@ -63,14 +176,14 @@ where
     ///
     #[cfg(feature = "scp")]
     pub fn open_scp(&mut self) -> SshResult<LocalScp<S>> {
-        let channel = self.open_channel()?;
+        let channel = self.open_channel_of(ChannelKind::Scp)?;
         channel.scp()
     }
 
     /// open a [LocalShell] channel which can download/upload files/directories
     ///
     pub fn open_shell(&mut self) -> SshResult<LocalShell<S>> {
-        self.open_shell_terminal(TerminalSize::from(80, 24))
+        self.open_shell_with_pty(PtyConfig::default())
     }
 
     /// open a [LocalShell] channel
@@ -78,8 +191,38 @@ where
     /// custom terminal dimensions
     ///
     pub fn open_shell_terminal(&mut self, tv: TerminalSize) -> SshResult<LocalShell<S>> {
-        let channel = self.open_channel()?;
-        channel.shell(tv)
+        self.open_shell_with_pty(tv.into())
+    }
+
+    /// open a [LocalShell] channel
+    ///
+    /// custom terminal type & dimensions, see [PtyConfig]
+    ///
+    pub fn open_shell_with_pty(&mut self, pty: PtyConfig) -> SshResult<LocalShell<S>> {
+        let mut channel = self.open_channel_of(ChannelKind::Shell)?;
+        let env = self.client.borrow().get_env().to_vec();
+        channel.send_env(&env)?;
+        channel.shell_with_pty(pty)
+    }
+
+    /// open a [LocalSubsystem] channel running `name`, such as `sftp`
+    ///
+    /// [SshError::SubsystemNotAllowed] without opening the channel
+    /// if it's not allowed by [crate::SessionBuilder::allowed_subsystems]
+    ///
+    pub fn open_subsystem(&mut self, name: &str) -> SshResult<LocalSubsystem<S>> {
+        subsystem::check(self.client.borrow().get_allowed_subsystems(), name)?;
+        let channel = self.open_channel_of(ChannelKind::Subsystem)?;
+        channel.subsystem(name)
+    }
+
+    /// open an [LocalSftp] session on the `sftp` subsystem
+    ///
+    /// [SshError::SubsystemNotAllowed] if it's not allowed by [crate::SessionBuilder::allowed_subsystems]
+    ///
+    pub fn open_sftp(&mut self) -> SshResult<LocalSftp<S>> {
+        let subsystem = self.open_subsystem(ssh_str::SFTP)?;
+        Sftp::start(subsystem)
     }
 
     pub fn get_raw_io(&mut self) -> RcMut<S> {
@@ -91,15 +234,22 @@ where
     /// need call `.exec()`, `.shell()`, `.scp()` and so on to convert it to a specific channel
     ///
     pub fn open_channel(&mut self) -> SshResult<LocalChannel<S>> {
+        self.open_channel_of(ChannelKind::Session)
+    }
+
+    fn open_channel_of(&mut self, kind: ChannelKind) -> SshResult<LocalChannel<S>> {
         info!("channel opened.");
 
         let client_channel_no = self.channel_num.next().unwrap();
-        self.send_open_channel(client_channel_no)?;
-        let (server_channel_no, remote_window_size) = self.receive_open_channel()?;
+        let local_window_size = self.client.borrow().get_window_sizes().get(kind);
+        self.send_open_channel(client_channel_no, local_window_size)?;
+        let (server_channel_no, remote_window_size) =
+            self.receive_open_channel(client_channel_no)?;
 
         Ok(LocalChannel::new(
             server_channel_no,
             client_channel_no,
+            local_window_size,
             remote_window_size,
             self.client.clone(),
             self.stream.clone(),
@@ -107,19 +257,19 @@ where
     }
 
     // open channel request
-    fn send_open_channel(&mut self, client_channel_no: u32) -> SshResult<()> {
+    fn send_open_channel(&mut self, client_channel_no: u32, window_size: u32) -> SshResult<()> {
         let mut data = Data::new();
         data.put_u8(ssh_connection_code::CHANNEL_OPEN)
             .put_str(ssh_str::SESSION)
             .put_u32(client_channel_no)
-            .put_u32(size::LOCAL_WINDOW_SIZE)
+            .put_u32(window_size)
             .put_u32(size::BUF_SIZE as u32);
         data.pack(&mut self.client.borrow_mut())
             .write_stream(&mut *self.stream.borrow_mut())
     }
 
     // get the response of the channel request
-    fn receive_open_channel(&mut self) -> SshResult<(u32, u32)> {
+    fn receive_open_channel(&mut self, client_channel_no: u32) -> SshResult<(u32, u32)> {
         loop {
             let mut data = Data::unpack(SecPacket::from_stream(
                 &mut *self.stream.borrow_mut(),
@@ -130,7 +280,12 @@ where
             match message_code {
                 // Successfully open a channel
                 ssh_connection_code::CHANNEL_OPEN_CONFIRMATION => {
-                    data.get_u32();
+                    let recipient = data.get_u32();
+                    if recipient != client_channel_no {
+                        return Err(SshError::ProtocolError(format!(
+                            "open confirmation of channel {recipient}, but channel {client_channel_no} is being opened"
+                        )));
+                    }
                     let server_channel_no = data.get_u32();
                     let remote_window_size = data.get_u32();
                     // remote packet size, currently don't need it
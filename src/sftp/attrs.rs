@@ -0,0 +1,188 @@
+use std::ops::BitOr;
+
+use super::{get_string, get_u32, get_u64};
+use crate::{constant::ssh_fxp_attr, model::Data};
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.3>
+///
+/// How [super::Sftp::open] opens a file, combined with `|`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: Self = Self(0x00000001);
+    pub const WRITE: Self = Self(0x00000002);
+    pub const APPEND: Self = Self(0x00000004);
+    /// create the file if it does not exist
+    pub const CREATE: Self = Self(0x00000008);
+    /// truncate an existing file, requires [OpenFlags::CREATE]
+    pub const TRUNCATE: Self = Self(0x00000010);
+    /// fail if the file exists, requires [OpenFlags::CREATE]
+    pub const EXCLUSIVE: Self = Self(0x00000020);
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-5>
+///
+/// The attributes of a remote file, None for the ones not told by the server
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    pub size: Option<u64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// the file type & mode bits as `st_mode`
+    pub permissions: Option<u32>,
+    pub atime: Option<u32>,
+    pub mtime: Option<u32>,
+}
+
+impl FileAttributes {
+    /// whether the permissions tell a directory
+    ///
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Some(0o040000)
+    }
+
+    /// whether the permissions tell a regular file
+    ///
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Some(0o100000)
+    }
+
+    fn file_type(&self) -> Option<u32> {
+        self.permissions.map(|p| p & 0o170000)
+    }
+
+    pub(crate) fn put(&self, data: &mut Data) {
+        let mut flags = 0;
+        if self.size.is_some() {
+            flags |= ssh_fxp_attr::SIZE;
+        }
+        if self.uid.is_some() && self.gid.is_some() {
+            flags |= ssh_fxp_attr::UIDGID;
+        }
+        if self.permissions.is_some() {
+            flags |= ssh_fxp_attr::PERMISSIONS;
+        }
+        if self.atime.is_some() && self.mtime.is_some() {
+            flags |= ssh_fxp_attr::ACMODTIME;
+        }
+
+        data.put_u32(flags);
+        if let Some(size) = self.size {
+            data.put_u64(size);
+        }
+        if let (Some(uid), Some(gid)) = (self.uid, self.gid) {
+            data.put_u32(uid).put_u32(gid);
+        }
+        if let Some(permissions) = self.permissions {
+            data.put_u32(permissions);
+        }
+        if let (Some(atime), Some(mtime)) = (self.atime, self.mtime) {
+            data.put_u32(atime).put_u32(mtime);
+        }
+    }
+
+    /// None if any of the fields is cut off
+    pub(crate) fn get(data: &mut Data) -> Option<Self> {
+        let flags = get_u32(data)?;
+        let mut attrs = Self::default();
+        if flags & ssh_fxp_attr::SIZE != 0 {
+            attrs.size = Some(get_u64(data)?);
+        }
+        if flags & ssh_fxp_attr::UIDGID != 0 {
+            attrs.uid = Some(get_u32(data)?);
+            attrs.gid = Some(get_u32(data)?);
+        }
+        if flags & ssh_fxp_attr::PERMISSIONS != 0 {
+            attrs.permissions = Some(get_u32(data)?);
+        }
+        if flags & ssh_fxp_attr::ACMODTIME != 0 {
+            attrs.atime = Some(get_u32(data)?);
+            attrs.mtime = Some(get_u32(data)?);
+        }
+        if flags & ssh_fxp_attr::EXTENDED != 0 {
+            // the extended attributes are not supported
+            for _ in 0..get_u32(data)? {
+                get_string(data)?;
+                get_string(data)?;
+            }
+        }
+        Some(attrs)
+    }
+}
+
+/// An entry of a remote directory, see [super::Sftp::read_dir]
+///
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// the line as `ls -l`, whose format is up to the server
+    pub long_name: String,
+    pub attrs: FileAttributes,
+}
+
+/// <https://github.com/openssh/openssh-portable/blob/master/PROTOCOL> section 4.4
+///
+/// The file system statistics of [super::Sftp::statvfs], as `struct statvfs`
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatVfs {
+    /// the file system block size, `f_bsize`
+    pub block_size: u64,
+    /// the fundamental block size, in which the block counts are, `f_frsize`
+    pub fragment_size: u64,
+    pub blocks: u64,
+    pub free_blocks: u64,
+    /// the free blocks available to non-root users, `f_bavail`
+    pub available_blocks: u64,
+    pub files: u64,
+    pub free_files: u64,
+    /// the free inodes available to non-root users, `f_favail`
+    pub available_files: u64,
+    pub fs_id: u64,
+    /// the mount flags, e.g. 0x1 for read-only
+    pub flags: u64,
+    pub max_name_len: u64,
+}
+
+impl StatVfs {
+    /// the bytes available to non-root users
+    ///
+    pub fn available_bytes(&self) -> u64 {
+        self.available_blocks.saturating_mul(self.fragment_size)
+    }
+
+    /// None if the 11 fields are not all there
+    pub(crate) fn get(data: &mut Data) -> Option<Self> {
+        if data.len() < 11 * 8 {
+            return None;
+        }
+        Some(Self {
+            block_size: data.get_u64(),
+            fragment_size: data.get_u64(),
+            blocks: data.get_u64(),
+            free_blocks: data.get_u64(),
+            available_blocks: data.get_u64(),
+            files: data.get_u64(),
+            free_files: data.get_u64(),
+            available_files: data.get_u64(),
+            fs_id: data.get_u64(),
+            flags: data.get_u64(),
+            max_name_len: data.get_u64(),
+        })
+    }
+}
@@ -0,0 +1,526 @@
+//! <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02>
+//!
+//! The SFTP client of the protocol version 3, spoken over the `sftp` subsystem
+//!
+//! ```no_run
+//! use ssh::sftp::OpenFlags;
+//!
+//! let mut session = ssh::create_session()
+//!     .username("ubuntu")
+//!     .password("password")
+//!     .connect("127.0.0.1:22")
+//!     .unwrap()
+//!     .run_local();
+//! let mut sftp = session.open_sftp().unwrap();
+//! for entry in sftp.read_dir("/var/log").unwrap() {
+//!     println!("{}", entry.long_name);
+//! }
+//! let file = sftp.open("/tmp/hello", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+//! sftp.write(&file, 0, b"hello").unwrap();
+//! sftp.close(file).unwrap();
+//! session.close();
+//! ```
+
+mod attrs;
+
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use tracing::*;
+
+use crate::{
+    constant::{ssh_fxp_code, ssh_str},
+    error::{SshError, SshResult},
+    model::Data,
+    LocalSubsystem, SubsystemBroker,
+};
+
+pub use attrs::{DirEntry, FileAttributes, OpenFlags, StatVfs};
+
+/// the version that we speak
+const VERSION: u32 = 3;
+/// the max length of the packets from the server that we accept
+const MAX_PACKET_LEN: usize = 256 * 1024;
+/// each SSH_FXP_WRITE has at most this much data, which all the servers accept
+const WRITE_CHUNK: usize = 32 * 1024;
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-7>
+///
+/// The codes of [SshError::SftpError]
+///
+pub mod status {
+    pub const OK: u32 = 0;
+    pub const EOF: u32 = 1;
+    pub const NO_SUCH_FILE: u32 = 2;
+    pub const PERMISSION_DENIED: u32 = 3;
+    pub const FAILURE: u32 = 4;
+    pub const BAD_MESSAGE: u32 = 5;
+    pub const NO_CONNECTION: u32 = 6;
+    pub const CONNECTION_LOST: u32 = 7;
+    pub const OP_UNSUPPORTED: u32 = 8;
+}
+
+mod sealed {
+    use std::time::Duration;
+
+    use crate::error::SshResult;
+
+    pub trait Transport {
+        fn send_bytes(&mut self, buf: &[u8]) -> SshResult<()>;
+
+        /// block until some data is appended to `buf`, 0 if the channel is closed
+        fn recv_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize>;
+
+        /// same as [Transport::recv_into], but block for at most `timeout`
+        fn recv_into_timeout(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> SshResult<usize>;
+    }
+}
+
+/// The subsystem channels that SFTP runs on,
+/// i.e. [LocalSubsystem] & [SubsystemBroker]
+///
+pub trait SftpChannel: sealed::Transport {}
+
+impl<S: Read + Write> sealed::Transport for LocalSubsystem<S> {
+    fn send_bytes(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.write(buf)
+    }
+
+    fn recv_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        self.read_into(buf)
+    }
+
+    fn recv_into_timeout(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> SshResult<usize> {
+        self.read_into_timeout(buf, timeout)
+    }
+}
+
+impl<S: Read + Write> SftpChannel for LocalSubsystem<S> {}
+
+impl sealed::Transport for SubsystemBroker {
+    fn send_bytes(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.write(buf)
+    }
+
+    fn recv_into(&mut self, buf: &mut Vec<u8>) -> SshResult<usize> {
+        self.read_into(buf)
+    }
+
+    fn recv_into_timeout(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> SshResult<usize> {
+        self.read_into_timeout(buf, timeout)
+    }
+}
+
+impl SftpChannel for SubsystemBroker {}
+
+/// The SFTP client of [crate::LocalSession::open_sftp]
+pub type LocalSftp<S> = Sftp<LocalSubsystem<S>>;
+/// The SFTP client of [crate::SessionBroker::open_sftp]
+pub type SftpBroker = Sftp<SubsystemBroker>;
+
+/// An open remote file or directory, see [Sftp::open]
+///
+#[derive(Debug)]
+pub struct FileHandle(Vec<u8>);
+
+/// An SFTP session on a subsystem channel
+///
+/// One request is answered before the next is sent,
+/// the channel is closed when it's dropped
+///
+/// Each request waits for its reply for at most [Sftp::set_timeout],
+/// after which it fails with [SshError::SftpTimeout]
+///
+pub struct Sftp<C: SftpChannel> {
+    channel: C,
+    next_id: u32,
+    // the received bytes that are not a whole packet yet
+    pending: Vec<u8>,
+    // the (name, data) pairs in SSH_FXP_VERSION
+    extensions: Vec<(String, String)>,
+    // how long a request waits for its reply
+    timeout: Option<Duration>,
+}
+
+impl<C> Sftp<C>
+where
+    C: SftpChannel,
+{
+    /// send SSH_FXP_INIT and wait for SSH_FXP_VERSION
+    pub(crate) fn start(channel: C) -> SshResult<Self> {
+        let mut sftp = Self {
+            channel,
+            next_id: 0,
+            pending: vec![],
+            extensions: vec![],
+            timeout: None,
+        };
+
+        let mut init = Data::new();
+        init.put_u8(ssh_fxp_code::INIT).put_u32(VERSION);
+        sftp.send_packet(init)?;
+
+        let mut reply = sftp.recv_packet(None)?;
+        let code = reply.get_u8();
+        if code != ssh_fxp_code::VERSION {
+            return Err(unexpected(code));
+        }
+        let version = get_u32(&mut reply).ok_or_else(|| truncated(code))?;
+        if version < VERSION {
+            return Err(SshError::ProtocolError(format!(
+                "Sftp version {version} of the server is not supported"
+            )));
+        }
+        sftp.extensions = extensions(reply)?;
+        info!("sftp version {} started.", VERSION);
+        Ok(sftp)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-4>
+    ///
+    /// the (name, data) of the extensions listed by the server in SSH_FXP_VERSION,
+    /// e.g. `("posix-rename@openssh.com", "1")`, to tell what the server supports
+    ///
+    pub fn extensions(&self) -> &[(String, String)] {
+        &self.extensions
+    }
+
+    /// how long each of the later requests waits for its reply,
+    /// None (the default) to wait forever
+    ///
+    /// a reply that comes after its request has timed out is dropped
+    ///
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.3>
+    ///
+    /// open the file at `path`, which is closed by [Sftp::close]
+    ///
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> SshResult<FileHandle> {
+        let mut data = Data::new();
+        data.put_str(path).put_u32(flags.bits());
+        FileAttributes::default().put(&mut data);
+        let reply = self.request(ssh_fxp_code::OPEN, data)?;
+        handle(reply)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.4>
+    ///
+    /// read at most `len` bytes at `offset`, which may be less than asked
+    ///
+    /// an empty result means the end of the file
+    ///
+    pub fn read(&mut self, handle: &FileHandle, offset: u64, len: u32) -> SshResult<Vec<u8>> {
+        let mut data = Data::new();
+        data.put_u8s(&handle.0).put_u64(offset).put_u32(len);
+        match self.request(ssh_fxp_code::READ, data)? {
+            (ssh_fxp_code::DATA, mut reply) => {
+                get_string(&mut reply).ok_or_else(|| truncated(ssh_fxp_code::DATA))
+            }
+            (ssh_fxp_code::STATUS, reply) => match status(reply) {
+                Err(SshError::SftpError {
+                    code: status::EOF, ..
+                }) => Ok(vec![]),
+                result => result.and(Err(unexpected(ssh_fxp_code::STATUS))),
+            },
+            (code, _) => Err(unexpected(code)),
+        }
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.4>
+    ///
+    /// write all the `buf` at `offset`, in several requests if it's large
+    ///
+    pub fn write(&mut self, handle: &FileHandle, offset: u64, buf: &[u8]) -> SshResult<()> {
+        let mut offset = offset;
+        for chunk in buf.chunks(WRITE_CHUNK) {
+            let mut data = Data::new();
+            data.put_u8s(&handle.0).put_u64(offset).put_u8s(chunk);
+            expect_status(self.request(ssh_fxp_code::WRITE, data)?)?;
+            offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.3>
+    ///
+    /// close a file or a directory
+    ///
+    pub fn close(&mut self, handle: FileHandle) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8s(&handle.0);
+        expect_status(self.request(ssh_fxp_code::CLOSE, data)?)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.7>
+    ///
+    /// all the entries of the directory at `path`, including `.` & `..` if the server lists them
+    ///
+    pub fn read_dir(&mut self, path: &str) -> SshResult<Vec<DirEntry>> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let dir = handle(self.request(ssh_fxp_code::OPENDIR, data)?)?;
+
+        let mut entries = vec![];
+        let result = loop {
+            let mut data = Data::new();
+            data.put_u8s(&dir.0);
+            match self.request(ssh_fxp_code::READDIR, data) {
+                Ok((ssh_fxp_code::NAME, mut reply)) => match dir_entries(&mut reply) {
+                    Ok(mut names) => entries.append(&mut names),
+                    Err(e) => break Err(e),
+                },
+                Ok((ssh_fxp_code::STATUS, reply)) => match status(reply) {
+                    Err(SshError::SftpError {
+                        code: status::EOF, ..
+                    }) => break Ok(()),
+                    result => break result.and(Err(unexpected(ssh_fxp_code::STATUS))),
+                },
+                Ok((code, _)) => break Err(unexpected(code)),
+                Err(e) => break Err(e),
+            }
+        };
+        // close it anyway, but the error of the listing comes first
+        let closed = self.close(dir);
+        result.and(closed).map(|_| entries)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.8>
+    ///
+    /// the attributes of `path`, following the symbolic links
+    ///
+    pub fn stat(&mut self, path: &str) -> SshResult<FileAttributes> {
+        let mut data = Data::new();
+        data.put_str(path);
+        match self.request(ssh_fxp_code::STAT, data)? {
+            (ssh_fxp_code::ATTRS, mut reply) => {
+                FileAttributes::get(&mut reply).ok_or_else(|| truncated(ssh_fxp_code::ATTRS))
+            }
+            reply => expect_status(reply).and(Err(unexpected(ssh_fxp_code::STATUS))),
+        }
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.6>
+    ///
+    /// create the directory `path` with the default permissions of the server
+    ///
+    pub fn mkdir(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        FileAttributes::default().put(&mut data);
+        expect_status(self.request(ssh_fxp_code::MKDIR, data)?)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.6>
+    ///
+    /// remove the empty directory `path`
+    ///
+    pub fn rmdir(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        expect_status(self.request(ssh_fxp_code::RMDIR, data)?)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.5>
+    ///
+    /// remove the file `path`
+    ///
+    pub fn remove(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        expect_status(self.request(ssh_fxp_code::REMOVE, data)?)
+    }
+
+    /// <https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02#section-6.5>
+    ///
+    /// rename `from` to `to`, most servers fail if `to` exists
+    ///
+    pub fn rename(&mut self, from: &str, to: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(from).put_str(to);
+        expect_status(self.request(ssh_fxp_code::RENAME, data)?)
+    }
+
+    /// <https://github.com/openssh/openssh-portable/blob/master/PROTOCOL> section 4.4
+    ///
+    /// the statistics of the file system containing `path`, e.g. to check the free space before an upload
+    ///
+    /// [SshError::SftpError] of [status::OP_UNSUPPORTED] if the server does not list `statvfs@openssh.com`
+    /// in [Sftp::extensions]
+    ///
+    pub fn statvfs(&mut self, path: &str) -> SshResult<StatVfs> {
+        if !self
+            .extensions
+            .iter()
+            .any(|(name, _)| name == ssh_str::STATVFS)
+        {
+            return Err(SshError::SftpError {
+                code: status::OP_UNSUPPORTED,
+                message: format!("{} is not supported by the server", ssh_str::STATVFS),
+            });
+        }
+        let mut data = Data::new();
+        data.put_str(ssh_str::STATVFS).put_str(path);
+        match self.request(ssh_fxp_code::EXTENDED, data)? {
+            (ssh_fxp_code::EXTENDED_REPLY, mut reply) => {
+                StatVfs::get(&mut reply).ok_or_else(|| truncated(ssh_fxp_code::EXTENDED_REPLY))
+            }
+            reply => expect_status(reply).and(Err(unexpected(ssh_fxp_code::STATUS))),
+        }
+    }
+
+    /// send a request with a new id, return the type & the rest of the reply to it
+    fn request(&mut self, code: u8, body: Data) -> SshResult<(u8, Data)> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut data = Data::new();
+        data.put_u8(code).put_u32(id);
+        data.extend(body.into_inner());
+        self.send_packet(data)?;
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let mut reply = self.recv_packet(deadline)?;
+            // never empty, as checked by recv_packet
+            let reply_code = reply.get_u8();
+            let reply_id = get_u32(&mut reply).ok_or_else(|| truncated(reply_code))?;
+            if reply_id == id {
+                return Ok((reply_code, reply));
+            }
+            debug!("Drop the sftp reply {} to request {}", reply_code, reply_id);
+        }
+    }
+
+    fn send_packet(&mut self, payload: Data) -> SshResult<()> {
+        let mut packet = Data::new();
+        packet.put_u8s(&payload);
+        self.channel.send_bytes(&packet)
+    }
+
+    /// wait for a whole packet till `deadline` if any
+    fn recv_packet(&mut self, deadline: Option<Instant>) -> SshResult<Data> {
+        loop {
+            if self.pending.len() >= 4 {
+                let len = u32::from_be_bytes(self.pending[..4].try_into().unwrap()) as usize;
+                if len == 0 || len > MAX_PACKET_LEN {
+                    return Err(SshError::ProtocolError(format!(
+                        "Sftp packet from the server has a bad length {len}"
+                    )));
+                }
+                if self.pending.len() >= 4 + len {
+                    let packet = self.pending[4..4 + len].to_vec();
+                    self.pending.drain(..4 + len);
+                    return Ok(packet.into());
+                }
+            }
+            let received = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.channel
+                        .recv_into_timeout(&mut self.pending, timeout)
+                        .map_err(|e| match e {
+                            SshError::TimeoutError => SshError::SftpTimeout,
+                            e => e,
+                        })?
+                }
+                None => self.channel.recv_into(&mut self.pending)?,
+            };
+            if received == 0 {
+                return Err(SshError::GeneralError(
+                    "The sftp channel is closed".to_owned(),
+                ));
+            }
+        }
+    }
+}
+
+fn unexpected(code: u8) -> SshError {
+    SshError::ProtocolError(format!("Unexpected sftp reply {code}"))
+}
+
+fn truncated(code: u8) -> SshError {
+    SshError::ProtocolError(format!("Sftp reply {code} is truncated"))
+}
+
+/// the u32 at the front of a reply, None if it's cut off
+fn get_u32(reply: &mut Data) -> Option<u32> {
+    (reply.len() >= 4).then(|| reply.get_u32())
+}
+
+/// the u64 at the front of a reply, None if it's cut off
+fn get_u64(reply: &mut Data) -> Option<u64> {
+    (reply.len() >= 8).then(|| reply.get_u64())
+}
+
+/// the string at the front of a reply, None if it's cut off
+fn get_string(reply: &mut Data) -> Option<Vec<u8>> {
+    let len = u32::from_be_bytes(reply.get(..4)?.try_into().unwrap()) as usize;
+    (reply.len() >= 4 + len).then(|| reply.get_u8s())
+}
+
+/// the extension pairs till the end of SSH_FXP_VERSION
+fn extensions(mut reply: Data) -> SshResult<Vec<(String, String)>> {
+    let string = |reply: &mut Data| match get_string(reply) {
+        Some(string) => Ok(String::from_utf8_lossy(&string).into_owned()),
+        None => Err(truncated(ssh_fxp_code::VERSION)),
+    };
+    let mut extensions = vec![];
+    while !reply.is_empty() {
+        let name = string(&mut reply)?;
+        let data = string(&mut reply)?;
+        debug!("sftp extension {}: {}", name, data);
+        extensions.push((name, data));
+    }
+    Ok(extensions)
+}
+
+/// Ok for SSH_FX_OK, otherwise the error of the code
+fn status(mut reply: Data) -> SshResult<()> {
+    let code = get_u32(&mut reply).ok_or_else(|| truncated(ssh_fxp_code::STATUS))?;
+    if code == status::OK {
+        return Ok(());
+    }
+    // the message is missing in some old servers
+    let message = match get_string(&mut reply) {
+        Some(message) => String::from_utf8_lossy(&message).into_owned(),
+        None => String::new(),
+    };
+    Err(SshError::SftpError { code, message })
+}
+
+fn expect_status((code, reply): (u8, Data)) -> SshResult<()> {
+    match code {
+        ssh_fxp_code::STATUS => status(reply),
+        code => Err(unexpected(code)),
+    }
+}
+
+fn handle(reply: (u8, Data)) -> SshResult<FileHandle> {
+    match reply {
+        (ssh_fxp_code::HANDLE, mut reply) => get_string(&mut reply)
+            .map(FileHandle)
+            .ok_or_else(|| truncated(ssh_fxp_code::HANDLE)),
+        reply => expect_status(reply).and(Err(unexpected(ssh_fxp_code::STATUS))),
+    }
+}
+
+/// the entries of SSH_FXP_NAME
+fn dir_entries(reply: &mut Data) -> SshResult<Vec<DirEntry>> {
+    let truncated = || truncated(ssh_fxp_code::NAME);
+    let count = get_u32(reply).ok_or_else(truncated)?;
+    let mut entries = vec![];
+    for _ in 0..count {
+        entries.push(DirEntry {
+            name: String::from_utf8(get_string(reply).ok_or_else(truncated)?)?,
+            long_name: String::from_utf8_lossy(&get_string(reply).ok_or_else(truncated)?)
+                .into_owned(),
+            attrs: FileAttributes::get(reply).ok_or_else(truncated)?,
+        });
+    }
+    Ok(entries)
+}
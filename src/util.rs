@@ -1,6 +1,5 @@
 use crate::error::SshResult;
-use rand::rngs::OsRng;
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 #[cfg(feature = "scp")]
 use crate::error::SshError;
@@ -17,17 +16,58 @@ pub(crate) fn sys_time_to_secs(time: SystemTime) -> SshResult<u64> {
 }
 
 // a random cookie
-pub(crate) fn cookie() -> Vec<u8> {
-    let cookie: [u8; 16] = OsRng.gen();
+pub(crate) fn cookie(rng: &mut dyn RngCore) -> Vec<u8> {
+    let cookie: [u8; 16] = rng.gen();
     cookie.to_vec()
 }
 
+// the standard base64 alphabet, with or without the `=` padding
+pub(crate) fn base64_encode(buf: &[u8], padding: bool) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in buf.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else if padding {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// format at most `limit` bytes of `buf` as lines of 16 hex bytes
+pub(crate) fn hex_dump(buf: &[u8], limit: usize) -> String {
+    let mut dump = format!("{} bytes", buf.len());
+    for (i, line) in buf[..buf.len().min(limit)].chunks(16).enumerate() {
+        dump += &format!("\n{:08x}:", i * 16);
+        for byte in line {
+            dump += &format!(" {byte:02x}");
+        }
+    }
+    if buf.len() > limit {
+        dump += "\n...";
+    }
+    dump
+}
+
+/// split the name-list by `pat`,
+/// the surrounding whitespaces (including CR/LF) of each name are trimmed,
+/// the empty names are ignored and only the first one of the duplicated names is kept
 pub(crate) fn vec_u8_to_string(v: Vec<u8>, pat: &str) -> SshResult<Vec<String>> {
     let result = String::from_utf8(v)?;
-    let r: Vec<&str> = result.split(pat).collect();
-    let mut vec = vec![];
-    for x in r {
-        vec.push(x.to_owned())
+    let mut vec: Vec<String> = vec![];
+    for name in result.split(pat).map(str::trim).filter(|x| !x.is_empty()) {
+        if vec.iter().any(|x| x == name) {
+            tracing::debug!("ignore the duplicated name {}", name);
+        } else {
+            vec.push(name.to_owned());
+        }
     }
     Ok(vec)
 }
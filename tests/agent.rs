@@ -0,0 +1,270 @@
+mod common;
+
+#[cfg(unix)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+        path::PathBuf,
+        thread::{self, JoinHandle},
+    };
+
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+
+    const AGENT_FAILURE: u8 = 5;
+    const REQUEST_IDENTITIES: u8 = 11;
+    const IDENTITIES_ANSWER: u8 = 12;
+    const SIGN_REQUEST: u8 = 13;
+    const SIGN_RESPONSE: u8 = 14;
+    const RSA_SHA2_256: u32 = 2;
+
+    fn ed25519_blob() -> Vec<u8> {
+        Buf::new().str("ssh-ed25519").bytes(&[1; 32]).into_inner()
+    }
+
+    fn rsa_blob() -> Vec<u8> {
+        Buf::new()
+            .str("ssh-rsa")
+            .bytes(&[1, 0, 1])
+            .bytes(&[0x7f; 256])
+            .into_inner()
+    }
+
+    fn agent_signature() -> Vec<u8> {
+        Buf::new()
+            .str("rsa-sha2-256")
+            .bytes(b"signed by the agent")
+            .into_inner()
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ssh-rs-agent-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// (key blob, data, flags) of a sign request
+    type SignRequest = (Vec<u8>, Vec<u8>, u32);
+
+    /// a mock agent with an ed25519 and an rsa identities,
+    /// which refuses to sign if `refuse`
+    fn spawn_agent(path: &PathBuf, refuse: bool) -> JoinHandle<Vec<SignRequest>> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut signs = vec![];
+            loop {
+                let mut len = [0; 4];
+                if stream.read_exact(&mut len).is_err() {
+                    break;
+                }
+                let mut request = vec![0; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut request).unwrap();
+                let reply = match request[0] {
+                    REQUEST_IDENTITIES => Buf::new()
+                        .u8(IDENTITIES_ANSWER)
+                        .u32(2)
+                        .bytes(&ed25519_blob())
+                        .str("ed25519 comment")
+                        .bytes(&rsa_blob())
+                        .str("rsa comment"),
+                    SIGN_REQUEST => {
+                        let mut r = Reader::new(&request[1..]);
+                        signs.push((r.bytes(), r.bytes(), r.u32()));
+                        if refuse {
+                            Buf::new().u8(AGENT_FAILURE)
+                        } else {
+                            Buf::new().u8(SIGN_RESPONSE).bytes(&agent_signature())
+                        }
+                    }
+                    x => panic!("mock agent: unexpected request {x}"),
+                };
+                let reply = Buf::new().bytes(&reply.into_inner()).into_inner();
+                stream.write_all(&reply).unwrap();
+            }
+            signs
+        })
+    }
+
+    /// (method, signed, algorithm, blob, the payload without the signature, signature)
+    type AuthRequest = (String, bool, String, Vec<u8>, Vec<u8>, Vec<u8>);
+
+    fn recv_auth_request(conn: &mut common::ServerConn) -> AuthRequest {
+        let p = conn.recv_msg(msg::USERAUTH_REQUEST);
+        let mut r = Reader::new(&p[1..]);
+        let _user = r.string();
+        let _service = r.string();
+        let method = r.string();
+        if method != "publickey" {
+            return (method, false, String::new(), vec![], vec![], vec![]);
+        }
+        let signed = r.bool();
+        let alg = r.string();
+        let blob = r.bytes();
+        let signature = if signed { r.bytes() } else { vec![] };
+        let unsigned = p[..p.len() - if signed { 4 + signature.len() } else { 0 }].to_vec();
+        (method, signed, alg, blob, unsigned, signature)
+    }
+
+    fn fail(conn: &mut common::ServerConn) {
+        conn.send(
+            Buf::new()
+                .u8(msg::USERAUTH_FAILURE)
+                .str("publickey,password")
+                .bool(false),
+        );
+    }
+
+    /// the rsa identity is signed with the same hash as the host key
+    fn config() -> common::ServerConfig {
+        common::ServerConfig {
+            host_key: "rsa-sha2-256".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_agent_auth() {
+        let path = socket_path("sign");
+        let agent = spawn_agent(&path, false);
+        let server = MockServer::spawn_with(config(), |conn| {
+            conn.handshake();
+            conn.accept_service();
+            // the first identity is not acceptable
+            let (method, signed, alg, blob, ..) = recv_auth_request(conn);
+            assert_eq!(method, "publickey");
+            assert!(!signed);
+            assert_eq!(alg, "ssh-ed25519");
+            assert_eq!(blob, ed25519_blob());
+            fail(conn);
+
+            // the second one is
+            let (_, signed, alg, blob, ..) = recv_auth_request(conn);
+            assert!(!signed);
+            assert_eq!(alg, "rsa-sha2-256");
+            assert_eq!(blob, rsa_blob());
+            conn.send(Buf::new().u8(msg::USERAUTH_PK_OK).str(&alg).bytes(&blob));
+
+            let (_, signed, alg, blob, unsigned, signature) = recv_auth_request(conn);
+            assert!(signed);
+            assert_eq!(alg, "rsa-sha2-256");
+            assert_eq!(blob, rsa_blob());
+            assert_eq!(signature, agent_signature());
+            conn.auth_success();
+            conn.wait_disconnect();
+            // what should be signed
+            Buf::new()
+                .bytes(&conn.session_id)
+                .raw(&unsigned)
+                .into_inner()
+        });
+
+        let session = ssh::create_session()
+            .username(common::USERNAME)
+            .ssh_agent_path(&path)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        let expected = server.join();
+        let signs = agent.join().unwrap();
+        assert_eq!(signs, [(rsa_blob(), expected, RSA_SHA2_256)]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_userauth_agent() {
+        let path = socket_path("env");
+        let agent = spawn_agent(&path, false);
+        // the only test here that reads the variable
+        std::env::set_var("SSH_AUTH_SOCK", &path);
+        let server = MockServer::spawn_with(config(), |conn| {
+            conn.handshake();
+            conn.accept_service();
+            let (method, signed, alg, blob, ..) = recv_auth_request(conn);
+            assert_eq!(method, "publickey");
+            assert!(!signed);
+            assert_eq!(blob, ed25519_blob());
+            conn.send(Buf::new().u8(msg::USERAUTH_PK_OK).str(&alg).bytes(&blob));
+            let (_, signed, ..) = recv_auth_request(conn);
+            assert!(signed);
+            conn.auth_success();
+            conn.wait_disconnect();
+        });
+
+        let session = ssh::create_session()
+            .userauth_agent(common::USERNAME)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        server.join();
+        let signs = agent.join().unwrap();
+        assert_eq!(signs.len(), 1);
+        assert_eq!(signs[0].0, ed25519_blob());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_agent_refuses_to_sign() {
+        let path = socket_path("refuse");
+        let agent = spawn_agent(&path, true);
+        let server = MockServer::spawn_with(config(), |conn| {
+            conn.handshake();
+            conn.accept_service();
+            let (method, _, _, blob, ..) = recv_auth_request(conn);
+            assert_eq!(method, "publickey");
+            assert_eq!(blob, ed25519_blob());
+            conn.send(
+                Buf::new()
+                    .u8(msg::USERAUTH_PK_OK)
+                    .str("ssh-ed25519")
+                    .bytes(&blob),
+            );
+            // the agent refuses, so the next identity is asked
+            let (_, signed, _, blob, ..) = recv_auth_request(conn);
+            assert!(!signed);
+            assert_eq!(blob, rsa_blob());
+            fail(conn);
+            // then the password
+            let method = conn.accept_auth();
+            conn.wait_disconnect();
+            method
+        });
+
+        let session = common::builder()
+            .ssh_agent_path(&path)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        assert_eq!(server.join(), "password");
+        assert_eq!(agent.join().unwrap().len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_agent_unavailable() {
+        let path = socket_path("none");
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            // straight to the password
+            let method = conn.accept_auth();
+            conn.wait_disconnect();
+            method
+        });
+
+        let session = common::builder()
+            .ssh_agent_path(&path)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        assert_eq!(server.join(), "password");
+    }
+}
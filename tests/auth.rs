@@ -0,0 +1,470 @@
+mod common;
+
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::{algorithm::PubKey, Prompt, SshError};
+
+    /// the (method, submethods) of a keyboard-interactive USERAUTH_REQUEST,
+    /// or the method only for the others
+    fn recv_auth_request(conn: &mut common::ServerConn) -> (String, Option<String>) {
+        let p = conn.recv_msg(msg::USERAUTH_REQUEST);
+        let mut r = Reader::new(&p[1..]);
+        assert_eq!(r.string(), common::USERNAME);
+        assert_eq!(r.string(), "ssh-connection");
+        let method = r.string();
+        if method != "keyboard-interactive" {
+            return (method, None);
+        }
+        let _language = r.string();
+        (method, Some(r.string()))
+    }
+
+    /// send an INFO_REQUEST & return the answers
+    fn ask(conn: &mut common::ServerConn, name: &str, prompts: &[(&str, bool)]) -> Vec<String> {
+        let mut req = Buf::new()
+            .u8(msg::USERAUTH_INFO_REQUEST)
+            .str(name)
+            .str("instruction")
+            .str("")
+            .u32(prompts.len() as u32);
+        for (prompt, echo) in prompts {
+            req = req.str(prompt).bool(*echo);
+        }
+        conn.send(req);
+
+        let p = conn.recv_msg(msg::USERAUTH_INFO_RESPONSE);
+        let mut r = Reader::new(&p[1..]);
+        (0..r.u32()).map(|_| r.string()).collect()
+    }
+
+    fn fail(conn: &mut common::ServerConn) {
+        conn.send(
+            Buf::new()
+                .u8(msg::USERAUTH_FAILURE)
+                .str("keyboard-interactive,password")
+                .bool(false),
+        );
+    }
+
+    type Rounds = Arc<Mutex<Vec<(String, String, Vec<Prompt>)>>>;
+
+    /// answer every prompt with `answer`, recording the rounds
+    fn answer_with(answer: &'static str, rounds: &Rounds) -> ssh::SessionBuilder {
+        let rounds = rounds.clone();
+        common::builder().keyboard_interactive(move |name, instruction, prompts| {
+            rounds.lock().unwrap().push((
+                name.to_owned(),
+                instruction.to_owned(),
+                prompts.to_vec(),
+            ));
+            prompts
+                .iter()
+                .map(|p| format!("{answer}-{}", p.prompt))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_keyboard_interactive_rounds() {
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            let (method, submethods) = recv_auth_request(conn);
+            assert_eq!(method, "keyboard-interactive");
+            assert_eq!(submethods.as_deref(), Some(""));
+
+            let first = ask(conn, "login", &[("password", false), ("user", true)]);
+            // a round with nothing to answer, as openssh does
+            let second = ask(conn, "notice", &[]);
+            let third = ask(conn, "2fa", &[("code", true)]);
+            conn.auth_success();
+            conn.wait_disconnect();
+            (first, second, third)
+        });
+
+        let rounds = Rounds::default();
+        let session = answer_with("secret", &rounds)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        let (first, second, third) = server.join();
+        assert_eq!(first, ["secret-password", "secret-user"]);
+        assert!(second.is_empty());
+        assert_eq!(third, ["secret-code"]);
+
+        let rounds = rounds.lock().unwrap();
+        let names: Vec<_> = rounds.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(names, ["login", "notice", "2fa"]);
+        assert!(rounds.iter().all(|r| r.1 == "instruction"));
+        assert_eq!(
+            rounds[0].2,
+            [
+                Prompt {
+                    prompt: "password".to_owned(),
+                    echo: false
+                },
+                Prompt {
+                    prompt: "user".to_owned(),
+                    echo: true
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyboard_interactive_fallback_to_password() {
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            let (method, _) = recv_auth_request(conn);
+            assert_eq!(method, "keyboard-interactive");
+            assert_eq!(ask(conn, "login", &[("code", true)]), ["wrong-code"]);
+            fail(conn);
+            // the password is the next one
+            let method = conn.accept_auth();
+            conn.wait_disconnect();
+            method
+        });
+
+        let rounds = Rounds::default();
+        let session = answer_with("wrong", &rounds)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        assert_eq!(server.join(), "password");
+        assert_eq!(rounds.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_keyboard_interactive_without_password() {
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            let (method, _) = recv_auth_request(conn);
+            assert_eq!(method, "keyboard-interactive");
+            fail(conn);
+            conn.wait_disconnect();
+        });
+
+        // no empty password is tried after keyboard-interactive
+        let err = ssh::create_session()
+            .username(common::USERNAME)
+            .keyboard_interactive(|_, _, prompts| vec![String::new(); prompts.len()])
+            .connect(server.addr)
+            .err()
+            .unwrap();
+        assert!(matches!(err, SshError::AuthError), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_keyboard_interactive_wrong_number_of_answers() {
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            recv_auth_request(conn);
+            conn.send(
+                Buf::new()
+                    .u8(msg::USERAUTH_INFO_REQUEST)
+                    .str("")
+                    .str("")
+                    .str("")
+                    .u32(2)
+                    .str("a")
+                    .bool(false)
+                    .str("b")
+                    .bool(false),
+            );
+            conn.wait_disconnect();
+        });
+
+        let err = common::builder()
+            .keyboard_interactive(|_, _, _| vec!["only one".to_owned()])
+            .connect(server.addr)
+            .err()
+            .unwrap();
+        assert!(matches!(err, SshError::GeneralError(_)), "{err:?}");
+        server.join();
+    }
+
+    /// `ssh-keygen -t ed25519 -a 2 -N passphrase`
+    const ENCRYPTED_KEY: &str = include_str!("common/ed25519_encrypted");
+    const ENCRYPTED_KEY_PUBLIC: &str =
+        "64661f5390bce3a9086f34ce27b32b66c638a4c179a68ed3d065726e2ab81ba4";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// accept the publickey auth of the encrypted key only if its signature verifies
+    fn verify_encrypted_key(conn: &mut common::ServerConn) -> String {
+        let blob = Buf::new()
+            .str("ssh-ed25519")
+            .bytes(&unhex(ENCRYPTED_KEY_PUBLIC))
+            .into_inner();
+        loop {
+            let p = conn.recv_msg(msg::USERAUTH_REQUEST);
+            let mut r = Reader::new(&p[1..]);
+            r.string();
+            r.string();
+            let method = r.string();
+            if method == "password" {
+                conn.auth_success();
+                return method;
+            }
+            assert_eq!(method, "publickey");
+            let signed = r.bool();
+            assert_eq!(r.string(), "ssh-ed25519");
+            assert_eq!(r.bytes(), blob);
+            if !signed {
+                conn.send(
+                    Buf::new()
+                        .u8(msg::USERAUTH_PK_OK)
+                        .str("ssh-ed25519")
+                        .bytes(&blob),
+                );
+                continue;
+            }
+            let signature = r.bytes();
+            let unsigned = &p[..p.len() - 4 - signature.len()];
+            let mut r = Reader::new(&signature);
+            assert_eq!(r.string(), "ssh-ed25519");
+            let signature = r.bytes();
+            let signed_data = Buf::new()
+                .bytes(&conn.session_id)
+                .raw(unsigned)
+                .into_inner();
+            ring::signature::UnparsedPublicKey::new(
+                &ring::signature::ED25519,
+                unhex(ENCRYPTED_KEY_PUBLIC),
+            )
+            .verify(&signed_data, &signature)
+            .unwrap();
+            conn.auth_success();
+            return method;
+        }
+    }
+
+    fn ed25519_config() -> common::ServerConfig {
+        common::ServerConfig {
+            host_key: "ssh-ed25519".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encrypted_private_key() {
+        let server = MockServer::spawn_with(ed25519_config(), |conn| {
+            conn.handshake();
+            conn.accept_service();
+            let method = verify_encrypted_key(conn);
+            conn.wait_disconnect();
+            method
+        });
+
+        let session = common::builder()
+            .add_pubkey_algorithms(PubKey::SshEd25519)
+            .private_key_with_passphrase(ENCRYPTED_KEY, "passphrase")
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        assert_eq!(server.join(), "publickey");
+    }
+
+    #[test]
+    fn test_encrypted_private_key_wrong_passphrase() {
+        let server = MockServer::spawn_with(ed25519_config(), |conn| {
+            conn.handshake();
+            conn.accept_service();
+            let method = verify_encrypted_key(conn);
+            conn.wait_disconnect();
+            method
+        });
+
+        // the bad key is skipped
+        let session = common::builder()
+            .add_pubkey_algorithms(PubKey::SshEd25519)
+            .private_key_with_passphrase(ENCRYPTED_KEY, "wrong")
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        assert_eq!(server.join(), "password");
+    }
+
+    #[test]
+    fn test_encrypted_private_key_without_passphrase() {
+        let err = ssh::connect_and_auth(
+            "127.0.0.1:1",
+            common::USERNAME,
+            ssh::AuthMethod::PrivateKey(ENCRYPTED_KEY.to_owned()),
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(err, SshError::SshPubKeyError(ref e) if e.contains("passphrase")),
+            "{err:?}"
+        );
+    }
+
+    const RSA_KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/common/rsa8192.pem");
+
+    /// whether each publickey request is signed, accepting the key if `accept`
+    fn serve_publickey(conn: &mut common::ServerConn, accept: bool) -> Vec<bool> {
+        conn.handshake();
+        conn.accept_service();
+        let mut signed = vec![];
+        loop {
+            let p = conn.recv_msg(msg::USERAUTH_REQUEST);
+            let mut r = Reader::new(&p[1..]);
+            assert_eq!(r.string(), common::USERNAME);
+            r.string();
+            assert_eq!(r.string(), "publickey");
+            signed.push(r.bool());
+            let (alg, blob) = (r.string(), r.bytes());
+            match (accept, signed.last()) {
+                (true, Some(false)) => {
+                    conn.send(Buf::new().u8(msg::USERAUTH_PK_OK).str(&alg).bytes(&blob))
+                }
+                (true, _) => {
+                    conn.auth_success();
+                    break;
+                }
+                (false, _) => {
+                    fail(conn);
+                    // then the password, which is empty
+                    assert_eq!(recv_auth_request(conn).0, "password");
+                    fail(conn);
+                    break;
+                }
+            }
+        }
+        conn.wait_disconnect();
+        signed
+    }
+
+    #[test]
+    fn test_userauth_publickey() {
+        let server = MockServer::spawn(|conn| serve_publickey(conn, true));
+
+        let session = ssh::create_session()
+            .userauth_publickey(common::USERNAME, RSA_KEY_PATH)
+            .unwrap()
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.close();
+
+        // queried, then signed once accepted
+        assert_eq!(server.join(), [false, true]);
+    }
+
+    #[test]
+    fn test_userauth_publickey_rejected() {
+        let server = MockServer::spawn(|conn| serve_publickey(conn, false));
+
+        let err = ssh::create_session()
+            .userauth_publickey(common::USERNAME, RSA_KEY_PATH)
+            .unwrap()
+            .connect(server.addr)
+            .err()
+            .unwrap();
+        assert!(matches!(err, SshError::AuthError), "{err:?}");
+        assert_eq!(server.join(), [false]);
+    }
+
+    #[test]
+    fn test_userauth_publickey_bad_key() {
+        let err = ssh::create_session()
+            .userauth_publickey(common::USERNAME, "/nonexistent/id_rsa")
+            .err()
+            .unwrap();
+        assert!(matches!(err, SshError::IoError(_)), "{err:?}");
+    }
+
+    fn banner(conn: &mut common::ServerConn, message: &str) {
+        conn.send(Buf::new().u8(msg::USERAUTH_BANNER).str(message).str("en"));
+    }
+
+    /// a banner before the first attempt & another one between the attempts
+    fn serve_banners(conn: &mut common::ServerConn) -> String {
+        conn.handshake();
+        conn.accept_service();
+        banner(conn, "Authorized use only.\n");
+        let (method, _) = recv_auth_request(conn);
+        assert_eq!(method, "keyboard-interactive");
+        banner(conn, "Activity is monitored.\n");
+        fail(conn);
+        let method = conn.accept_auth();
+        conn.wait_disconnect();
+        method
+    }
+
+    #[test]
+    fn test_banner_local() {
+        let server = MockServer::spawn(serve_banners);
+
+        let shown = Arc::new(Mutex::new(vec![]));
+        let seen = shown.clone();
+        let session = common::builder()
+            .keyboard_interactive(|_, _, prompts| vec![String::new(); prompts.len()])
+            .banner_callback(move |message| seen.lock().unwrap().push(message.to_owned()))
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        assert_eq!(
+            *shown.lock().unwrap(),
+            ["Authorized use only.\n", "Activity is monitored.\n"]
+        );
+        assert_eq!(
+            session.banner().as_deref(),
+            Some("Authorized use only.\nActivity is monitored.\n")
+        );
+        session.close();
+
+        assert_eq!(server.join(), "password");
+    }
+
+    #[test]
+    fn test_banner_backend() {
+        let server = MockServer::spawn(serve_banners);
+
+        let session = common::builder()
+            .keyboard_interactive(|_, _, prompts| vec![String::new(); prompts.len()])
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        assert_eq!(
+            session.banner().as_deref(),
+            Some("Authorized use only.\nActivity is monitored.\n")
+        );
+        session.close();
+
+        assert_eq!(server.join(), "password");
+    }
+
+    #[test]
+    fn test_no_banner() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+
+        let session = common::builder().connect(server.addr).unwrap().run_local();
+        assert_eq!(session.banner(), None);
+        session.close();
+        server.join();
+    }
+}
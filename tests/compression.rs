@@ -0,0 +1,73 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, MockServer, ServerConfig};
+    use ssh::algorithm::Compress;
+
+    fn zlib_server(output: Vec<u8>) -> MockServer<String> {
+        let config = ServerConfig {
+            compression: "zlib@openssh.com".to_owned(),
+            ..Default::default()
+        };
+        MockServer::spawn_with(config, move |conn| conn.ready_and_serve_exec(&output, 0))
+    }
+
+    #[test]
+    fn test_compression_stats() {
+        let output = b"compressible ".repeat(2000);
+        let command = format!("echo {}", "a".repeat(4000));
+        let server = zlib_server(output.clone());
+
+        let mut session = common::builder()
+            .del_compress_algorithms(Compress::None)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command(&command).unwrap(), output);
+        assert_eq!(server.join(), command);
+
+        let stats = session.compression_stats();
+        assert!(stats.recv.uncompressed > output.len() as u64);
+        assert!(stats.recv.ratio() > 1.0, "{stats:?}");
+        assert!(stats.send.uncompressed > command.len() as u64);
+        assert!(stats.send.ratio() > 1.0, "{stats:?}");
+        session.close();
+    }
+
+    #[test]
+    fn test_compression_stats_backend() {
+        let output = b"compressible ".repeat(2000);
+        let server = zlib_server(output.clone());
+
+        let mut session = common::builder()
+            .del_compress_algorithms(Compress::None)
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("cat").unwrap();
+        assert_eq!(exec.get_result().unwrap(), output);
+        assert_eq!(server.join(), "cat");
+
+        let stats = session.compression_stats();
+        assert!(stats.recv.ratio() > 1.0, "{stats:?}");
+        session.close();
+    }
+
+    #[test]
+    fn test_no_compression_stats() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"hello", 0));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        server.join();
+
+        let stats = session.compression_stats();
+        assert_eq!(stats.send.uncompressed, stats.send.compressed);
+        assert_eq!(stats.recv.uncompressed, stats.recv.compressed);
+        assert_eq!(stats.recv.ratio(), 1.0);
+        session.close();
+    }
+}
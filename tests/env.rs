@@ -0,0 +1,256 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::SshError;
+    use std::{thread, time::Duration};
+
+    /// reply the env requests on `ch` until a request of another type,
+    /// only the names in `accept` are accepted,
+    /// the env requests must ask for a reply as `want_reply`
+    ///
+    /// return the (name, value) of them & the name of the other request,
+    /// None if the client gives up the channel after a refusal
+    fn serve_env(
+        conn: &mut common::ServerConn,
+        ch: &common::MockChannel,
+        accept: &[&str],
+        want_reply: bool,
+    ) -> (Vec<(String, String)>, Option<String>) {
+        let mut env = vec![];
+        loop {
+            let p = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| conn.recv_packet()));
+            let Ok(p) = p else {
+                // the client is gone
+                return (env, None);
+            };
+            match p[0] {
+                msg::CHANNEL_REQUEST => (),
+                msg::CHANNEL_CLOSE | msg::DISCONNECT => return (env, None),
+                msg::CHANNEL_WINDOW_ADJUST | msg::IGNORE => continue,
+                x => panic!("mock: unexpected message {x}"),
+            }
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            let (name, reply_wanted) = (r.string(), r.bool());
+            if name != "env" {
+                if reply_wanted {
+                    conn.send(Buf::new().u8(msg::CHANNEL_SUCCESS).u32(ch.client_id));
+                }
+                return (env, Some(name));
+            }
+            assert_eq!(reply_wanted, want_reply);
+            let pair = (r.string(), r.string());
+            if !want_reply {
+                env.push(pair);
+                continue;
+            }
+            let reply = if accept.contains(&pair.0.as_str()) {
+                msg::CHANNEL_SUCCESS
+            } else {
+                msg::CHANNEL_FAILURE
+            };
+            conn.send(Buf::new().u8(reply).u32(ch.client_id));
+            env.push(pair);
+        }
+    }
+
+    /// only `LANG` is accepted, then the exec or the shell prints "ok"
+    fn serve_with_env(conn: &mut common::ServerConn) -> (Vec<(String, String)>, Option<String>) {
+        serve_exec_or_shell(conn, true)
+    }
+
+    /// the env set on the channel asks for no reply, so nothing is replied
+    fn serve_with_channel_env(
+        conn: &mut common::ServerConn,
+    ) -> (Vec<(String, String)>, Option<String>) {
+        serve_exec_or_shell(conn, false)
+    }
+
+    fn serve_exec_or_shell(
+        conn: &mut common::ServerConn,
+        want_reply: bool,
+    ) -> (Vec<(String, String)>, Option<String>) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        let (env, req) = serve_env(conn, &ch, &["LANG"], want_reply);
+        match req.as_deref() {
+            Some("exec") => {
+                conn.send_data(&ch, b"ok");
+                conn.finish_channel(&ch, 0);
+            }
+            Some("pty-req") => {
+                conn.accept_channel_request(&ch);
+                conn.send_data(&ch, b"ok");
+                conn.finish_channel(&ch, 0);
+            }
+            _ => (),
+        }
+        conn.wait_disconnect();
+        (env, req)
+    }
+
+    fn pairs(env: &[(&str, &str)]) -> Vec<(String, String)> {
+        env.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_optional_env_refused_local() {
+        let server = MockServer::spawn(serve_with_env);
+
+        let mut session = common::builder()
+            .env("LANG", "en_US.UTF-8")
+            .env("FOO", "bar")
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        // only warned
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("locale").unwrap(), b"ok");
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("LANG", "en_US.UTF-8"), ("FOO", "bar")]));
+        assert_eq!(req.as_deref(), Some("exec"));
+    }
+
+    #[test]
+    fn test_required_env_refused_local() {
+        let server = MockServer::spawn(serve_with_env);
+
+        let mut session = common::builder()
+            .env("FOO", "bar")
+            .required_env("DEPLOY_ENV", "prod")
+            .env("LANG", "C")
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let err = session.open_exec().err().unwrap();
+        assert!(matches!(err, SshError::EnvRefused(ref name) if name == "DEPLOY_ENV"));
+        session.close();
+
+        // neither the later env nor the exec is sent
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("FOO", "bar"), ("DEPLOY_ENV", "prod")]));
+        assert_eq!(req, None);
+    }
+
+    #[test]
+    fn test_shell_env_backend() {
+        let server = MockServer::spawn(serve_with_env);
+
+        let mut session = common::builder()
+            .required_env("LANG", "C")
+            .env("FOO", "bar")
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.read().unwrap(), b"ok");
+        while !shell.is_remote_closed() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(shell);
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("LANG", "C"), ("FOO", "bar")]));
+        assert_eq!(req.as_deref(), Some("pty-req"));
+    }
+
+    #[test]
+    fn test_required_env_refused_backend() {
+        let server = MockServer::spawn(serve_with_env);
+
+        let mut session = common::builder()
+            .required_env("DEPLOY_ENV", "prod")
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let err = session.open_exec().err().unwrap();
+        assert!(matches!(err, SshError::EnvRefused(ref name) if name == "DEPLOY_ENV"));
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("DEPLOY_ENV", "prod")]));
+        assert_eq!(req, None);
+    }
+
+    #[test]
+    fn test_channel_env_local() {
+        let server = MockServer::spawn(serve_with_channel_env);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let output = session
+            .open_exec()
+            .unwrap()
+            .set_env("LANG", "en_US.UTF-8")
+            .unwrap()
+            .set_env("DEPLOY_ENV", "prod")
+            .unwrap()
+            .send_command("locale")
+            .unwrap();
+        assert_eq!(output, b"ok");
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(
+            env,
+            pairs(&[("LANG", "en_US.UTF-8"), ("DEPLOY_ENV", "prod")])
+        );
+        assert_eq!(req.as_deref(), Some("exec"));
+    }
+
+    #[test]
+    fn test_channel_env_backend() {
+        let server = MockServer::spawn(serve_with_channel_env);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.set_env("DEPLOY_ENV", "prod")
+            .unwrap()
+            .send_command("./deploy.sh")
+            .unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"ok");
+        // too late for the command
+        assert!(exec.set_env("LANG", "C").is_err());
+        drop(exec);
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("DEPLOY_ENV", "prod")]));
+        assert_eq!(req.as_deref(), Some("exec"));
+    }
+
+    #[test]
+    fn test_channel_env_shell_backend() {
+        let server = MockServer::spawn(serve_with_channel_env);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session
+            .open_channel()
+            .unwrap()
+            .set_env("LANG", "C")
+            .unwrap()
+            .shell(ssh::TerminalSize::from(80, 24))
+            .unwrap();
+        assert_eq!(shell.read().unwrap(), b"ok");
+        while !shell.is_remote_closed() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(shell);
+        session.close();
+
+        let (env, req) = server.join();
+        assert_eq!(env, pairs(&[("LANG", "C")]));
+        assert_eq!(req.as_deref(), Some("pty-req"));
+    }
+}
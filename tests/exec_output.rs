@@ -0,0 +1,332 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::{ExecOutput, SshError};
+
+    /// answer the exec with interleaved stdout & stderr, then exit with 3
+    fn serve_exec_with_status(conn: &mut common::ServerConn) -> String {
+        let ch = conn.accept_channel();
+        let req = conn.accept_channel_request(&ch);
+        assert_eq!(req.name, "exec");
+        conn.send_data(&ch, b"line 1\n");
+        conn.send_stderr(&ch, b"warning\n");
+        conn.send_data(&ch, b"line 2\n");
+        conn.finish_channel(&ch, 3);
+        Reader::new(&req.data).string()
+    }
+
+    /// send the output then get killed by `SIGKILL`
+    fn serve_exec_killed(conn: &mut common::ServerConn) {
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.send_data(&ch, b"partial");
+        conn.send(
+            Buf::new()
+                .u8(msg::CHANNEL_REQUEST)
+                .u32(ch.client_id)
+                .str("exit-signal")
+                .bool(false)
+                .str("KILL")
+                .bool(false)
+                .str("killed")
+                .str(""),
+        );
+        conn.send_eof(&ch);
+        conn.send_close(&ch);
+        conn.recv_msg(msg::CHANNEL_CLOSE);
+    }
+
+    /// run until a signal comes, then get killed by it
+    ///
+    /// return the signal
+    fn serve_exec_signalled(conn: &mut common::ServerConn) -> String {
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.send_data(&ch, b"running");
+        let p = conn.recv_msg(msg::CHANNEL_REQUEST);
+        let mut r = Reader::new(&p[1..]);
+        assert_eq!(r.u32(), ch.server_id);
+        assert_eq!(r.string(), "signal");
+        assert!(!r.bool());
+        let signal = r.string();
+        conn.send(
+            Buf::new()
+                .u8(msg::CHANNEL_REQUEST)
+                .u32(ch.client_id)
+                .str("exit-signal")
+                .bool(false)
+                .str(&signal)
+                .bool(false)
+                .str("")
+                .str(""),
+        );
+        conn.send_eof(&ch);
+        conn.send_close(&ch);
+        conn.recv_msg(msg::CHANNEL_CLOSE);
+        signal
+    }
+
+    fn expected() -> ExecOutput {
+        ExecOutput {
+            output: b"line 1\nline 2\n".to_vec(),
+            stderr: b"warning\n".to_vec(),
+            exit_status: Some(3),
+            exit_signal: None,
+            terminate_msg: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_wait_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("make").unwrap();
+        assert_eq!(exec.wait().unwrap(), expected());
+
+        assert_eq!(server.join(), "make");
+        session.close();
+    }
+
+    #[test]
+    fn test_wait_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("make").unwrap();
+        assert_eq!(exec.wait().unwrap(), expected());
+
+        assert_eq!(server.join(), "make");
+        session.close();
+    }
+
+    #[test]
+    fn test_wait_killed() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_killed(conn)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("sleep 1000").unwrap();
+        let result = exec.wait().unwrap();
+        assert_eq!(result.output, b"partial");
+        // no exit status is not a success
+        assert_eq!(result.exit_status, None);
+        assert_eq!(result.exit_signal.as_deref(), Some("KILL"));
+        assert!(
+            result.terminate_msg.contains("KILL"),
+            "{}",
+            result.terminate_msg
+        );
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_wait_killed_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_killed(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("sleep 1000").unwrap();
+        let result = exec.wait().unwrap();
+        assert_eq!(result.output, b"partial");
+        assert_eq!(result.exit_status, None);
+        assert_eq!(result.exit_signal.as_deref(), Some("KILL"));
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_wait_without_command() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.accept_channel();
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert!(exec.wait().is_err());
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_exit_status_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn);
+            serve_exec_killed(conn);
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        assert_eq!(exec.exit_status(), None);
+        exec.exec_command("make").unwrap();
+        exec.get_output().unwrap();
+        assert_eq!(exec.exit_status(), Some(3));
+        assert_eq!(exec.exit_signal(), None);
+
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("sleep 1000").unwrap();
+        exec.get_output().unwrap();
+        assert_eq!(exec.exit_status(), None);
+        assert_eq!(exec.exit_signal().as_deref(), Some("KILL"));
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_exit_status_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn);
+            serve_exec_killed(conn);
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("make").unwrap();
+        exec.get_result().unwrap();
+        assert_eq!(exec.exit_status(), Some(3));
+        assert_eq!(exec.exit_signal(), None);
+
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("sleep 1000").unwrap();
+        exec.get_result().unwrap();
+        assert_eq!(exec.exit_status(), None);
+        assert_eq!(exec.exit_signal().as_deref(), Some("KILL"));
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_send_signal_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_signalled(conn)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("tail -f /var/log/syslog").unwrap();
+        exec.send_signal("INT").unwrap();
+        assert_eq!(exec.get_output().unwrap(), b"running");
+        assert_eq!(exec.exit_status(), None);
+        assert_eq!(exec.exit_signal().as_deref(), Some("INT"));
+
+        assert_eq!(server.join(), "INT");
+        session.close();
+    }
+
+    #[test]
+    fn test_send_signal_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_signalled(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("tail -f /var/log/syslog").unwrap();
+        // the prefix is dropped
+        exec.send_signal("SIGTERM").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"running");
+        assert_eq!(exec.exit_signal().as_deref(), Some("TERM"));
+
+        assert_eq!(server.join(), "TERM");
+        session.close();
+    }
+
+    #[test]
+    fn test_stderr_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("make").unwrap();
+        assert_eq!(exec.get_output().unwrap(), b"line 1\nline 2\n");
+        assert_eq!(exec.stderr(), b"warning\n");
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_stderr_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            serve_exec_with_status(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("make").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"line 1\nline 2\n");
+        assert_eq!(exec.stderr(), b"warning\n");
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_stderr_on_failure_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.recv_channel_request();
+            conn.send_stderr(&ch, b"exec is not permitted\n");
+            conn.send(Buf::new().u8(msg::CHANNEL_FAILURE).u32(ch.client_id));
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        let err = exec.send_command("make").unwrap_err();
+        assert_eq!(err.to_string(), "Channel failure: exec is not permitted");
+        match err {
+            SshError::ChannelFailure { stderr } => assert_eq!(stderr, b"exec is not permitted\n"),
+            err => panic!("{err:?}"),
+        }
+
+        session.close();
+        server.join();
+    }
+}
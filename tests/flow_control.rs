@@ -0,0 +1,290 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::ChannelKind;
+
+    #[test]
+    fn test_manual_window_adjust() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            assert_eq!(conn.accept_channel_request(&ch).name, "pty-req");
+            assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+            conn.send_data(&ch, b"one");
+            conn.send_data(&ch, b"two");
+
+            // the next packet is the manual adjust rather than the automatic ones
+            let p = conn.recv_packet();
+            let mut r = Reader::new(&p[1..]);
+            let adjust = (p[0], r.u32(), r.u32());
+
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            (adjust, ch.server_id)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        shell.set_auto_window_adjust(false);
+        let mut output = vec![];
+        while output.len() < 6 {
+            output.extend(shell.read().unwrap());
+        }
+        assert_eq!(output, b"onetwo");
+        shell.adjust_window(100).unwrap();
+        shell.close().unwrap();
+
+        let (adjust, server_id) = server.join();
+        assert_eq!(adjust, (msg::CHANNEL_WINDOW_ADJUST, server_id, 100));
+        session.close();
+    }
+
+    #[test]
+    fn test_auto_window_adjust() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"one");
+
+            let p = conn.recv_packet();
+            let mut r = Reader::new(&p[1..]);
+            let adjust = (p[0], r.u32(), r.u32());
+
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            (adjust, ch.server_id)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.read().unwrap(), b"one");
+        shell.close().unwrap();
+
+        let (adjust, server_id) = server.join();
+        assert_eq!(adjust, (msg::CHANNEL_WINDOW_ADJUST, server_id, 3));
+        session.close();
+    }
+
+    #[test]
+    fn test_window_size_per_channel_kind() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let shell = conn.accept_channel();
+            assert_eq!(conn.accept_channel_request(&shell).name, "pty-req");
+            assert_eq!(conn.accept_channel_request(&shell).name, "shell");
+            let exec = conn.accept_channel();
+            let raw = conn.accept_channel();
+            conn.wait_disconnect();
+            (shell.client_window, exec.client_window, raw.client_window)
+        });
+
+        let mut session = common::builder()
+            .window_size(ChannelKind::Shell, 16 * 1024)
+            .window_size(ChannelKind::Exec, 64 * 1024 * 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let _shell = session.open_shell().unwrap();
+        let _exec = session.open_exec().unwrap();
+        let _raw = session.open_channel().unwrap();
+        session.shutdown().unwrap();
+
+        let (shell, exec, raw) = server.join();
+        assert_eq!(shell, 16 * 1024);
+        assert_eq!(exec, 64 * 1024 * 1024);
+        // the default
+        assert_eq!(raw, 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_small_window_is_adjusted() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.accept_channel_request(&ch);
+            let window = ch.client_window;
+            conn.send_data(&ch, &vec![b'x'; window as usize]);
+
+            // the window is used up and then given back
+            let p = conn.recv_msg(msg::CHANNEL_WINDOW_ADJUST);
+            let mut r = Reader::new(&p[1..]);
+            let adjust = (r.u32(), r.u32());
+
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            (window, adjust, ch.server_id)
+        });
+
+        let mut session = common::builder()
+            .window_size(ChannelKind::Shell, 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let mut shell = session.open_shell().unwrap();
+        let mut output = vec![];
+        while output.len() < 1024 {
+            output.extend(shell.read().unwrap());
+        }
+        shell.close().unwrap();
+
+        let (window, adjust, server_id) = server.join();
+        assert_eq!(window, 1024);
+        assert_eq!(adjust, (server_id, 1024));
+        session.close();
+    }
+
+    /// serve an exec, then adjust the window of the finished exec channel
+    /// while the shell is waiting for its own window
+    ///
+    /// return the data sizes received on the shell
+    fn serve_stale_window_adjust(conn: &mut common::ServerConn) -> Vec<usize> {
+        conn.ready();
+        let exec = conn.accept_channel();
+        conn.accept_channel_request(&exec);
+        conn.finish_channel(&exec, 0);
+
+        let ch = conn.accept_channel_with_window(10);
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        let mut sizes = vec![];
+        let mut recv_data = |conn: &mut common::ServerConn| {
+            let p = conn.recv_msg(msg::CHANNEL_DATA);
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            sizes.push(r.bytes().len());
+        };
+        recv_data(conn);
+        conn.send(
+            Buf::new()
+                .u8(msg::CHANNEL_WINDOW_ADJUST)
+                .u32(exec.client_id)
+                .u32(1000),
+        );
+        for _ in 0..2 {
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_WINDOW_ADJUST)
+                    .u32(ch.client_id)
+                    .u32(5),
+            );
+            recv_data(conn);
+        }
+        conn.send_close(&ch);
+        conn.recv_msg(msg::CHANNEL_CLOSE);
+        sizes
+    }
+
+    #[test]
+    fn test_window_adjust_for_stale_channel() {
+        let server = MockServer::spawn(serve_stale_window_adjust);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        session.open_exec().unwrap().send_command("true").unwrap();
+        let mut shell = session.open_shell().unwrap();
+        shell.write(&[b'x'; 20]).unwrap();
+        shell.close().unwrap();
+
+        // only the adjusts of the shell itself let it send more
+        assert_eq!(server.join(), [10, 5, 5]);
+        session.close();
+    }
+
+    #[test]
+    fn test_window_adjust_for_stale_channel_backend() {
+        let server = MockServer::spawn(serve_stale_window_adjust);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        exec.get_result().unwrap();
+        let mut shell = session.open_shell().unwrap();
+        shell.write(&[b'x'; 20]).unwrap();
+
+        // the backend answers the close of the server
+        assert_eq!(server.join(), [10, 5, 5]);
+        session.close();
+    }
+
+    /// send 10 packets of 8 KiB to an exec, return the window adjusts
+    fn serve_bulk_exec(conn: &mut common::ServerConn) -> Vec<u32> {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        for _ in 0..10 {
+            conn.send_data(&ch, &[b'x'; 8 * 1024]);
+        }
+        conn.finish_channel(&ch, 0);
+        conn.wait_disconnect();
+        conn.received
+            .iter()
+            .filter(|p| p[0] == msg::CHANNEL_WINDOW_ADJUST)
+            .map(|p| {
+                let mut r = Reader::new(&p[1..]);
+                r.u32();
+                r.u32()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_window_adjust_threshold_local() {
+        let server = MockServer::spawn(serve_bulk_exec);
+
+        let mut session = common::builder()
+            .window_size(ChannelKind::Exec, 64 * 1024)
+            .window_adjust_threshold(32 * 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("cat").unwrap().len(), 80 * 1024);
+        session.close();
+
+        // the last 16 KiB are not given back
+        assert_eq!(server.join(), [32 * 1024, 32 * 1024]);
+    }
+
+    #[test]
+    fn test_window_adjust_threshold_backend() {
+        let server = MockServer::spawn(serve_bulk_exec);
+
+        let mut session = common::builder()
+            .window_size(ChannelKind::Exec, 64 * 1024)
+            .window_adjust_threshold(32 * 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("cat").unwrap();
+        assert_eq!(exec.get_result().unwrap().len(), 80 * 1024);
+        drop(exec);
+        session.close();
+
+        assert_eq!(server.join(), [32 * 1024, 32 * 1024]);
+    }
+
+    #[test]
+    fn test_window_adjust_threshold_capped() {
+        let server = MockServer::spawn(serve_bulk_exec);
+
+        // larger than the window, which would be used up otherwise
+        let mut session = common::builder()
+            .window_size(ChannelKind::Exec, 16 * 1024)
+            .window_adjust_threshold(1024 * 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("cat").unwrap().len(), 80 * 1024);
+        session.close();
+
+        assert_eq!(server.join(), [16 * 1024; 5]);
+    }
+}
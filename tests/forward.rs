@@ -0,0 +1,532 @@
+mod common;
+
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::common::{self, msg, Buf, MockServer, Reader, ServerConfig, ServerConn};
+
+    fn request(addr: std::net::SocketAddr, data: &str, expect: &str) {
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket.write_all(data.as_bytes()).unwrap();
+        let mut answer = vec![0; expect.len()];
+        socket.read_exact(&mut answer).unwrap();
+        assert_eq!(String::from_utf8(answer).unwrap(), expect);
+    }
+
+    #[test]
+    fn test_multiple_local_forwards() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.serve_direct_tcpip(2)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let db = session
+            .local_forward("127.0.0.1:0", "db.internal", 5432)
+            .unwrap();
+        let cache = session
+            .local_forward("127.0.0.1:0", "cache.internal", 6379)
+            .unwrap();
+
+        let (db_addr, cache_addr) = (db.local_addr(), cache.local_addr());
+        let db_client =
+            thread::spawn(move || request(db_addr, "select 1", "db.internal:5432 select 1"));
+        let cache_client =
+            thread::spawn(move || request(cache_addr, "PING", "cache.internal:6379 PING"));
+        db_client.join().unwrap();
+        cache_client.join().unwrap();
+
+        let opened = server.join();
+        let mut targets = opened
+            .iter()
+            .map(|t| (t.host.as_str(), t.port))
+            .collect::<Vec<_>>();
+        targets.sort();
+        assert_eq!(targets, [("cache.internal", 6379), ("db.internal", 5432)]);
+        for t in opened.iter() {
+            assert_eq!(t.originator_host, "127.0.0.1");
+            assert_ne!(t.originator_port, 0);
+        }
+        drop(db);
+        drop(cache);
+        session.close();
+    }
+
+    #[test]
+    fn test_stop_one_local_forward() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.serve_direct_tcpip(3)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let db = session
+            .local_forward("127.0.0.1:0", "db.internal", 5432)
+            .unwrap();
+        let cache = session
+            .local_forward("127.0.0.1:0", "cache.internal", 6379)
+            .unwrap();
+        let (db_addr, cache_addr) = (db.local_addr(), cache.local_addr());
+
+        let mut db_socket = TcpStream::connect(db_addr).unwrap();
+        db_socket.write_all(b"select 1").unwrap();
+        let mut answer = [0; 25];
+        db_socket.read_exact(&mut answer).unwrap();
+        assert_eq!(&answer, b"db.internal:5432 select 1");
+        let mut cache_socket = TcpStream::connect(cache_addr).unwrap();
+        cache_socket.write_all(b"PING").unwrap();
+        let mut answer = [0; 24];
+        cache_socket.read_exact(&mut answer).unwrap();
+        assert_eq!(&answer, b"cache.internal:6379 PING");
+
+        db.stop();
+
+        // the connection of the stopped forward is torn down
+        let mut rest = vec![];
+        assert_eq!(db_socket.read_to_end(&mut rest).unwrap_or(0), 0);
+        // and it no longer listens
+        assert!(TcpStream::connect(db_addr).is_err());
+
+        // while the other one keeps working
+        cache_socket.write_all(b"GET k").unwrap();
+        let mut answer = [0; 25];
+        cache_socket.read_exact(&mut answer).unwrap();
+        assert_eq!(&answer, b"cache.internal:6379 GET k");
+        drop(cache_socket);
+        request(cache_addr, "PING", "cache.internal:6379 PING");
+
+        let opened = server.join();
+        assert_eq!(opened.len(), 3);
+        drop(cache);
+        session.close();
+    }
+
+    #[test]
+    fn test_local_forward_half_close() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            assert_eq!(ch.kind, "direct-tcpip");
+            let mut query = vec![];
+            loop {
+                let p = conn.recv_packet();
+                let mut r = Reader::new(&p[1..]);
+                match p[0] {
+                    msg::CHANNEL_DATA => {
+                        r.u32();
+                        query.extend(r.bytes());
+                    }
+                    msg::CHANNEL_EOF => break,
+                    msg::IGNORE | msg::CHANNEL_WINDOW_ADJUST => (),
+                    x => panic!("mock: unexpected message {x}"),
+                }
+            }
+            // answer only once the whole query is received
+            conn.send_data(&ch, &query.to_ascii_uppercase());
+            conn.send_eof(&ch);
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let forward = session
+            .local_forward("127.0.0.1:0", "db.internal", 5432)
+            .unwrap();
+        let mut socket = TcpStream::connect(forward.local_addr()).unwrap();
+        socket.write_all(b"select 1").unwrap();
+        socket.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut answer = vec![];
+        socket.read_to_end(&mut answer).unwrap();
+        assert_eq!(answer, b"SELECT 1");
+
+        drop(forward);
+        session.close();
+        server.join();
+    }
+
+    #[test]
+    fn test_stop_local_forward_while_opening() {
+        let (opened_send, opened) = mpsc::channel();
+        let (stopped_send, stopped) = mpsc::channel();
+        let (closed_send, closed) = mpsc::channel();
+        let server = MockServer::spawn(move |conn| {
+            conn.ready();
+            let p = conn.recv_msg(msg::CHANNEL_OPEN);
+            let mut r = Reader::new(&p[1..]);
+            assert_eq!(r.string(), "direct-tcpip");
+            let client_id = r.u32();
+            opened_send.send(()).unwrap();
+
+            // confirmed only after the forward is stopped
+            stopped.recv().unwrap();
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_OPEN_CONFIRMATION)
+                    .u32(client_id)
+                    .u32(77)
+                    .u32(1024)
+                    .u32(32768),
+            );
+            let close = conn.recv_msg(msg::CHANNEL_CLOSE);
+            closed_send.send(Reader::new(&close[1..]).u32()).unwrap();
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let forward = session
+            .local_forward("127.0.0.1:0", "db.internal", 5432)
+            .unwrap();
+        let _socket = TcpStream::connect(forward.local_addr()).unwrap();
+        opened.recv().unwrap();
+
+        let start = Instant::now();
+        forward.stop();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        stopped_send.send(()).unwrap();
+
+        // the late channel is closed rather than the session
+        assert_eq!(closed.recv().unwrap(), 77);
+        session.close();
+        server.join();
+    }
+
+    #[test]
+    fn test_direct_tcpip_ipv6() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.serve_direct_tcpip(2)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        for host in ["::1", "[::1]"] {
+            let mut channel = session.open_direct_tcpip(host, 8080).unwrap();
+            channel.write(b"hello").unwrap();
+            let mut answer = vec![];
+            while answer.len() < 14 {
+                answer.extend(channel.read().unwrap());
+            }
+            assert_eq!(answer, b"::1:8080 hello");
+        }
+
+        let opened = server.join();
+        for t in opened.iter() {
+            assert_eq!(t.host, "::1");
+            assert_eq!(t.port, 8080);
+        }
+        session.close();
+    }
+
+    #[test]
+    fn test_direct_tcpip_shutdown_write() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            // a tiny window, so that the EOF has to wait for the data
+            let ch = conn.accept_channel_with_window(4);
+            assert_eq!(ch.kind, "direct-tcpip");
+            let mut received = vec![];
+            loop {
+                let p = conn.recv_packet();
+                let mut r = Reader::new(&p[1..]);
+                match p[0] {
+                    msg::IGNORE | msg::CHANNEL_WINDOW_ADJUST => (),
+                    msg::CHANNEL_DATA => {
+                        assert_eq!(r.u32(), ch.server_id);
+                        let data = r.bytes();
+                        received.extend(&data);
+                        conn.send(
+                            Buf::new()
+                                .u8(msg::CHANNEL_WINDOW_ADJUST)
+                                .u32(ch.client_id)
+                                .u32(data.len() as u32),
+                        );
+                    }
+                    msg::CHANNEL_EOF => {
+                        assert_eq!(r.u32(), ch.server_id);
+                        break;
+                    }
+                    x => panic!("mock: unexpected message {x}"),
+                }
+            }
+            // the answer comes after the EOF of the client
+            conn.send_data(&ch, &received.to_ascii_uppercase());
+            conn.send_data(&ch, b"!");
+            conn.send_eof(&ch);
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            conn.wait_disconnect();
+            received
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut stream = session
+            .open_direct_tcpip("localhost", 8080)
+            .unwrap()
+            .into_stream();
+        stream.write_all(b"hello world").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        assert!(stream.write_all(b"more").is_err());
+
+        let mut answer = vec![];
+        stream.read_to_end(&mut answer).unwrap();
+        assert_eq!(answer, b"HELLO WORLD!");
+        drop(stream);
+        session.close();
+
+        assert_eq!(server.join(), b"hello world");
+    }
+
+    #[test]
+    fn test_tcpip_forward_allocated_port() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let (name, want_reply, data) = conn.recv_global_request();
+            assert!(want_reply);
+            let mut r = Reader::new(&data);
+            let request = (name, r.string(), r.u32());
+            // the allocated port
+            conn.send(Buf::new().u8(msg::REQUEST_SUCCESS).u32(40123));
+
+            let (_, _, data) = conn.recv_global_request();
+            let mut r = Reader::new(&data);
+            let fixed = (r.string(), r.u32());
+            // no port in the reply when it's specified
+            conn.send(Buf::new().u8(msg::REQUEST_SUCCESS));
+
+            conn.recv_global_request();
+            conn.send(Buf::new().u8(msg::REQUEST_FAILURE));
+            (request, fixed)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        assert_eq!(session.tcpip_forward("localhost", 0).unwrap(), 40123);
+        assert_eq!(session.tcpip_forward("0.0.0.0", 8022).unwrap(), 8022);
+        assert!(session.tcpip_forward("0.0.0.0", 22).is_err());
+
+        let (request, fixed) = server.join();
+        assert_eq!(
+            request,
+            ("tcpip-forward".to_owned(), "localhost".to_owned(), 0)
+        );
+        assert_eq!(fixed, ("0.0.0.0".to_owned(), 8022));
+        session.close();
+    }
+
+    /// the server opens a forwarded-tcpip channel for a connection to `port`,
+    /// return the reply of the client
+    fn open_forwarded(conn: &mut ServerConn, channel_type: &str, port: u32) -> Vec<u8> {
+        conn.send(
+            Buf::new()
+                .u8(msg::CHANNEL_OPEN)
+                .str(channel_type)
+                .u32(7)
+                .u32(1 << 20)
+                .u32(32768)
+                .str("localhost")
+                .u32(port)
+                .str("203.0.113.5")
+                .u32(55555),
+        );
+        loop {
+            let p = conn.recv_packet();
+            match p[0] {
+                msg::CHANNEL_OPEN_CONFIRMATION | msg::CHANNEL_OPEN_FAILURE => return p,
+                msg::IGNORE | msg::DEBUG => continue,
+                x => panic!("mock: unexpected message {x}"),
+            }
+        }
+    }
+
+    fn open_failure_code(reply: &[u8]) -> u32 {
+        assert_eq!(reply[0], msg::CHANNEL_OPEN_FAILURE);
+        let mut r = Reader::new(&reply[1..]);
+        assert_eq!(r.u32(), 7);
+        r.u32()
+    }
+
+    #[test]
+    fn test_remote_forward() {
+        let (done_send, done_recv) = mpsc::channel();
+        let server = MockServer::spawn(move |conn| {
+            conn.ready();
+            let (name, want_reply, data) = conn.recv_global_request();
+            assert!(want_reply);
+            let mut r = Reader::new(&data);
+            let request = (name, r.string(), r.u32());
+            conn.send(Buf::new().u8(msg::REQUEST_SUCCESS).u32(40123));
+
+            // nobody listens on the other ports, or for the other types
+            let unknown_port = open_failure_code(&open_forwarded(conn, "forwarded-tcpip", 40124));
+            let unknown_type = open_failure_code(&open_forwarded(conn, "x11", 40123));
+
+            let reply = open_forwarded(conn, "forwarded-tcpip", 40123);
+            assert_eq!(reply[0], msg::CHANNEL_OPEN_CONFIRMATION);
+            let mut r = Reader::new(&reply[1..]);
+            assert_eq!(r.u32(), 7);
+            let client_id = r.u32();
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_DATA)
+                    .u32(client_id)
+                    .bytes(b"ping"),
+            );
+            let p = conn.recv_msg(msg::CHANNEL_DATA);
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            let answer = r.bytes();
+            // the local target closes after answering
+            conn.recv_msg(msg::CHANNEL_EOF);
+            conn.send(Buf::new().u8(msg::CHANNEL_CLOSE).u32(client_id));
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+            done_send.send(()).unwrap();
+
+            let (name, _, data) = conn.recv_global_request();
+            let mut r = Reader::new(&data);
+            let cancel = (name, r.string(), r.u32());
+            conn.send(Buf::new().u8(msg::REQUEST_SUCCESS));
+            // no longer forwarded
+            let cancelled = open_failure_code(&open_forwarded(conn, "forwarded-tcpip", 40123));
+            done_send.send(()).unwrap();
+            conn.wait_disconnect();
+            (
+                request,
+                answer,
+                cancel,
+                [unknown_port, unknown_type, cancelled],
+            )
+        });
+
+        let target = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_addr = target.local_addr().unwrap();
+        let echo = thread::spawn(move || {
+            let (mut socket, _) = target.accept().unwrap();
+            let mut data = [0; 4];
+            socket.read_exact(&mut data).unwrap();
+            socket.write_all(b"pong: ").unwrap();
+            socket.write_all(&data).unwrap();
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let forward = session.remote_forward("localhost", 0, target_addr).unwrap();
+        assert_eq!(forward.remote_port(), 40123);
+        echo.join().unwrap();
+        done_recv.recv().unwrap();
+        forward.stop();
+        done_recv.recv().unwrap();
+        session.close();
+
+        let (request, answer, cancel, failures) = server.join();
+        assert_eq!(
+            request,
+            ("tcpip-forward".to_owned(), "localhost".to_owned(), 0)
+        );
+        assert_eq!(answer, b"pong: ping");
+        // with the allocated port
+        assert_eq!(
+            cancel,
+            (
+                "cancel-tcpip-forward".to_owned(),
+                "localhost".to_owned(),
+                40123
+            )
+        );
+        // administratively prohibited, unknown channel type
+        assert_eq!(failures, [1, 3, 1]);
+    }
+
+    /// the forwarded answer as long as `expect`,
+    /// or None if the connection is refused or torn down
+    fn try_request(addr: std::net::SocketAddr, data: &str, expect: &str) -> Option<String> {
+        let mut socket = TcpStream::connect(addr).ok()?;
+        socket.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+        socket.write_all(data.as_bytes()).ok()?;
+        let mut answer = vec![0; expect.len()];
+        socket.read_exact(&mut answer).ok()?;
+        Some(String::from_utf8(answer).unwrap())
+    }
+
+    #[test]
+    fn test_reconnect_resumes_forwards() {
+        let server = MockServer::start(|listener| {
+            let mut requested = vec![];
+            let mut opened = vec![];
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut conn = ServerConn::new(stream, ServerConfig::default());
+                conn.ready();
+                let (name, _, data) = conn.recv_global_request();
+                let mut r = Reader::new(&data);
+                requested.push((name, r.string(), r.u32()));
+                conn.send(Buf::new().u8(msg::REQUEST_SUCCESS));
+                opened.extend(conn.serve_direct_tcpip(1));
+                // then the connection drops
+            }
+            (requested, opened)
+        });
+
+        let mut session = common::builder()
+            .auto_reconnect(Some(Duration::from_millis(50)))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        assert_eq!(session.tcpip_forward("0.0.0.0", 8080).unwrap(), 8080);
+        let db = session
+            .local_forward("127.0.0.1:0", "db.internal", 5432)
+            .unwrap();
+
+        let db_addr = db.local_addr();
+        request(db_addr, "select 1", "db.internal:5432 select 1");
+        // the channel is closed by the target after the EOF of the socket,
+        // let it finish before the next request
+        thread::sleep(Duration::from_millis(200));
+
+        // the same listener works again once reconnected
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let answer = loop {
+            if let Some(answer) = try_request(db_addr, "select 2", "db.internal:5432 select 2") {
+                break answer;
+            }
+            assert!(Instant::now() < deadline, "the forward never resumes");
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(answer, "db.internal:5432 select 2");
+
+        let (requested, opened) = server.join();
+        let forward = ("tcpip-forward".to_owned(), "0.0.0.0".to_owned(), 8080);
+        // the remote forward is requested again on the new connection
+        assert_eq!(requested, [forward.clone(), forward]);
+        assert_eq!(opened.len(), 2);
+        drop(db);
+        session.close();
+    }
+}
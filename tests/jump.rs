@@ -0,0 +1,154 @@
+mod common;
+
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use crate::common::{self, msg, MockServer, Reader, ServerConn};
+
+    /// relay one `direct-tcpip` channel to `target` until the client goes
+    ///
+    /// return the host & port asked by the client
+    fn serve_jump(conn: &mut ServerConn, target: SocketAddr) -> (String, u32) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        assert_eq!(ch.kind, "direct-tcpip");
+        let mut r = Reader::new(&ch.extra);
+        let asked = (r.string(), r.u32());
+
+        let mut socket = TcpStream::connect(target).unwrap();
+        let (snd, rcv) = mpsc::channel();
+        let mut reader = socket.try_clone().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            loop {
+                let len = reader.read(&mut buf).unwrap_or(0);
+                let _ = snd.send(buf[..len].to_vec());
+                if len == 0 {
+                    break;
+                }
+            }
+        });
+
+        loop {
+            while let Ok(data) = rcv.try_recv() {
+                if data.is_empty() {
+                    // the target goes after the client session,
+                    // whose jump session may be gone too
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        conn.send_eof(&ch);
+                        conn.send_close(&ch);
+                    }));
+                } else {
+                    conn.send_data(&ch, &data);
+                }
+            }
+            // poll the client between the relayed data
+            conn.stream
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .unwrap();
+            match conn.stream.peek(&mut [0]) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                // the client is gone
+                Err(_) => break,
+            }
+            conn.stream.set_read_timeout(None).unwrap();
+            let p = conn.recv_packet();
+            let mut r = Reader::new(&p[1..]);
+            match p[0] {
+                msg::CHANNEL_DATA => {
+                    r.u32();
+                    socket.write_all(&r.bytes()).unwrap();
+                }
+                // the client may be gone before a reply
+                msg::CHANNEL_CLOSE => {
+                    conn.wait_disconnect();
+                    break;
+                }
+                msg::DISCONNECT => break,
+                _ => (),
+            }
+        }
+        asked
+    }
+
+    #[test]
+    fn test_connect_via() {
+        let target = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"behind the bastion", 0));
+        let target_addr = target.addr;
+        let bastion = MockServer::spawn(move |conn| serve_jump(conn, target_addr));
+
+        let jump = common::builder()
+            .connect(bastion.addr)
+            .unwrap()
+            .run_backend();
+        let mut session = common::builder()
+            .connect_via(&jump, &target_addr.to_string())
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("hostname").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"behind the bastion");
+        session.close();
+        assert_eq!(target.join(), "hostname");
+
+        jump.close();
+        let (host, port) = bastion.join();
+        assert_eq!(host, "127.0.0.1");
+        assert_eq!(port, target_addr.port() as u32);
+    }
+
+    #[test]
+    fn test_connect_via_local() {
+        let target = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"ok", 0));
+        let target_addr = target.addr;
+        let bastion = MockServer::spawn(move |conn| serve_jump(conn, target_addr));
+
+        let jump = common::builder()
+            .connect(bastion.addr)
+            .unwrap()
+            .run_backend();
+        // the brackets are stripped as for IPv6
+        let mut session = common::builder()
+            .connect_via(&jump, &format!("[127.0.0.1]:{}", target_addr.port()))
+            .unwrap()
+            .run_local();
+        let output = session.open_exec().unwrap().send_command("id").unwrap();
+        assert_eq!(output, b"ok");
+        session.close();
+        target.join();
+
+        jump.close();
+        assert_eq!(bastion.join().0, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_connect_via_invalid_addr() {
+        let bastion = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+
+        let jump = common::builder()
+            .connect(bastion.addr)
+            .unwrap()
+            .run_backend();
+        for addr in ["10.0.0.5", "10.0.0.5:ssh"] {
+            let err = common::builder().connect_via(&jump, addr).err().unwrap();
+            assert!(
+                matches!(err, ssh::SshError::IoError(ref e) if e.kind() == ErrorKind::InvalidInput)
+            );
+        }
+        jump.close();
+        bastion.join();
+    }
+}
@@ -0,0 +1,1515 @@
+mod common;
+
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Barrier},
+        time::Duration,
+    };
+
+    use crate::common::{self, has_name, msg, Buf, KexInit, MockServer, Reader};
+    use ssh::{
+        algorithm::{parse_public_key, Compress, Enc, FingerprintHash, Kex, Mac, PubKey},
+        KexDetails, KeyLengths, SshError,
+    };
+
+    #[test]
+    fn test_kexinit_reserved_not_zero() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            let config = conn.config.clone();
+            let payload = Buf::new()
+                .u8(common::msg::KEXINIT)
+                .raw(&[0x11; 16])
+                .str(&config.kex)
+                .str(&config.host_key)
+                .str(&config.ciphers)
+                .str(&config.ciphers)
+                .str(&config.macs)
+                .str(&config.macs)
+                .str(&config.compression)
+                .str(&config.compression)
+                .str("")
+                .str("")
+                .bool(false)
+                .u32(1)
+                .into_inner();
+            conn.send_kexinit_payload(payload);
+        });
+
+        let err = match common::builder().connect(server.addr) {
+            Ok(_) => panic!("a non-zero reserved field should be rejected"),
+            Err(e) => e,
+        };
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_strong_ciphers_only() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            KexInit::parse(&conn.client_kexinit).lists
+        });
+
+        let session = common::builder()
+            .add_enc_algorithms(Enc::Aes128Ctr)
+            .strong_ciphers_only(true)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+
+        let lists = server.join();
+        // c2s & s2c encryption
+        for ciphers in &lists[2..4] {
+            assert_eq!(
+                ciphers,
+                "chacha20-poly1305@openssh.com,aes256-gcm@openssh.com"
+            );
+        }
+        session.close();
+    }
+
+    #[test]
+    fn test_cipher_strength() {
+        assert!(Enc::Chacha20Poly1305Openssh.is_aead());
+        assert!(Enc::Aes256GcmOpenssh.is_aead());
+        assert_eq!(Enc::Aes256GcmOpenssh.strength(), 256);
+        assert!(!Enc::Aes256Ctr.is_aead());
+        assert_eq!(Enc::Aes128Ctr.strength(), 128);
+        assert_eq!(Enc::Aes192Ctr.strength(), 192);
+        assert_eq!(Enc::Chacha20Poly1305Openssh.strength(), 256);
+    }
+
+    #[test]
+    fn test_prefer_aead_drops_macs() {
+        let lists = KexInit::parse(
+            &common::builder()
+                .strong_ciphers_only(true)
+                .prefer_aead(true)
+                .dry_run()
+                .unwrap(),
+        )
+        .lists;
+        // c2s & s2c mac
+        assert_eq!(lists[4], "");
+        assert_eq!(lists[5], "");
+
+        // the server still has MACs, which are not negotiated
+        let server = MockServer::spawn(|conn| {
+            conn.ready_and_serve_exec(b"hello", 0);
+            conn.negotiated.clone()
+        });
+        let mut session = common::builder()
+            .strong_ciphers_only(true)
+            .prefer_aead(true)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        session.close();
+        let negotiated = server.join();
+        assert_eq!(negotiated.c2s_cipher, "chacha20-poly1305@openssh.com");
+        assert_eq!(negotiated.c2s_mac, "");
+        assert_eq!(negotiated.s2c_mac, "");
+    }
+
+    #[test]
+    fn test_prefer_aead_keeps_macs_for_other_ciphers() {
+        let lists = KexInit::parse(
+            &common::builder()
+                .add_enc_algorithms(Enc::Aes128Ctr)
+                .del_enc_algorithms(Enc::Chacha20Poly1305Openssh)
+                .add_enc_algorithms(Enc::Chacha20Poly1305Openssh)
+                .prefer_aead(true)
+                .dry_run()
+                .unwrap(),
+        )
+        .lists;
+        for ciphers in &lists[2..4] {
+            assert!(ciphers.starts_with("aes256-gcm@openssh.com,chacha20-poly1305@openssh.com,"));
+            assert!(has_name(ciphers, "aes128-ctr"));
+        }
+        // a CTR cipher may still be chosen
+        for macs in &lists[4..6] {
+            assert!(has_name(macs, "hmac-sha2-256"));
+        }
+    }
+
+    #[test]
+    fn test_aead_without_common_mac() {
+        let config = common::ServerConfig {
+            macs: "hmac-md5".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+        let connector = common::builder().connect(server.addr).unwrap();
+        assert_eq!(connector.kex_details().c_keys.mac_key, 0);
+        connector.run_local().close();
+        server.join();
+
+        // but required by the other ciphers
+        let config = common::ServerConfig {
+            ciphers: "aes256-ctr".to_owned(),
+            macs: "hmac-md5".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_msg(msg::KEXINIT);
+        });
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    /// the key exchange up to the KEX reply, without the NEWKEYS
+    fn kex_until_reply(conn: &mut common::ServerConn) {
+        conn.send_version();
+        conn.read_version();
+        conn.send_kexinit();
+        conn.recv_kexinit();
+        conn.kex_reply();
+    }
+
+    #[test]
+    fn test_server_newkeys_first() {
+        let server = MockServer::spawn(|conn| {
+            kex_until_reply(conn);
+            // the server switches its outgoing keys before the client sends NEWKEYS
+            conn.send_newkeys();
+            conn.send(Buf::new().u8(common::msg::IGNORE).str("encrypted"));
+            conn.recv_newkeys();
+            conn.accept_service();
+            conn.accept_auth();
+            conn.serve_exec(b"hello", 0)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_server_newkeys_after_client() {
+        let server = MockServer::spawn(|conn| {
+            kex_until_reply(conn);
+            // the server does not send NEWKEYS until it gets the client's
+            conn.recv_newkeys();
+            conn.send_newkeys();
+            conn.accept_service();
+            conn.accept_auth();
+            conn.serve_exec(b"hello", 0)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_reject_ignore_before_kexinit() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send(Buf::new().u8(common::msg::IGNORE).str("injected"));
+            conn.send_kexinit();
+        });
+
+        let result = common::builder().connect(server.addr);
+        assert!(matches!(result, Err(SshError::KexError(_))));
+        server.join();
+    }
+
+    #[test]
+    fn test_reject_ignore_mid_handshake() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            // before the KEXDH_REPLY
+            conn.send(Buf::new().u8(common::msg::IGNORE).str("injected"));
+        });
+
+        let result = common::builder().connect(server.addr);
+        assert!(matches!(result, Err(SshError::KexError(_))));
+        server.join();
+    }
+
+    #[test]
+    fn test_reject_injection_before_first_newkeys() {
+        let injections = [
+            Buf::new().u8(common::msg::IGNORE).str("plain"),
+            Buf::new()
+                .u8(common::msg::DEBUG)
+                .bool(false)
+                .str("plain")
+                .str(""),
+            Buf::new().u8(common::msg::UNIMPLEMENTED).u32(3),
+        ];
+        for injection in injections {
+            let server = MockServer::spawn(move |conn| {
+                kex_until_reply(conn);
+                conn.recv_newkeys();
+                // rejected even without the strict key exchange
+                conn.send(injection);
+                conn.send_newkeys();
+            });
+
+            let result = common::builder().strict_kex(false).connect(server.addr);
+            assert!(matches!(result, Err(SshError::KexError(_))));
+            server.join();
+        }
+    }
+
+    #[test]
+    fn test_send_encrypted_recv_plain_on_rekey() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            let req = conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"before ");
+
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            conn.kex_reply();
+            conn.recv_newkeys();
+            // still under the old keys
+            conn.send(Buf::new().u8(common::msg::IGNORE).str("old keys"));
+            conn.send_newkeys();
+
+            conn.send_data(&ch, b"after");
+            conn.finish_channel(&ch, 0);
+            req.name
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("echo").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"before after");
+        assert_eq!(server.join(), "exec");
+        session.close();
+    }
+
+    #[test]
+    fn test_name_list_with_spaces_and_empty_fields() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            let payload = Buf::new()
+                .u8(common::msg::KEXINIT)
+                .raw(&[0x11; 16])
+                .str(" curve25519-sha256 ,,ecdh-sha2-nistp256\r\n")
+                .str("rsa-sha2-512, rsa-sha2-256 ,")
+                .str(",\tchacha20-poly1305@openssh.com,aes128-ctr ")
+                .str("chacha20-poly1305@openssh.com ,,aes128-ctr")
+                .str(" hmac-sha2-256 , hmac-sha1")
+                .str("hmac-sha2-256\r")
+                .str(" none ")
+                .str("none,")
+                .str("")
+                .str("")
+                .bool(false)
+                .u32(0)
+                .into_inner();
+            conn.send_kexinit_payload(payload);
+            conn.recv_kexinit();
+            conn.kex_reply();
+            conn.send_newkeys();
+            conn.recv_newkeys();
+            conn.accept_service();
+            conn.accept_auth();
+            conn.serve_exec(b"hello", 0)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    /// a host key list padded with an unknown name of `len` bytes
+    fn padded_host_keys(len: usize) -> common::ServerConfig {
+        common::ServerConfig {
+            host_key: format!("rsa-sha2-512,{}", "x".repeat(len)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_oversized_name_list() {
+        let server = MockServer::spawn_with(padded_host_keys(64 * 1024), |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_oversized_name_list_within_cap() {
+        let server = MockServer::spawn_with(padded_host_keys(64 * 1024), |conn| {
+            conn.ready_and_serve_exec(b"hello", 0)
+        });
+
+        let mut session = common::builder()
+            .max_name_list_len(128 * 1024)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_multi_megabyte_name_list() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            // the client may hang up before the whole list is written
+            let list = "x".repeat(4 * 1024 * 1024);
+            let payload = Buf::new()
+                .u8(common::msg::KEXINIT)
+                .raw(&[0x11; 16])
+                .str(&list)
+                .into_inner();
+            let packet = Buf::new()
+                .u32(payload.len() as u32 + 5)
+                .u8(4)
+                .raw(&payload)
+                .raw(&[0; 4])
+                .into_inner();
+            let _ = conn.stream.write_all(&packet);
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::ProtocolError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_truncated_name_list() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            let payload = Buf::new()
+                .u8(common::msg::KEXINIT)
+                .raw(&[0x11; 16])
+                .str("curve25519-sha256")
+                .u32(1000)
+                .raw(b"rsa-sha2-512")
+                .into_inner();
+            conn.send_kexinit_payload(payload);
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_truncated_cookie() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            let payload = Buf::new()
+                .u8(common::msg::KEXINIT)
+                .raw(&[0x11; 5])
+                .into_inner();
+            conn.send_kexinit_payload(payload);
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_truncated_trailing_fields() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            let mut payload = conn.kexinit_payload();
+            // the reserved u32 is cut off
+            payload.truncate(payload.len() - 3);
+            conn.send_kexinit_payload(payload);
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_forced_host_key_algorithm() {
+        let config = common::ServerConfig {
+            host_key: "ssh-ed25519,rsa-sha2-512".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            KexInit::parse(&conn.client_kexinit).lists
+        });
+
+        let session = common::builder()
+            .add_pubkey_algorithms(PubKey::RsaSha2_256)
+            .host_key_algorithm(Some(PubKey::SshEd25519))
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+
+        let lists = server.join();
+        assert_eq!(lists[1], "ssh-ed25519");
+        session.close();
+    }
+
+    #[test]
+    fn test_offered_host_key_algorithms() {
+        let offered = "ssh-ed25519,ecdsa-sha2-nistp256,rsa-sha2-512,rsa-sha2-256";
+        let expected: Vec<String> = offered.split(',').map(String::from).collect();
+        for backend in [false, true] {
+            let config = common::ServerConfig {
+                host_key: offered.to_owned(),
+                ..Default::default()
+            };
+            let server = MockServer::spawn_with(config, |conn| {
+                conn.ready();
+                conn.wait_disconnect()
+            });
+
+            let connector = common::builder().connect(server.addr).unwrap();
+            // including the unsupported ecdsa
+            assert_eq!(connector.offered_host_key_algorithms(), expected);
+            if backend {
+                let session = connector.run_backend();
+                assert_eq!(session.offered_host_key_algorithms(), expected);
+                session.shutdown().unwrap();
+            } else {
+                let session = connector.run_local();
+                assert_eq!(session.offered_host_key_algorithms(), expected);
+                session.shutdown().unwrap();
+            }
+            server.join();
+        }
+    }
+
+    #[test]
+    fn test_server_public_key_openssh() {
+        for (alg, key_type) in [
+            ("ssh-ed25519", "ssh-ed25519 "),
+            ("rsa-sha2-256", "ssh-rsa "),
+        ] {
+            for backend in [false, true] {
+                let config = common::ServerConfig {
+                    host_key: alg.to_owned(),
+                    ..Default::default()
+                };
+                let server = MockServer::spawn_with(config, |conn| {
+                    conn.ready();
+                    conn.wait_disconnect();
+                    conn.host_key_blob()
+                });
+
+                let connector = common::builder()
+                    .add_pubkey_algorithms(PubKey::SshEd25519)
+                    .connect(server.addr)
+                    .unwrap();
+                let line = connector.server_public_key_openssh().unwrap();
+                let from_session = if backend {
+                    let session = connector.run_backend();
+                    let line = session.server_public_key_openssh().unwrap();
+                    session.shutdown().unwrap();
+                    line
+                } else {
+                    let session = connector.run_local();
+                    let line = session.server_public_key_openssh().unwrap();
+                    session.shutdown().unwrap();
+                    line
+                };
+                assert_eq!(from_session, line);
+                assert!(line.starts_with(key_type), "{line}");
+
+                // round trip to the blob sent by the server
+                let key = parse_public_key(&line).unwrap();
+                assert_eq!(key.key_blob, server.join());
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_key_fingerprint() {
+        let config = common::ServerConfig {
+            host_key: "rsa-sha2-256".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+
+        let connector = common::builder().connect(server.addr).unwrap();
+        let blob = common::host_key_blob("rsa-sha2-256");
+        assert_eq!(connector.server_host_key(), blob);
+        let sha256 = connector.host_key_fingerprint(FingerprintHash::Sha256);
+        let md5 = connector.host_key_fingerprint(FingerprintHash::Md5);
+        // as printed by `ssh-keygen -l -E sha256|md5`
+        assert_eq!(sha256, "SHA256:VemzTWAcCmPjbluRPzd/RcIoq2Wqkj/O81ndRpVDUuE");
+        assert_eq!(
+            sha256,
+            ssh_key::PublicKey::from_bytes(&blob)
+                .unwrap()
+                .fingerprint(ssh_key::HashAlg::Sha256)
+                .to_string()
+        );
+        assert_eq!(md5, "MD5:d1:5e:b2:74:b3:86:08:67:d2:46:d8:cb:9b:df:60:0b");
+
+        let session = connector.run_backend();
+        assert_eq!(session.server_host_key(), blob);
+        assert_eq!(session.host_key_fingerprint(FingerprintHash::Md5), md5);
+        session.shutdown().unwrap();
+        server.join();
+    }
+
+    /// the cookie of the client KEXINIT seeded with 42
+    const SEEDED_COOKIE: [u8; 16] = [
+        0xa2, 0x63, 0x7d, 0x13, 0xd1, 0x71, 0xb2, 0x78, 0xea, 0xdf, 0xa8, 0xa3, 0xfb, 0xe8, 0x37,
+        0x9b,
+    ];
+
+    fn derivation(details: KexDetails) -> (String, KeyLengths, KeyLengths) {
+        (details.hash, details.c_keys, details.s_keys)
+    }
+
+    #[test]
+    fn test_kex_details() {
+        // chacha20-poly1305 takes no IV and no MAC key
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+        let connector = common::builder().connect(server.addr).unwrap();
+        let keys = KeyLengths {
+            iv: 0,
+            key: 64,
+            mac_key: 0,
+        };
+        let expected = ("sha256".to_owned(), keys, keys);
+        assert_eq!(derivation(connector.kex_details()), expected);
+        let session = connector.run_local();
+        assert_eq!(derivation(session.kex_details()), expected);
+        session.close();
+        server.join();
+
+        let config = common::ServerConfig {
+            kex: "diffie-hellman-group16-sha512".to_owned(),
+            ciphers: "aes192-ctr".to_owned(),
+            macs: "hmac-sha2-512".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+        let session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let keys = KeyLengths {
+            iv: 16,
+            key: 24,
+            mac_key: 64,
+        };
+        assert_eq!(
+            derivation(session.kex_details()),
+            ("sha512".to_owned(), keys, keys)
+        );
+        session.shutdown().unwrap();
+        server.join();
+    }
+
+    #[test]
+    fn test_kexinit_capture() {
+        let config = common::ServerConfig {
+            kex: "curve25519-sha256".to_owned(),
+            host_key: "ssh-ed25519".to_owned(),
+            ciphers: "aes256-ctr".to_owned(),
+            macs: "hmac-sha2-256".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+            (conn.client_kexinit.clone(), conn.server_kexinit.clone())
+        });
+        let connector = ssh::create_session_without_default()
+            .username(common::USERNAME)
+            .password(common::PASSWORD)
+            .rng_seed(Some(42))
+            .add_kex_algorithms(Kex::Curve25519Sha256)
+            .add_pubkey_algorithms(PubKey::SshEd25519)
+            .add_enc_algorithms(Enc::Aes256Ctr)
+            .add_mac_algortihms(Mac::HmacSha2_256)
+            .add_compress_algorithms(Compress::None)
+            .connect(server.addr)
+            .unwrap();
+        let details = connector.kex_details();
+        let session = connector.run_backend();
+        assert_eq!(session.kex_details(), details);
+        session.shutdown().unwrap();
+        let (client_kexinit, server_kexinit) = server.join();
+
+        // exactly what is on the wire
+        assert_eq!(details.client_kexinit, client_kexinit);
+        assert_eq!(details.server_kexinit, server_kexinit);
+
+        let lists = |buf: Buf| {
+            buf.str("ssh-ed25519")
+                .str("aes256-ctr")
+                .str("aes256-ctr")
+                .str("hmac-sha2-256")
+                .str("hmac-sha2-256")
+                .str("none")
+                .str("none")
+                .str("")
+                .str("")
+                .bool(false)
+                .u32(0)
+                .into_inner()
+        };
+        let client_fixture = lists(
+            Buf::new()
+                .u8(msg::KEXINIT)
+                .raw(&SEEDED_COOKIE)
+                .str("curve25519-sha256,ext-info-c,kex-strict-c-v00@openssh.com"),
+        );
+        let server_fixture = lists(
+            Buf::new()
+                .u8(msg::KEXINIT)
+                .raw(&[0x11; 16])
+                .str("curve25519-sha256"),
+        );
+        assert_eq!(details.client_kexinit, client_fixture);
+        assert_eq!(details.server_kexinit, server_fixture);
+    }
+
+    /// the client KEXINIT and KEX_ECDH_INIT of a session seeded with `seed`
+    fn seeded_handshake(kex: &str, seed: u64) -> Vec<Vec<u8>> {
+        let config = common::ServerConfig {
+            kex: kex.to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+            conn.received[..2].to_vec()
+        });
+
+        let session = common::builder()
+            .rng_seed(Some(seed))
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        session.shutdown().unwrap();
+        server.join()
+    }
+
+    #[test]
+    fn test_rng_seed() {
+        for kex in [
+            "curve25519-sha256",
+            "ecdh-sha2-nistp256",
+            "diffie-hellman-group14-sha256",
+        ] {
+            let first = seeded_handshake(kex, 42);
+            assert_eq!(first[0][0], common::msg::KEXINIT);
+            assert_eq!(first[1][0], common::msg::KEX_ECDH_INIT);
+            assert_eq!(first, seeded_handshake(kex, 42), "{kex}");
+
+            let other = seeded_handshake(kex, 43);
+            // the cookie
+            assert_ne!(first[0][1..17], other[0][1..17], "{kex}");
+            assert_ne!(first[1], other[1], "{kex}");
+        }
+    }
+
+    /// a seeded handshake in which the mock hashes the client KEXINIT patched by `patch`,
+    /// return whether the client accepts the exchange hash & the KEXINIT on the wire
+    fn handshake_with_hashed_kexinit(patch: fn(&mut Vec<u8>)) -> (bool, Vec<u8>) {
+        let server = MockServer::spawn(move |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            let on_wire = conn.client_kexinit.clone();
+            patch(&mut conn.client_kexinit);
+            conn.kex_reply();
+            // the client only sends NEWKEYS once the signature over H is verified
+            let newkeys = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                conn.recv_msg(common::msg::NEWKEYS)
+            }));
+            (newkeys.is_ok(), on_wire)
+        });
+
+        let result = common::builder().rng_seed(Some(42)).connect(server.addr);
+        let (accepted, on_wire) = server.join();
+        if !accepted {
+            let err = result.err().unwrap();
+            assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        }
+        (accepted, on_wire)
+    }
+
+    #[test]
+    fn test_exchange_hash_covers_exact_kexinit() {
+        let (accepted, on_wire) = handshake_with_hashed_kexinit(|_| ());
+        // the signature over H with the very bytes on the wire is verified,
+        // so the client hashes them as is
+        assert!(accepted);
+        assert_eq!(on_wire[0], common::msg::KEXINIT);
+        // byte-identical for the same seed, the cookie included
+        let (_, again) = handshake_with_hashed_kexinit(|_| ());
+        assert_eq!(on_wire, again);
+
+        // any difference, in the cookie or in the trailing fields, breaks the signature
+        let (accepted, _) = handshake_with_hashed_kexinit(|k| k[1] ^= 1);
+        assert!(!accepted, "the cookie is not hashed as sent");
+        let (accepted, _) = handshake_with_hashed_kexinit(|k| *k.last_mut().unwrap() ^= 1);
+        assert!(!accepted, "the reserved field is not hashed as sent");
+        let (accepted, _) = handshake_with_hashed_kexinit(|k| k.push(0));
+        assert!(!accepted, "the length of the payload is not hashed as sent");
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let builder = || {
+            common::builder()
+                .rng_seed(Some(42))
+                .del_enc_algorithms(Enc::Aes128Ctr)
+        };
+        let kexinit = builder().dry_run().unwrap();
+
+        // the very payload that a connect sends first
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.client_kexinit.clone()
+        });
+        // the mock stops after the key exchange
+        assert!(builder().connect(server.addr).is_err());
+        assert_eq!(kexinit, server.join());
+
+        let parsed = KexInit::parse(&kexinit);
+        assert!(has_name(&parsed.lists[0], "ext-info-c"));
+        assert!(!has_name(&parsed.lists[2], "aes128-ctr"));
+        assert!(has_name(&parsed.lists[2], "aes256-ctr"));
+        assert!(!parsed.first_kex_follows);
+        assert_eq!(parsed.reserved, 0);
+    }
+
+    #[test]
+    fn test_duplicated_algorithms() {
+        let config = common::ServerConfig {
+            host_key: "rsa-sha2-256,ssh-ed25519,rsa-sha2-256".to_owned(),
+            ciphers: "aes256-ctr,aes256-ctr".to_owned(),
+            macs: "hmac-sha2-256, hmac-sha2-256".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready_and_serve_exec(b"hello", 0);
+            conn.negotiated.clone()
+        });
+
+        let connector = common::builder().connect(server.addr).unwrap();
+        assert_eq!(
+            connector.offered_host_key_algorithms(),
+            ["rsa-sha2-256", "ssh-ed25519"]
+        );
+        let mut session = connector.run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        let negotiated = server.join();
+        assert_eq!(negotiated.c2s_cipher, "aes256-ctr");
+        assert_eq!(negotiated.s2c_mac, "hmac-sha2-256");
+        assert_eq!(negotiated.host_key, "rsa-sha2-256");
+        session.close();
+    }
+
+    #[test]
+    fn test_rsa_8192_host_key() {
+        for alg in ["rsa-sha2-512", "rsa-sha2-256"] {
+            let config = common::ServerConfig {
+                host_key: alg.to_owned(),
+                rsa_host_key: common::RSA_8192_HOST_KEY,
+                ..Default::default()
+            };
+            let server = MockServer::spawn_with(config, |conn| {
+                conn.ready_and_serve_exec(b"hello", 0);
+                conn.host_key_blob()
+            });
+
+            let mut session = common::builder().connect(server.addr).unwrap().run_local();
+            let exec = session.open_exec().unwrap();
+            assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+            // "ssh-rsa", e and n of 1024 bytes
+            assert!(server.join().len() > 1024);
+            session.close();
+        }
+    }
+
+    #[test]
+    fn test_dh_group16_sha512() {
+        let config = common::ServerConfig {
+            kex: "diffie-hellman-group16-sha512".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.handshake();
+            conn.accept_service();
+            conn.accept_auth();
+            let command = conn.serve_exec(b"hello", 0);
+            (
+                conn.negotiated.kex.clone(),
+                conn.session_id.clone(),
+                command,
+            )
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        let (kex, session_id, command) = server.join();
+        assert_eq!(kex, "diffie-hellman-group16-sha512");
+        // sha512
+        assert_eq!(session_id.len(), 64);
+        assert_eq!(command, "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_aes256_gcm() {
+        let config = common::ServerConfig {
+            ciphers: "aes256-gcm@openssh.com".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            let first = conn.serve_exec(b"hello", 0);
+            let second = conn.serve_exec(&b"x".repeat(1000), 0);
+            (conn.negotiated.clone(), first, second)
+        });
+
+        let connector = common::builder().connect(server.addr).unwrap();
+        assert!(connector.is_aead());
+        let keys = connector.kex_details().c_keys;
+        assert_eq!((keys.iv, keys.key, keys.mac_key), (12, 32, 0));
+        let mut session = connector.run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("yes x").unwrap(), b"x".repeat(1000));
+
+        let (negotiated, first, second) = server.join();
+        assert_eq!(negotiated.c2s_cipher, "aes256-gcm@openssh.com");
+        assert_eq!(negotiated.s2c_cipher, "aes256-gcm@openssh.com");
+        assert_eq!((first.as_str(), second.as_str()), ("echo hello", "yes x"));
+        session.close();
+    }
+
+    fn group1_config() -> common::ServerConfig {
+        common::ServerConfig {
+            kex: "diffie-hellman-group1-sha1".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dh_group1_sha1_disabled_by_default() {
+        let server = MockServer::spawn_with(group1_config(), |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            KexInit::parse(&conn.recv_msg(common::msg::KEXINIT)).lists
+        });
+
+        let err = match common::builder().connect(server.addr) {
+            Ok(_) => panic!("diffie-hellman-group1-sha1 should not be negotiated by default"),
+            Err(e) => e,
+        };
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+        let lists = server.join();
+        assert!(
+            !lists[0].contains("diffie-hellman-group1-sha1"),
+            "{}",
+            lists[0]
+        );
+    }
+
+    #[cfg(feature = "deprecated-dh-group1-sha1")]
+    #[test]
+    fn test_dh_group1_sha1_enabled() {
+        use ssh::algorithm::Kex;
+
+        let server = MockServer::spawn_with(group1_config(), |conn| {
+            conn.ready_and_serve_exec(b"hello", 0);
+            conn.negotiated.kex.clone()
+        });
+
+        let mut session = common::builder()
+            .add_kex_algorithms(Kex::DiffieHellmanGroup1Sha1)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        assert_eq!(server.join(), "diffie-hellman-group1-sha1");
+        session.close();
+    }
+
+    fn strict_config() -> common::ServerConfig {
+        common::ServerConfig {
+            kex: "curve25519-sha256,ext-info-s,kex-strict-s-v00@openssh.com".to_owned(),
+            extensions: vec![("server-sig-algs", "ssh-ed25519,rsa-sha2-512")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ext_info_and_strict_kex_markers() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let initial = KexInit::parse(&conn.client_kexinit).lists[0].clone();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.rekey();
+            let rekey = KexInit::parse(&conn.client_kexinit).lists[0].clone();
+            conn.finish_channel(&ch, 0);
+            (initial, rekey)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        exec.get_result().unwrap();
+
+        let (initial, rekey) = server.join();
+        // both markers, after the real algorithms
+        assert!(
+            initial.ends_with(",ext-info-c,kex-strict-c-v00@openssh.com"),
+            "{initial}"
+        );
+        // only in the initial key exchange
+        assert!(!has_name(&rekey, "ext-info-c"), "{rekey}");
+        assert!(!has_name(&rekey, "kex-strict-c-v00@openssh.com"), "{rekey}");
+        // no extension is sent by a server without them
+        assert!(session.server_extensions().is_empty());
+        session.close();
+    }
+
+    #[test]
+    fn test_ext_info_and_strict_kex_both_active() {
+        let server = MockServer::spawn_with(strict_config(), |conn| {
+            conn.ready();
+            let strict = conn.strict_kex;
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"before ");
+            // the sequence numbers restart after every NEWKEYS
+            conn.rekey();
+            conn.send_data(&ch, b"after");
+            conn.finish_channel(&ch, 0);
+            strict
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        assert_eq!(
+            session.server_extensions(),
+            vec![(
+                "server-sig-algs".to_owned(),
+                b"ssh-ed25519,rsa-sha2-512".to_vec()
+            )]
+        );
+        // the packets are only authenticated if the client resets its sequence numbers as well
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo").unwrap(), b"before after");
+        assert!(server.join());
+        session.close();
+    }
+
+    #[test]
+    fn test_ext_info_and_strict_kex_disabled() {
+        let server = MockServer::spawn_with(strict_config(), |conn| {
+            conn.ready();
+            let kex = KexInit::parse(&conn.client_kexinit).lists[0].clone();
+            let strict = conn.strict_kex;
+            conn.serve_exec(b"hello", 0);
+            (kex, strict)
+        });
+
+        let mut session = common::builder()
+            .ext_info(false)
+            .strict_kex(false)
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        assert!(session.server_extensions().is_empty());
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        let (kex, strict) = server.join();
+        assert!(!has_name(&kex, "ext-info-c"), "{kex}");
+        assert!(!has_name(&kex, "kex-strict-c-v00@openssh.com"), "{kex}");
+        assert!(!strict);
+        session.close();
+    }
+
+    #[test]
+    fn test_strict_kex_rejects_ignore() {
+        let server = MockServer::spawn_with(strict_config(), |conn| {
+            kex_until_reply(conn);
+            conn.send(Buf::new().u8(common::msg::IGNORE).str("injected"));
+            conn.send_newkeys();
+        });
+
+        let result = common::builder().connect(server.addr);
+        assert!(matches!(result, Err(SshError::KexError(_))));
+        server.join();
+    }
+
+    fn client_kexinit_count(conn: &common::ServerConn) -> usize {
+        conn.received
+            .iter()
+            .filter(|p| p[0] == common::msg::KEXINIT)
+            .count()
+    }
+
+    #[test]
+    fn test_rekey_by_client() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"before ");
+            // answer the client's KEXINIT
+            conn.recv_kexinit();
+            conn.send_kexinit();
+            conn.kex_reply();
+            conn.send_newkeys();
+            conn.recv_newkeys();
+            conn.send_data(&ch, b"after");
+            conn.finish_channel(&ch, 0);
+            client_kexinit_count(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        session.rekey().unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"before after");
+
+        assert_eq!(server.join(), 2);
+        session.close();
+    }
+
+    #[test]
+    fn test_simultaneous_rekey() {
+        let barrier = Arc::new(Barrier::new(2));
+        let server_barrier = barrier.clone();
+        let server = MockServer::spawn(move |conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"before ");
+            server_barrier.wait();
+            // send ours without waiting for the client's
+            conn.rekey();
+            conn.send_data(&ch, b"after");
+            conn.finish_channel(&ch, 0);
+            client_kexinit_count(conn)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        barrier.wait();
+        session.rekey().unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"before after");
+
+        // the initial one plus a single one for both initiations
+        assert_eq!(server.join(), 2);
+        session.close();
+    }
+
+    /// answer the client's KEXINIT between `before` and `after` on a channel
+    fn serve_rekey_by_client(conn: &mut common::ServerConn, before: &[u8]) -> usize {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        for chunk in before.chunks(16 * 1024) {
+            conn.send_data(&ch, chunk);
+        }
+        conn.recv_kexinit();
+        conn.send_kexinit();
+        conn.kex_reply();
+        conn.send_newkeys();
+        conn.recv_newkeys();
+        conn.send_data(&ch, b"after");
+        conn.finish_channel(&ch, 0);
+        client_kexinit_count(conn)
+    }
+
+    #[test]
+    fn test_rekey_limit() {
+        let before = vec![b'x'; 100 * 1024];
+        let data = before.clone();
+        let server = MockServer::spawn(move |conn| serve_rekey_by_client(conn, &data));
+
+        let mut session = common::builder()
+            .rekey_limit(Some(64 * 1024))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("cat big").unwrap();
+        assert_eq!(exec.get_result().unwrap(), [&before[..], b"after"].concat());
+
+        assert_eq!(server.join(), 2);
+        session.close();
+    }
+
+    #[test]
+    fn test_rekey_interval() {
+        let server = MockServer::spawn(|conn| serve_rekey_by_client(conn, b"before "));
+
+        let mut session = common::builder()
+            .rekey_limit(None)
+            .rekey_interval(Some(Duration::from_millis(300)))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"before after");
+
+        assert_eq!(server.join(), 2);
+        session.close();
+    }
+
+    /// what the server sends amid a re-exchange, each needs a reply
+    #[derive(Clone, Copy)]
+    enum Amid {
+        // adjusted for by the client
+        Data,
+        // lets the client send the rest of its data
+        WindowAdjust,
+    }
+
+    /// the shell has the window for 4 of its bytes, then `amid` is sent
+    /// between the client KEXINIT and ours, or right before ours if `by_server`
+    ///
+    /// return the messages received from the client
+    fn serve_rekey_amid(
+        conn: &mut common::ServerConn,
+        amid: Amid,
+        by_server: bool,
+        barrier: &Barrier,
+    ) -> Vec<u8> {
+        conn.ready();
+        let ch = conn.accept_channel_with_window(4);
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        conn.recv_msg(msg::CHANNEL_DATA);
+        let before = conn.received.len();
+        barrier.wait();
+
+        if !by_server {
+            conn.recv_kexinit();
+        }
+        match amid {
+            Amid::Data => conn.send_data(&ch, b"amid"),
+            Amid::WindowAdjust => conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_WINDOW_ADJUST)
+                    .u32(ch.client_id)
+                    .u32(100),
+            ),
+        }
+        conn.send_kexinit();
+        if by_server {
+            // the reply may go ahead of the client KEXINIT
+            let kexinit = loop {
+                let p = conn.recv_packet();
+                if p[0] == msg::KEXINIT {
+                    break p;
+                }
+            };
+            conn.take_kexinit(kexinit);
+        }
+        conn.kex_reply();
+        conn.send_newkeys();
+        conn.recv_newkeys();
+
+        let reply = match amid {
+            Amid::Data => msg::CHANNEL_WINDOW_ADJUST,
+            Amid::WindowAdjust => msg::CHANNEL_DATA,
+        };
+        while !conn.received[before..].iter().any(|p| p[0] == reply) {
+            conn.recv_packet();
+        }
+        conn.send_close(&ch);
+        while conn.recv_packet()[0] != msg::CHANNEL_CLOSE {}
+        conn.received.iter().map(|p| p[0]).collect()
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc4253#section-7.1>
+    ///
+    /// the messages of the client between its KEXINIT & NEWKEYS of the re-exchange
+    fn amid_rekey(received: &[u8]) -> &[u8] {
+        let kexinit = received.iter().rposition(|c| *c == msg::KEXINIT).unwrap();
+        let newkeys = kexinit
+            + received[kexinit..]
+                .iter()
+                .position(|c| *c == msg::NEWKEYS)
+                .unwrap();
+        &received[kexinit + 1..newkeys]
+    }
+
+    fn rekey_amid(amid: Amid, by_server: bool) -> Vec<u8> {
+        let barrier = Arc::new(Barrier::new(2));
+        let server_barrier = barrier.clone();
+        let server =
+            MockServer::spawn(move |conn| serve_rekey_amid(conn, amid, by_server, &server_barrier));
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        shell.write(b"held back").unwrap();
+        barrier.wait();
+        if !by_server {
+            session.rekey().unwrap();
+        }
+
+        let received = server.join();
+        assert_eq!(amid_rekey(&received), [msg::KEX_ECDH_INIT]);
+        drop(shell);
+        session.close();
+        received
+    }
+
+    #[test]
+    fn test_rekey_holds_back_window_adjust() {
+        let received = rekey_amid(Amid::Data, false);
+        // sent once the keys are renewed
+        let newkeys = received.iter().rposition(|c| *c == msg::NEWKEYS).unwrap();
+        assert!(received[newkeys..].contains(&msg::CHANNEL_WINDOW_ADJUST));
+    }
+
+    #[test]
+    fn test_rekey_holds_back_data() {
+        let received = rekey_amid(Amid::WindowAdjust, false);
+        let newkeys = received.iter().rposition(|c| *c == msg::NEWKEYS).unwrap();
+        assert!(received[newkeys..].contains(&msg::CHANNEL_DATA));
+    }
+
+    #[test]
+    fn test_server_rekey_amid_data() {
+        rekey_amid(Amid::Data, true);
+    }
+
+    #[test]
+    fn test_server_rekey_amid_window_adjust() {
+        rekey_amid(Amid::WindowAdjust, true);
+    }
+
+    /// <https://www.rfc-editor.org/rfc/rfc7748#section-6.1>
+    ///
+    /// Alice is the client and Bob the server
+    const ALICE_PRIVATE: &str = "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+    const ALICE_PUBLIC: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+    const BOB_PRIVATE: &str = "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb";
+    const BOB_PUBLIC: &str = "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f";
+    const SHARED_SECRET: &str = "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Q_C, Q_S, K & the session id seen by Bob
+    type BobSeen = (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>);
+
+    /// Bob serves an exec
+    fn spawn_bob() -> MockServer<BobSeen> {
+        use ring::agreement;
+
+        let config = common::ServerConfig {
+            kex: "curve25519-sha256".to_owned(),
+            host_key: "ssh-ed25519".to_owned(),
+            ciphers: "aes256-ctr".to_owned(),
+            macs: "hmac-sha2-256".to_owned(),
+            ..Default::default()
+        };
+        MockServer::spawn_with(config, |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            // the reply of Bob
+            let init = conn.recv_msg(msg::KEX_ECDH_INIT);
+            let q_c = Reader::new(&init[1..]).bytes();
+            let bob = unhex(BOB_PRIVATE);
+            #[allow(deprecated)]
+            let rng = ring::test::rand::FixedSliceRandom { bytes: &bob };
+            let private =
+                agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng).unwrap();
+            let q_s = private.compute_public_key().unwrap().as_ref().to_vec();
+            let k = agreement::agree_ephemeral(
+                private,
+                &agreement::UnparsedPublicKey::new(&agreement::X25519, &q_c),
+                |k| k.to_vec(),
+            )
+            .unwrap();
+            conn.finish_kex(&q_c, &q_s, &k);
+            conn.send_newkeys();
+            conn.send_ext_info();
+            conn.recv_newkeys();
+            conn.accept_service();
+            conn.accept_auth();
+            conn.serve_exec(b"ok", 0);
+            conn.wait_disconnect();
+            (q_c, q_s, k, conn.session_id.clone())
+        })
+    }
+
+    fn connect_alice(addr: std::net::SocketAddr) -> ssh::LocalSession<std::net::TcpStream> {
+        ssh::create_session_without_default()
+            .username(common::USERNAME)
+            .password(common::PASSWORD)
+            .rng_seed(Some(42))
+            .ephemeral_key(Some(&unhex(ALICE_PRIVATE)))
+            .add_kex_algorithms(Kex::Curve25519Sha256)
+            .add_pubkey_algorithms(PubKey::SshEd25519)
+            .add_enc_algorithms(Enc::Aes256Ctr)
+            .add_mac_algortihms(Mac::HmacSha2_256)
+            .add_compress_algorithms(Compress::None)
+            .connect(addr)
+            .unwrap()
+            .run_local()
+    }
+
+    #[test]
+    fn test_fixed_ephemeral_key() {
+        let server = spawn_bob();
+
+        let mut session = connect_alice(server.addr);
+        // the keys derived from the shared secret work both ways
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("true").unwrap(), b"ok");
+        session.close();
+
+        let (q_c, q_s, k, session_id) = server.join();
+        assert_eq!(q_c, unhex(ALICE_PUBLIC));
+        assert_eq!(q_s, unhex(BOB_PUBLIC));
+        assert_eq!(k, unhex(SHARED_SECRET));
+        // everything in the exchange hash is fixed by the seed, the keys & the mock
+        assert_eq!(
+            session_id,
+            unhex("61857f5904c7c7583a7d96ddfabd7f3dc41a91e24290e7fc4acbcddf3d517987")
+        );
+    }
+
+    #[cfg(feature = "dangerous-key-export")]
+    #[test]
+    fn test_export_session_keys() {
+        use sha2::{Digest, Sha256};
+
+        let server = spawn_bob();
+
+        let mut session = connect_alice(server.addr);
+        let keys = session.dangerous_session_keys().unwrap();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("true").unwrap(), b"ok");
+        session.close();
+
+        let (_, _, k, session_id) = server.join();
+        // HASH(K || H || X || session_id), K as mpint which needs no leading zero here,
+        // H is the session id in the initial key exchange
+        assert!(k[0] < 0x80);
+        let derive = |x: u8| {
+            let mut hash = Sha256::new();
+            hash.update((k.len() as u32).to_be_bytes());
+            hash.update(&k);
+            hash.update(&session_id);
+            hash.update([x]);
+            hash.update(&session_id);
+            hash.finalize().to_vec()
+        };
+        assert_eq!(keys.session_id, session_id);
+        // aes256-ctr & hmac-sha2-256 take the IVs of 16 bytes, the keys of 32 bytes
+        assert_eq!(keys.c_keys.iv, derive(b'A')[..16]);
+        assert_eq!(keys.s_keys.iv, derive(b'B')[..16]);
+        assert_eq!(keys.c_keys.key, derive(b'C'));
+        assert_eq!(keys.s_keys.key, derive(b'D'));
+        assert_eq!(keys.c_keys.mac_key, derive(b'E'));
+        assert_eq!(keys.s_keys.mac_key, derive(b'F'));
+    }
+
+    #[test]
+    fn test_fixed_ephemeral_key_unsupported() {
+        let config = common::ServerConfig {
+            kex: "diffie-hellman-group14-sha256".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            conn.wait_disconnect();
+        });
+
+        let err = common::builder()
+            .ephemeral_key(Some(&unhex(ALICE_PRIVATE)))
+            .add_kex_algorithms(Kex::DiffieHellmanGroup14Sha256)
+            .connect(server.addr)
+            .err()
+            .unwrap();
+        assert!(matches!(err, SshError::KexError(ref msg) if msg.contains("not supported")));
+        server.join();
+    }
+}
@@ -0,0 +1,297 @@
+mod common;
+
+mod tests {
+    use std::{
+        net::SocketAddr,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::common::{self, Buf, MockServer, ServerConfig};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use ssh::{algorithm::PubKey, HostKeyVerification, SshError, SshResult};
+    use ssh_key::{HashAlg, PublicKey};
+
+    fn ed25519_server() -> MockServer<()> {
+        let config = ServerConfig {
+            host_key: "ssh-ed25519".to_owned(),
+            ..Default::default()
+        };
+        MockServer::spawn_with(config, |conn| conn.ready())
+    }
+
+    fn public_key(blob: &[u8]) -> PublicKey {
+        PublicKey::from_bytes(blob).unwrap()
+    }
+
+    fn fingerprint(blob: &[u8]) -> String {
+        public_key(blob).fingerprint(HashAlg::Sha256).to_string()
+    }
+
+    /// the server host key
+    fn server_key() -> Vec<u8> {
+        common::host_key_blob("ssh-ed25519")
+    }
+
+    /// another ed25519 key
+    fn other_key() -> Vec<u8> {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        Buf::new()
+            .str("ssh-ed25519")
+            .bytes(pair.public_key().as_ref())
+            .into_inner()
+    }
+
+    fn known_hosts(test: &str, addr: SocketAddr, blob: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ssh-rs-known-hosts-{}-{}",
+            std::process::id(),
+            test
+        ));
+        let line = format!(
+            "[{}]:{} {}\n",
+            addr.ip(),
+            addr.port(),
+            public_key(blob).to_openssh().unwrap()
+        );
+        std::fs::write(&path, line).unwrap();
+        path
+    }
+
+    fn builder() -> ssh::SessionBuilder {
+        common::builder().add_pubkey_algorithms(PubKey::SshEd25519)
+    }
+
+    fn connect(builder: ssh::SessionBuilder, addr: SocketAddr) -> SshResult<()> {
+        builder.connect(addr).map(|s| s.run_local().close())
+    }
+
+    #[test]
+    fn test_host_key_known() {
+        let server = ed25519_server();
+        let path = known_hosts("known", server.addr, &server_key());
+
+        let builder = builder()
+            .known_hosts(&path)
+            .on_unknown_host_key(|_, _| panic!("the host is known"))
+            .on_host_key_changed(|_, _, _| panic!("the host key is not changed"));
+        connect(builder, server.addr).unwrap();
+        server.join();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_host_key_changed_callback() {
+        let server = ed25519_server();
+        let path = known_hosts("changed", server.addr, &other_key());
+
+        let changed = Arc::new(Mutex::new(vec![]));
+        let record = changed.clone();
+        let builder = builder()
+            .known_hosts(&path)
+            .on_unknown_host_key(|_, _| panic!("the host is known"))
+            .on_host_key_changed(move |host, old, new| {
+                record
+                    .lock()
+                    .unwrap()
+                    .push((host.to_owned(), old.to_owned(), new.to_owned()));
+                true
+            });
+        connect(builder, server.addr).unwrap();
+        let host = format!("[127.0.0.1]:{}", server.addr.port());
+        server.join();
+
+        assert_eq!(
+            *changed.lock().unwrap(),
+            vec![(host, fingerprint(&other_key()), fingerprint(&server_key()))]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_host_key_changed_rejected_by_default() {
+        let server = MockServer::spawn_with(
+            ServerConfig {
+                host_key: "ssh-ed25519".to_owned(),
+                ..Default::default()
+            },
+            |conn| {
+                conn.send_version();
+                conn.read_version();
+                conn.send_kexinit();
+                conn.recv_kexinit();
+                conn.kex_reply();
+            },
+        );
+        let path = known_hosts("rejected", server.addr, &other_key());
+
+        let err = connect(builder().known_hosts(&path), server.addr).unwrap_err();
+        match err {
+            SshError::HostKeyMismatch {
+                expected, actual, ..
+            } => {
+                assert_eq!(expected, fingerprint(&other_key()));
+                assert_eq!(actual, fingerprint(&server_key()));
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+        server.join();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_host_callback() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            conn.kex_reply();
+        });
+        // a key of another type is not a change
+        let path = known_hosts("unknown", server.addr, &other_key());
+
+        let unknown = Arc::new(Mutex::new(vec![]));
+        let record = unknown.clone();
+        let builder = builder()
+            .known_hosts(&path)
+            .on_unknown_host_key(move |host, fingerprint| {
+                record
+                    .lock()
+                    .unwrap()
+                    .push((host.to_owned(), fingerprint.to_owned()));
+                false
+            })
+            .on_host_key_changed(|_, _, _| panic!("the host is not known"));
+        let err = connect(builder, server.addr).unwrap_err();
+        assert!(matches!(err, SshError::UnknownHostKey { .. }), "{err:?}");
+        let host = format!("[127.0.0.1]:{}", server.addr.port());
+        server.join();
+
+        assert_eq!(
+            *unknown.lock().unwrap(),
+            vec![(host, fingerprint(&common::host_key_blob("rsa-sha2-512")))]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_strict_rejects_unknown_host() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            conn.kex_reply();
+        });
+        let path = known_hosts("strict", server.addr, &other_key());
+        std::fs::write(&path, "").unwrap();
+
+        let builder = builder()
+            .known_hosts(&path)
+            .host_key_verification(HostKeyVerification::Strict);
+        let err = connect(builder, server.addr).unwrap_err();
+        assert!(matches!(err, SshError::UnknownHostKey { .. }), "{err:?}");
+        server.join();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_accept_new_appends_host_key() {
+        let server = ed25519_server();
+        let path = known_hosts("accept-new", server.addr, &other_key());
+        // the existing line is not terminated
+        std::fs::write(&path, "# my hosts").unwrap();
+
+        let accept_new = builder()
+            .known_hosts(&path)
+            .known_host_name("example.com")
+            .host_key_verification(HostKeyVerification::AcceptNew);
+        connect(accept_new, server.addr).unwrap();
+        server.join();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            format!(
+                "# my hosts\nexample.com {}\n",
+                public_key(&server_key()).to_openssh().unwrap()
+            )
+        );
+
+        // known from now on
+        let server = ed25519_server();
+        let strict = builder()
+            .known_hosts(&path)
+            .known_host_name("example.com")
+            .host_key_verification(HostKeyVerification::Strict);
+        connect(strict, server.addr).unwrap();
+        server.join();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_host_key_known_by_name() {
+        let server = ed25519_server();
+        let path = known_hosts("by-name", server.addr, &server_key());
+        // recorded under the name, as OpenSSH does
+        let line = format!(
+            "[localhost]:{} {}\n",
+            server.addr.port(),
+            public_key(&server_key()).to_openssh().unwrap()
+        );
+        std::fs::write(&path, line).unwrap();
+
+        let builder = builder()
+            .known_hosts(&path)
+            .host_key_verification(HostKeyVerification::Strict);
+        builder
+            .connect_host("localhost", server.addr.port())
+            .map(|s| s.run_local().close())
+            .unwrap();
+        server.join();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_accept_new_rejects_changed_host_key() {
+        let server = MockServer::spawn_with(
+            ServerConfig {
+                host_key: "ssh-ed25519".to_owned(),
+                ..Default::default()
+            },
+            |conn| {
+                conn.send_version();
+                conn.read_version();
+                conn.send_kexinit();
+                conn.recv_kexinit();
+                conn.kex_reply();
+            },
+        );
+        let path = known_hosts("accept-new-changed", server.addr, &other_key());
+        let before = std::fs::read_to_string(&path).unwrap();
+
+        let builder = builder()
+            .known_hosts(&path)
+            .host_key_verification(HostKeyVerification::AcceptNew);
+        let err = connect(builder, server.addr).unwrap_err();
+        assert!(matches!(err, SshError::HostKeyMismatch { .. }), "{err:?}");
+        server.join();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_accept_any_host_key() {
+        let server = ed25519_server();
+        let path = known_hosts("accept-any", server.addr, &other_key());
+
+        let builder = builder()
+            .known_hosts(&path)
+            .host_key_verification(HostKeyVerification::AcceptAny)
+            .on_host_key_changed(|_, _, _| panic!("known_hosts is not read"));
+        connect(builder, server.addr).unwrap();
+        server.join();
+        std::fs::remove_file(path).unwrap();
+    }
+}
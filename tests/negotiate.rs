@@ -0,0 +1,44 @@
+mod tests {
+    use ssh::{algorithm::negotiate, SshError};
+
+    #[test]
+    fn test_negotiate_normal() {
+        let client = ["curve25519-sha256", "ecdh-sha2-nistp256"];
+        let server = ["ecdh-sha2-nistp256", "diffie-hellman-group14-sha1"];
+        assert_eq!(negotiate(&client, &server).unwrap(), "ecdh-sha2-nistp256");
+    }
+
+    #[test]
+    fn test_negotiate_no_intersection() {
+        let client = ["aes128-ctr", "aes256-ctr"];
+        let server = ["3des-cbc", "blowfish-cbc"];
+        assert!(matches!(
+            negotiate(&client, &server),
+            Err(SshError::KexError(_))
+        ));
+
+        // empty lists
+        assert!(negotiate(&client, &[] as &[&str]).is_err());
+        assert!(negotiate(&[], &server).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_client_preference() {
+        // the client order wins, no matter how the server orders them
+        let client = ["hmac-sha2-512", "hmac-sha2-256", "hmac-sha1"];
+        let server = ["hmac-sha1", "hmac-sha2-256", "hmac-sha2-512"];
+        assert_eq!(negotiate(&client, &server).unwrap(), "hmac-sha2-512");
+
+        let client = ["hmac-sha1", "hmac-sha2-512"];
+        assert_eq!(negotiate(&client, &server).unwrap(), "hmac-sha1");
+    }
+
+    #[test]
+    fn test_negotiate_exact_name() {
+        // no prefix or case-insensitive matching
+        let server = vec!["aes128-ctr".to_owned(), "ZLIB".to_owned()];
+        assert!(negotiate(&["aes128"], &server).is_err());
+        assert!(negotiate(&["zlib"], &server).is_err());
+        assert_eq!(negotiate(&["aes128-ctr"], &server).unwrap(), "aes128-ctr");
+    }
+}
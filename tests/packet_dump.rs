@@ -0,0 +1,75 @@
+mod common;
+
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::common::{self, MockServer};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// run a handshake with the trace level logs captured
+    fn handshake_logs(packet_dump: Option<usize>) -> String {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect()
+        });
+        tracing::subscriber::with_default(subscriber, || {
+            let session = common::builder()
+                .packet_dump(packet_dump)
+                .connect(server.addr)
+                .unwrap()
+                .run_local();
+            session.shutdown().unwrap();
+        });
+        server.join();
+
+        let logs = capture.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn test_packet_dump_enabled() {
+        let logs = handshake_logs(Some(64));
+        assert!(logs.contains(">>> send packet, seq 0"));
+        assert!(logs.contains("<<< recv packet, seq 0"));
+        // KEXINIT is the first packet of both sides
+        assert!(logs.contains("00000000: 14"));
+        // and it's longer than the limit
+        assert!(logs.contains("00000030:"));
+        assert!(!logs.contains("00000040:"));
+        assert!(logs.contains("..."));
+    }
+
+    #[test]
+    fn test_packet_dump_disabled() {
+        let logs = handshake_logs(None);
+        // the logs are captured
+        assert!(!logs.is_empty());
+        assert!(!logs.contains("send packet"));
+        assert!(!logs.contains("recv packet"));
+        assert!(!logs.contains("00000000:"));
+    }
+}
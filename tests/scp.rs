@@ -57,12 +57,12 @@ mod test {
 
         if peer {
             let _ = std::process::Command::new("chown")
-                .arg(&get_username())
+                .arg(get_username())
                 .arg(path)
                 .output();
             let _ = std::process::Command::new("sudo")
                 .arg("chown")
-                .arg(&get_username())
+                .arg(get_username())
                 .arg(path)
                 .output();
         }
@@ -79,13 +79,13 @@ mod test {
 
         if peer {
             let _ = std::process::Command::new("chown")
-                .arg(&get_username())
+                .arg(get_username())
                 .arg("-R")
                 .arg(path)
                 .output();
             let _ = std::process::Command::new("sudo")
                 .arg("chown")
-                .arg(&get_username())
+                .arg(get_username())
                 .arg("-R")
                 .arg(path)
                 .output();
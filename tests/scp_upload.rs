@@ -0,0 +1,187 @@
+#[cfg(feature = "scp")]
+mod common;
+
+#[cfg(feature = "scp")]
+mod tests {
+    use crate::common::{self, msg, Buf, MockChannel, MockServer, Reader, ServerConn};
+    use std::{
+        fs::File,
+        path::{Path, PathBuf},
+        thread,
+        time::Duration,
+    };
+
+    const WINDOW: u32 = 128 * 1024;
+    const FILE_SIZE: u64 = 4 * 1024 * 1024;
+    // at most one chunk read ahead of the window
+    const MAX_READ_AHEAD: u64 = 32 * 1024;
+
+    /// a scp sink which only gives `WINDOW` bytes at a time
+    struct Sink {
+        ch: MockChannel,
+        window: u32,
+        buf: Vec<u8>,
+        packets: usize,
+        max_packet: usize,
+    }
+
+    impl Sink {
+        fn recv(&mut self, conn: &mut ServerConn) {
+            let p = conn.recv_msg(msg::CHANNEL_DATA);
+            let mut r = Reader::new(&p[1..]);
+            assert_eq!(r.u32(), self.ch.server_id);
+            let data = r.bytes();
+            assert!(data.len() as u32 <= self.window, "the window is exceeded");
+            self.window -= data.len() as u32;
+            self.packets += 1;
+            self.max_packet = self.max_packet.max(data.len());
+            self.buf.extend(data);
+        }
+
+        fn line(&mut self, conn: &mut ServerConn) -> String {
+            while !self.buf.contains(&b'\n') {
+                self.recv(conn);
+            }
+            let end = self.buf.iter().position(|b| *b == b'\n').unwrap();
+            let line = self.buf.drain(..=end).collect();
+            String::from_utf8(line).unwrap()
+        }
+
+        fn ack(&mut self, conn: &mut ServerConn) {
+            conn.send_data(&self.ch, &[0]);
+        }
+
+        fn adjust(&mut self, conn: &mut ServerConn) {
+            let to_add = WINDOW - self.window;
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_WINDOW_ADJUST)
+                    .u32(self.ch.client_id)
+                    .u32(to_add),
+            );
+            self.window = WINDOW;
+        }
+    }
+
+    /// the read offset of `path` opened by this process
+    fn read_offset(path: &Path) -> u64 {
+        for fd in std::fs::read_dir("/proc/self/fd").unwrap().flatten() {
+            if std::fs::read_link(fd.path()).ok().as_deref() != Some(path) {
+                continue;
+            }
+            let info = std::fs::read_to_string(Path::new("/proc/self/fdinfo").join(fd.file_name()))
+                .unwrap();
+            let pos = info.lines().find_map(|l| l.strip_prefix("pos:")).unwrap();
+            return pos.trim().parse().unwrap();
+        }
+        panic!("{path:?} is not opened");
+    }
+
+    /// receive the upload of a file of `FILE_SIZE`
+    ///
+    /// return (received bytes, data packets, max packet, max read ahead)
+    fn serve_upload(conn: &mut ServerConn, path: PathBuf) -> (u64, usize, usize, u64) {
+        conn.ready();
+        let ch = conn.accept_channel_with_window(WINDOW);
+        let req = conn.accept_channel_request(&ch);
+        assert!(Reader::new(&req.data).string().starts_with("scp -t"));
+        let mut sink = Sink {
+            ch,
+            window: WINDOW,
+            buf: vec![],
+            packets: 0,
+            max_packet: 0,
+        };
+        sink.ack(conn);
+        assert!(sink.line(conn).starts_with('T'));
+        sink.ack(conn);
+        let header = sink.line(conn);
+        assert!(
+            header.starts_with(&format!("C0664 {FILE_SIZE} ")),
+            "{header}"
+        );
+        sink.ack(conn);
+
+        let (packets, mut read_ahead) = (sink.packets, 0);
+        let mut received = 0;
+        while received < FILE_SIZE {
+            if sink.window == 0 {
+                // let the client run ahead as far as it would
+                if read_ahead == 0 || received < 4 * WINDOW as u64 {
+                    thread::sleep(Duration::from_millis(100));
+                    read_ahead = read_ahead.max(read_offset(&path) - received);
+                }
+                sink.adjust(conn);
+            }
+            sink.recv(conn);
+            received += sink.buf.len() as u64;
+            sink.buf.clear();
+        }
+        // the trailing END
+        sink.adjust(conn);
+        while sink.buf.is_empty() {
+            sink.recv(conn);
+        }
+        assert_eq!(sink.buf, [0]);
+        sink.ack(conn);
+
+        conn.recv_msg(msg::CHANNEL_CLOSE);
+        conn.send_close(&sink.ch);
+        (
+            received,
+            sink.packets - packets - 1,
+            sink.max_packet,
+            read_ahead,
+        )
+    }
+
+    fn sparse_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ssh-rs-{}-{name}", std::process::id()));
+        File::create(&path).unwrap().set_len(FILE_SIZE).unwrap();
+        path
+    }
+
+    fn check(stats: (u64, usize, usize, u64)) {
+        let (received, packets, max_packet, read_ahead) = stats;
+        assert_eq!(received, FILE_SIZE);
+        assert!(max_packet <= 32768, "{max_packet}");
+        assert!(packets as u64 >= FILE_SIZE / 32768, "{packets}");
+        assert!(
+            read_ahead <= MAX_READ_AHEAD,
+            "{read_ahead} bytes read ahead"
+        );
+    }
+
+    #[test]
+    fn test_upload_streams_local() {
+        let path = sparse_file("local");
+        let server_path = path.clone();
+        let server = MockServer::spawn(move |conn| serve_upload(conn, server_path));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let scp = session.open_scp().unwrap();
+        scp.upload(path.to_str().unwrap(), "/tmp/remote").unwrap();
+
+        check(server.join());
+        std::fs::remove_file(path).unwrap();
+        session.close();
+    }
+
+    #[test]
+    fn test_upload_streams_backend() {
+        let path = sparse_file("backend");
+        let server_path = path.clone();
+        let server = MockServer::spawn(move |conn| serve_upload(conn, server_path));
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let scp = session.open_scp().unwrap();
+        scp.upload(path.to_str().unwrap(), "/tmp/remote").unwrap();
+
+        check(server.join());
+        std::fs::remove_file(path).unwrap();
+        session.close();
+    }
+}
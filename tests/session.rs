@@ -0,0 +1,785 @@
+mod common;
+
+mod tests {
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::{AuthMethod, DisconnectReason, SshError};
+
+    #[test]
+    fn test_shutdown_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect()
+        });
+
+        let session = common::builder().connect(server.addr).unwrap().run_local();
+        session.shutdown().unwrap();
+
+        let (code, _) = server.join().expect("no disconnect message received");
+        assert_eq!(code, 11);
+    }
+
+    #[test]
+    fn test_shutdown_unblocks_other_threads() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            let req = conn.accept_channel_request(&ch);
+            assert_eq!(req.name, "exec");
+            conn.send_data(&ch, b"partial");
+            // never finish the command
+            conn.wait_disconnect()
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("sleep 1000").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            let output = exec.get_result();
+            tx.send(()).unwrap();
+            output
+        });
+
+        // the reader is blocked as the command never ends
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+        session.shutdown().unwrap();
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("the reader is still blocked after shutdown");
+        assert_eq!(reader.join().unwrap().unwrap(), b"partial");
+
+        let (code, _) = server.join().expect("no disconnect message received");
+        assert_eq!(code, 11);
+    }
+
+    #[test]
+    fn test_disconnect_with_reason() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect()
+        });
+
+        let session = common::builder().connect(server.addr).unwrap().run_local();
+        session
+            .disconnect(DisconnectReason::TooManyConnections, "bye from local")
+            .unwrap();
+
+        let (code, description) = server.join().expect("no disconnect message received");
+        assert_eq!(code, 12);
+        assert_eq!(description, "bye from local");
+    }
+
+    #[test]
+    fn test_disconnect_with_reason_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect()
+        });
+
+        let session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        session
+            .disconnect(DisconnectReason::AuthCancelledByUser, "bye from backend")
+            .unwrap();
+
+        let (code, description) = server.join().expect("no disconnect message received");
+        assert_eq!(code, DisconnectReason::AuthCancelledByUser.code());
+        assert_eq!(description, "bye from backend");
+    }
+
+    #[test]
+    fn test_connect_and_auth() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"hello", 0));
+
+        let mut session = ssh::connect_and_auth(
+            server.addr,
+            common::USERNAME,
+            AuthMethod::Password(common::PASSWORD.to_owned()),
+        )
+        .unwrap();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_connect_and_auth_bad_key() {
+        let result = ssh::connect_and_auth(
+            "127.0.0.1:22",
+            common::USERNAME,
+            AuthMethod::PrivateKey("not a key".to_owned()),
+        );
+        assert!(matches!(result, Err(ssh::SshError::SshPubKeyError(_))));
+    }
+
+    #[test]
+    fn test_keepalive_only_when_idle() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            // busy for a while
+            for _ in 0..12 {
+                conn.send_data(&ch, b"tick");
+                thread::sleep(Duration::from_millis(50));
+            }
+            let busy_end = Instant::now();
+
+            // then idle
+            let p = conn.recv_msg(msg::GLOBAL_REQUEST);
+            let idle = busy_end.elapsed();
+            let mut r = Reader::new(&p[1..]);
+            let request = (r.string(), r.bool());
+            conn.send(Buf::new().u8(msg::REQUEST_FAILURE));
+            conn.finish_channel(&ch, 0);
+            (request, idle)
+        });
+
+        let mut session = common::builder()
+            .keepalive_idle(Some(Duration::from_millis(200)))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("tail -f log").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"tick".repeat(12));
+
+        let (request, idle) = server.join();
+        assert_eq!(request, ("keepalive@openssh.com".to_owned(), true));
+        // no keepalive when busy
+        assert!(idle >= Duration::from_millis(150), "{idle:?}");
+        session.close();
+    }
+
+    /// answer the first `answered` keepalive probes,
+    /// return the number of probes received until the client is gone
+    fn serve_keepalive(conn: &mut common::ServerConn, answered: usize) -> usize {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        for _ in 0..answered {
+            conn.recv_msg(msg::GLOBAL_REQUEST);
+            conn.send(Buf::new().u8(msg::REQUEST_SUCCESS));
+        }
+        // the others are left unanswered
+        conn.wait_disconnect();
+        conn.received
+            .iter()
+            .filter(|p| p[0] == msg::GLOBAL_REQUEST)
+            .count()
+    }
+
+    #[test]
+    fn test_keepalive_max_missed() {
+        let server = MockServer::spawn(|conn| serve_keepalive(conn, 3));
+
+        let mut session = common::builder()
+            .keepalive_idle(Some(Duration::from_millis(100)))
+            .keepalive_max_missed(Some(2))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("tail -f log").unwrap();
+        // the backend gives up the connection
+        assert!(exec.get_result().is_err());
+
+        // the answered ones are not counted
+        assert_eq!(server.join(), 5);
+        drop(exec);
+        session.close();
+    }
+
+    #[test]
+    fn test_post_auth_hook() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready_and_serve_exec(b"hello", 0);
+            (conn.session_id.clone(), conn.negotiated.c2s_cipher.clone())
+        });
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let hook_calls = calls.clone();
+        let mut session = common::builder()
+            .post_auth(move |session_id, algs| {
+                hook_calls
+                    .lock()
+                    .unwrap()
+                    .push((session_id.to_vec(), algs.clone()));
+            })
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        // fired by the connect, before any channel
+        assert_eq!(calls.lock().unwrap().len(), 1);
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        let (session_id, cipher) = server.join();
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        let (hooked_id, algs) = &calls[0];
+        assert!(!session_id.is_empty());
+        assert_eq!(hooked_id, &session_id);
+        assert_eq!(algs.c_encryption, cipher);
+        assert_eq!(algs.key_exchange, "curve25519-sha256");
+        session.close();
+    }
+
+    fn names(negotiated: &common::Negotiated) -> ssh::NegotiatedAlgorithms {
+        ssh::NegotiatedAlgorithms {
+            key_exchange: negotiated.kex.clone(),
+            host_key: negotiated.host_key.clone(),
+            c_encryption: negotiated.c2s_cipher.clone(),
+            s_encryption: negotiated.s2c_cipher.clone(),
+            c_mac: negotiated.c2s_mac.clone(),
+            s_mac: negotiated.s2c_mac.clone(),
+            c_compression: negotiated.c2s_compression.clone(),
+            s_compression: negotiated.s2c_compression.clone(),
+        }
+    }
+
+    #[test]
+    fn test_negotiated_algorithms_local() {
+        let config = common::ServerConfig {
+            kex: "ecdh-sha2-nistp256".to_owned(),
+            ciphers: "aes256-ctr".to_owned(),
+            macs: "hmac-sha2-512".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::spawn_with(config, |conn| {
+            conn.ready();
+            conn.wait_disconnect();
+            conn.negotiated.clone()
+        });
+
+        let session = common::builder().connect(server.addr).unwrap().run_local();
+        let algs = session.negotiated_algorithms();
+        session.close();
+
+        assert_eq!(algs, names(&server.join()));
+        assert_eq!(algs.key_exchange, "ecdh-sha2-nistp256");
+        assert_eq!(algs.c_encryption, "aes256-ctr");
+        assert_eq!(algs.s_mac, "hmac-sha2-512");
+        assert_eq!(algs.c_compression, "none");
+    }
+
+    #[test]
+    fn test_negotiated_algorithms_after_rekey() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let initial = conn.negotiated.clone();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.config.ciphers = "aes128-ctr".to_owned();
+            conn.rekey();
+            conn.send_data(&ch, b"rekeyed");
+            conn.finish_channel(&ch, 0);
+            conn.wait_disconnect();
+            (initial, conn.negotiated.clone())
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let initial = session.negotiated_algorithms();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"rekeyed");
+        let rekeyed = session.negotiated_algorithms();
+        drop(exec);
+        session.close();
+
+        let (server_initial, server_rekeyed) = server.join();
+        assert_eq!(initial, names(&server_initial));
+        assert_eq!(initial.c_encryption, "chacha20-poly1305@openssh.com");
+        assert_eq!(rekeyed, names(&server_rekeyed));
+        assert_eq!(rekeyed.c_encryption, "aes128-ctr");
+    }
+
+    #[test]
+    fn test_channel_numbers() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let mut channels = vec![];
+            for server_id in [30, 10, 20] {
+                // a stray confirmation must not be taken for a pending channel
+                conn.send(
+                    Buf::new()
+                        .u8(msg::CHANNEL_OPEN_CONFIRMATION)
+                        .u32(99)
+                        .u32(5)
+                        .u32(1024)
+                        .u32(32768),
+                );
+                let ch = conn.accept_channel_numbered(1024 * 1024, Some(server_id));
+                let req = conn.accept_channel_request(&ch);
+                assert_eq!(req.recipient, ch.server_id);
+                channels.push(ch);
+            }
+            for ch in &channels {
+                conn.send_data(ch, format!("to {}", ch.client_id).as_bytes());
+            }
+            for ch in channels.iter().rev() {
+                conn.finish_channel(ch, 0);
+            }
+            channels.iter().map(|ch| ch.client_id).collect::<Vec<_>>()
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut execs = vec![];
+        for _ in 0..3 {
+            let mut exec = session.open_exec().unwrap();
+            exec.send_command("true").unwrap();
+            execs.push(exec);
+        }
+        let outputs = execs
+            .into_iter()
+            .map(|mut exec| exec.get_result().unwrap())
+            .collect::<Vec<_>>();
+
+        let client_ids = server.join();
+        assert_eq!(client_ids, [0, 1, 2]);
+        assert_eq!(outputs, [b"to 0", b"to 1", b"to 2"]);
+        session.close();
+    }
+
+    #[test]
+    fn test_unknown_channel_messages_backend() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            // the messages to a channel that is gone or never was
+            for p in [
+                Buf::new().u8(msg::CHANNEL_DATA).u32(77).str("stale"),
+                Buf::new()
+                    .u8(msg::CHANNEL_EXTENDED_DATA)
+                    .u32(77)
+                    .u32(1)
+                    .str("stale"),
+                Buf::new()
+                    .u8(msg::CHANNEL_REQUEST)
+                    .u32(77)
+                    .str("exit-status")
+                    .bool(false)
+                    .u32(1),
+                Buf::new().u8(msg::CHANNEL_SUCCESS).u32(77),
+                Buf::new().u8(msg::CHANNEL_FAILURE).u32(77),
+                Buf::new().u8(msg::CHANNEL_CLOSE).u32(77),
+            ] {
+                conn.send(p);
+            }
+            conn.serve_exec(b"still alive", 0)
+        });
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("true").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"still alive");
+
+        assert_eq!(server.join(), "true");
+        session.close();
+    }
+
+    #[test]
+    fn test_channel_number_mismatch_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.recv_msg(msg::CHANNEL_OPEN);
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_OPEN_CONFIRMATION)
+                    .u32(99)
+                    .u32(5)
+                    .u32(1024)
+                    .u32(32768),
+            );
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let err = session.open_exec().err().unwrap();
+        assert!(matches!(err, ssh::SshError::ProtocolError(_)), "{err:?}");
+        server.join();
+    }
+
+    #[test]
+    fn test_read_into() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"one");
+            conn.send_data(&ch, b"two");
+            conn.send_close(&ch);
+            conn.recv_msg(msg::CHANNEL_CLOSE);
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        let mut buf = b"prompt: ".to_vec();
+        let mut appended = 0;
+        while appended < 6 {
+            appended += shell.read_into(&mut buf).unwrap();
+        }
+        assert_eq!(buf, b"prompt: onetwo");
+        // nothing more once closed
+        assert_eq!(shell.read_into(&mut buf).unwrap(), 0);
+        assert_eq!(buf, b"prompt: onetwo");
+
+        server.join();
+        session.close();
+    }
+
+    /// the exec exits with 7 only `delay` after the client closes it,
+    /// then another exec is served
+    fn serve_late_close(conn: &mut common::ServerConn, delay: Duration) -> String {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.recv_msg(msg::CHANNEL_CLOSE);
+        thread::sleep(delay);
+        conn.send_exit_status(&ch, 7);
+        conn.send_close(&ch);
+        conn.serve_exec(b"after", 0)
+    }
+
+    #[test]
+    fn test_close_with_timeout_local() {
+        let server = MockServer::spawn(|conn| serve_late_close(conn, Duration::ZERO));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("sleep 1").unwrap();
+        exec.close_with_timeout(Duration::from_secs(5)).unwrap();
+        assert!(exec.is_remote_closed());
+        assert_eq!(exec.exit_status(), Some(7));
+
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo after").unwrap(), b"after");
+        assert_eq!(server.join(), "echo after");
+        session.close();
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        // the server never sends its KEXINIT
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.wait_disconnect();
+        });
+
+        let start = Instant::now();
+        let err = common::builder()
+            .timeout(Some(Duration::from_millis(200)))
+            .connect(server.addr)
+            .err();
+        assert!(matches!(err, Some(SshError::TimeoutError)), "{err:?}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        server.join();
+    }
+
+    #[test]
+    fn test_connect_timeout_no_address() {
+        let addrs: &[std::net::SocketAddr] = &[];
+        let err = common::builder()
+            .connect_with_timeout(addrs, Some(Duration::from_millis(200)))
+            .err();
+        assert!(matches!(err, Some(SshError::IoError(_))), "{err:?}");
+    }
+
+    #[test]
+    fn test_set_timeout_local() {
+        // the exec is never answered
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.accept_channel();
+            conn.recv_channel_request();
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        session.set_timeout(Some(Duration::from_millis(200)));
+        let exec = session.open_exec().unwrap();
+        let start = Instant::now();
+        let err = exec.send_command("sleep 100");
+        assert!(matches!(err, Err(SshError::TimeoutError)), "{err:?}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(session);
+        server.join();
+    }
+
+    #[test]
+    fn test_close_timed_out_local() {
+        let server = MockServer::spawn(|conn| serve_late_close(conn, Duration::from_millis(500)));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("sleep 1").unwrap();
+        let start = Instant::now();
+        let err = exec.close_with_timeout(Duration::from_millis(100));
+        assert!(matches!(err, Err(SshError::TimeoutError)), "{err:?}");
+        assert!(start.elapsed() < Duration::from_millis(500));
+        drop(exec);
+
+        // the late close does not confuse the session
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo after").unwrap(), b"after");
+        assert_eq!(server.join(), "echo after");
+        session.close();
+    }
+
+    #[test]
+    fn test_close_nowait_local() {
+        let server = MockServer::spawn(|conn| serve_late_close(conn, Duration::from_millis(500)));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut exec = session.open_exec().unwrap();
+        exec.exec_command("sleep 1").unwrap();
+        let start = Instant::now();
+        exec.close_nowait().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(!exec.is_remote_closed());
+        drop(exec);
+
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo after").unwrap(), b"after");
+        assert_eq!(server.join(), "echo after");
+        session.close();
+    }
+
+    #[test]
+    fn test_close_with_timeout_backend() {
+        let server = MockServer::spawn(|conn| serve_late_close(conn, Duration::ZERO));
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("sleep 1").unwrap();
+        exec.close_with_timeout(Duration::from_secs(5)).unwrap();
+        assert!(exec.is_remote_closed());
+        assert_eq!(exec.exit_status(), Some(7));
+
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("echo after").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"after");
+        assert_eq!(server.join(), "echo after");
+        session.close();
+    }
+
+    #[test]
+    fn test_close_timed_out_backend() {
+        let server = MockServer::spawn(|conn| serve_late_close(conn, Duration::from_millis(500)));
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("sleep 1").unwrap();
+        let start = Instant::now();
+        let err = exec.close_with_timeout(Duration::from_millis(100));
+        assert!(matches!(err, Err(SshError::TimeoutError)), "{err:?}");
+        assert!(start.elapsed() < Duration::from_millis(500));
+        exec.close_nowait().unwrap();
+        drop(exec);
+
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("echo after").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"after");
+        assert_eq!(server.join(), "echo after");
+        session.close();
+    }
+
+    /// the shell prints a line then exits by itself
+    fn serve_exiting_shell(conn: &mut common::ServerConn) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        conn.send_data(&ch, b"bye\n");
+        conn.finish_channel(&ch, 0);
+    }
+
+    #[test]
+    fn test_remote_close_local() {
+        let server = MockServer::spawn(serve_exiting_shell);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        let (tx, rx) = mpsc::channel();
+        shell.on_remote_close(move || tx.send(()).unwrap());
+        assert!(!shell.is_remote_closed());
+
+        let mut output = vec![];
+        while !shell.is_remote_closed() {
+            output.extend(shell.read().unwrap());
+        }
+        assert_eq!(output, b"bye\n");
+        rx.try_recv().expect("the callback is not called");
+        assert!(rx.try_recv().is_err());
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_remote_close_backend() {
+        let server = MockServer::spawn(serve_exiting_shell);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        let (tx, rx) = mpsc::channel();
+        shell.on_remote_close(move || tx.send(()).unwrap());
+
+        // known without reading anything
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("the callback is not called");
+        assert!(shell.is_remote_closed());
+        // the data before the close is still there
+        assert_eq!(shell.read().unwrap(), b"bye\n");
+
+        // a callback set after the close is called at once
+        let (tx, rx) = mpsc::channel();
+        shell.on_remote_close(move || tx.send(()).unwrap());
+        rx.try_recv().expect("the late callback is not called");
+
+        server.join();
+        session.close();
+    }
+
+    /// the shell writes to stderr then stdout, and exits once it reads anything
+    fn serve_shell_with_stderr(conn: &mut common::ServerConn) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        conn.send_stderr(&ch, b"oops\n");
+        conn.send_data(&ch, b"$ ");
+        conn.recv_msg(msg::CHANNEL_DATA);
+        conn.finish_channel(&ch, 0);
+    }
+
+    /// poll `read_stderr` until something is there
+    fn poll_stderr(mut read_stderr: impl FnMut() -> Vec<u8>) -> Vec<u8> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let stderr = read_stderr();
+            if !stderr.is_empty() || Instant::now() > deadline {
+                return stderr;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_shell_read_stderr_local() {
+        let server = MockServer::spawn(serve_shell_with_stderr);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(poll_stderr(|| shell.read_stderr().unwrap()), b"oops\n");
+        // the stdout is not lost
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        shell.write(b"exit\n").unwrap();
+        while !shell.is_remote_closed() {
+            shell.read().unwrap();
+        }
+
+        server.join();
+        session.close();
+    }
+
+    #[test]
+    fn test_shell_read_stderr_backend() {
+        let server = MockServer::spawn(serve_shell_with_stderr);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(poll_stderr(|| shell.read_stderr().unwrap()), b"oops\n");
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        shell.write(b"exit\n").unwrap();
+
+        server.join();
+        session.close();
+    }
+
+    /// an empty data packet before the prompt,
+    /// return the windows adjusted by the client
+    fn serve_shell_with_empty_data(conn: &mut common::ServerConn) -> Vec<u32> {
+        conn.ready();
+        let ch = conn.accept_channel();
+        conn.accept_channel_request(&ch);
+        conn.accept_channel_request(&ch);
+        conn.send_data(&ch, b"");
+        conn.send_data(&ch, b"$ ");
+        conn.recv_msg(msg::CHANNEL_DATA);
+        conn.finish_channel(&ch, 0);
+        conn.received
+            .iter()
+            .filter(|p| p[0] == msg::CHANNEL_WINDOW_ADJUST)
+            .map(|p| u32::from_be_bytes(p[5..9].try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_shell_empty_data_local() {
+        let server = MockServer::spawn(serve_shell_with_empty_data);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        // neither the end of the data nor an error
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        shell.write(b"exit\n").unwrap();
+        while !shell.is_remote_closed() {
+            shell.read().unwrap();
+        }
+
+        assert!(!server.join().contains(&0));
+        session.close();
+    }
+
+    #[test]
+    fn test_shell_empty_data_backend() {
+        let server = MockServer::spawn(serve_shell_with_empty_data);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        shell.write(b"exit\n").unwrap();
+
+        assert!(!server.join().contains(&0));
+        session.close();
+    }
+}
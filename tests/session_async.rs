@@ -0,0 +1,137 @@
+mod common;
+
+#[cfg(feature = "async")]
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_async_exec() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"up 1 day", 0));
+
+        let session = common::builder().connect_async(server.addr).await.unwrap();
+        let output = session
+            .open_exec()
+            .await
+            .unwrap()
+            .send_command("uptime")
+            .await
+            .unwrap();
+        assert_eq!(output, b"up 1 day");
+        session.close().await;
+
+        assert_eq!(server.join(), "uptime");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_async_exec_wait() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"", 3));
+
+        let session = common::builder().connect_async(server.addr).await.unwrap();
+        let mut exec = session.open_exec().await.unwrap();
+        exec.exec_command("false").await.unwrap();
+        assert_eq!(exec.wait().await.unwrap().exit_status, Some(3));
+        session.close().await;
+
+        server.join();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_async_shell() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            assert_eq!(conn.accept_channel_request(&ch).name, "pty-req");
+            assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+            let p = conn.recv_msg(msg::CHANNEL_DATA);
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            assert_eq!(r.bytes(), b"ls\n");
+            conn.send_data(&ch, b"a b c\n");
+            conn.wait_disconnect();
+        });
+
+        let session = common::builder().connect_async(server.addr).await.unwrap();
+        let mut shell = session.open_shell().await.unwrap();
+        assert_eq!(shell.try_read().unwrap(), None);
+        // a cancelled read leaves the shell usable
+        let read = tokio::time::timeout(Duration::from_millis(50), shell.read()).await;
+        assert!(read.is_err());
+        shell.write(b"ls\n").unwrap();
+        assert_eq!(shell.read().await.unwrap(), b"a b c\n");
+        drop(shell);
+        session.close().await;
+
+        server.join();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_async_open_dropped() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let p = conn.recv_msg(msg::CHANNEL_OPEN);
+            let mut r = Reader::new(&p[1..]);
+            r.string();
+            let client_id = r.u32();
+            // confirm it after the client has given up
+            std::thread::sleep(Duration::from_millis(200));
+            conn.send(
+                Buf::new()
+                    .u8(msg::CHANNEL_OPEN_CONFIRMATION)
+                    .u32(client_id)
+                    .u32(7)
+                    .u32(2 * 1024 * 1024)
+                    .u32(32768),
+            );
+            // the channel nobody takes is closed by the client
+            let p = conn.recv_msg(msg::CHANNEL_CLOSE);
+            assert_eq!(Reader::new(&p[1..]).u32(), 7);
+            conn.send(Buf::new().u8(msg::CHANNEL_CLOSE).u32(client_id));
+            conn.serve_exec(b"ok", 0)
+        });
+
+        let session = common::builder().connect_async(server.addr).await.unwrap();
+        let open = tokio::time::timeout(Duration::from_millis(50), session.open_exec()).await;
+        assert!(open.is_err());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let output = session
+            .open_exec()
+            .await
+            .unwrap()
+            .send_command("echo ok")
+            .await
+            .unwrap();
+        assert_eq!(output, b"ok");
+        session.close().await;
+
+        assert_eq!(server.join(), "echo ok");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_async_server_rekey() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            let req = conn.accept_channel_request(&ch);
+            assert_eq!(req.name, "exec");
+            conn.send_data(&ch, b"before ");
+            conn.rekey();
+            conn.send_data(&ch, b"after");
+            conn.finish_channel(&ch, 0);
+            conn.wait_disconnect();
+        });
+
+        let session = common::builder().connect_async(server.addr).await.unwrap();
+        let output = session
+            .open_exec()
+            .await
+            .unwrap()
+            .send_command("cat")
+            .await
+            .unwrap();
+        assert_eq!(output, b"before after");
+        session.close().await;
+
+        server.join();
+    }
+}
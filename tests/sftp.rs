@@ -0,0 +1,569 @@
+mod common;
+
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        time::{Duration, Instant},
+    };
+
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::{
+        sftp::{self, OpenFlags},
+        SshError,
+    };
+
+    const FXP_INIT: u8 = 1;
+    const FXP_VERSION: u8 = 2;
+    const FXP_OPEN: u8 = 3;
+    const FXP_CLOSE: u8 = 4;
+    const FXP_READ: u8 = 5;
+    const FXP_WRITE: u8 = 6;
+    const FXP_OPENDIR: u8 = 11;
+    const FXP_READDIR: u8 = 12;
+    const FXP_REMOVE: u8 = 13;
+    const FXP_MKDIR: u8 = 14;
+    const FXP_STAT: u8 = 17;
+    const FXP_RENAME: u8 = 18;
+    const FXP_EXTENDED: u8 = 200;
+    const FXP_STATUS: u8 = 101;
+    const FXP_HANDLE: u8 = 102;
+    const FXP_DATA: u8 = 103;
+    const FXP_NAME: u8 = 104;
+    const FXP_ATTRS: u8 = 105;
+    const FXP_EXTENDED_REPLY: u8 = 201;
+
+    const FXF_CREAT: u32 = 0x08;
+    const FXF_TRUNC: u32 = 0x10;
+    const ATTR_SIZE_PERMISSIONS: u32 = 0x01 | 0x04;
+
+    /// an in-memory file system
+    #[derive(Default)]
+    struct Fs {
+        files: BTreeMap<String, Vec<u8>>,
+        dirs: BTreeSet<String>,
+        // handle => (path, listed for the directories)
+        handles: HashMap<Vec<u8>, (String, bool)>,
+        next_handle: u32,
+        // the reply held back by a stalled request, sent along with the next one
+        stalled: Option<Buf>,
+    }
+
+    impl Fs {
+        fn attrs(&self, path: &str) -> Option<Buf> {
+            let (size, mode) = match self.files.get(path) {
+                Some(content) => (content.len() as u64, 0o100644),
+                None if self.dirs.contains(path) => (0, 0o040755),
+                None => return None,
+            };
+            Some(Buf::new().u32(ATTR_SIZE_PERMISSIONS).u64(size).u32(mode))
+        }
+
+        fn handle(&mut self, path: String) -> Vec<u8> {
+            self.next_handle += 1;
+            let handle = format!("h{}", self.next_handle).into_bytes();
+            self.handles.insert(handle.clone(), (path, false));
+            handle
+        }
+
+        /// the replies to one request
+        fn serve(&mut self, packet: &[u8]) -> Vec<Buf> {
+            let mut r = Reader::new(packet);
+            let code = r.u8();
+            let id = r.u32();
+            let status = |code: u32| {
+                Buf::new()
+                    .u8(FXP_STATUS)
+                    .u32(id)
+                    .u32(code)
+                    .str(&format!("status {code}"))
+                    .str("")
+            };
+            let ok = status(sftp::status::OK);
+            let no_such_file = status(sftp::status::NO_SUCH_FILE);
+
+            let reply = match code {
+                FXP_OPEN => {
+                    let path = r.string();
+                    let flags = r.u32();
+                    if flags & FXF_CREAT != 0 {
+                        let content = self.files.entry(path.clone()).or_default();
+                        if flags & FXF_TRUNC != 0 {
+                            content.clear();
+                        }
+                    }
+                    if self.files.contains_key(&path) {
+                        Buf::new().u8(FXP_HANDLE).u32(id).bytes(&self.handle(path))
+                    } else {
+                        no_such_file
+                    }
+                }
+                FXP_OPENDIR => {
+                    let path = r.string();
+                    if self.dirs.contains(&path) {
+                        Buf::new().u8(FXP_HANDLE).u32(id).bytes(&self.handle(path))
+                    } else {
+                        no_such_file
+                    }
+                }
+                FXP_READ => {
+                    let (path, _) = &self.handles[&r.bytes()];
+                    let content = &self.files[path];
+                    let offset = r.u64() as usize;
+                    let len = r.u32() as usize;
+                    if offset >= content.len() {
+                        status(sftp::status::EOF)
+                    } else {
+                        let end = content.len().min(offset + len);
+                        Buf::new().u8(FXP_DATA).u32(id).bytes(&content[offset..end])
+                    }
+                }
+                FXP_WRITE => {
+                    let (path, _) = &self.handles[&r.bytes()];
+                    let content = self.files.get_mut(path).unwrap();
+                    let offset = r.u64() as usize;
+                    let data = r.bytes();
+                    if content.len() < offset + data.len() {
+                        content.resize(offset + data.len(), 0);
+                    }
+                    content[offset..offset + data.len()].copy_from_slice(&data);
+                    ok
+                }
+                FXP_READDIR => {
+                    let (dir, listed) = self.handles.get_mut(&r.bytes()).unwrap();
+                    if *listed {
+                        status(sftp::status::EOF)
+                    } else if dir == "/truncated-name" {
+                        // 2 entries are told but only the name of one is there
+                        Buf::new().u8(FXP_NAME).u32(id).u32(2).str("a.bin")
+                    } else {
+                        *listed = true;
+                        let dir = format!("{dir}/");
+                        let names: Vec<_> = self
+                            .files
+                            .keys()
+                            .chain(self.dirs.iter())
+                            .filter_map(|p| p.strip_prefix(&dir))
+                            .filter(|name| !name.contains('/'))
+                            .map(String::from)
+                            .collect();
+                        let mut reply = Buf::new().u8(FXP_NAME).u32(id).u32(names.len() as u32);
+                        for name in names {
+                            let attrs = self.attrs(&format!("{dir}{name}")).unwrap();
+                            reply = reply
+                                .str(&name)
+                                .str(&format!("-rw-r--r-- 1 mock mock {name}"))
+                                .raw(&attrs.into_inner());
+                        }
+                        reply
+                    }
+                }
+                FXP_CLOSE => {
+                    self.handles.remove(&r.bytes());
+                    ok
+                }
+                FXP_STAT => match r.string() {
+                    path if path == "/stall" => {
+                        self.stalled = Some(no_such_file);
+                        return vec![];
+                    }
+                    // the size is cut off
+                    path if path == "/truncated-attrs" => {
+                        Buf::new().u8(FXP_ATTRS).u32(id).u32(0x1).raw(&[0, 0, 1])
+                    }
+                    // the code is missing
+                    path if path == "/truncated-status" => Buf::new().u8(FXP_STATUS).u32(id),
+                    // not even the request id
+                    path if path == "/truncated-id" => Buf::new().u8(FXP_STATUS),
+                    path => match self.attrs(&path) {
+                        Some(attrs) => Buf::new().u8(FXP_ATTRS).u32(id).raw(&attrs.into_inner()),
+                        None => no_such_file,
+                    },
+                },
+                FXP_MKDIR => {
+                    self.dirs.insert(r.string());
+                    ok
+                }
+                FXP_REMOVE => {
+                    let removed = self.files.remove(&r.string()).is_some();
+                    // a stale reply to another request goes first
+                    let stale = Buf::new()
+                        .u8(FXP_STATUS)
+                        .u32(id + 1000)
+                        .u32(sftp::status::FAILURE)
+                        .str("stale")
+                        .str("");
+                    return vec![stale, if removed { ok } else { no_such_file }];
+                }
+                FXP_RENAME => {
+                    let (from, to) = (r.string(), r.string());
+                    match self.files.remove(&from) {
+                        Some(content) => {
+                            self.files.insert(to, content);
+                            ok
+                        }
+                        None => no_such_file,
+                    }
+                }
+                FXP_EXTENDED => {
+                    assert_eq!(r.string(), "statvfs@openssh.com");
+                    if self.dirs.contains(&r.string()) {
+                        // 4 KiB blocks, 1000 of 2500 free, 900 available
+                        let mut reply = Buf::new().u8(FXP_EXTENDED_REPLY).u32(id);
+                        for field in [4096, 4096, 2500, 1000, 900, 100, 60, 50, 7, 0x2, 255] {
+                            reply = reply.u64(field);
+                        }
+                        reply
+                    } else {
+                        no_such_file
+                    }
+                }
+                x => panic!("mock: unexpected sftp request {x}"),
+            };
+            match self.stalled.take() {
+                Some(stalled) => vec![stalled, reply],
+                None => vec![reply],
+            }
+        }
+    }
+
+    /// the extensions in SSH_FXP_VERSION of the mock
+    const EXTENSIONS: &[(&str, &str)] = &[
+        ("posix-rename@openssh.com", "1"),
+        ("statvfs@openssh.com", "2"),
+    ];
+
+    fn serve_sftp(conn: &mut common::ServerConn) -> (u32, Vec<u8>) {
+        serve_sftp_with(conn, EXTENSIONS)
+    }
+
+    /// serve the sftp subsystem until the client goes,
+    /// every reply is split into two data messages
+    ///
+    /// return the version in SSH_FXP_INIT & the requests
+    fn serve_sftp_with(
+        conn: &mut common::ServerConn,
+        extensions: &[(&str, &str)],
+    ) -> (u32, Vec<u8>) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        let req = conn.accept_channel_request(&ch);
+        assert_eq!(req.name, "subsystem");
+        assert_eq!(Reader::new(&req.data).string(), "sftp");
+
+        let mut fs = Fs::default();
+        let (mut version, mut requests) = (0, vec![]);
+        let mut pending = vec![];
+        loop {
+            let p = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| conn.recv_packet()));
+            let Ok(p) = p else {
+                break;
+            };
+            match p[0] {
+                msg::CHANNEL_DATA => (),
+                msg::CHANNEL_EOF | msg::CHANNEL_CLOSE | msg::DISCONNECT => break,
+                _ => continue,
+            }
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            pending.extend(r.bytes());
+
+            while pending.len() >= 4 {
+                let len = u32::from_be_bytes(pending[..4].try_into().unwrap()) as usize;
+                if pending.len() < 4 + len {
+                    break;
+                }
+                let packet: Vec<u8> = pending.drain(..4 + len).skip(4).collect();
+                requests.push(packet[0]);
+                let replies = if packet[0] == FXP_INIT {
+                    version = Reader::new(&packet[1..]).u32();
+                    let mut reply = Buf::new().u8(FXP_VERSION).u32(3);
+                    for (name, data) in extensions {
+                        reply = reply.str(name).str(data);
+                    }
+                    vec![reply]
+                } else {
+                    fs.serve(&packet)
+                };
+                for reply in replies {
+                    let reply = Buf::new().bytes(&reply.into_inner()).into_inner();
+                    let (head, tail) = reply.split_at(reply.len() / 2);
+                    conn.send_data(&ch, head);
+                    conn.send_data(&ch, tail);
+                }
+            }
+        }
+        (version, requests)
+    }
+
+    fn file_ops<C: sftp::SftpChannel>(sftp: &mut sftp::Sftp<C>) {
+        let content: Vec<u8> = (0..70000).map(|i| (i % 251) as u8).collect();
+
+        sftp.mkdir("/data").unwrap();
+        let file = sftp
+            .open(
+                "/data/a.bin",
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+            )
+            .unwrap();
+        sftp.write(&file, 0, &content).unwrap();
+        sftp.close(file).unwrap();
+
+        let file = sftp.open("/data/a.bin", OpenFlags::READ).unwrap();
+        let mut read = vec![];
+        loop {
+            let data = sftp.read(&file, read.len() as u64, 32768).unwrap();
+            if data.is_empty() {
+                break;
+            }
+            read.extend(data);
+        }
+        sftp.close(file).unwrap();
+        assert_eq!(read, content);
+
+        let attrs = sftp.stat("/data/a.bin").unwrap();
+        assert_eq!(attrs.size, Some(70000));
+        assert!(attrs.is_file());
+        assert!(sftp.stat("/data").unwrap().is_dir());
+
+        sftp.rename("/data/a.bin", "/data/b.bin").unwrap();
+        let entries = sftp.read_dir("/data").unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["b.bin"]);
+        assert_eq!(entries[0].attrs.size, Some(70000));
+
+        sftp.remove("/data/b.bin").unwrap();
+        let err = sftp.stat("/data/b.bin").err().unwrap();
+        assert!(matches!(
+            err,
+            SshError::SftpError {
+                code: sftp::status::NO_SUCH_FILE,
+                ..
+            }
+        ));
+        assert!(sftp.open("/data/b.bin", OpenFlags::READ).is_err());
+    }
+
+    fn stalled_reply<C: sftp::SftpChannel>(sftp: &mut sftp::Sftp<C>) {
+        sftp.mkdir("/data").unwrap();
+        sftp.set_timeout(Some(Duration::from_millis(300)));
+
+        let start = Instant::now();
+        let err = sftp.stat("/stall").err().unwrap();
+        assert!(matches!(err, SshError::SftpTimeout));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // the late reply to the stalled request is dropped
+        assert!(sftp.stat("/data").unwrap().is_dir());
+        sftp.set_timeout(None);
+        assert!(sftp.stat("/data").unwrap().is_dir());
+    }
+
+    fn truncated_replies<C: sftp::SftpChannel>(sftp: &mut sftp::Sftp<C>) {
+        let is_truncated = |err: SshError| matches!(err, SshError::ProtocolError(ref msg) if msg.contains("truncated"));
+        for path in ["/truncated-attrs", "/truncated-status", "/truncated-id"] {
+            assert!(is_truncated(sftp.stat(path).err().unwrap()), "{path}");
+        }
+        sftp.mkdir("/truncated-name").unwrap();
+        assert!(is_truncated(
+            sftp.read_dir("/truncated-name").err().unwrap()
+        ));
+
+        // the channel is still in sync for the following requests
+        sftp.mkdir("/data").unwrap();
+        assert!(sftp.stat("/data").unwrap().is_dir());
+    }
+
+    fn check_requests(version: u32, requests: &[u8]) {
+        assert_eq!(version, 3);
+        assert_eq!(requests[0], FXP_INIT);
+        // the 70000 bytes are written in 3 requests
+        assert_eq!(requests.iter().filter(|c| **c == FXP_WRITE).count(), 3);
+        assert!(requests.contains(&FXP_OPENDIR));
+        assert!(requests.contains(&FXP_READDIR));
+    }
+
+    #[test]
+    fn test_sftp_local() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut sftp = session.open_sftp().unwrap();
+        file_ops(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        let (version, requests) = server.join();
+        check_requests(version, &requests);
+    }
+
+    #[test]
+    fn test_sftp_backend() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut sftp = session.open_sftp().unwrap();
+        file_ops(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        let (version, requests) = server.join();
+        check_requests(version, &requests);
+    }
+
+    #[test]
+    fn test_sftp_timeout_local() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut sftp = session.open_sftp().unwrap();
+        stalled_reply(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        server.join();
+    }
+
+    #[test]
+    fn test_sftp_timeout_backend() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut sftp = session.open_sftp().unwrap();
+        stalled_reply(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        server.join();
+    }
+
+    #[test]
+    fn test_sftp_truncated_local() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut sftp = session.open_sftp().unwrap();
+        truncated_replies(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        server.join();
+    }
+
+    #[test]
+    fn test_sftp_truncated_backend() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut sftp = session.open_sftp().unwrap();
+        truncated_replies(&mut sftp);
+        drop(sftp);
+        session.close();
+
+        server.join();
+    }
+
+    #[test]
+    fn test_sftp_extensions() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let sftp = session.open_sftp().unwrap();
+        let extensions: Vec<_> = sftp
+            .extensions()
+            .iter()
+            .map(|(name, data)| (name.as_str(), data.as_str()))
+            .collect();
+        assert_eq!(extensions, EXTENSIONS);
+        drop(sftp);
+        session.close();
+
+        let (version, requests) = server.join();
+        assert_eq!(version, 3);
+        assert_eq!(requests, [FXP_INIT]);
+    }
+
+    #[test]
+    fn test_sftp_statvfs() {
+        let server = MockServer::spawn(serve_sftp);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut sftp = session.open_sftp().unwrap();
+        sftp.mkdir("/data").unwrap();
+        let stat = sftp.statvfs("/data").unwrap();
+        assert_eq!(stat.block_size, 4096);
+        assert_eq!(stat.blocks, 2500);
+        assert_eq!(stat.free_blocks, 1000);
+        assert_eq!(stat.available_blocks, 900);
+        assert_eq!(stat.free_files, 60);
+        assert_eq!(stat.flags, 0x2);
+        assert_eq!(stat.max_name_len, 255);
+        assert_eq!(stat.available_bytes(), 900 * 4096);
+        let err = sftp.statvfs("/none").err().unwrap();
+        assert!(matches!(
+            err,
+            SshError::SftpError {
+                code: sftp::status::NO_SUCH_FILE,
+                ..
+            }
+        ));
+        drop(sftp);
+        session.close();
+
+        let (_, requests) = server.join();
+        assert_eq!(requests.iter().filter(|c| **c == FXP_EXTENDED).count(), 2);
+    }
+
+    #[test]
+    fn test_sftp_statvfs_unsupported() {
+        let server =
+            MockServer::spawn(|conn| serve_sftp_with(conn, &[("posix-rename@openssh.com", "1")]));
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut sftp = session.open_sftp().unwrap();
+        let err = sftp.statvfs("/").err().unwrap();
+        assert!(matches!(
+            err,
+            SshError::SftpError {
+                code: sftp::status::OP_UNSUPPORTED,
+                ref message,
+            } if message.contains("statvfs@openssh.com")
+        ));
+        drop(sftp);
+        session.close();
+
+        // nothing is sent for it
+        let (_, requests) = server.join();
+        assert_eq!(requests, [FXP_INIT]);
+    }
+
+    #[test]
+    fn test_sftp_not_allowed() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder()
+            .allowed_subsystems(Some(&["netconf"]))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let err = session.open_sftp().err().unwrap();
+        assert!(matches!(err, SshError::SubsystemNotAllowed(ref name) if name == "sftp"));
+        session.close();
+        server.join();
+    }
+}
@@ -0,0 +1,384 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::{PtyConfig, TerminalSize, TerminalSizeType};
+    use std::sync::mpsc;
+
+    /// the (term, cols, rows, pixel width, pixel height) of the pty-req
+    fn serve_pty(conn: &mut common::ServerConn) -> (String, [u32; 4]) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        let req = conn.accept_channel_request(&ch);
+        assert_eq!(req.name, "pty-req");
+        assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+        conn.send_data(&ch, b"$ ");
+        conn.wait_disconnect();
+
+        let mut r = Reader::new(&req.data);
+        let term = r.string();
+        (term, [r.u32(), r.u32(), r.u32(), r.u32()])
+    }
+
+    /// the window-change request between the data "a" & "b",
+    /// which is not replied
+    fn serve_resize(conn: &mut common::ServerConn) -> [u32; 4] {
+        conn.ready();
+        let ch = conn.accept_channel();
+        assert_eq!(conn.accept_channel_request(&ch).name, "pty-req");
+        assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+        let data = |conn: &mut common::ServerConn| {
+            let p = conn.recv_msg(msg::CHANNEL_DATA);
+            let mut r = Reader::new(&p[1..]);
+            r.u32();
+            r.bytes()
+        };
+        assert_eq!(data(conn), b"a");
+        let req = conn.recv_channel_request();
+        assert_eq!(req.name, "window-change");
+        assert!(!req.want_reply);
+        assert_eq!(data(conn), b"b");
+        conn.send_data(&ch, b"ab");
+        conn.wait_disconnect();
+
+        let mut r = Reader::new(&req.data);
+        [r.u32(), r.u32(), r.u32(), r.u32()]
+    }
+
+    #[test]
+    fn test_change_window_size_local() {
+        let server = MockServer::spawn(serve_resize);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        shell.write(b"a").unwrap();
+        shell.change_window_size(120, 40, 0, 0).unwrap();
+        shell.write(b"b").unwrap();
+        assert_eq!(shell.read().unwrap(), b"ab");
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), [120, 40, 0, 0]);
+    }
+
+    #[test]
+    fn test_change_window_size_backend() {
+        let server = MockServer::spawn(serve_resize);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        shell.write(b"a").unwrap();
+        shell.change_window_size(100, 30, 800, 600).unwrap();
+        shell.write(b"b").unwrap();
+        assert_eq!(shell.read().unwrap(), b"ab");
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), [100, 30, 800, 600]);
+    }
+
+    /// after the shell is started, write the data "one", "two" & "three" in one segment,
+    /// together with the messages of no data between them
+    fn serve_burst(conn: &mut common::ServerConn) {
+        conn.ready();
+        let ch = conn.accept_channel();
+        assert_eq!(conn.accept_channel_request(&ch).name, "pty-req");
+        assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+        let data = |s: &str| {
+            Buf::new()
+                .u8(msg::CHANNEL_DATA)
+                .u32(ch.client_id)
+                .bytes(s.as_bytes())
+        };
+        let burst = [
+            data("one"),
+            Buf::new()
+                .u8(msg::CHANNEL_WINDOW_ADJUST)
+                .u32(ch.client_id)
+                .u32(1024),
+            data("two"),
+            Buf::new()
+                .u8(msg::CHANNEL_REQUEST)
+                .u32(ch.client_id)
+                .str("exit-status")
+                .bool(false)
+                .u32(0),
+            data("three"),
+        ];
+        let mut segment = vec![];
+        for payload in burst {
+            segment.extend(conn.encode_packet(&payload.into_inner()));
+        }
+        conn.write_raw(&segment);
+        conn.send_eof(&ch);
+        conn.send_close(&ch);
+        conn.wait_disconnect();
+    }
+
+    #[test]
+    fn test_read_drains_packets_local() {
+        let server = MockServer::spawn(serve_burst);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        // wait for the whole segment
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(shell.read().unwrap(), b"onetwothree");
+        drop(shell);
+        session.close();
+
+        server.join();
+    }
+
+    #[test]
+    fn test_read_drains_packets_backend() {
+        let server = MockServer::spawn(serve_burst);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        // the exit status amid the data is not taken for the close
+        let mut output = vec![];
+        loop {
+            let data = shell.read().unwrap();
+            if data.is_empty() {
+                break;
+            }
+            output.extend(data);
+        }
+        assert_eq!(output, b"onetwothree");
+        assert!(shell.is_remote_closed());
+        drop(shell);
+        session.close();
+
+        server.join();
+    }
+
+    /// send "out" once the client writes "poll",
+    /// return the window adjust for it
+    fn serve_poll(conn: &mut common::ServerConn) -> u32 {
+        conn.ready();
+        let ch = conn.accept_channel();
+        assert_eq!(conn.accept_channel_request(&ch).name, "pty-req");
+        assert_eq!(conn.accept_channel_request(&ch).name, "shell");
+        let p = conn.recv_msg(msg::CHANNEL_DATA);
+        let mut r = Reader::new(&p[1..]);
+        r.u32();
+        assert_eq!(r.bytes(), b"poll");
+        conn.send_data(&ch, b"out");
+
+        let p = conn.recv_msg(msg::CHANNEL_WINDOW_ADJUST);
+        let mut r = Reader::new(&p[1..]);
+        r.u32();
+        let adjust = r.u32();
+        conn.send_eof(&ch);
+        conn.send_close(&ch);
+        conn.wait_disconnect();
+        adjust
+    }
+
+    /// poll `try_read` until it has something
+    fn poll<F>(mut try_read: F) -> Vec<u8>
+    where
+        F: FnMut() -> ssh::SshResult<Option<Vec<u8>>>,
+    {
+        loop {
+            if let Some(data) = try_read().unwrap() {
+                return data;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_try_read_local() {
+        let server = MockServer::spawn(serve_poll);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.try_read().unwrap(), None);
+        shell.write(b"poll").unwrap();
+        assert_eq!(poll(|| shell.try_read()), b"out");
+        // the window adjust is sent while polling, then the close is read
+        assert!(poll(|| shell.try_read()).is_empty());
+        assert!(shell.is_remote_closed());
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), 3);
+    }
+
+    #[test]
+    fn test_try_read_backend() {
+        let server = MockServer::spawn(serve_poll);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.try_read().unwrap(), None);
+        shell.write(b"poll").unwrap();
+        assert_eq!(poll(|| shell.try_read()), b"out");
+        assert!(poll(|| shell.try_read()).is_empty());
+        assert!(shell.is_remote_closed());
+        // still empty after the close
+        assert_eq!(shell.try_read().unwrap(), Some(vec![]));
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), 3);
+    }
+
+    const BULK_CHUNK: usize = 32768;
+    const BULK_CHUNKS: usize = 1024;
+
+    /// the bulk bytes on the wire before the keystrokes to the shell,
+    /// nothing is read until the client has queued all of them
+    fn serve_bulk_and_shell(
+        conn: &mut common::ServerConn,
+        queued: mpsc::Receiver<()>,
+        received: mpsc::Sender<()>,
+    ) -> usize {
+        conn.ready();
+        let bulk = conn.accept_channel_with_window(u32::MAX);
+        let shell = conn.accept_channel();
+        assert_eq!(conn.accept_channel_request(&shell).name, "pty-req");
+        assert_eq!(conn.accept_channel_request(&shell).name, "shell");
+        queued.recv().unwrap();
+
+        let (mut bulk_len, mut before_shell) = (0, None);
+        while bulk_len < BULK_CHUNK * BULK_CHUNKS || before_shell.is_none() {
+            let p = conn.recv_packet();
+            if p[0] != msg::CHANNEL_DATA {
+                continue;
+            }
+            let mut r = Reader::new(&p[1..]);
+            let id = r.u32();
+            let data = r.bytes();
+            if id == bulk.server_id {
+                bulk_len += data.len();
+            } else {
+                assert_eq!(id, shell.server_id);
+                assert_eq!(data, b"ls\n");
+                before_shell = Some(bulk_len);
+            }
+        }
+        received.send(()).unwrap();
+        conn.wait_disconnect();
+        before_shell.unwrap()
+    }
+
+    #[test]
+    fn test_interactive_ahead_of_bulk_backend() {
+        let (queued_send, queued_recv) = mpsc::channel();
+        let (received_send, received_recv) = mpsc::channel();
+        let server =
+            MockServer::spawn(move |conn| serve_bulk_and_shell(conn, queued_recv, received_send));
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut bulk = session.open_direct_tcpip("files.internal", 873).unwrap();
+        let mut shell = session.open_shell().unwrap();
+        for _ in 0..BULK_CHUNKS {
+            bulk.write(&[0; BULK_CHUNK]).unwrap();
+        }
+        shell.write(b"ls\n").unwrap();
+        queued_send.send(()).unwrap();
+        received_recv.recv().unwrap();
+        drop(shell);
+        drop(bulk);
+        session.close();
+
+        // only what is already in the socket buffers goes first
+        let before_shell = server.join();
+        assert!(
+            before_shell < BULK_CHUNK * BULK_CHUNKS / 2,
+            "{before_shell} bulk bytes before the shell data"
+        );
+    }
+
+    #[test]
+    fn test_default_pty_local() {
+        let server = MockServer::spawn(serve_pty);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session.open_shell().unwrap();
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), ("xterm-256color".to_owned(), [80, 24, 0, 0]));
+    }
+
+    #[test]
+    fn test_pty_config_local() {
+        let server = MockServer::spawn(serve_pty);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let mut shell = session
+            .open_shell_with_pty(PtyConfig {
+                cols: 200,
+                rows: 50,
+                term: "vt100",
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), ("vt100".to_owned(), [200, 50, 0, 0]));
+    }
+
+    #[test]
+    fn test_pty_config_backend() {
+        let server = MockServer::spawn(serve_pty);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut shell = session
+            .open_shell_with_pty(PtyConfig {
+                cols: 132,
+                rows: 43,
+                pixel_width: 1056,
+                pixel_height: 688,
+                term: "xterm",
+            })
+            .unwrap();
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        drop(shell);
+        session.close();
+
+        assert_eq!(server.join(), ("xterm".to_owned(), [132, 43, 1056, 688]));
+    }
+
+    #[test]
+    fn test_terminal_size_backend() {
+        let server = MockServer::spawn(serve_pty);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let tv = TerminalSize::from_type(640, 480, TerminalSizeType::Pixel);
+        let mut shell = session.open_shell_terminal(tv).unwrap();
+        assert_eq!(shell.read().unwrap(), b"$ ");
+        drop(shell);
+        session.close();
+
+        assert_eq!(
+            server.join(),
+            ("xterm-256color".to_owned(), [0, 0, 640, 480])
+        );
+    }
+}
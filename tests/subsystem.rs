@@ -0,0 +1,96 @@
+mod common;
+
+mod tests {
+    use crate::common::{self, msg, Buf, MockServer, Reader};
+    use ssh::SshError;
+
+    /// accept the subsystem request, then echo the data back once
+    ///
+    /// return the name of the subsystem
+    fn serve_echo_subsystem(conn: &mut common::ServerConn) -> String {
+        conn.ready();
+        let ch = conn.accept_channel();
+        let req = conn.accept_channel_request(&ch);
+        assert_eq!(req.name, "subsystem");
+        assert!(req.want_reply);
+        let p = conn.recv_msg(msg::CHANNEL_DATA);
+        let mut r = Reader::new(&p[1..]);
+        r.u32();
+        conn.send_data(&ch, &r.bytes());
+        conn.wait_disconnect();
+        Reader::new(&req.data).string()
+    }
+
+    #[test]
+    fn test_subsystem_not_allowed_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let mut codes = vec![];
+            while let Ok(p) =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| conn.recv_packet()))
+            {
+                codes.push(p[0]);
+            }
+            codes
+        });
+
+        let mut session = common::builder()
+            .allowed_subsystems(Some(&["sftp"]))
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let err = session.open_subsystem("netconf").err().unwrap();
+        assert!(matches!(err, SshError::SubsystemNotAllowed(ref name) if name == "netconf"));
+        session.close();
+
+        // neither the channel nor the request is sent
+        let codes = server.join();
+        assert!(!codes.contains(&msg::CHANNEL_OPEN));
+        assert!(!codes.contains(&msg::CHANNEL_REQUEST));
+    }
+
+    #[test]
+    fn test_allowed_subsystem_backend() {
+        let server = MockServer::spawn(serve_echo_subsystem);
+
+        let mut session = common::builder()
+            .allowed_subsystems(Some(&["sftp"]))
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        assert!(matches!(
+            session.open_subsystem("netconf").err().unwrap(),
+            SshError::SubsystemNotAllowed(_)
+        ));
+        let mut sftp = session.open_subsystem("sftp").unwrap();
+        assert_eq!(sftp.name(), "sftp");
+        sftp.write(b"\x00\x00\x00\x05\x01\x00\x00\x00\x03").unwrap();
+        assert_eq!(
+            sftp.read().unwrap(),
+            b"\x00\x00\x00\x05\x01\x00\x00\x00\x03"
+        );
+        drop(sftp);
+        session.close();
+
+        assert_eq!(server.join(), "sftp");
+    }
+
+    #[test]
+    fn test_subsystem_refused_local() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            let req = conn.recv_channel_request();
+            assert_eq!(req.name, "subsystem");
+            conn.send(Buf::new().u8(msg::CHANNEL_FAILURE).u32(ch.client_id));
+            conn.wait_disconnect();
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let err = session.open_subsystem("sftp").err().unwrap();
+        assert!(matches!(err, SshError::SubsystemRefused(ref name) if name == "sftp"));
+        session.close();
+
+        server.join();
+    }
+}
@@ -0,0 +1,55 @@
+mod common;
+
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        time::Duration,
+    };
+
+    use crate::common::{self, MockServer};
+    use ssh::TcpKeepalive;
+
+    fn keepalive() -> TcpKeepalive {
+        TcpKeepalive::new()
+            .with_idle(Duration::from_secs(60))
+            .with_interval(Duration::from_secs(10))
+            .with_retries(3)
+    }
+
+    #[test]
+    fn test_tcp_keepalive_applied() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let socket = socket2::SockRef::from(&tcp);
+        assert!(!socket.keepalive().unwrap());
+
+        keepalive().apply(&tcp).unwrap();
+        assert!(socket.keepalive().unwrap());
+
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+        {
+            assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(60));
+            assert_eq!(
+                socket.keepalive_interval().unwrap(),
+                Duration::from_secs(10)
+            );
+            assert_eq!(socket.keepalive_retries().unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn test_tcp_keepalive_on_connect() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"hello", 0));
+
+        let mut session = common::builder()
+            .tcp_keepalive(Some(keepalive()))
+            .connect(server.addr)
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+}
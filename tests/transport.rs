@@ -0,0 +1,442 @@
+mod common;
+
+mod tests {
+    use std::{
+        io::{self, Read, Write},
+        net::TcpStream,
+    };
+
+    use crate::common::{self, msg, Buf, MockServer};
+    use ssh::{algorithm::Enc, DisconnectReason, SshError};
+
+    /// a stream which gives at most one byte for each read
+    struct Trickle(TcpStream);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn test_one_byte_reads_local() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"hello", 0));
+
+        let stream = TcpStream::connect(server.addr).unwrap();
+        let mut session = common::builder()
+            .connect_bio(Trickle(stream))
+            .unwrap()
+            .run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_one_byte_reads_backend() {
+        let server = MockServer::spawn(|conn| conn.ready_and_serve_exec(b"hello", 0));
+
+        let stream = TcpStream::connect(server.addr).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut session = common::builder()
+            .connect_bio(Trickle(stream))
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("echo hello").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"hello");
+
+        assert_eq!(server.join(), "echo hello");
+        session.close();
+    }
+
+    #[test]
+    fn test_connection_closed_by_server() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            conn.accept_channel();
+            // then close the connection
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        server.join();
+        assert!(exec.send_command("echo hello").is_err());
+    }
+
+    fn send_disconnect(conn: &mut common::ServerConn, code: u32, description: &str) {
+        conn.send(
+            Buf::new()
+                .u8(msg::DISCONNECT)
+                .u32(code)
+                .str(description)
+                .str("en"),
+        );
+    }
+
+    #[test]
+    fn test_disconnected_during_auth() {
+        let server = MockServer::spawn(|conn| {
+            conn.handshake();
+            conn.accept_service();
+            conn.recv_msg(msg::USERAUTH_REQUEST);
+            send_disconnect(conn, 14, "Too many authentication failures");
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        server.join();
+        let SshError::Disconnected { code, description } = err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(code, 14);
+        assert_eq!(description, "Too many authentication failures");
+        assert_eq!(
+            DisconnectReason::from_code(code),
+            Some(DisconnectReason::NoMoreAuthMethodsAvailable)
+        );
+    }
+
+    #[test]
+    fn test_disconnected_by_server() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            send_disconnect(conn, 11, "idle timeout");
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        let err = exec.send_command("sleep 3600").err().unwrap();
+        server.join();
+        assert!(
+            matches!(
+                err,
+                SshError::Disconnected { code: 11, ref description } if description == "idle timeout"
+            ),
+            "{err:?}"
+        );
+        assert_eq!(
+            DisconnectReason::from_code(11),
+            Some(DisconnectReason::ByApplication)
+        );
+        assert_eq!(DisconnectReason::from_code(99), None);
+    }
+
+    #[test]
+    fn test_truncated_disconnect() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            // the description claims more bytes than there are
+            conn.send(Buf::new().u8(msg::DISCONNECT).u32(11).u32(64).raw(b"idle"));
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        let err = exec.send_command("sleep 3600").err().unwrap();
+        server.join();
+        assert!(matches!(err, SshError::ProtocolError(_)), "{err:?}");
+    }
+
+    /// the messages which may show up at any time
+    fn send_noise(conn: &mut common::ServerConn) {
+        conn.send(Buf::new().u8(msg::IGNORE).bytes(&[0x5a; 64]));
+        conn.send(
+            Buf::new()
+                .u8(msg::DEBUG)
+                .bool(true)
+                .str("debug from the mock")
+                .str("en"),
+        );
+        conn.send(Buf::new().u8(msg::UNIMPLEMENTED).u32(3));
+    }
+
+    /// an exec whose every step is followed by the noise,
+    /// including a re-exchange in the middle of the output
+    fn serve_noisy_exec(conn: &mut common::ServerConn) -> String {
+        conn.handshake();
+        send_noise(conn);
+        conn.accept_service();
+        send_noise(conn);
+        conn.accept_auth();
+        send_noise(conn);
+        let ch = conn.accept_channel();
+        send_noise(conn);
+        let req = conn.accept_channel_request(&ch);
+        send_noise(conn);
+        conn.send_data(&ch, b"hel");
+
+        conn.send_kexinit();
+        conn.recv_kexinit();
+        send_noise(conn);
+        conn.kex_reply();
+        send_noise(conn);
+        conn.send_newkeys();
+        conn.recv_newkeys();
+
+        send_noise(conn);
+        conn.send_data(&ch, b"lo");
+        send_noise(conn);
+        conn.finish_channel(&ch, 0);
+        conn.wait_disconnect();
+        common::Reader::new(&req.data).string()
+    }
+
+    #[test]
+    fn test_skip_ignore_and_debug_local() {
+        let server = MockServer::spawn(serve_noisy_exec);
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        session.close();
+        assert_eq!(server.join(), "echo hello");
+    }
+
+    #[test]
+    fn test_skip_ignore_and_debug_backend() {
+        let server = MockServer::spawn(serve_noisy_exec);
+
+        let mut session = common::builder()
+            .connect(server.addr)
+            .unwrap()
+            .run_backend();
+        let mut exec = session.open_exec().unwrap();
+        exec.send_command("echo hello").unwrap();
+        assert_eq!(exec.get_result().unwrap(), b"hello");
+        drop(exec);
+        session.close();
+        assert_eq!(server.join(), "echo hello");
+    }
+
+    #[test]
+    fn test_skip_malformed_ignore_and_debug() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            // no string in the ignore, a truncated message in the debug
+            conn.send(Buf::new().u8(msg::IGNORE).raw(&[0x5a; 2]));
+            conn.send(Buf::new().u8(msg::DEBUG).bool(true).u32(100).raw(b"short"));
+            conn.send(Buf::new().u8(msg::DEBUG));
+            conn.serve_exec(b"hello", 0)
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+        session.close();
+        assert_eq!(server.join(), "echo hello");
+    }
+
+    #[test]
+    fn test_truncated_unimplemented() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.send(Buf::new().u8(msg::UNIMPLEMENTED).raw(&[0, 3]));
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        let err = exec.send_command("sleep 3600").err().unwrap();
+        server.join();
+        assert!(matches!(err, SshError::ProtocolError(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_reject_ignore_in_initial_kex() {
+        let server = MockServer::spawn(|conn| {
+            conn.send_version();
+            conn.read_version();
+            conn.send_kexinit();
+            conn.recv_kexinit();
+            // not protected by any key yet
+            conn.send(Buf::new().u8(msg::IGNORE).bytes(b"padding"));
+            conn.wait_disconnect();
+        });
+
+        let err = common::builder().connect(server.addr).err().unwrap();
+        server.join();
+        assert!(matches!(err, SshError::KexError(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_reject_stale_sequence_number() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready();
+            let ch = conn.accept_channel();
+            conn.accept_channel_request(&ch);
+            conn.send_data(&ch, b"hello");
+
+            // replay the data with the sequence number of the prior packet
+            let payload = Buf::new()
+                .u8(msg::CHANNEL_DATA)
+                .u32(ch.client_id)
+                .bytes(b"hello")
+                .into_inner();
+            let stale = conn.send_seq - 1;
+            conn.send_packet_with_seq(&payload, stale);
+            // feed enough bytes in case that a bad length is decrypted
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                conn.write_raw(&[0; 300 * 1024])
+            }));
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        let err = exec.send_command("cat").unwrap_err();
+        assert!(
+            matches!(
+                err,
+                SshError::ProtocolError(_) | SshError::EncryptionError(_)
+            ),
+            "{err:?}"
+        );
+        drop(session);
+        server.join();
+    }
+
+    #[test]
+    fn test_hmac_mismatch() {
+        for mac in ["hmac-sha2-256", "hmac-sha2-512"] {
+            let config = common::ServerConfig {
+                ciphers: "aes256-ctr".to_owned(),
+                macs: mac.to_owned(),
+                ..Default::default()
+            };
+            let server = MockServer::spawn_with(config, |conn| {
+                conn.ready();
+                let ch = conn.accept_channel();
+                conn.accept_channel_request(&ch);
+                conn.send_data(&ch, b"hello");
+
+                // the keystream goes on, but the MAC is computed over a wrong sequence number
+                let payload = Buf::new()
+                    .u8(msg::CHANNEL_DATA)
+                    .u32(ch.client_id)
+                    .bytes(b"world")
+                    .into_inner();
+                let seq = conn.send_seq;
+                conn.send_packet_with_seq(&payload, seq + 1);
+                seq
+            });
+
+            let mut session = common::builder().connect(server.addr).unwrap().run_local();
+            let exec = session.open_exec().unwrap();
+            let err = exec.send_command("cat").unwrap_err();
+            let seq = server.join();
+            assert!(
+                matches!(err, SshError::MacVerificationError(x) if x == seq),
+                "{mac}: {err:?}"
+            );
+            drop(session);
+        }
+    }
+
+    #[test]
+    fn test_aes_ctr_key_sizes() {
+        for (enc, key_size) in [
+            (Enc::Aes128Ctr, 16),
+            (Enc::Aes192Ctr, 24),
+            (Enc::Aes256Ctr, 32),
+        ] {
+            assert_eq!(enc.key_size(), key_size);
+
+            let config = common::ServerConfig {
+                ciphers: enc.as_ref().to_owned(),
+                ..Default::default()
+            };
+            let server = MockServer::spawn_with(config, |conn| {
+                conn.ready_and_serve_exec(b"hello", 0);
+                let c2s = conn.client_keys.as_ref().unwrap();
+                let s2c = conn.server_keys.as_ref().unwrap();
+                (
+                    conn.negotiated.c2s_cipher.clone(),
+                    (c2s.key.len(), c2s.iv.len()),
+                    (s2c.key.len(), s2c.iv.len()),
+                )
+            });
+
+            // the server derives the keys of the negotiated width,
+            // so the exec only works if the client derives the same ones
+            let mut session = common::builder().connect(server.addr).unwrap().run_local();
+            let exec = session.open_exec().unwrap();
+            assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+            let (cipher, c2s, s2c) = server.join();
+            assert_eq!(cipher, enc.as_ref());
+            assert_eq!(c2s, (key_size, 16));
+            assert_eq!(s2c, (key_size, 16));
+            session.close();
+        }
+    }
+
+    #[test]
+    fn test_is_aead() {
+        for (enc, aead) in [
+            (Enc::Chacha20Poly1305Openssh, true),
+            (Enc::Aes256Ctr, false),
+        ] {
+            let config = common::ServerConfig {
+                ciphers: enc.as_ref().to_owned(),
+                ..Default::default()
+            };
+            let server =
+                MockServer::spawn_with(config, |conn| conn.ready_and_serve_exec(b"hello", 0));
+
+            let connector = common::builder().connect(server.addr).unwrap();
+            assert_eq!(connector.is_aead(), aead, "{}", enc.as_ref());
+            let mut session = connector.run_backend();
+            assert_eq!(session.is_aead(), aead, "{}", enc.as_ref());
+            let mut exec = session.open_exec().unwrap();
+            exec.send_command("echo hello").unwrap();
+            assert_eq!(exec.get_result().unwrap(), b"hello");
+
+            server.join();
+            session.close();
+        }
+    }
+
+    #[test]
+    fn test_random_padding() {
+        let server = MockServer::spawn(|conn| {
+            conn.ready_and_serve_exec(b"hello", 0);
+            conn.paddings.clone()
+        });
+
+        let mut session = common::builder().connect(server.addr).unwrap().run_local();
+        let exec = session.open_exec().unwrap();
+        assert_eq!(exec.send_command("echo hello").unwrap(), b"hello");
+
+        // both the plain text packets and the encrypted ones
+        let paddings = server.join();
+        assert!(paddings.len() > 4, "{paddings:?}");
+        for padding in &paddings {
+            assert!(padding.len() >= 4, "{padding:?}");
+        }
+        let zeros = paddings
+            .iter()
+            .filter(|p| p.iter().all(|&x| x == 0))
+            .count();
+        assert_eq!(zeros, 0, "{paddings:?}");
+        for (i, padding) in paddings.iter().enumerate() {
+            assert!(!paddings[i + 1..].contains(padding), "{paddings:?}");
+        }
+        session.close();
+    }
+}
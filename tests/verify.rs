@@ -0,0 +1,174 @@
+mod common;
+
+mod tests {
+    use crate::common::{host_key_blob, sign_with_host_key, ED25519_SEED, RSA_HOST_KEY};
+    use ssh::{
+        algorithm::{parse_public_key, sign, verify, PubKey},
+        SshError,
+    };
+    use ssh_key::{private::Ed25519Keypair, LineEnding, PrivateKey, PublicKey};
+
+    const DATA: &[u8] = b"the data to be signed";
+
+    #[test]
+    fn test_verify_ed25519() {
+        let key = host_key_blob("ssh-ed25519");
+        let sig = sign_with_host_key("ssh-ed25519", DATA);
+        assert!(verify(&key, DATA, &sig, PubKey::SshEd25519).unwrap());
+
+        // tampered data
+        assert!(!verify(&key, b"the data to be signeD", &sig, PubKey::SshEd25519).unwrap());
+
+        // tampered signature
+        let mut bad = sig.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert!(!verify(&key, DATA, &bad, PubKey::SshEd25519).unwrap());
+    }
+
+    #[test]
+    fn test_verify_rsa() {
+        let key = host_key_blob("ssh-rsa");
+        for (alg, name) in [
+            (PubKey::RsaSha2_256, "rsa-sha2-256"),
+            (PubKey::RsaSha2_512, "rsa-sha2-512"),
+        ] {
+            let sig = sign_with_host_key(name, DATA);
+            assert!(verify(&key, DATA, &sig, alg).unwrap());
+
+            let mut bad = sig.clone();
+            let last = bad.len() - 1;
+            bad[last] ^= 0x80;
+            assert!(!verify(&key, DATA, &bad, alg).unwrap());
+        }
+
+        // signed with rsa-sha2-512 but verified as rsa-sha2-256
+        let sig = sign_with_host_key("rsa-sha2-512", DATA);
+        assert!(!verify(&key, DATA, &sig, PubKey::RsaSha2_256).unwrap());
+    }
+
+    #[test]
+    fn test_verify_malformed() {
+        let key = host_key_blob("ssh-ed25519");
+        let sig = sign_with_host_key("ssh-ed25519", DATA);
+
+        // the key does not fit the algorithm
+        assert!(matches!(
+            verify(&key, DATA, &sig, PubKey::RsaSha2_256),
+            Err(SshError::SshPubKeyError(_))
+        ));
+        // truncated blobs
+        assert!(matches!(
+            verify(&key[..key.len() - 1], DATA, &sig, PubKey::SshEd25519),
+            Err(SshError::SshPubKeyError(_))
+        ));
+        assert!(matches!(
+            verify(&key, DATA, &sig[..sig.len() - 1], PubKey::SshEd25519),
+            Err(SshError::SshPubKeyError(_))
+        ));
+    }
+
+    fn ed25519_openssh_key() -> String {
+        let pair = Ed25519Keypair::from_seed(&ED25519_SEED);
+        PrivateKey::from(pair)
+            .to_openssh(LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_sign_ed25519() {
+        let key = host_key_blob("ssh-ed25519");
+        let sig = sign(&ed25519_openssh_key(), DATA, PubKey::SshEd25519).unwrap();
+        assert!(verify(&key, DATA, &sig, PubKey::SshEd25519).unwrap());
+        assert!(!verify(&key, b"other data", &sig, PubKey::SshEd25519).unwrap());
+
+        // deterministic, the same as the one of ring
+        assert_eq!(sig, sign_with_host_key("ssh-ed25519", DATA));
+    }
+
+    #[test]
+    fn test_sign_rsa() {
+        let key = host_key_blob("ssh-rsa");
+        for alg in [PubKey::RsaSha2_256, PubKey::RsaSha2_512] {
+            let sig = sign(RSA_HOST_KEY, DATA, alg).unwrap();
+            assert!(verify(&key, DATA, &sig, alg).unwrap());
+            assert!(!verify(&key, b"other data", &sig, alg).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_sign_mismatched_algorithm() {
+        assert!(matches!(
+            sign(RSA_HOST_KEY, DATA, PubKey::SshEd25519),
+            Err(SshError::SshPubKeyError(_))
+        ));
+        assert!(matches!(
+            sign(&ed25519_openssh_key(), DATA, PubKey::RsaSha2_256),
+            Err(SshError::SshPubKeyError(_))
+        ));
+    }
+
+    /// the authorized_keys line of the mock host key
+    fn host_key_line(alg: &str, comment: &str) -> String {
+        let mut key = PublicKey::from_bytes(&host_key_blob(alg)).unwrap();
+        key.set_comment(comment);
+        key.to_openssh().unwrap()
+    }
+
+    #[test]
+    fn test_parse_public_key_ed25519() {
+        let line = host_key_line("ssh-ed25519", "ed25519@example");
+        let key = parse_public_key(&line).unwrap();
+        assert_eq!(key.algorithm, "ssh-ed25519");
+        assert_eq!(key.comment, "ed25519@example");
+        assert!(key.options.is_empty());
+        assert_eq!(key.key_blob, host_key_blob("ssh-ed25519"));
+
+        let sig = sign_with_host_key("ssh-ed25519", DATA);
+        assert!(verify(&key.key_blob, DATA, &sig, PubKey::SshEd25519).unwrap());
+    }
+
+    #[test]
+    fn test_parse_public_key_rsa() {
+        let line = format!(
+            "no-pty,command=\"echo hi\" {}\n",
+            host_key_line("ssh-rsa", "rsa@example")
+        );
+        let key = parse_public_key(&line).unwrap();
+        assert_eq!(key.algorithm, "ssh-rsa");
+        assert_eq!(key.comment, "rsa@example");
+        assert_eq!(key.options, ["no-pty", "command=\"echo hi\""]);
+        assert_eq!(key.key_blob, host_key_blob("ssh-rsa"));
+
+        let sig = sign_with_host_key("rsa-sha2-256", DATA);
+        assert!(verify(&key.key_blob, DATA, &sig, PubKey::RsaSha2_256).unwrap());
+    }
+
+    #[test]
+    fn test_parse_public_key_ecdsa() {
+        let key = parse_public_key(
+            "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBPiPn8WevOe1yJqYjC1dEpSmJ59rhr/2BDSz4140/DZSU6ogeFm8m5clMZj88TmygOGCyNrCTMzEqYllmly6sps=",
+        )
+        .unwrap();
+        assert_eq!(key.algorithm, "ecdsa-sha2-nistp256");
+        assert_eq!(key.comment, "");
+        // string "ecdsa-sha2-nistp256", string "nistp256", string Q
+        assert_eq!(key.key_blob.len(), 4 + 19 + 4 + 8 + 4 + 65);
+        assert_eq!(&key.key_blob[4..23], b"ecdsa-sha2-nistp256");
+    }
+
+    #[test]
+    fn test_parse_public_key_malformed() {
+        for line in [
+            "",
+            "ssh-ed25519",
+            "ssh-ed25519 not-base64 comment",
+            "ssh-rsa AAAAC3NzaC1lZDI1NTE5AAAAIPti3hHmkEba9H5JvhiP8sATekyhxWXOneXTAE4Yl3S0",
+        ] {
+            assert!(
+                matches!(parse_public_key(line), Err(SshError::SshPubKeyError(_))),
+                "{line}"
+            );
+        }
+    }
+}